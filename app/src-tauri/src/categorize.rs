@@ -0,0 +1,326 @@
+use crate::duplicates::normalize_payee;
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::path::PathBuf;
+
+// How many categories `suggest_category` returns unless asked otherwise
+pub const DEFAULT_SUGGESTIONS: usize = 3;
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct CategorySuggestion {
+    pub category: String,
+    // Probability of the category among all known ones, between 0 and 1
+    pub confidence: f64,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug, Default, PartialEq)]
+pub struct ModelSyncReport {
+    pub learned: usize,
+    pub forgotten: usize,
+    pub categories: usize,
+}
+
+// Words of the payee and notes plus a bucket for the sign and order of magnitude of the amount,
+// so a 4.50 coffee and a 1200.00 rent payment to the same landlord read differently
+pub fn features(payee: &str, notes: Option<&str>, amount: f64) -> Vec<String> {
+    let mut features = BTreeSet::new();
+    for token in normalize_payee(payee).split(' ').filter(|t| t.len() > 1) {
+        features.insert(format!("p:{}", token));
+    }
+    for token in normalize_payee(notes.unwrap_or_default())
+        .split(' ')
+        .filter(|t| t.len() > 1)
+    {
+        features.insert(format!("n:{}", token));
+    }
+    let sign = if amount < 0.0 { '-' } else { '+' };
+    let magnitude = amount.abs().max(1.0).log10().floor() as i32;
+    features.insert(format!("a:{}{}", sign, magnitude));
+    features.into_iter().collect()
+}
+
+// Transactions the model learns from. Transfers and trades are not categories a user picks.
+const LEARNABLE: &str =
+    "category IS NOT NULL AND TRIM(category) != '' AND category != 'Transfer' AND shares IS NULL";
+
+// Adds a transaction to the model
+pub fn learn(
+    conn: &Connection,
+    id: i32,
+    category: &str,
+    features: &[String],
+) -> Result<(), String> {
+    for feature in features {
+        conn.execute(
+            "INSERT INTO category_features (category, feature, count) VALUES (?1, ?2, 1)
+             ON CONFLICT(category, feature) DO UPDATE SET count = count + 1",
+            params![category, feature],
+        )
+        .map_err(|e| e.to_string())?;
+    }
+    let features = serde_json::to_string(features).map_err(|e| e.to_string())?;
+    conn.execute(
+        "INSERT INTO category_training (transaction_id, category, features) VALUES (?1, ?2, ?3)",
+        params![id, category, features],
+    )
+    .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+fn forget(conn: &Connection, id: i32, category: &str, features: &[String]) -> Result<(), String> {
+    for feature in features {
+        conn.execute(
+            "UPDATE category_features SET count = count - 1 WHERE category = ?1 AND feature = ?2",
+            params![category, feature],
+        )
+        .map_err(|e| e.to_string())?;
+    }
+    conn.execute("DELETE FROM category_features WHERE count <= 0", [])
+        .map_err(|e| e.to_string())?;
+    conn.execute(
+        "DELETE FROM category_training WHERE transaction_id = ?1",
+        params![id],
+    )
+    .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+// Brings what the model learned from one transaction in line with the transaction as it is now.
+// Write paths call this for every transaction they insert or change, so suggestions never need a
+// full pass over the ledger.
+pub fn refresh(conn: &Connection, id: i32) -> Result<(), String> {
+    let current: Option<(String, Vec<String>)> = conn
+        .query_row(
+            &format!(
+                "SELECT category, payee, notes, amount FROM transactions WHERE id = ?1 AND {}",
+                LEARNABLE
+            ),
+            params![id],
+            |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, Option<String>>(2)?,
+                    row.get::<_, f64>(3)?,
+                ))
+            },
+        )
+        .optional()
+        .map_err(|e| e.to_string())?
+        .map(|(category, payee, notes, amount)| {
+            (category, features(&payee, notes.as_deref(), amount))
+        });
+    let trained: Option<(String, Vec<String>)> = conn
+        .query_row(
+            "SELECT category, features FROM category_training WHERE transaction_id = ?1",
+            params![id],
+            |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?)),
+        )
+        .optional()
+        .map_err(|e| e.to_string())?
+        .map(|(category, features)| {
+            serde_json::from_str(&features).map(|features| (category, features))
+        })
+        .transpose()
+        .map_err(|e| e.to_string())?;
+    if current == trained {
+        return Ok(());
+    }
+    if let Some((category, features)) = trained {
+        forget(conn, id, &category, &features)?;
+    }
+    if let Some((category, features)) = current {
+        learn(conn, id, &category, &features)?;
+    }
+    Ok(())
+}
+
+// Brings the model up to date with the ledger. Only transactions added, recategorized, edited or
+// deleted since the last sync are learned or forgotten. Scans the whole ledger, so it only runs
+// when asked to retrain and once for ledgers from before the model was kept up to date.
+pub fn sync_model(conn: &mut Connection, rebuild: bool) -> Result<ModelSyncReport, String> {
+    let tx = conn.transaction().map_err(|e| e.to_string())?;
+    if rebuild {
+        tx.execute("DELETE FROM category_features", [])
+            .map_err(|e| e.to_string())?;
+        tx.execute("DELETE FROM category_training", [])
+            .map_err(|e| e.to_string())?;
+    }
+
+    let mut current: BTreeMap<i32, (String, Vec<String>)> = BTreeMap::new();
+    {
+        let mut stmt = tx
+            .prepare(&format!(
+                "SELECT id, category, payee, notes, amount FROM transactions WHERE {}",
+                LEARNABLE
+            ))
+            .map_err(|e| e.to_string())?;
+        let rows = stmt
+            .query_map([], |row| {
+                Ok((
+                    row.get::<_, i32>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, String>(2)?,
+                    row.get::<_, Option<String>>(3)?,
+                    row.get::<_, f64>(4)?,
+                ))
+            })
+            .map_err(|e| e.to_string())?;
+        for row in rows {
+            let (id, category, payee, notes, amount) = row.map_err(|e| e.to_string())?;
+            current.insert(id, (category, features(&payee, notes.as_deref(), amount)));
+        }
+    }
+
+    let mut trained: BTreeMap<i32, (String, Vec<String>)> = BTreeMap::new();
+    {
+        let mut stmt = tx
+            .prepare("SELECT transaction_id, category, features FROM category_training")
+            .map_err(|e| e.to_string())?;
+        let rows = stmt
+            .query_map([], |row| {
+                Ok((
+                    row.get::<_, i32>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, String>(2)?,
+                ))
+            })
+            .map_err(|e| e.to_string())?;
+        for row in rows {
+            let (id, category, features) = row.map_err(|e| e.to_string())?;
+            let features = serde_json::from_str(&features).map_err(|e| e.to_string())?;
+            trained.insert(id, (category, features));
+        }
+    }
+
+    let mut report = ModelSyncReport::default();
+    for (id, learned) in &trained {
+        if current.get(id) != Some(learned) {
+            forget(&tx, *id, &learned.0, &learned.1)?;
+            report.forgotten += 1;
+        }
+    }
+    for (id, entry) in &current {
+        if trained.get(id) != Some(entry) {
+            learn(&tx, *id, &entry.0, &entry.1)?;
+            report.learned += 1;
+        }
+    }
+    report.categories = tx
+        .query_row(
+            "SELECT COUNT(DISTINCT category) FROM category_training",
+            [],
+            |row| row.get::<_, i64>(0),
+        )
+        .map_err(|e| e.to_string())? as usize;
+    tx.commit().map_err(|e| e.to_string())?;
+    Ok(report)
+}
+
+// Multinomial naive Bayes with add-one smoothing. Scores are turned into probabilities over all
+// known categories; ties rank alphabetically so results are stable.
+fn rank(conn: &Connection, query: &[String]) -> Result<Vec<CategorySuggestion>, String> {
+    let mut documents: BTreeMap<String, f64> = BTreeMap::new();
+    let mut stmt = conn
+        .prepare("SELECT category, COUNT(*) FROM category_training GROUP BY category")
+        .map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map([], |row| {
+            Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)?))
+        })
+        .map_err(|e| e.to_string())?;
+    for row in rows {
+        let (category, count) = row.map_err(|e| e.to_string())?;
+        documents.insert(category, count as f64);
+    }
+    if documents.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let mut totals: HashMap<String, f64> = HashMap::new();
+    let mut counts: HashMap<(String, String), f64> = HashMap::new();
+    let mut vocabulary = BTreeSet::new();
+    let mut stmt = conn
+        .prepare("SELECT category, feature, count FROM category_features")
+        .map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map([], |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, i64>(2)?,
+            ))
+        })
+        .map_err(|e| e.to_string())?;
+    for row in rows {
+        let (category, feature, count) = row.map_err(|e| e.to_string())?;
+        *totals.entry(category.clone()).or_default() += count as f64;
+        vocabulary.insert(feature.clone());
+        if query.contains(&feature) {
+            counts.insert((category, feature), count as f64);
+        }
+    }
+
+    let all_documents: f64 = documents.values().sum();
+    let vocabulary = vocabulary.len() as f64;
+    let scores: Vec<(String, f64)> = documents
+        .into_iter()
+        .map(|(category, docs)| {
+            let total = totals.get(&category).copied().unwrap_or_default();
+            let likelihood: f64 = query
+                .iter()
+                .map(|feature| {
+                    let count = counts
+                        .get(&(category.clone(), feature.clone()))
+                        .copied()
+                        .unwrap_or_default();
+                    ((count + 1.0) / (total + vocabulary)).ln()
+                })
+                .sum();
+            let score = (docs / all_documents).ln() + likelihood;
+            (category, score)
+        })
+        .collect();
+
+    let best = scores
+        .iter()
+        .map(|(_, score)| *score)
+        .fold(f64::NEG_INFINITY, f64::max);
+    let norm: f64 = scores.iter().map(|(_, s)| (s - best).exp()).sum();
+    let mut suggestions: Vec<CategorySuggestion> = scores
+        .into_iter()
+        .map(|(category, score)| CategorySuggestion {
+            category,
+            confidence: (score - best).exp() / norm,
+        })
+        .collect();
+    suggestions.sort_by(|a, b| {
+        b.confidence
+            .total_cmp(&a.confidence)
+            .then_with(|| a.category.cmp(&b.category))
+    });
+    Ok(suggestions)
+}
+
+// Only reads the model; the write paths keep it current
+pub fn suggest_category_db(
+    db_path: &PathBuf,
+    payee: &str,
+    notes: Option<&str>,
+    amount: f64,
+    limit: Option<usize>,
+) -> Result<Vec<CategorySuggestion>, String> {
+    let conn = Connection::open(db_path).map_err(|e| e.to_string())?;
+    let mut suggestions = rank(&conn, &features(payee, notes, amount))?;
+    suggestions.truncate(limit.unwrap_or(DEFAULT_SUGGESTIONS));
+    Ok(suggestions)
+}
+
+pub fn retrain_category_model_db(
+    db_path: &PathBuf,
+    rebuild: bool,
+) -> Result<ModelSyncReport, String> {
+    let mut conn = Connection::open(db_path).map_err(|e| e.to_string())?;
+    sync_model(&mut conn, rebuild)
+}
@@ -0,0 +1,343 @@
+use crate::import::ImportRow;
+use chrono::NaiveDate;
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+
+// How far apart two postings of the same amount may be and still count as a likely duplicate.
+// Banks often book card payments a day or two after the purchase date.
+pub const DEFAULT_WINDOW_DAYS: i64 = 3;
+
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum MatchKind {
+    // Same account, date, amount and normalized payee
+    Exact,
+    // Same account and amount, a similar payee, within the date window
+    Fuzzy,
+}
+
+// An import row that resembles a transaction already in the ledger. Exact matches of rows without
+// an external id are skipped as duplicates instead; everything listed here is still imported.
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct PossibleDuplicate {
+    pub row: usize,
+    pub transaction_id: i32,
+    pub kind: MatchKind,
+    pub days_apart: i64,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct LedgerEntry {
+    pub id: i32,
+    pub account_id: i32,
+    pub date: String,
+    pub payee: String,
+    pub notes: Option<String>,
+    pub category: Option<String>,
+    pub amount: f64,
+    pub external_id: Option<String>,
+}
+
+// Two transactions of the ledger that look like the same thing booked twice. `keep` is the one a
+// merge holds on to: the one carrying a bank id, otherwise the older one.
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct DuplicatePair {
+    pub kind: MatchKind,
+    pub days_apart: i64,
+    pub keep: LedgerEntry,
+    pub duplicate: LedgerEntry,
+}
+
+// Lowercases and drops punctuation and purely numeric tokens (card numbers, references), so
+// `AMAZON MKTPLACE 1234*XY` and `Amazon Mktplace XY` compare equal
+pub fn normalize_payee(payee: &str) -> String {
+    payee
+        .to_lowercase()
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|token| !token.is_empty() && !token.chars().all(|c| c.is_ascii_digit()))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn cents(amount: f64) -> i64 {
+    (amount * 100.0).round() as i64
+}
+
+// Identity of a transaction that has no external id
+pub fn fingerprint(account_id: i32, date: &str, amount: f64, payee: &str) -> String {
+    format!(
+        "{}|{}|{}|{}",
+        account_id,
+        date,
+        cents(amount),
+        normalize_payee(payee)
+    )
+}
+
+// Normalized payees match when equal, when one contains the other, or when they start with the
+// same meaningful word
+fn payees_match(a: &str, b: &str) -> bool {
+    if a == b {
+        return true;
+    }
+    if a.is_empty() || b.is_empty() {
+        return false;
+    }
+    let first = |p: &str| p.split(' ').next().map(str::to_string);
+    a.contains(b)
+        || b.contains(a)
+        || (first(a) == first(b) && first(a).is_some_and(|w| w.len() >= 3))
+}
+
+fn days_between(a: &str, b: &str) -> Option<i64> {
+    let a = NaiveDate::parse_from_str(a, "%Y-%m-%d").ok()?;
+    let b = NaiveDate::parse_from_str(b, "%Y-%m-%d").ok()?;
+    Some((b - a).num_days())
+}
+
+fn shift(date: &str, days: i64) -> Option<String> {
+    NaiveDate::parse_from_str(date, "%Y-%m-%d")
+        .ok()
+        .and_then(|d| d.checked_add_signed(chrono::Duration::days(days)))
+        .map(|d| d.format("%Y-%m-%d").to_string())
+}
+
+fn entry_from_row(row: &rusqlite::Row<'_>) -> rusqlite::Result<LedgerEntry> {
+    Ok(LedgerEntry {
+        id: row.get(0)?,
+        account_id: row.get(1)?,
+        date: row.get(2)?,
+        payee: row.get(3)?,
+        notes: row.get(4)?,
+        category: row.get(5)?,
+        amount: row.get(6)?,
+        external_id: row.get(7)?,
+    })
+}
+
+const ENTRY_COLUMNS: &str = "id, account_id, date, payee, notes, category, amount, external_id";
+
+// Rows to import, rows skipped as duplicates, and look-alikes to show in the preview
+type Screened = (Vec<ImportRow>, Vec<ImportRow>, Vec<PossibleDuplicate>);
+
+// Screens import rows against the ledger. Rows without an external id that exactly match an
+// existing transaction are returned as duplicates, one ledger transaction per row, so importing an
+// overlapping date range twice is a no-op while two identical purchases in one file still land.
+pub fn screen_rows(
+    conn: &Connection,
+    rows: Vec<ImportRow>,
+    window_days: i64,
+) -> Result<Screened, String> {
+    let mut stmt = conn
+        .prepare(&format!(
+            "SELECT {} FROM transactions WHERE account_id = ?1 AND amount BETWEEN ?2 - 0.005 AND ?2 + 0.005 AND date BETWEEN ?3 AND ?4 ORDER BY id",
+            ENTRY_COLUMNS
+        ))
+        .map_err(|e| e.to_string())?;
+    let mut claimed: HashSet<i32> = HashSet::new();
+    let mut fresh = Vec::with_capacity(rows.len());
+    let mut duplicates = Vec::new();
+    let mut possible = Vec::new();
+
+    for row in rows {
+        let (Some(account_id), Some(from), Some(to)) = (
+            row.account_id,
+            shift(&row.date, -window_days),
+            shift(&row.date, window_days),
+        ) else {
+            fresh.push(row);
+            continue;
+        };
+        let candidates = stmt
+            .query_map(params![account_id, row.amount, from, to], entry_from_row)
+            .map_err(|e| e.to_string())?
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| e.to_string())?;
+
+        let payee = normalize_payee(&row.payee);
+        let identity = fingerprint(account_id, &row.date, row.amount, &row.payee);
+        let mut best: Option<(MatchKind, i64, i32)> = None;
+        for candidate in candidates.iter().filter(|c| !claimed.contains(&c.id)) {
+            // Two different bank ids are two different bank lines, however alike
+            if row.external_id.is_some() && candidate.external_id.is_some() {
+                continue;
+            }
+            let Some(days) = days_between(&candidate.date, &row.date) else {
+                continue;
+            };
+            let candidate_payee = normalize_payee(&candidate.payee);
+            let kind = if fingerprint(
+                account_id,
+                &candidate.date,
+                candidate.amount,
+                &candidate.payee,
+            ) == identity
+            {
+                MatchKind::Exact
+            } else if payees_match(&candidate_payee, &payee) {
+                MatchKind::Fuzzy
+            } else {
+                continue;
+            };
+            let better = match best {
+                None => true,
+                Some((best_kind, best_days, _)) => {
+                    (kind == MatchKind::Exact && best_kind == MatchKind::Fuzzy)
+                        || (kind == best_kind && days.abs() < best_days.abs())
+                }
+            };
+            if better {
+                best = Some((kind, days, candidate.id));
+            }
+        }
+
+        match best {
+            Some((MatchKind::Exact, _, id)) if row.external_id.is_none() => {
+                claimed.insert(id);
+                duplicates.push(row);
+            }
+            Some((kind, days, id)) => {
+                claimed.insert(id);
+                possible.push(PossibleDuplicate {
+                    row: row.row,
+                    transaction_id: id,
+                    kind,
+                    days_apart: days,
+                });
+                fresh.push(row);
+            }
+            None => fresh.push(row),
+        }
+    }
+    Ok((fresh, duplicates, possible))
+}
+
+// Scans the ledger (or one account) for likely duplicates. Every transaction appears in at most
+// one pair; transfer counterparts live in other accounts and are never paired with each other.
+pub fn find_duplicates_db(
+    db_path: &PathBuf,
+    window_days: i64,
+    account_id: Option<i32>,
+) -> Result<Vec<DuplicatePair>, String> {
+    let conn = Connection::open(db_path).map_err(|e| e.to_string())?;
+    let mut stmt = conn
+        .prepare(&format!(
+            "SELECT {} FROM transactions WHERE ?1 IS NULL OR account_id = ?1 ORDER BY account_id, date, id",
+            ENTRY_COLUMNS
+        ))
+        .map_err(|e| e.to_string())?;
+    let entries = stmt
+        .query_map(params![account_id], entry_from_row)
+        .map_err(|e| e.to_string())?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())?;
+
+    let mut groups: HashMap<(i32, i64), Vec<&LedgerEntry>> = HashMap::new();
+    for entry in &entries {
+        groups
+            .entry((entry.account_id, cents(entry.amount)))
+            .or_default()
+            .push(entry);
+    }
+
+    let mut pairs = Vec::new();
+    let mut paired: HashSet<i32> = HashSet::new();
+    for entry in &entries {
+        if paired.contains(&entry.id) {
+            continue;
+        }
+        let payee = normalize_payee(&entry.payee);
+        let identity = fingerprint(entry.account_id, &entry.date, entry.amount, &entry.payee);
+        let group = &groups[&(entry.account_id, cents(entry.amount))];
+        let found = group.iter().find_map(|other| {
+            if other.id == entry.id
+                || paired.contains(&other.id)
+                || (entry.external_id.is_some() && other.external_id.is_some())
+            {
+                return None;
+            }
+            let days = days_between(&entry.date, &other.date)?;
+            // Entries are visited in date order, so only look forward
+            if days < 0 || days > window_days || (days == 0 && other.id < entry.id) {
+                return None;
+            }
+            let other_payee = normalize_payee(&other.payee);
+            let kind = if fingerprint(other.account_id, &other.date, other.amount, &other.payee)
+                == identity
+            {
+                MatchKind::Exact
+            } else if payees_match(&payee, &other_payee) {
+                MatchKind::Fuzzy
+            } else {
+                return None;
+            };
+            Some((kind, days, *other))
+        });
+        if let Some((kind, days, other)) = found {
+            paired.insert(entry.id);
+            paired.insert(other.id);
+            let (keep, duplicate) = if other.external_id.is_some() && entry.external_id.is_none() {
+                (other, entry)
+            } else {
+                (entry, other)
+            };
+            pairs.push(DuplicatePair {
+                kind,
+                days_apart: days,
+                keep: keep.clone(),
+                duplicate: duplicate.clone(),
+            });
+        }
+    }
+    Ok(pairs)
+}
+
+// Folds `duplicate_id` into `keep_id`: details only the duplicate has (notes, category, bank id)
+// move over, then the duplicate and its transfer counterpart, if any, are removed.
+pub fn merge_duplicates_db(
+    db_path: &PathBuf,
+    keep_id: i32,
+    duplicate_id: i32,
+) -> Result<LedgerEntry, String> {
+    if keep_id == duplicate_id {
+        return Err("Cannot merge a transaction into itself".to_string());
+    }
+    let mut conn = Connection::open(db_path).map_err(|e| e.to_string())?;
+    let tx = conn.transaction().map_err(|e| e.to_string())?;
+
+    let load = |id: i32| -> Result<(LedgerEntry, Option<i32>), String> {
+        tx.query_row(
+            &format!(
+                "SELECT {}, linked_tx_id FROM transactions WHERE id = ?1",
+                ENTRY_COLUMNS
+            ),
+            params![id],
+            |row| Ok((entry_from_row(row)?, row.get(8)?)),
+        )
+        .optional()
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| format!("Transaction {} not found", id))
+    };
+    let (keep, _) = load(keep_id)?;
+    let (duplicate, linked) = load(duplicate_id)?;
+    if keep.account_id != duplicate.account_id {
+        return Err("Only transactions of the same account can be merged".to_string());
+    }
+
+    tx.execute(
+        "UPDATE transactions SET notes = COALESCE(notes, ?1), category = COALESCE(category, ?2), external_id = COALESCE(external_id, ?3) WHERE id = ?4",
+        params![duplicate.notes, duplicate.category, duplicate.external_id, keep_id],
+    )
+    .map_err(|e| e.to_string())?;
+    crate::categorize::refresh(&tx, keep_id)?;
+
+    for id in std::iter::once(duplicate_id).chain(linked.filter(|l| *l != keep_id)) {
+        crate::remove_transaction(&tx, id)?;
+    }
+
+    let (merged, _) = load(keep_id)?;
+    tx.commit().map_err(|e| e.to_string())?;
+    Ok(merged)
+}
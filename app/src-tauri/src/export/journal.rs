@@ -0,0 +1,547 @@
+use super::{is_trade, load_transactions, ExportTransaction};
+use rusqlite::Connection;
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+
+const DEFAULT_CURRENCY: &str = "USD";
+const OPENING_BALANCES: &str = "Equity:Opening-Balances";
+const TRANSFERS: &str = "Equity:Transfers";
+const FEES: &str = "Expenses:Investment-Fees";
+const GAINS: &str = "Income:Capital-Gains";
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum JournalFormat {
+    Beancount,
+    Hledger,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Price {
+    Unit(f64, String),
+    Total(f64, String),
+}
+
+#[derive(Debug, Clone)]
+struct Posting {
+    account: String,
+    // None lets the tool compute the balancing amount
+    units: Option<(f64, String)>,
+    // Lot cost per unit and acquisition date
+    cost: Option<(f64, String, Option<String>)>,
+    price: Option<Price>,
+}
+
+impl Posting {
+    fn new(account: &str, amount: f64, commodity: &str) -> Self {
+        Posting {
+            account: account.to_string(),
+            units: Some((amount, commodity.to_string())),
+            cost: None,
+            price: None,
+        }
+    }
+}
+
+struct Entry {
+    date: String,
+    payee: String,
+    narration: String,
+    postings: Vec<Posting>,
+}
+
+#[derive(Debug, Clone)]
+struct Lot {
+    shares: f64,
+    cost: f64,
+    currency: String,
+    date: String,
+}
+
+// Formats a number with at least two decimals and without float noise
+fn number(value: f64) -> String {
+    let rounded = (value * 1e8).round() / 1e8;
+    let mut text = format!("{:.8}", if rounded == 0.0 { 0.0 } else { rounded });
+    while text.ends_with('0') && text.len() - text.find('.').unwrap_or(0) > 3 {
+        text.pop();
+    }
+    text
+}
+
+// Account components must start with an uppercase letter or digit and contain only letters,
+// digits and dashes in Beancount; hledger accepts the same names.
+fn component(name: &str) -> String {
+    let words: Vec<String> = name
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .map(|w| {
+            let mut chars = w.chars();
+            chars
+                .next()
+                .map(|c| c.to_ascii_uppercase().to_string() + chars.as_str())
+                .unwrap_or_default()
+        })
+        .collect();
+    words.join("-")
+}
+
+fn category_account(category: Option<&str>, income: bool) -> String {
+    let root = if income { "Income" } else { "Expenses" };
+    let mut parts: Vec<String> = category
+        .unwrap_or("")
+        .split(':')
+        .map(component)
+        .filter(|p| !p.is_empty())
+        .collect();
+    // `Income` as a category would otherwise become `Income:Income`
+    if parts.first().is_some_and(|p| p.eq_ignore_ascii_case(root)) {
+        parts.remove(0);
+    }
+    if parts.is_empty() {
+        parts.push("Uncategorized".to_string());
+    }
+    format!("{}:{}", root, parts.join(":"))
+}
+
+// Commodities are uppercase, start with a letter and end with a letter or digit
+fn commodity(symbol: &str) -> String {
+    let mut out: String = symbol
+        .trim()
+        .to_ascii_uppercase()
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-' | '\'') {
+                c
+            } else {
+                '-'
+            }
+        })
+        .collect();
+    while out.ends_with(|c: char| !c.is_ascii_alphanumeric()) {
+        out.pop();
+    }
+    if !out.starts_with(|c: char| c.is_ascii_alphabetic()) {
+        out.insert(0, 'X');
+    }
+    out.truncate(24);
+    out
+}
+
+fn asset_names(accounts: &[crate::Account]) -> HashMap<i32, String> {
+    let mut used = HashSet::new();
+    let mut names = HashMap::new();
+    for account in accounts {
+        // Keep the app's own `Parent:Child` naming as account hierarchy
+        let base = account
+            .name
+            .split(':')
+            .map(component)
+            .filter(|p| !p.is_empty())
+            .collect::<Vec<_>>()
+            .join(":");
+        let mut name = if base.is_empty() {
+            format!("Assets:Account-{}", account.id)
+        } else {
+            format!("Assets:{}", base)
+        };
+        if !used.insert(name.clone()) {
+            name = format!("{}-{}", name, account.id);
+            used.insert(name.clone());
+        }
+        names.insert(account.id, name);
+    }
+    names
+}
+
+struct JournalBuilder<'a> {
+    assets: HashMap<i32, String>,
+    currencies: HashMap<i32, String>,
+    by_id: HashMap<i32, &'a ExportTransaction>,
+    lots: HashMap<(i32, String), VecDeque<Lot>>,
+    entries: Vec<Entry>,
+}
+
+impl JournalBuilder<'_> {
+    fn currency_of(&self, tx: &crate::Transaction) -> String {
+        commodity(
+            tx.currency
+                .as_deref()
+                .filter(|c| !c.trim().is_empty())
+                .or(self.currencies.get(&tx.account_id).map(String::as_str))
+                .unwrap_or(DEFAULT_CURRENCY),
+        )
+    }
+
+    fn asset(&self, account_id: i32) -> String {
+        self.assets
+            .get(&account_id)
+            .cloned()
+            .unwrap_or_else(|| format!("Assets:Account-{}", account_id))
+    }
+
+    fn push(&mut self, tx: &crate::Transaction, postings: Vec<Posting>) {
+        self.entries.push(Entry {
+            date: tx.date.clone(),
+            payee: tx.payee.clone(),
+            narration: tx.notes.clone().unwrap_or_default(),
+            postings,
+        });
+    }
+
+    fn cash_entry(&mut self, item: &ExportTransaction) {
+        let tx = &item.tx;
+        let currency = self.currency_of(tx);
+        let asset = self.asset(tx.account_id);
+        let mut postings = vec![Posting::new(&asset, tx.amount, &currency)];
+
+        let counterpart = item
+            .linked_tx_id
+            .and_then(|id| self.by_id.get(&id).copied());
+        if let Some(other) = counterpart {
+            // Both rows of a linked transfer become one entry, written at the lower id
+            if other.tx.id < tx.id {
+                return;
+            }
+            let other_currency = self.currency_of(&other.tx);
+            let other_asset = self.asset(other.tx.account_id);
+            if other_currency == currency {
+                postings.push(Posting::new(&other_asset, other.tx.amount, &currency));
+                let residual = tx.amount + other.tx.amount;
+                if residual.abs() >= 0.005 {
+                    postings.push(Posting::new(TRANSFERS, -residual, &currency));
+                }
+            } else {
+                // Cross-currency transfer: the total price converts one leg into the other
+                postings[0].price =
+                    Some(Price::Total(other.tx.amount.abs(), other_currency.clone()));
+                postings.push(Posting::new(&other_asset, other.tx.amount, &other_currency));
+            }
+        } else {
+            let account = if item.transfer_account.is_some() {
+                // Unlinked transfer rows on both sides net out in this equity account
+                TRANSFERS.to_string()
+            } else if tx.payee == "Opening Balance" {
+                OPENING_BALANCES.to_string()
+            } else {
+                category_account(tx.category.as_deref(), tx.amount > 0.0)
+            };
+            postings.push(Posting::new(&account, -tx.amount, &currency));
+        }
+        self.push(tx, postings);
+    }
+
+    // Trades book lots at cost on buys and relieve them first-in first-out on sells, so the
+    // Beancount output carries explicit lot costs and realized gains
+    fn trade_entry(&mut self, item: &ExportTransaction, format: JournalFormat) {
+        let tx = &item.tx;
+        let currency = self.currency_of(tx);
+        let asset = self.asset(tx.account_id);
+        let symbol = commodity(tx.ticker.as_deref().unwrap_or_default());
+        let shares = tx.shares.unwrap_or(0.0);
+        let price = tx
+            .price_per_share
+            .filter(|p| *p > 0.0)
+            .unwrap_or_else(|| (tx.amount / shares).abs());
+        // Derived rather than read from `fee` so the entry balances even for inconsistent rows
+        let fee = -(tx.amount + shares * price);
+
+        let mut postings = Vec::new();
+        let lots = self
+            .lots
+            .entry((tx.account_id, symbol.clone()))
+            .or_default();
+        if shares > 0.0 {
+            lots.push_back(Lot {
+                shares,
+                cost: price,
+                currency: currency.clone(),
+                date: tx.date.clone(),
+            });
+            let mut posting = Posting::new(&asset, shares, &symbol);
+            match format {
+                JournalFormat::Beancount => posting.cost = Some((price, currency.clone(), None)),
+                JournalFormat::Hledger => {
+                    posting.price = Some(Price::Unit(price, currency.clone()))
+                }
+            }
+            postings.push(posting);
+        } else {
+            let mut remaining = -shares;
+            let mut relieved = Vec::new();
+            while remaining > 1e-9 {
+                let Some(lot) = lots.front_mut() else {
+                    // Selling more than was bought: book the rest at the sale price
+                    relieved.push((remaining, price, currency.clone(), tx.date.clone()));
+                    break;
+                };
+                let take = remaining.min(lot.shares);
+                relieved.push((take, lot.cost, lot.currency.clone(), lot.date.clone()));
+                lot.shares -= take;
+                remaining -= take;
+                if lot.shares <= 1e-9 {
+                    lots.pop_front();
+                }
+            }
+            match format {
+                JournalFormat::Beancount => {
+                    for (quantity, cost, cost_currency, date) in relieved {
+                        let mut posting = Posting::new(&asset, -quantity, &symbol);
+                        posting.cost = Some((cost, cost_currency, Some(date)));
+                        posting.price = Some(Price::Unit(price, currency.clone()));
+                        postings.push(posting);
+                    }
+                }
+                JournalFormat::Hledger => {
+                    let mut posting = Posting::new(&asset, shares, &symbol);
+                    posting.price = Some(Price::Unit(price, currency.clone()));
+                    postings.push(posting);
+                }
+            }
+        }
+
+        if fee.abs() >= 0.005 {
+            postings.push(Posting::new(FEES, fee, &currency));
+        }
+        postings.push(Posting::new(&asset, tx.amount, &currency));
+        if shares < 0.0 && format == JournalFormat::Beancount {
+            postings.push(Posting {
+                account: GAINS.to_string(),
+                units: None,
+                cost: None,
+                price: None,
+            });
+        }
+        self.push(tx, postings);
+    }
+}
+
+fn quote(text: &str) -> String {
+    format!(
+        "\"{}\"",
+        text.replace(['\r', '\n'], " ")
+            .replace('\\', "\\\\")
+            .replace('"', "\\\"")
+    )
+}
+
+// hledger wants symbols with anything but letters in double quotes
+fn hledger_commodity(symbol: &str) -> String {
+    if symbol.chars().all(|c| c.is_ascii_alphabetic()) {
+        symbol.to_string()
+    } else {
+        format!("\"{}\"", symbol)
+    }
+}
+
+fn render_amount(format: JournalFormat, value: f64, symbol: &str) -> String {
+    match format {
+        JournalFormat::Beancount => format!("{} {}", number(value), symbol),
+        JournalFormat::Hledger => format!("{} {}", number(value), hledger_commodity(symbol)),
+    }
+}
+
+fn render_posting(format: JournalFormat, posting: &Posting) -> String {
+    let indent = match format {
+        JournalFormat::Beancount => "  ",
+        JournalFormat::Hledger => "    ",
+    };
+    let mut line = format!("{}{}", indent, posting.account);
+    if let Some((value, symbol)) = &posting.units {
+        let _ = write!(line, "  {}", render_amount(format, *value, symbol));
+    }
+    if let Some((cost, currency, date)) = &posting.cost {
+        match date {
+            Some(date) => {
+                let _ = write!(line, " {{{} {}, {}}}", number(*cost), currency, date);
+            }
+            None => {
+                let _ = write!(line, " {{{} {}}}", number(*cost), currency);
+            }
+        }
+    }
+    match &posting.price {
+        Some(Price::Unit(value, symbol)) => {
+            let _ = write!(line, " @ {}", render_amount(format, *value, symbol));
+        }
+        Some(Price::Total(value, symbol)) => {
+            let _ = write!(line, " @@ {}", render_amount(format, *value, symbol));
+        }
+        None => {}
+    }
+    line
+}
+
+fn load_prices(conn: &Connection) -> Result<Vec<(String, String, f64)>, String> {
+    let mut stmt = conn
+        .prepare("SELECT ticker, date, price FROM daily_stock_prices ORDER BY date ASC, ticker ASC")
+        .map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))
+        .map_err(|e| e.to_string())?;
+    let mut prices = Vec::new();
+    for r in rows {
+        prices.push(r.map_err(|e| e.to_string())?);
+    }
+    Ok(prices)
+}
+
+// Renders the whole ledger as a Beancount or hledger journal. Returns the text and the number of
+// transaction entries written.
+pub fn write_journal(
+    accounts: &[crate::Account],
+    transactions: &[ExportTransaction],
+    prices: &[(String, String, f64)],
+    format: JournalFormat,
+) -> (String, usize) {
+    let mut builder = JournalBuilder {
+        assets: asset_names(accounts),
+        currencies: accounts
+            .iter()
+            .filter_map(|a| a.currency.clone().map(|c| (a.id, c)))
+            .collect(),
+        by_id: transactions.iter().map(|t| (t.tx.id, t)).collect(),
+        lots: HashMap::new(),
+        entries: Vec::new(),
+    };
+    // Transactions come ordered by date, which keeps lot relief first-in first-out
+    for item in transactions {
+        if is_trade(&item.tx) {
+            builder.trade_entry(item, format);
+        } else {
+            builder.cash_entry(item);
+        }
+    }
+
+    // Securities are priced in the currency they were traded in
+    let mut price_currency: HashMap<String, String> = HashMap::new();
+    for item in transactions.iter().filter(|t| is_trade(&t.tx)) {
+        let symbol = commodity(item.tx.ticker.as_deref().unwrap_or_default());
+        let currency = builder.currency_of(&item.tx);
+        price_currency.entry(symbol).or_insert(currency);
+    }
+
+    // Opening date and, for asset accounts, the commodities held (cash currencies first)
+    let mut opened: BTreeMap<String, (String, Vec<String>)> = BTreeMap::new();
+    for entry in &builder.entries {
+        for posting in &entry.postings {
+            let (date, commodities) = opened
+                .entry(posting.account.clone())
+                .or_insert_with(|| (entry.date.clone(), Vec::new()));
+            if entry.date < *date {
+                *date = entry.date.clone();
+            }
+            if let Some((_, symbol)) = &posting.units {
+                if posting.account.starts_with("Assets:") && !commodities.contains(symbol) {
+                    commodities.push(symbol.clone());
+                }
+            }
+        }
+    }
+    for (_, commodities) in opened.values_mut() {
+        commodities.sort_by_key(|c| price_currency.contains_key(c));
+    }
+
+    let mut out = String::new();
+    match format {
+        JournalFormat::Beancount => {
+            out.push_str("option \"title\" \"HoneyBear Folio\"\n");
+            out.push_str("option \"booking_method\" \"FIFO\"\n\n");
+            for (account, (date, commodities)) in &opened {
+                if commodities.is_empty() {
+                    let _ = writeln!(out, "{} open {}", date, account);
+                } else {
+                    let _ = writeln!(out, "{} open {} {}", date, account, commodities.join(","));
+                }
+            }
+        }
+        JournalFormat::Hledger => {
+            for account in opened.keys() {
+                let _ = writeln!(out, "account {}", account);
+            }
+        }
+    }
+    out.push('\n');
+
+    for entry in &builder.entries {
+        match format {
+            JournalFormat::Beancount => {
+                let _ = writeln!(
+                    out,
+                    "{} * {} {}",
+                    entry.date,
+                    quote(&entry.payee),
+                    quote(&entry.narration)
+                );
+            }
+            JournalFormat::Hledger => {
+                // `;` would start a comment in the description
+                let clean = |s: &str| s.replace(['\r', '\n'], " ").replace(';', ",");
+                if entry.narration.is_empty() {
+                    let _ = writeln!(out, "{} {}", entry.date, clean(&entry.payee));
+                } else {
+                    let _ = writeln!(
+                        out,
+                        "{} {} | {}",
+                        entry.date,
+                        clean(&entry.payee),
+                        clean(&entry.narration)
+                    );
+                }
+            }
+        }
+        for posting in &entry.postings {
+            out.push_str(&render_posting(format, posting));
+            out.push('\n');
+        }
+        out.push('\n');
+    }
+
+    for (ticker, date, price) in prices {
+        let symbol = commodity(ticker);
+        let currency = price_currency
+            .get(&symbol)
+            .map(String::as_str)
+            .unwrap_or(DEFAULT_CURRENCY);
+        match format {
+            JournalFormat::Beancount => {
+                let _ = writeln!(
+                    out,
+                    "{} price {} {} {}",
+                    date,
+                    symbol,
+                    number(*price),
+                    currency
+                );
+            }
+            JournalFormat::Hledger => {
+                let _ = writeln!(
+                    out,
+                    "P {} {} {}",
+                    date,
+                    hledger_commodity(&symbol),
+                    render_amount(format, *price, currency)
+                );
+            }
+        }
+    }
+
+    (out, builder.entries.len())
+}
+
+pub fn export_journal_db(
+    db_path: &PathBuf,
+    file_path: &Path,
+    format: JournalFormat,
+) -> Result<usize, String> {
+    let mut accounts = crate::get_accounts_db(db_path)?;
+    accounts.sort_by_key(|a| a.id);
+    let conn = Connection::open(db_path).map_err(|e| e.to_string())?;
+    let transactions = load_transactions(&conn, None)?;
+    let prices = load_prices(&conn)?;
+
+    let (text, count) = write_journal(&accounts, &transactions, &prices, format);
+    std::fs::write(file_path, text).map_err(|e| e.to_string())?;
+    Ok(count)
+}
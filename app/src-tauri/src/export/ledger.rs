@@ -0,0 +1,627 @@
+use rusqlite::types::Value;
+use rusqlite::{params_from_iter, Connection};
+use rust_xlsxwriter::{ExcelDateTime, Format, Workbook, Worksheet};
+use serde::Deserialize;
+use serde_json::json;
+use std::borrow::Cow;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum LedgerFormat {
+    Csv,
+    Xlsx,
+    Json,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum LedgerColumn {
+    Date,
+    Account,
+    Payee,
+    Category,
+    Amount,
+    Notes,
+    Ticker,
+    Shares,
+    Price,
+    Fee,
+    Currency,
+}
+
+const ALL_COLUMNS: [LedgerColumn; 11] = [
+    LedgerColumn::Date,
+    LedgerColumn::Account,
+    LedgerColumn::Payee,
+    LedgerColumn::Category,
+    LedgerColumn::Amount,
+    LedgerColumn::Notes,
+    LedgerColumn::Ticker,
+    LedgerColumn::Shares,
+    LedgerColumn::Price,
+    LedgerColumn::Fee,
+    LedgerColumn::Currency,
+];
+
+impl LedgerColumn {
+    fn header(self) -> &'static str {
+        match self {
+            LedgerColumn::Date => "Date",
+            LedgerColumn::Account => "Account",
+            LedgerColumn::Payee => "Payee",
+            LedgerColumn::Category => "Category",
+            LedgerColumn::Amount => "Amount",
+            LedgerColumn::Notes => "Notes",
+            LedgerColumn::Ticker => "Ticker",
+            LedgerColumn::Shares => "Shares",
+            LedgerColumn::Price => "Price",
+            LedgerColumn::Fee => "Fee",
+            LedgerColumn::Currency => "Currency",
+        }
+    }
+
+    fn key(self) -> &'static str {
+        match self {
+            LedgerColumn::Date => "date",
+            LedgerColumn::Account => "account",
+            LedgerColumn::Payee => "payee",
+            LedgerColumn::Category => "category",
+            LedgerColumn::Amount => "amount",
+            LedgerColumn::Notes => "notes",
+            LedgerColumn::Ticker => "ticker",
+            LedgerColumn::Shares => "shares",
+            LedgerColumn::Price => "price_per_share",
+            LedgerColumn::Fee => "fee",
+            LedgerColumn::Currency => "currency",
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct LedgerExportOptions {
+    pub format: LedgerFormat,
+    // CSV only; default to "," and "."
+    pub delimiter: Option<String>,
+    pub decimal_separator: Option<String>,
+    pub account_ids: Option<Vec<i32>>,
+    pub date_from: Option<String>,
+    pub date_to: Option<String>,
+    // Transaction columns in output order; all of them when omitted
+    pub columns: Option<Vec<LedgerColumn>>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Cell {
+    Text(String),
+    Number(f64),
+    Date(String),
+    Empty,
+}
+
+impl Cell {
+    fn text(value: Option<String>) -> Cell {
+        match value {
+            Some(s) if !s.is_empty() => Cell::Text(s),
+            _ => Cell::Empty,
+        }
+    }
+
+    fn number(value: Option<f64>) -> Cell {
+        value.map(Cell::Number).unwrap_or(Cell::Empty)
+    }
+
+    fn to_json(&self) -> serde_json::Value {
+        match self {
+            Cell::Text(s) | Cell::Date(s) => json!(s),
+            Cell::Number(n) => json!(n),
+            Cell::Empty => serde_json::Value::Null,
+        }
+    }
+}
+
+// Spreadsheet apps evaluate text starting with these as a formula
+pub fn sanitize_cell(text: &str) -> Cow<'_, str> {
+    if text.starts_with(['=', '+', '-', '@']) {
+        Cow::Owned(format!("'{}", text))
+    } else {
+        Cow::Borrowed(text)
+    }
+}
+
+// SQL conditions shared by every transaction-based query, plus their parameters
+fn transaction_filter(options: &LedgerExportOptions) -> (String, Vec<Value>) {
+    let mut sql = String::new();
+    let mut values = Vec::new();
+    if let Some(ids) = options.account_ids.as_deref().filter(|ids| !ids.is_empty()) {
+        sql.push_str(&format!(
+            " AND t.account_id IN ({})",
+            vec!["?"; ids.len()].join(", ")
+        ));
+        values.extend(ids.iter().map(|id| Value::Integer(*id as i64)));
+    }
+    if let Some(from) = &options.date_from {
+        sql.push_str(" AND t.date >= ?");
+        values.push(Value::Text(from.clone()));
+    }
+    if let Some(to) = &options.date_to {
+        sql.push_str(" AND t.date <= ?");
+        values.push(Value::Text(to.clone()));
+    }
+    (sql, values)
+}
+
+// Streams the filtered transactions, one row of cells per call
+fn for_each_transaction(
+    conn: &Connection,
+    options: &LedgerExportOptions,
+    columns: &[LedgerColumn],
+    mut write: impl FnMut(Vec<Cell>) -> Result<(), String>,
+) -> Result<usize, String> {
+    let (filter, values) = transaction_filter(options);
+    let sql = format!(
+        "SELECT t.date, a.name, t.payee, t.category, t.amount, t.notes, t.ticker, t.shares, t.price_per_share, t.fee, COALESCE(t.currency, a.currency)
+         FROM transactions t
+         JOIN accounts a ON a.id = t.account_id
+         WHERE 1 = 1{}
+         ORDER BY t.date ASC, t.id ASC",
+        filter
+    );
+    let mut stmt = conn.prepare(&sql).map_err(|e| e.to_string())?;
+    let mut rows = stmt
+        .query(params_from_iter(values))
+        .map_err(|e| e.to_string())?;
+
+    let mut count = 0;
+    while let Some(row) = rows.next().map_err(|e| e.to_string())? {
+        let mut cells = Vec::with_capacity(columns.len());
+        for column in columns {
+            let cell = match column {
+                LedgerColumn::Date => Cell::Date(row.get(0).map_err(|e| e.to_string())?),
+                LedgerColumn::Account => Cell::text(row.get(1).map_err(|e| e.to_string())?),
+                LedgerColumn::Payee => Cell::text(row.get(2).map_err(|e| e.to_string())?),
+                LedgerColumn::Category => Cell::text(row.get(3).map_err(|e| e.to_string())?),
+                LedgerColumn::Amount => Cell::Number(row.get(4).map_err(|e| e.to_string())?),
+                LedgerColumn::Notes => Cell::text(row.get(5).map_err(|e| e.to_string())?),
+                LedgerColumn::Ticker => Cell::text(row.get(6).map_err(|e| e.to_string())?),
+                LedgerColumn::Shares => Cell::number(row.get(7).map_err(|e| e.to_string())?),
+                LedgerColumn::Price => Cell::number(row.get(8).map_err(|e| e.to_string())?),
+                LedgerColumn::Fee => Cell::number(row.get(9).map_err(|e| e.to_string())?),
+                LedgerColumn::Currency => Cell::text(row.get(10).map_err(|e| e.to_string())?),
+            };
+            cells.push(cell);
+        }
+        write(cells)?;
+        count += 1;
+    }
+    Ok(count)
+}
+
+// Runs a query whose columns map to the given cell kinds ('t'ext, 'n'umber, 'd'ate)
+fn query_cells(
+    conn: &Connection,
+    sql: &str,
+    values: Vec<Value>,
+    kinds: &str,
+) -> Result<Vec<Vec<Cell>>, String> {
+    let mut stmt = conn.prepare(sql).map_err(|e| e.to_string())?;
+    let mut rows = stmt
+        .query(params_from_iter(values))
+        .map_err(|e| e.to_string())?;
+    let mut out = Vec::new();
+    while let Some(row) = rows.next().map_err(|e| e.to_string())? {
+        let mut cells = Vec::with_capacity(kinds.len());
+        for (i, kind) in kinds.chars().enumerate() {
+            let cell = match kind {
+                'n' => Cell::number(row.get(i).map_err(|e| e.to_string())?),
+                'd' => row
+                    .get::<_, Option<String>>(i)
+                    .map_err(|e| e.to_string())?
+                    .map(Cell::Date)
+                    .unwrap_or(Cell::Empty),
+                _ => Cell::text(row.get(i).map_err(|e| e.to_string())?),
+            };
+            cells.push(cell);
+        }
+        out.push(cells);
+    }
+    Ok(out)
+}
+
+fn account_filter(options: &LedgerExportOptions, column: &str) -> (String, Vec<Value>) {
+    match options.account_ids.as_deref().filter(|ids| !ids.is_empty()) {
+        Some(ids) => (
+            format!(" AND {} IN ({})", column, vec!["?"; ids.len()].join(", ")),
+            ids.iter().map(|id| Value::Integer(*id as i64)).collect(),
+        ),
+        None => (String::new(), Vec::new()),
+    }
+}
+
+fn load_accounts(
+    conn: &Connection,
+    options: &LedgerExportOptions,
+) -> Result<Vec<Vec<Cell>>, String> {
+    let (filter, values) = account_filter(options, "id");
+    query_cells(
+        conn,
+        &format!(
+            "SELECT name, currency, balance FROM accounts WHERE 1 = 1{} ORDER BY id",
+            filter
+        ),
+        values,
+        "ttn",
+    )
+}
+
+// Net share position per account and ticker as of `date_to`, valued at the latest known price
+fn load_holdings(
+    conn: &Connection,
+    options: &LedgerExportOptions,
+) -> Result<Vec<Vec<Cell>>, String> {
+    let (filter, ids) = account_filter(options, "t.account_id");
+    let as_of = Value::Text(
+        options
+            .date_to
+            .clone()
+            .unwrap_or_else(|| "9999-12-31".to_string()),
+    );
+    let mut values = vec![as_of.clone(), as_of.clone()];
+    values.extend(ids);
+    values.push(as_of);
+    let sql = format!(
+        "SELECT name, ticker, shares, price, price_date, shares * price FROM (
+            SELECT a.name AS name, t.ticker AS ticker, SUM(t.shares) AS shares,
+                COALESCE(
+                    (SELECT d.price FROM daily_stock_prices d WHERE d.ticker = t.ticker AND d.date <= ? ORDER BY d.date DESC LIMIT 1),
+                    (SELECT s.price FROM stock_prices s WHERE s.ticker = t.ticker)
+                ) AS price,
+                (SELECT MAX(d.date) FROM daily_stock_prices d WHERE d.ticker = t.ticker AND d.date <= ?) AS price_date
+            FROM transactions t
+            JOIN accounts a ON a.id = t.account_id
+            WHERE t.ticker IS NOT NULL AND t.ticker != '' AND t.shares IS NOT NULL{}
+                AND t.date <= ?
+            GROUP BY t.account_id, t.ticker
+            HAVING ABS(SUM(t.shares)) > 1e-9
+        )
+        ORDER BY name, ticker",
+        filter
+    );
+    query_cells(conn, &sql, values, "ttnndn")
+}
+
+// One row per rule, with a line per condition and per action
+fn load_rules(conn: &Connection) -> Result<Vec<Vec<Cell>>, String> {
+    let mut out = Vec::new();
+    for rule in crate::rules::load_rules(conn)? {
+        // Rules without conditions match their legacy field exactly
+        let conditions: Vec<String> = match rule.conditions.is_empty() {
+            true => vec![format!(
+                "{} equals {}",
+                rule.match_field, rule.match_pattern
+            )],
+            false => rule
+                .conditions
+                .iter()
+                .map(|c| {
+                    let mut line = format!("{} {} {}", c.field, c.operator.as_str(), c.value);
+                    if let Some(to) = &c.value_to {
+                        line.push_str(&format!(" to {}", to));
+                    }
+                    if c.case_sensitive {
+                        line.push_str(" (case-sensitive)");
+                    }
+                    line
+                })
+                .collect(),
+        };
+        let actions = rule
+            .actions
+            .iter()
+            .map(|a| {
+                a.summary()
+                    .map(|(field, value)| format!("{}: {}", field, value))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        out.push(vec![
+            Cell::Number(rule.priority as f64),
+            Cell::text(Some(rule.match_join.as_str().to_string())),
+            Cell::text(Some(conditions.join("\n"))),
+            Cell::text(Some(actions.join("\n"))),
+        ]);
+    }
+    Ok(out)
+}
+
+fn load_exchange_rates(conn: &Connection) -> Result<Vec<Vec<Cell>>, String> {
+    query_cells(
+        conn,
+        "SELECT currency, rate FROM custom_exchange_rates ORDER BY currency",
+        Vec::new(),
+        "tn",
+    )
+}
+
+fn load_daily_prices(
+    conn: &Connection,
+    options: &LedgerExportOptions,
+) -> Result<Vec<Vec<Cell>>, String> {
+    let mut sql = "SELECT ticker, date, price FROM daily_stock_prices WHERE 1 = 1".to_string();
+    let mut values = Vec::new();
+    if let Some(from) = &options.date_from {
+        sql.push_str(" AND date >= ?");
+        values.push(Value::Text(from.clone()));
+    }
+    if let Some(to) = &options.date_to {
+        sql.push_str(" AND date <= ?");
+        values.push(Value::Text(to.clone()));
+    }
+    sql.push_str(" ORDER BY ticker, date");
+    query_cells(conn, &sql, values, "tdn")
+}
+
+fn format_number(value: f64, decimal: char) -> String {
+    let text = value.to_string();
+    if decimal == '.' {
+        text
+    } else {
+        text.replace('.', &decimal.to_string())
+    }
+}
+
+fn single_char(value: Option<&str>, default: char, what: &str) -> Result<char, String> {
+    match value {
+        None => Ok(default),
+        Some(s) => {
+            let mut chars = s.chars();
+            match (chars.next(), chars.next()) {
+                (Some(c), None) if c.is_ascii() => Ok(c),
+                _ => Err(format!("{} must be a single ASCII character", what)),
+            }
+        }
+    }
+}
+
+fn write_csv(
+    conn: &Connection,
+    file_path: &Path,
+    options: &LedgerExportOptions,
+    columns: &[LedgerColumn],
+) -> Result<usize, String> {
+    let delimiter = single_char(options.delimiter.as_deref(), ',', "Delimiter")?;
+    let decimal = single_char(
+        options.decimal_separator.as_deref(),
+        '.',
+        "Decimal separator",
+    )?;
+    if decimal != '.' && decimal != ',' {
+        return Err("Decimal separator must be '.' or ','".to_string());
+    }
+    if delimiter == decimal {
+        return Err("Delimiter and decimal separator must differ".to_string());
+    }
+
+    let mut writer = csv::WriterBuilder::new()
+        .delimiter(delimiter as u8)
+        .from_path(file_path)
+        .map_err(|e| e.to_string())?;
+    writer
+        .write_record(columns.iter().map(|c| c.header()))
+        .map_err(|e| e.to_string())?;
+
+    let count = for_each_transaction(conn, options, columns, |cells| {
+        let record: Vec<String> = cells
+            .iter()
+            .map(|cell| match cell {
+                Cell::Text(s) => sanitize_cell(s).into_owned(),
+                Cell::Number(n) => format_number(*n, decimal),
+                Cell::Date(s) => s.clone(),
+                Cell::Empty => String::new(),
+            })
+            .collect();
+        writer.write_record(&record).map_err(|e| e.to_string())
+    })?;
+    writer.flush().map_err(|e| e.to_string())?;
+    Ok(count)
+}
+
+fn write_sheet_row(
+    sheet: &mut Worksheet,
+    row: u32,
+    cells: &[Cell],
+    date_format: &Format,
+) -> Result<(), String> {
+    for (col, cell) in cells.iter().enumerate() {
+        let col = col as u16;
+        match cell {
+            Cell::Text(s) => {
+                sheet
+                    .write_string(row, col, sanitize_cell(s))
+                    .map_err(|e| e.to_string())?;
+            }
+            Cell::Number(n) => {
+                sheet
+                    .write_number(row, col, *n)
+                    .map_err(|e| e.to_string())?;
+            }
+            Cell::Date(s) => match ExcelDateTime::parse_from_str(s) {
+                Ok(date) => {
+                    sheet
+                        .write_datetime_with_format(row, col, &date, date_format)
+                        .map_err(|e| e.to_string())?;
+                }
+                Err(_) => {
+                    sheet
+                        .write_string(row, col, sanitize_cell(s))
+                        .map_err(|e| e.to_string())?;
+                }
+            },
+            Cell::Empty => {}
+        }
+    }
+    Ok(())
+}
+
+fn add_sheet(
+    workbook: &mut Workbook,
+    name: &str,
+    headers: &[&str],
+    rows: &[Vec<Cell>],
+    date_format: &Format,
+) -> Result<(), String> {
+    let header_format = Format::new().set_bold();
+    let sheet = workbook.add_worksheet();
+    sheet.set_name(name).map_err(|e| e.to_string())?;
+    for (col, header) in headers.iter().enumerate() {
+        sheet
+            .write_string_with_format(0, col as u16, *header, &header_format)
+            .map_err(|e| e.to_string())?;
+    }
+    sheet.set_freeze_panes(1, 0).map_err(|e| e.to_string())?;
+    for (i, cells) in rows.iter().enumerate() {
+        write_sheet_row(sheet, i as u32 + 1, cells, date_format)?;
+    }
+    Ok(())
+}
+
+fn write_xlsx(
+    conn: &Connection,
+    file_path: &Path,
+    options: &LedgerExportOptions,
+    columns: &[LedgerColumn],
+) -> Result<usize, String> {
+    let date_format = Format::new().set_num_format("yyyy-mm-dd");
+    let header_format = Format::new().set_bold();
+    let mut workbook = Workbook::new();
+
+    let sheet = workbook.add_worksheet();
+    sheet.set_name("Transactions").map_err(|e| e.to_string())?;
+    for (col, column) in columns.iter().enumerate() {
+        sheet
+            .write_string_with_format(0, col as u16, column.header(), &header_format)
+            .map_err(|e| e.to_string())?;
+    }
+    sheet.set_freeze_panes(1, 0).map_err(|e| e.to_string())?;
+    let mut row = 0u32;
+    let count = for_each_transaction(conn, options, columns, |cells| {
+        row += 1;
+        write_sheet_row(sheet, row, &cells, &date_format)
+    })?;
+
+    add_sheet(
+        &mut workbook,
+        "Accounts",
+        &["Name", "Currency", "Balance"],
+        &load_accounts(conn, options)?,
+        &date_format,
+    )?;
+    add_sheet(
+        &mut workbook,
+        "Holdings",
+        &[
+            "Account",
+            "Ticker",
+            "Shares",
+            "Price",
+            "Price Date",
+            "Value",
+        ],
+        &load_holdings(conn, options)?,
+        &date_format,
+    )?;
+    add_sheet(
+        &mut workbook,
+        "Rules",
+        &["Priority", "Match", "Conditions", "Actions"],
+        &load_rules(conn)?,
+        &date_format,
+    )?;
+    add_sheet(
+        &mut workbook,
+        "Exchange Rates",
+        &["Currency", "Rate"],
+        &load_exchange_rates(conn)?,
+        &date_format,
+    )?;
+    add_sheet(
+        &mut workbook,
+        "Daily Prices",
+        &["Ticker", "Date", "Price"],
+        &load_daily_prices(conn, options)?,
+        &date_format,
+    )?;
+
+    workbook.save(file_path).map_err(|e| e.to_string())?;
+    Ok(count)
+}
+
+// Keeps the shape of the old webview export: accounts, transactions and the export date
+fn write_json(
+    conn: &Connection,
+    file_path: &Path,
+    options: &LedgerExportOptions,
+    columns: &[LedgerColumn],
+) -> Result<usize, String> {
+    let file = File::create(file_path).map_err(|e| e.to_string())?;
+    let mut out = BufWriter::new(file);
+
+    let accounts: Vec<serde_json::Value> = load_accounts(conn, options)?
+        .iter()
+        .map(|cells| {
+            json!({
+                "name": cells[0].to_json(),
+                "currency": cells[1].to_json(),
+                "balance": cells[2].to_json(),
+            })
+        })
+        .collect();
+    write!(
+        out,
+        "{{\n  \"exportDate\": {},\n  \"accounts\": {},\n  \"transactions\": [",
+        json!(chrono::Utc::now().to_rfc3339()),
+        serde_json::to_string(&accounts).map_err(|e| e.to_string())?
+    )
+    .map_err(|e| e.to_string())?;
+
+    let mut first = true;
+    let count = for_each_transaction(conn, options, columns, |cells| {
+        let object: serde_json::Map<String, serde_json::Value> = columns
+            .iter()
+            .zip(&cells)
+            .map(|(column, cell)| (column.key().to_string(), cell.to_json()))
+            .collect();
+        let separator = if first { "" } else { "," };
+        first = false;
+        write!(
+            out,
+            "{}\n    {}",
+            separator,
+            serde_json::Value::Object(object)
+        )
+        .map_err(|e| e.to_string())
+    })?;
+
+    write!(out, "\n  ]\n}}\n").map_err(|e| e.to_string())?;
+    out.flush().map_err(|e| e.to_string())?;
+    Ok(count)
+}
+
+pub fn export_ledger_db(
+    db_path: &PathBuf,
+    file_path: &Path,
+    options: LedgerExportOptions,
+) -> Result<usize, String> {
+    let columns: Vec<LedgerColumn> = match &options.columns {
+        Some(columns) if !columns.is_empty() => columns.clone(),
+        _ => ALL_COLUMNS.to_vec(),
+    };
+    let conn = Connection::open(db_path).map_err(|e| e.to_string())?;
+    match options.format {
+        LedgerFormat::Csv => write_csv(&conn, file_path, &options, &columns),
+        LedgerFormat::Xlsx => write_xlsx(&conn, file_path, &options, &columns),
+        LedgerFormat::Json => write_json(&conn, file_path, &options, &columns),
+    }
+}
@@ -0,0 +1,78 @@
+use rusqlite::{params_from_iter, Connection};
+
+pub mod journal;
+pub mod ledger;
+pub mod qif;
+pub mod snapshot;
+
+// A transaction as exporters see it: the stored row plus the account on the other side of a
+// transfer, resolved through `linked_tx_id` (or the payee, for transfers created before links).
+#[derive(Debug, Clone)]
+pub struct ExportTransaction {
+    pub tx: crate::Transaction,
+    pub linked_tx_id: Option<i32>,
+    pub transfer_account: Option<String>,
+    // Shared by the parts of a split transaction
+    pub split_id: Option<i32>,
+}
+
+// Loads transactions ordered by date, optionally restricted to some accounts
+pub fn load_transactions(
+    conn: &Connection,
+    account_ids: Option<&[i32]>,
+) -> Result<Vec<ExportTransaction>, String> {
+    let filter = match account_ids {
+        Some(ids) if !ids.is_empty() => format!(
+            "WHERE t.account_id IN ({})",
+            vec!["?"; ids.len()].join(", ")
+        ),
+        _ => String::new(),
+    };
+    let sql = format!(
+        "SELECT t.id, t.account_id, t.date, t.payee, t.notes, t.category, t.amount, t.ticker, t.shares, t.price_per_share, t.fee, t.currency, t.linked_tx_id, la.name, pa.name, t.split_id
+         FROM transactions t
+         LEFT JOIN transactions l ON l.id = t.linked_tx_id
+         LEFT JOIN accounts la ON la.id = l.account_id
+         LEFT JOIN accounts pa ON pa.name = t.payee AND pa.id != t.account_id AND t.category = 'Transfer'
+         {}
+         ORDER BY t.date ASC, t.id ASC",
+        filter
+    );
+    let mut stmt = conn.prepare(&sql).map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map(params_from_iter(account_ids.unwrap_or(&[])), |row| {
+            let linked: Option<String> = row.get(13)?;
+            let by_payee: Option<String> = row.get(14)?;
+            Ok(ExportTransaction {
+                tx: crate::Transaction {
+                    id: row.get(0)?,
+                    account_id: row.get(1)?,
+                    date: row.get(2)?,
+                    payee: row.get(3)?,
+                    notes: row.get(4)?,
+                    category: row.get(5)?,
+                    amount: row.get(6)?,
+                    ticker: row.get(7)?,
+                    shares: row.get(8)?,
+                    price_per_share: row.get(9)?,
+                    fee: row.get(10)?,
+                    currency: row.get(11)?,
+                },
+                linked_tx_id: row.get(12)?,
+                transfer_account: linked.or(by_payee),
+                split_id: row.get(15)?,
+            })
+        })
+        .map_err(|e| e.to_string())?;
+
+    let mut transactions = Vec::new();
+    for r in rows {
+        transactions.push(r.map_err(|e| e.to_string())?);
+    }
+    Ok(transactions)
+}
+
+// Brokerage trades carry a ticker and a signed share count
+pub fn is_trade(tx: &crate::Transaction) -> bool {
+    tx.ticker.as_deref().is_some_and(|t| !t.is_empty()) && tx.shares.is_some_and(|s| s != 0.0)
+}
@@ -0,0 +1,187 @@
+use super::{is_trade, load_transactions, ExportTransaction};
+use crate::Account;
+use chrono::NaiveDate;
+use rusqlite::Connection;
+use std::collections::HashSet;
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+
+// QIF is line-based, so embedded newlines would start new fields
+fn clean(value: &str) -> String {
+    value.replace(['\r', '\n'], " ")
+}
+
+// Amounts are written to the cent; `f64` display would show float noise such as 12.300000000000001
+fn qif_amount(amount: f64) -> String {
+    format!("{:.2}", amount)
+}
+
+// Prices and share counts need more places than money, but not the float noise of `{}`
+fn qif_quantity(value: f64) -> String {
+    format!("{:.6}", value)
+}
+
+fn qif_date(date: &str) -> String {
+    NaiveDate::parse_from_str(date, "%Y-%m-%d")
+        .map(|d| d.format("%m/%d/%Y").to_string())
+        .unwrap_or_else(|_| date.to_string())
+}
+
+fn write_text_fields(out: &mut String, tx: &crate::Transaction) {
+    if !tx.payee.is_empty() {
+        let _ = writeln!(out, "P{}", clean(&tx.payee));
+    }
+    if let Some(notes) = tx.notes.as_deref().filter(|n| !n.is_empty()) {
+        let _ = writeln!(out, "M{}", clean(notes));
+    }
+}
+
+fn write_bank_line(out: &mut String, item: &ExportTransaction) {
+    let tx = &item.tx;
+    let _ = writeln!(out, "D{}", qif_date(&tx.date));
+    let _ = writeln!(out, "T{}", qif_amount(tx.amount));
+    write_text_fields(out, tx);
+    if let Some(category) = category_field(item) {
+        let _ = writeln!(out, "L{}", category);
+    }
+    out.push_str("^\n");
+}
+
+// `[Account]` for transfers, the category otherwise
+fn category_field(item: &ExportTransaction) -> Option<String> {
+    match (&item.transfer_account, item.tx.category.as_deref()) {
+        (Some(account), _) => Some(format!("[{}]", clean(account))),
+        (None, Some(category)) if !category.is_empty() => Some(clean(category)),
+        _ => None,
+    }
+}
+
+// One record for all parts of a split: the total in `T`, then an `S`/`E`/`$` group per part.
+// The memo every part shares goes in `M`; parts with their own note get it in `E`.
+fn write_split(out: &mut String, parts: &[&ExportTransaction]) {
+    let first = &parts[0].tx;
+    let total: f64 = parts.iter().map(|p| p.tx.amount).sum();
+    let _ = writeln!(out, "D{}", qif_date(&first.date));
+    let _ = writeln!(out, "T{}", qif_amount(total));
+    // Transfer parts carry the other account as payee, so prefer a categorized part's payee
+    let payee = parts
+        .iter()
+        .find(|p| p.transfer_account.is_none())
+        .map_or(&first.payee, |p| &p.tx.payee);
+    if !payee.is_empty() {
+        let _ = writeln!(out, "P{}", clean(payee));
+    }
+    let memo = first
+        .notes
+        .as_deref()
+        .filter(|n| !n.is_empty())
+        .filter(|n| parts.iter().all(|p| p.tx.notes.as_deref() == Some(*n)));
+    if let Some(memo) = memo {
+        let _ = writeln!(out, "M{}", clean(memo));
+    }
+    for part in parts {
+        let _ = writeln!(out, "S{}", category_field(part).unwrap_or_default());
+        if memo.is_none() {
+            if let Some(notes) = part.tx.notes.as_deref().filter(|n| !n.is_empty()) {
+                let _ = writeln!(out, "E{}", clean(notes));
+            }
+        }
+        let _ = writeln!(out, "${}", qif_amount(part.tx.amount));
+    }
+    out.push_str("^\n");
+}
+
+fn write_investment_line(out: &mut String, item: &ExportTransaction) {
+    let tx = &item.tx;
+    let _ = writeln!(out, "D{}", qif_date(&tx.date));
+
+    if is_trade(tx) {
+        let shares = tx.shares.unwrap_or(0.0);
+        let _ = writeln!(out, "N{}", if shares > 0.0 { "Buy" } else { "Sell" });
+        let _ = writeln!(out, "Y{}", clean(tx.ticker.as_deref().unwrap_or_default()));
+        if let Some(price) = tx.price_per_share {
+            let _ = writeln!(out, "I{}", qif_quantity(price));
+        }
+        let _ = writeln!(out, "Q{}", qif_quantity(shares.abs()));
+        if let Some(fee) = tx.fee {
+            let _ = writeln!(out, "O{}", qif_amount(fee));
+        }
+        let _ = writeln!(out, "T{}", qif_amount(tx.amount.abs()));
+        if let Some(notes) = tx.notes.as_deref().filter(|n| !n.is_empty()) {
+            let _ = writeln!(out, "M{}", clean(notes));
+        }
+    } else if let Some(account) = &item.transfer_account {
+        let _ = writeln!(out, "N{}", if tx.amount >= 0.0 { "XIn" } else { "XOut" });
+        let _ = writeln!(out, "T{}", qif_amount(tx.amount.abs()));
+        write_text_fields(out, tx);
+        let _ = writeln!(out, "L[{}]", clean(account));
+    } else {
+        // Plain cash movement; unlike the X actions it keeps its sign
+        out.push_str("NCash\n");
+        let _ = writeln!(out, "T{}", qif_amount(tx.amount));
+        write_text_fields(out, tx);
+        if let Some(category) = tx.category.as_deref().filter(|c| !c.is_empty()) {
+            let _ = writeln!(out, "L{}", clean(category));
+        }
+    }
+    out.push_str("^\n");
+}
+
+// Writes one `!Account` header and `!Type` section per account. Accounts holding trades are
+// written as investment accounts, whose records cannot hold splits, so split parts are only
+// grouped in bank sections. QIF has no currency field, so currencies are not exported.
+pub fn write_qif(accounts: &[Account], transactions: &[ExportTransaction]) -> String {
+    let mut out = String::new();
+    for account in accounts {
+        let items: Vec<&ExportTransaction> = transactions
+            .iter()
+            .filter(|t| t.tx.account_id == account.id)
+            .collect();
+        let investment = items.iter().any(|t| is_trade(&t.tx));
+        let kind = if investment { "Invst" } else { "Bank" };
+
+        let _ = writeln!(out, "!Account\nN{}\nT{}\n^", clean(&account.name), kind);
+        let _ = writeln!(out, "!Type:{}", kind);
+        let mut written_splits = HashSet::new();
+        for item in &items {
+            if investment {
+                write_investment_line(&mut out, item);
+                continue;
+            }
+            match item.split_id {
+                Some(split_id) => {
+                    if written_splits.insert(split_id) {
+                        let parts: Vec<&ExportTransaction> = items
+                            .iter()
+                            .copied()
+                            .filter(|t| t.split_id == Some(split_id))
+                            .collect();
+                        write_split(&mut out, &parts);
+                    }
+                }
+                None => write_bank_line(&mut out, item),
+            }
+        }
+    }
+    out
+}
+
+// Exports the selected accounts (all when `account_ids` is None) and returns the number of
+// transactions written.
+pub fn export_qif_db(
+    db_path: &PathBuf,
+    file_path: &Path,
+    account_ids: Option<Vec<i32>>,
+) -> Result<usize, String> {
+    let mut accounts = crate::get_accounts_db(db_path)?;
+    if let Some(ref ids) = account_ids {
+        accounts.retain(|a| ids.contains(&a.id));
+    }
+    accounts.sort_by_key(|a| a.id);
+
+    let conn = Connection::open(db_path).map_err(|e| e.to_string())?;
+    let transactions = load_transactions(&conn, account_ids.as_deref())?;
+
+    std::fs::write(file_path, write_qif(&accounts, &transactions)).map_err(|e| e.to_string())?;
+    Ok(transactions.len())
+}
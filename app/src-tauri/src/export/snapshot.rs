@@ -0,0 +1,1002 @@
+use crate::holdings::CostBasisMethod;
+use crate::rules::{
+    load_rules, write_actions, write_conditions, MatchJoin, RuleAction, RuleCondition,
+};
+use rusqlite::{params, Connection};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+// Bump when a table or column is added; older snapshots must keep loading
+pub const SNAPSHOT_VERSION: u32 = 6;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SnapshotAccount {
+    pub id: i32,
+    pub name: String,
+    pub balance: f64,
+    #[serde(default)]
+    pub kind: Option<String>,
+    #[serde(default)]
+    pub currency: Option<String>,
+    #[serde(default)]
+    pub cost_basis_method: CostBasisMethod,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SnapshotTransaction {
+    pub id: i32,
+    pub account_id: i32,
+    pub date: String,
+    pub payee: String,
+    #[serde(default)]
+    pub notes: Option<String>,
+    #[serde(default)]
+    pub category: Option<String>,
+    pub amount: f64,
+    #[serde(default)]
+    pub ticker: Option<String>,
+    #[serde(default)]
+    pub shares: Option<f64>,
+    #[serde(default)]
+    pub price_per_share: Option<f64>,
+    #[serde(default)]
+    pub fee: Option<f64>,
+    #[serde(default)]
+    pub currency: Option<String>,
+    #[serde(default)]
+    pub linked_tx_id: Option<i32>,
+    #[serde(default)]
+    pub external_id: Option<String>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    #[serde(default)]
+    pub raw_payee: Option<String>,
+    #[serde(default)]
+    pub import_batch_id: Option<i64>,
+    #[serde(default)]
+    pub rule_ids: Vec<i32>,
+    #[serde(default)]
+    pub value_date: Option<String>,
+    #[serde(default)]
+    pub split_id: Option<i32>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SnapshotRule {
+    pub id: i32,
+    pub priority: i32,
+    pub match_field: String,
+    pub match_pattern: String,
+    pub action_field: String,
+    pub action_value: String,
+    #[serde(default)]
+    pub match_join: MatchJoin,
+    #[serde(default)]
+    pub conditions: Vec<RuleCondition>,
+    // Empty in older snapshots, whose rules have the one action in `action_field`/`action_value`
+    #[serde(default)]
+    pub actions: Vec<RuleAction>,
+}
+
+impl From<crate::Rule> for SnapshotRule {
+    fn from(rule: crate::Rule) -> Self {
+        SnapshotRule {
+            id: rule.id,
+            priority: rule.priority,
+            match_field: rule.match_field,
+            match_pattern: rule.match_pattern,
+            action_field: rule.action_field,
+            action_value: rule.action_value,
+            match_join: rule.match_join,
+            conditions: rule.conditions,
+            actions: rule.actions,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SnapshotLotSelection {
+    pub sale_id: i32,
+    pub lot_id: i32,
+    pub shares: f64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SnapshotImportBatch {
+    pub id: i64,
+    pub file_name: String,
+    pub file_hash: String,
+    pub imported_at: String,
+    pub total_rows: i64,
+    pub imported_rows: i64,
+    pub duplicate_rows: i64,
+    pub error_rows: i64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SnapshotImportProfile {
+    pub id: i32,
+    pub name: String,
+    pub header_fingerprint: String,
+    // The CSV mapping as stored, in JSON
+    pub mapping: String,
+    #[serde(default)]
+    pub rule_ids: Option<Vec<i32>>,
+}
+
+// Undo record of a rule run; `changes` is kept as stored, in JSON
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SnapshotRuleRun {
+    pub id: i64,
+    pub applied_at: String,
+    pub changes: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SnapshotCategoryTraining {
+    pub transaction_id: i32,
+    pub category: String,
+    // JSON list of the features learned from the transaction
+    pub features: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SnapshotCategoryFeature {
+    pub category: String,
+    pub feature: String,
+    pub count: i64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SnapshotPayeeRewrite {
+    // Missing in version 4 snapshots
+    #[serde(default)]
+    pub id: Option<i32>,
+    pub position: i32,
+    pub pattern: String,
+    pub replacement: String,
+    #[serde(default)]
+    pub is_regex: bool,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SnapshotExchangeRate {
+    pub currency: String,
+    pub rate: f64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SnapshotStockPrice {
+    pub ticker: String,
+    pub price: f64,
+    pub last_updated: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SnapshotDailyPrice {
+    pub ticker: String,
+    pub date: String,
+    pub price: f64,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Snapshot {
+    pub version: u32,
+    pub exported_at: String,
+    #[serde(default)]
+    pub accounts: Vec<SnapshotAccount>,
+    #[serde(default)]
+    pub transactions: Vec<SnapshotTransaction>,
+    #[serde(default)]
+    pub lot_selections: Vec<SnapshotLotSelection>,
+    #[serde(default)]
+    pub import_batches: Vec<SnapshotImportBatch>,
+    #[serde(default)]
+    pub rules: Vec<SnapshotRule>,
+    #[serde(default)]
+    pub rule_runs: Vec<SnapshotRuleRun>,
+    #[serde(default)]
+    pub import_profiles: Vec<SnapshotImportProfile>,
+    #[serde(default)]
+    pub category_training: Vec<SnapshotCategoryTraining>,
+    #[serde(default)]
+    pub category_features: Vec<SnapshotCategoryFeature>,
+    #[serde(default)]
+    pub payee_rewrites: Vec<SnapshotPayeeRewrite>,
+    #[serde(default)]
+    pub custom_exchange_rates: Vec<SnapshotExchangeRate>,
+    #[serde(default)]
+    pub stock_prices: Vec<SnapshotStockPrice>,
+    #[serde(default)]
+    pub daily_stock_prices: Vec<SnapshotDailyPrice>,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum SnapshotMode {
+    // Wipe the ledger and restore every row with its original id
+    Replace,
+    // Add the snapshot on top of the ledger, giving rows fresh ids
+    Merge,
+}
+
+#[derive(Serialize, Debug, Clone, Default, PartialEq)]
+pub struct SnapshotReport {
+    pub accounts: usize,
+    pub transactions: usize,
+    pub rules: usize,
+    pub exchange_rates: usize,
+    pub stock_prices: usize,
+    pub daily_prices: usize,
+}
+
+impl SnapshotReport {
+    fn of(snapshot: &Snapshot) -> Self {
+        SnapshotReport {
+            accounts: snapshot.accounts.len(),
+            transactions: snapshot.transactions.len(),
+            rules: snapshot.rules.len(),
+            exchange_rates: snapshot.custom_exchange_rates.len(),
+            stock_prices: snapshot.stock_prices.len(),
+            daily_prices: snapshot.daily_stock_prices.len(),
+        }
+    }
+}
+
+fn collect<T>(
+    conn: &Connection,
+    sql: &str,
+    map: impl FnMut(&rusqlite::Row) -> rusqlite::Result<T>,
+) -> Result<Vec<T>, String> {
+    let mut stmt = conn.prepare(sql).map_err(|e| e.to_string())?;
+    let rows = stmt.query_map([], map).map_err(|e| e.to_string())?;
+    let mut out = Vec::new();
+    for r in rows {
+        out.push(r.map_err(|e| e.to_string())?);
+    }
+    Ok(out)
+}
+
+// JSON lists such as `tags` and `rule_ids` are stored as NULL when empty
+fn json_list<T: serde::de::DeserializeOwned>(value: Option<String>) -> Vec<T> {
+    value
+        .and_then(|v| serde_json::from_str(&v).ok())
+        .unwrap_or_default()
+}
+
+fn json_list_column<T: Serialize>(values: &[T]) -> Result<Option<String>, String> {
+    match values.is_empty() {
+        true => Ok(None),
+        false => serde_json::to_string(values)
+            .map(Some)
+            .map_err(|e| e.to_string()),
+    }
+}
+
+pub fn read_snapshot(conn: &Connection) -> Result<Snapshot, String> {
+    Ok(Snapshot {
+        version: SNAPSHOT_VERSION,
+        exported_at: chrono::Utc::now().to_rfc3339(),
+        accounts: collect(
+            conn,
+            "SELECT id, name, balance, kind, currency, cost_basis_method FROM accounts ORDER BY id",
+            |row| {
+                Ok(SnapshotAccount {
+                    id: row.get(0)?,
+                    name: row.get(1)?,
+                    balance: row.get(2)?,
+                    kind: row.get(3)?,
+                    currency: row.get(4)?,
+                    cost_basis_method: CostBasisMethod::parse(&row.get::<_, String>(5)?),
+                })
+            },
+        )?,
+        transactions: collect(
+            conn,
+            "SELECT id, account_id, date, payee, notes, category, amount, ticker, shares, price_per_share, fee, currency, linked_tx_id, external_id, tags, raw_payee, import_batch_id, rule_ids, value_date, split_id FROM transactions ORDER BY id",
+            |row| {
+                Ok(SnapshotTransaction {
+                    id: row.get(0)?,
+                    account_id: row.get(1)?,
+                    date: row.get(2)?,
+                    payee: row.get(3)?,
+                    notes: row.get(4)?,
+                    category: row.get(5)?,
+                    amount: row.get(6)?,
+                    ticker: row.get(7)?,
+                    shares: row.get(8)?,
+                    price_per_share: row.get(9)?,
+                    fee: row.get(10)?,
+                    currency: row.get(11)?,
+                    linked_tx_id: row.get(12)?,
+                    external_id: row.get(13)?,
+                    tags: json_list(row.get(14)?),
+                    raw_payee: row.get(15)?,
+                    import_batch_id: row.get(16)?,
+                    rule_ids: json_list(row.get(17)?),
+                    value_date: row.get(18)?,
+                    split_id: row.get(19)?,
+                })
+            },
+        )?,
+        lot_selections: collect(
+            conn,
+            "SELECT sale_id, lot_id, shares FROM lot_selections ORDER BY sale_id, lot_id",
+            |row| {
+                Ok(SnapshotLotSelection {
+                    sale_id: row.get(0)?,
+                    lot_id: row.get(1)?,
+                    shares: row.get(2)?,
+                })
+            },
+        )?,
+        import_batches: collect(
+            conn,
+            "SELECT id, file_name, file_hash, imported_at, total_rows, imported_rows, duplicate_rows, error_rows FROM import_batches ORDER BY id",
+            |row| {
+                Ok(SnapshotImportBatch {
+                    id: row.get(0)?,
+                    file_name: row.get(1)?,
+                    file_hash: row.get(2)?,
+                    imported_at: row.get(3)?,
+                    total_rows: row.get(4)?,
+                    imported_rows: row.get(5)?,
+                    duplicate_rows: row.get(6)?,
+                    error_rows: row.get(7)?,
+                })
+            },
+        )?,
+        rules: {
+            let mut rules: Vec<SnapshotRule> = load_rules(conn)?
+                .into_iter()
+                .map(SnapshotRule::from)
+                .collect();
+            rules.sort_by_key(|rule| rule.id);
+            rules
+        },
+        rule_runs: collect(
+            conn,
+            "SELECT id, applied_at, changes FROM rule_runs ORDER BY id",
+            |row| {
+                Ok(SnapshotRuleRun {
+                    id: row.get(0)?,
+                    applied_at: row.get(1)?,
+                    changes: row.get(2)?,
+                })
+            },
+        )?,
+        import_profiles: collect(
+            conn,
+            "SELECT id, name, header_fingerprint, mapping, rule_ids FROM import_profiles ORDER BY id",
+            |row| {
+                Ok(SnapshotImportProfile {
+                    id: row.get(0)?,
+                    name: row.get(1)?,
+                    header_fingerprint: row.get(2)?,
+                    mapping: row.get(3)?,
+                    rule_ids: row
+                        .get::<_, Option<String>>(4)?
+                        .and_then(|ids| serde_json::from_str(&ids).ok()),
+                })
+            },
+        )?,
+        category_training: collect(
+            conn,
+            "SELECT transaction_id, category, features FROM category_training ORDER BY transaction_id",
+            |row| {
+                Ok(SnapshotCategoryTraining {
+                    transaction_id: row.get(0)?,
+                    category: row.get(1)?,
+                    features: row.get(2)?,
+                })
+            },
+        )?,
+        category_features: collect(
+            conn,
+            "SELECT category, feature, count FROM category_features ORDER BY category, feature",
+            |row| {
+                Ok(SnapshotCategoryFeature {
+                    category: row.get(0)?,
+                    feature: row.get(1)?,
+                    count: row.get(2)?,
+                })
+            },
+        )?,
+        payee_rewrites: crate::payees::list_payee_rewrites(conn)?
+            .into_iter()
+            .map(|rewrite| SnapshotPayeeRewrite {
+                id: Some(rewrite.id),
+                position: rewrite.position,
+                pattern: rewrite.pattern,
+                replacement: rewrite.replacement,
+                is_regex: rewrite.is_regex,
+            })
+            .collect(),
+        custom_exchange_rates: collect(
+            conn,
+            "SELECT currency, rate FROM custom_exchange_rates ORDER BY currency",
+            |row| {
+                Ok(SnapshotExchangeRate {
+                    currency: row.get(0)?,
+                    rate: row.get(1)?,
+                })
+            },
+        )?,
+        stock_prices: collect(
+            conn,
+            "SELECT ticker, price, last_updated FROM stock_prices ORDER BY ticker",
+            |row| {
+                Ok(SnapshotStockPrice {
+                    ticker: row.get(0)?,
+                    price: row.get(1)?,
+                    last_updated: row.get(2)?,
+                })
+            },
+        )?,
+        daily_stock_prices: collect(
+            conn,
+            "SELECT ticker, date, price FROM daily_stock_prices ORDER BY ticker, date",
+            |row| {
+                Ok(SnapshotDailyPrice {
+                    ticker: row.get(0)?,
+                    date: row.get(1)?,
+                    price: row.get(2)?,
+                })
+            },
+        )?,
+    })
+}
+
+// Rejects snapshots from newer versions or with dangling ids before anything is written
+pub fn validate_snapshot(snapshot: &Snapshot) -> Result<(), String> {
+    if snapshot.version == 0 || snapshot.version > SNAPSHOT_VERSION {
+        return Err(format!(
+            "Unsupported snapshot version {} (this build reads up to {})",
+            snapshot.version, SNAPSHOT_VERSION
+        ));
+    }
+    let mut account_ids = HashSet::new();
+    for account in &snapshot.accounts {
+        if !account_ids.insert(account.id) {
+            return Err(format!("Duplicate account id {}", account.id));
+        }
+    }
+    let mut tx_ids = HashSet::new();
+    for tx in &snapshot.transactions {
+        if !tx_ids.insert(tx.id) {
+            return Err(format!("Duplicate transaction id {}", tx.id));
+        }
+        if !account_ids.contains(&tx.account_id) {
+            return Err(format!(
+                "Transaction {} refers to missing account {}",
+                tx.id, tx.account_id
+            ));
+        }
+    }
+    for tx in &snapshot.transactions {
+        for (link, id) in [("linked", tx.linked_tx_id), ("split", tx.split_id)] {
+            if let Some(id) = id.filter(|id| !tx_ids.contains(id)) {
+                return Err(format!(
+                    "Transaction {} refers to missing {} transaction {}",
+                    tx.id, link, id
+                ));
+            }
+        }
+    }
+    for selection in &snapshot.lot_selections {
+        for id in [selection.sale_id, selection.lot_id] {
+            if !tx_ids.contains(&id) {
+                return Err(format!(
+                    "Lot selection refers to missing transaction {}",
+                    id
+                ));
+            }
+        }
+    }
+    let mut rule_ids = HashSet::new();
+    for rule in &snapshot.rules {
+        if !rule_ids.insert(rule.id) {
+            return Err(format!("Duplicate rule id {}", rule.id));
+        }
+    }
+    Ok(())
+}
+
+// Links to other rows (`linked_tx_id`, `split_id`) are set once every row is in. Batch and rule
+// ids are passed in so a merge can give the remapped ones.
+fn insert_transaction_row(
+    conn: &Connection,
+    id: Option<i32>,
+    account_id: i32,
+    import_batch_id: Option<i64>,
+    rule_ids: &[i32],
+    tx: &SnapshotTransaction,
+) -> Result<i32, String> {
+    conn.execute(
+        "INSERT INTO transactions (id, account_id, date, payee, notes, category, amount, ticker, shares, price_per_share, fee, currency, external_id, tags, raw_payee, import_batch_id, rule_ids, value_date) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18)",
+        params![
+            id,
+            account_id,
+            tx.date,
+            tx.payee,
+            tx.notes,
+            tx.category,
+            tx.amount,
+            tx.ticker,
+            tx.shares,
+            tx.price_per_share,
+            tx.fee,
+            tx.currency,
+            tx.external_id,
+            json_list_column(&tx.tags)?,
+            tx.raw_payee,
+            import_batch_id,
+            json_list_column(rule_ids)?,
+            tx.value_date
+        ],
+    )
+    .map_err(|e| e.to_string())?;
+    Ok(conn.last_insert_rowid() as i32)
+}
+
+fn insert_lot_selection(
+    conn: &Connection,
+    sale_id: i32,
+    lot_id: i32,
+    shares: f64,
+) -> Result<(), String> {
+    conn.execute(
+        "INSERT INTO lot_selections (sale_id, lot_id, shares) VALUES (?1, ?2, ?3)",
+        params![sale_id, lot_id, shares],
+    )
+    .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+// Rewrites already in the ledger are kept; the snapshot's are added after them
+fn restore_payee_rewrites(conn: &Connection, snapshot: &Snapshot) -> Result<(), String> {
+    let existing = crate::payees::list_payee_rewrites(conn)?;
+    let offset = existing.iter().map(|r| r.position + 1).max().unwrap_or(0);
+    for rewrite in &snapshot.payee_rewrites {
+        if existing.iter().any(|r| {
+            (&r.pattern, &r.replacement, r.is_regex)
+                == (&rewrite.pattern, &rewrite.replacement, rewrite.is_regex)
+        }) {
+            continue;
+        }
+        conn.execute(
+            "INSERT INTO payee_rewrites (position, pattern, replacement, is_regex) VALUES (?1, ?2, ?3, ?4)",
+            params![
+                offset + rewrite.position,
+                rewrite.pattern,
+                rewrite.replacement,
+                rewrite.is_regex
+            ],
+        )
+        .map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
+
+// A merge keeps the ledger's own rates and closing prices, and takes a current price only when
+// the snapshot's is newer
+fn restore_prices(conn: &Connection, snapshot: &Snapshot, merge: bool) -> Result<(), String> {
+    let insert = if merge {
+        "INSERT OR IGNORE"
+    } else {
+        "INSERT OR REPLACE"
+    };
+    for rate in &snapshot.custom_exchange_rates {
+        conn.execute(
+            &format!(
+                "{} INTO custom_exchange_rates (currency, rate) VALUES (?1, ?2)",
+                insert
+            ),
+            params![rate.currency, rate.rate],
+        )
+        .map_err(|e| e.to_string())?;
+    }
+    for price in &snapshot.stock_prices {
+        conn.execute(
+            "INSERT INTO stock_prices (ticker, price, last_updated) VALUES (?1, ?2, ?3)
+             ON CONFLICT(ticker) DO UPDATE SET price = excluded.price, last_updated = excluded.last_updated
+             WHERE excluded.last_updated > stock_prices.last_updated",
+            params![price.ticker, price.price, price.last_updated],
+        )
+        .map_err(|e| e.to_string())?;
+    }
+    for price in &snapshot.daily_stock_prices {
+        conn.execute(
+            &format!(
+                "{} INTO daily_stock_prices (ticker, date, price) VALUES (?1, ?2, ?3)",
+                insert
+            ),
+            params![price.ticker, price.date, price.price],
+        )
+        .map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
+
+// Everything that decides what a rule does; older snapshots carry the one action in the legacy
+// columns only
+fn rule_definition(
+    rule: &SnapshotRule,
+) -> (&str, &str, MatchJoin, &[RuleCondition], Vec<RuleAction>) {
+    let actions = match rule.actions.is_empty() {
+        true => vec![RuleAction::SetField {
+            field: rule.action_field.clone(),
+            value: rule.action_value.clone(),
+        }],
+        false => rule.actions.clone(),
+    };
+    (
+        &rule.match_field,
+        &rule.match_pattern,
+        rule.match_join,
+        &rule.conditions,
+        actions,
+    )
+}
+
+fn insert_import_batch(
+    conn: &Connection,
+    id: Option<i64>,
+    batch: &SnapshotImportBatch,
+) -> Result<i64, String> {
+    conn.execute(
+        "INSERT INTO import_batches (id, file_name, file_hash, imported_at, total_rows, imported_rows, duplicate_rows, error_rows) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+        params![
+            id,
+            batch.file_name,
+            batch.file_hash,
+            batch.imported_at,
+            batch.total_rows,
+            batch.imported_rows,
+            batch.duplicate_rows,
+            batch.error_rows
+        ],
+    )
+    .map_err(|e| e.to_string())?;
+    Ok(conn.last_insert_rowid())
+}
+
+fn insert_import_profile(
+    conn: &Connection,
+    id: Option<i32>,
+    profile: &SnapshotImportProfile,
+    rule_ids: Option<&[i32]>,
+) -> Result<(), String> {
+    let rule_ids = rule_ids
+        .map(serde_json::to_string)
+        .transpose()
+        .map_err(|e| e.to_string())?;
+    conn.execute(
+        "INSERT INTO import_profiles (id, name, header_fingerprint, mapping, rule_ids) VALUES (?1, ?2, ?3, ?4, ?5)",
+        params![
+            id,
+            profile.name,
+            profile.header_fingerprint,
+            profile.mapping,
+            rule_ids
+        ],
+    )
+    .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+// Every table the snapshot holds, children first; a replace empties all of them
+const SNAPSHOT_TABLES: [&str; 15] = [
+    "lot_selections",
+    "category_training",
+    "category_features",
+    "transactions",
+    "import_batches",
+    "accounts",
+    "rule_conditions",
+    "rule_actions",
+    "rule_runs",
+    "import_profiles",
+    "rules",
+    "payee_rewrites",
+    "custom_exchange_rates",
+    "stock_prices",
+    "daily_stock_prices",
+];
+
+fn replace_ledger(conn: &Connection, snapshot: &Snapshot) -> Result<(), String> {
+    for table in SNAPSHOT_TABLES {
+        conn.execute(&format!("DELETE FROM {}", table), [])
+            .map_err(|e| e.to_string())?;
+    }
+
+    for account in &snapshot.accounts {
+        conn.execute(
+            "INSERT INTO accounts (id, name, balance, kind, currency, cost_basis_method) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            params![
+                account.id,
+                account.name,
+                account.balance,
+                account.kind,
+                account.currency,
+                account.cost_basis_method.as_str()
+            ],
+        )
+        .map_err(|e| e.to_string())?;
+    }
+    for batch in &snapshot.import_batches {
+        insert_import_batch(conn, Some(batch.id), batch)?;
+    }
+    for tx in &snapshot.transactions {
+        insert_transaction_row(
+            conn,
+            Some(tx.id),
+            tx.account_id,
+            tx.import_batch_id,
+            &tx.rule_ids,
+            tx,
+        )?;
+    }
+    for tx in snapshot
+        .transactions
+        .iter()
+        .filter(|t| t.linked_tx_id.is_some() || t.split_id.is_some())
+    {
+        conn.execute(
+            "UPDATE transactions SET linked_tx_id = ?1, split_id = ?2 WHERE id = ?3",
+            params![tx.linked_tx_id, tx.split_id, tx.id],
+        )
+        .map_err(|e| e.to_string())?;
+    }
+    for selection in &snapshot.lot_selections {
+        insert_lot_selection(conn, selection.sale_id, selection.lot_id, selection.shares)?;
+    }
+    for rule in &snapshot.rules {
+        conn.execute(
+            "INSERT INTO rules (id, priority, match_field, match_pattern, action_field, action_value, match_join) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            params![
+                rule.id,
+                rule.priority,
+                rule.match_field,
+                rule.match_pattern,
+                rule.action_field,
+                rule.action_value,
+                rule.match_join.as_str()
+            ],
+        )
+        .map_err(|e| e.to_string())?;
+        write_conditions(conn, rule.id, &rule.conditions)?;
+        write_actions(conn, rule.id, &rule.actions)?;
+    }
+    for run in &snapshot.rule_runs {
+        conn.execute(
+            "INSERT INTO rule_runs (id, applied_at, changes) VALUES (?1, ?2, ?3)",
+            params![run.id, run.applied_at, run.changes],
+        )
+        .map_err(|e| e.to_string())?;
+    }
+    for profile in &snapshot.import_profiles {
+        insert_import_profile(conn, Some(profile.id), profile, profile.rule_ids.as_deref())?;
+    }
+    for training in &snapshot.category_training {
+        conn.execute(
+            "INSERT INTO category_training (transaction_id, category, features) VALUES (?1, ?2, ?3)",
+            params![training.transaction_id, training.category, training.features],
+        )
+        .map_err(|e| e.to_string())?;
+    }
+    for feature in &snapshot.category_features {
+        conn.execute(
+            "INSERT INTO category_features (category, feature, count) VALUES (?1, ?2, ?3)",
+            params![feature.category, feature.feature, feature.count],
+        )
+        .map_err(|e| e.to_string())?;
+    }
+    for rewrite in &snapshot.payee_rewrites {
+        conn.execute(
+            "INSERT INTO payee_rewrites (id, position, pattern, replacement, is_regex) VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![
+                rewrite.id,
+                rewrite.position,
+                rewrite.pattern,
+                rewrite.replacement,
+                rewrite.is_regex
+            ],
+        )
+        .map_err(|e| e.to_string())?;
+    }
+    restore_prices(conn, snapshot, false)
+}
+
+// Accounts are matched by name, ignoring case (their balances add up), everything else gets a fresh id and
+// links between rows are rewritten to the new ids. Identical rules are not duplicated, and import
+// profiles whose name is taken keep the ledger's version. Rule runs are left out: their undo
+// records describe the history of the other ledger.
+fn merge_ledger(conn: &Connection, snapshot: &Snapshot) -> Result<SnapshotReport, String> {
+    let mut report = SnapshotReport::of(snapshot);
+    let existing: HashMap<String, i32> = collect(conn, "SELECT name, id FROM accounts", |row| {
+        Ok((row.get::<_, String>(0)?.to_lowercase(), row.get(1)?))
+    })?
+    .into_iter()
+    .collect();
+
+    let mut account_map = HashMap::new();
+    report.accounts = 0;
+    for account in &snapshot.accounts {
+        let id = match existing.get(&account.name.to_lowercase()) {
+            Some(id) => {
+                conn.execute(
+                    "UPDATE accounts SET balance = balance + ?1 WHERE id = ?2",
+                    params![account.balance, id],
+                )
+                .map_err(|e| e.to_string())?;
+                *id
+            }
+            None => {
+                conn.execute(
+                    "INSERT INTO accounts (name, balance, kind, currency, cost_basis_method) VALUES (?1, ?2, ?3, ?4, ?5)",
+                    params![
+                        account.name,
+                        account.balance,
+                        account.kind,
+                        account.currency,
+                        account.cost_basis_method.as_str()
+                    ],
+                )
+                .map_err(|e| e.to_string())?;
+                report.accounts += 1;
+                conn.last_insert_rowid() as i32
+            }
+        };
+        account_map.insert(account.id, id);
+    }
+
+    // Duplicates map to the rule already in the ledger
+    let ledger_rules: Vec<SnapshotRule> = load_rules(conn)?
+        .into_iter()
+        .map(SnapshotRule::from)
+        .collect();
+    let mut rule_map = HashMap::new();
+    report.rules = 0;
+    for rule in &snapshot.rules {
+        let duplicate = ledger_rules
+            .iter()
+            .find(|existing| rule_definition(existing) == rule_definition(rule));
+        if let Some(existing) = duplicate {
+            rule_map.insert(rule.id, existing.id);
+            continue;
+        }
+        conn.execute(
+            "INSERT INTO rules (priority, match_field, match_pattern, action_field, action_value, match_join) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            params![
+                rule.priority,
+                rule.match_field,
+                rule.match_pattern,
+                rule.action_field,
+                rule.action_value,
+                rule.match_join.as_str()
+            ],
+        )
+        .map_err(|e| e.to_string())?;
+        let rule_id = conn.last_insert_rowid() as i32;
+        write_conditions(conn, rule_id, &rule.conditions)?;
+        write_actions(conn, rule_id, &rule.actions)?;
+        rule_map.insert(rule.id, rule_id);
+        report.rules += 1;
+    }
+    let map_rules = |ids: &[i32]| -> Vec<i32> {
+        ids.iter()
+            .filter_map(|id| rule_map.get(id).copied())
+            .collect()
+    };
+
+    let mut batch_map = HashMap::new();
+    for batch in &snapshot.import_batches {
+        batch_map.insert(batch.id, insert_import_batch(conn, None, batch)?);
+    }
+
+    let mut tx_map = HashMap::new();
+    for tx in &snapshot.transactions {
+        let batch_id = tx
+            .import_batch_id
+            .and_then(|id| batch_map.get(&id).copied());
+        let id = insert_transaction_row(
+            conn,
+            None,
+            account_map[&tx.account_id],
+            batch_id,
+            &map_rules(&tx.rule_ids),
+            tx,
+        )?;
+        tx_map.insert(tx.id, id);
+    }
+    for tx in &snapshot.transactions {
+        let linked = tx.linked_tx_id.map(|id| tx_map[&id]);
+        let split = tx.split_id.map(|id| tx_map[&id]);
+        if linked.is_some() || split.is_some() {
+            conn.execute(
+                "UPDATE transactions SET linked_tx_id = ?1, split_id = ?2 WHERE id = ?3",
+                params![linked, split, tx_map[&tx.id]],
+            )
+            .map_err(|e| e.to_string())?;
+        }
+    }
+    for selection in &snapshot.lot_selections {
+        insert_lot_selection(
+            conn,
+            tx_map[&selection.sale_id],
+            tx_map[&selection.lot_id],
+            selection.shares,
+        )?;
+    }
+
+    for profile in &snapshot.import_profiles {
+        let taken: i64 = conn
+            .query_row(
+                "SELECT COUNT(*) FROM import_profiles WHERE name = ?1",
+                params![profile.name],
+                |row| row.get(0),
+            )
+            .map_err(|e| e.to_string())?;
+        if taken == 0 {
+            let rule_ids = profile.rule_ids.as_deref().map(map_rules);
+            insert_import_profile(conn, None, profile, rule_ids.as_deref())?;
+        }
+    }
+    // What the model learned from the merged transactions is added to the ledger's model
+    for training in &snapshot.category_training {
+        if let Some(id) = tx_map.get(&training.transaction_id) {
+            let features: Vec<String> =
+                serde_json::from_str(&training.features).map_err(|e| e.to_string())?;
+            crate::categorize::learn(conn, *id, &training.category, &features)?;
+        }
+    }
+
+    restore_payee_rewrites(conn, snapshot)?;
+    restore_prices(conn, snapshot, true)?;
+    Ok(report)
+}
+
+pub fn write_snapshot(
+    conn: &mut Connection,
+    snapshot: &Snapshot,
+    mode: SnapshotMode,
+) -> Result<SnapshotReport, String> {
+    validate_snapshot(snapshot)?;
+    let tx = conn.transaction().map_err(|e| e.to_string())?;
+    let report = match mode {
+        SnapshotMode::Replace => {
+            replace_ledger(&tx, snapshot)?;
+            SnapshotReport::of(snapshot)
+        }
+        SnapshotMode::Merge => merge_ledger(&tx, snapshot)?,
+    };
+    tx.commit().map_err(|e| e.to_string())?;
+    Ok(report)
+}
+
+pub fn export_snapshot_db(db_path: &PathBuf, file_path: &Path) -> Result<SnapshotReport, String> {
+    let conn = Connection::open(db_path).map_err(|e| e.to_string())?;
+    let snapshot = read_snapshot(&conn)?;
+
+    let file = File::create(file_path).map_err(|e| e.to_string())?;
+    let mut out = BufWriter::new(file);
+    serde_json::to_writer_pretty(&mut out, &snapshot).map_err(|e| e.to_string())?;
+    out.flush().map_err(|e| e.to_string())?;
+    Ok(SnapshotReport::of(&snapshot))
+}
+
+pub fn import_snapshot_db(
+    db_path: &PathBuf,
+    file_path: &Path,
+    mode: SnapshotMode,
+) -> Result<SnapshotReport, String> {
+    let file = File::open(file_path).map_err(|e| e.to_string())?;
+    let snapshot: Snapshot = serde_json::from_reader(BufReader::new(file))
+        .map_err(|e| format!("Invalid snapshot: {}", e))?;
+    let mut conn = Connection::open(db_path).map_err(|e| e.to_string())?;
+    write_snapshot(&mut conn, &snapshot, mode)
+}
@@ -0,0 +1,343 @@
+use super::{replay, Disposal, Replay};
+use crate::export::ledger::sanitize_cell;
+use chrono::{Months, NaiveDate};
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+// Shares held for more than this are a long-term disposal
+const LONG_TERM_MONTHS: u32 = 12;
+
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum HoldingTerm {
+    Short,
+    Long,
+}
+
+impl HoldingTerm {
+    fn label(self) -> &'static str {
+        match self {
+            HoldingTerm::Short => "Short-term",
+            HoldingTerm::Long => "Long-term",
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct RealizedGainsQuery {
+    // Sale dates, both ends included; a tax year is one such range
+    pub date_from: Option<String>,
+    pub date_to: Option<String>,
+    pub account_id: Option<i32>,
+    // Reporting currency; defaults to USD
+    pub currency: Option<String>,
+}
+
+// The shares of one lot closed by one sale. Amounts are in the reporting currency: the cost
+// basis at the rate of the day the lot was bought, proceeds and fees at the rate of the sale.
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct RealizedGain {
+    pub sale_id: i32,
+    pub lot_id: i32,
+    pub account_id: i32,
+    pub account: String,
+    pub ticker: String,
+    pub acquired: String,
+    pub disposed: String,
+    pub shares: f64,
+    pub proceeds: f64,
+    // Buy fees are part of the cost basis
+    pub cost_basis: f64,
+    // Sale fees
+    pub fees: f64,
+    pub gain: f64,
+    pub term: HoldingTerm,
+    pub holding_days: i64,
+    pub trade_currency: String,
+    pub acquisition_rate: f64,
+    pub disposal_rate: f64,
+}
+
+// Shares a sale sold beyond the lots the account held, so they have no cost basis to report
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct UnmatchedSale {
+    pub sale_id: i32,
+    pub account_id: i32,
+    pub account: String,
+    pub ticker: String,
+    pub disposed: String,
+    pub shares: f64,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct RealizedGainsReport {
+    pub currency: String,
+    pub gains: Vec<RealizedGain>,
+    pub unmatched: Vec<UnmatchedSale>,
+    pub proceeds: f64,
+    pub cost_basis: f64,
+    pub fees: f64,
+    pub short_term_gain: f64,
+    pub long_term_gain: f64,
+    pub total_gain: f64,
+}
+
+fn parse_date(date: &str) -> Result<NaiveDate, String> {
+    NaiveDate::parse_from_str(date.trim(), "%Y-%m-%d")
+        .map_err(|_| format!("Invalid date '{}', expected YYYY-MM-DD", date))
+}
+
+// Historical rates come from the daily prices of Yahoo FX tickers such as EURUSD=X, as kept by
+// `update_daily_stock_prices`; custom rates (value of one unit in USD) are the fallback
+struct RateTable<'a> {
+    conn: &'a Connection,
+    cache: HashMap<(String, String, String), Option<f64>>,
+}
+
+impl RateTable<'_> {
+    fn daily(&self, ticker: &str, date: &str) -> Result<Option<f64>, String> {
+        self.conn
+            .query_row(
+                "SELECT price FROM daily_stock_prices WHERE ticker = ?1 AND date <= ?2 AND price > 0 ORDER BY date DESC LIMIT 1",
+                params![ticker, date],
+                |row| row.get(0),
+            )
+            .optional()
+            .map_err(|e| e.to_string())
+    }
+
+    fn to_usd(&self, currency: &str, date: &str) -> Result<Option<f64>, String> {
+        if currency == "USD" {
+            return Ok(Some(1.0));
+        }
+        if let Some(rate) = self.daily(&format!("{}USD=X", currency), date)? {
+            return Ok(Some(rate));
+        }
+        self.conn
+            .query_row(
+                "SELECT rate FROM custom_exchange_rates WHERE currency = ?1",
+                params![currency],
+                |row| row.get(0),
+            )
+            .optional()
+            .map_err(|e| e.to_string())
+    }
+
+    fn lookup(&self, from: &str, to: &str, date: &str) -> Result<Option<f64>, String> {
+        if from == to {
+            return Ok(Some(1.0));
+        }
+        if let Some(rate) = self.daily(&format!("{}{}=X", from, to), date)? {
+            return Ok(Some(rate));
+        }
+        if let Some(rate) = self.daily(&format!("{}{}=X", to, from), date)? {
+            return Ok(Some(1.0 / rate));
+        }
+        match (self.to_usd(from, date)?, self.to_usd(to, date)?) {
+            (Some(from), Some(to)) if to > 0.0 => Ok(Some(from / to)),
+            _ => Ok(None),
+        }
+    }
+
+    fn rate(&mut self, from: &str, to: &str, date: &str) -> Result<f64, String> {
+        let key = (from.to_string(), to.to_string(), date.to_string());
+        let rate = match self.cache.get(&key) {
+            Some(rate) => *rate,
+            None => {
+                let rate = self.lookup(from, to, date)?;
+                self.cache.insert(key, rate);
+                rate
+            }
+        };
+        rate.ok_or_else(|| {
+            format!(
+                "No {} to {} exchange rate on or before {}; fetch daily prices for {}{}=X",
+                from, to, date, from, to
+            )
+        })
+    }
+}
+
+fn account_names(conn: &Connection) -> Result<HashMap<i32, String>, String> {
+    let mut stmt = conn
+        .prepare("SELECT id, name FROM accounts")
+        .map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+        .map_err(|e| e.to_string())?;
+    rows.collect::<Result<HashMap<_, _>, _>>()
+        .map_err(|e| e.to_string())
+}
+
+fn realized_gain(
+    disposal: Disposal,
+    acquired: NaiveDate,
+    disposed: NaiveDate,
+    account: String,
+    currency: &str,
+    rates: &mut RateTable,
+) -> Result<RealizedGain, String> {
+    let term = match acquired.checked_add_months(Months::new(LONG_TERM_MONTHS)) {
+        Some(anniversary) if disposed > anniversary => HoldingTerm::Long,
+        _ => HoldingTerm::Short,
+    };
+    let trade_currency = disposal
+        .currency
+        .unwrap_or_else(|| currency.to_string())
+        .to_uppercase();
+    let acquisition_rate = rates.rate(&trade_currency, currency, &disposal.acquired)?;
+    let disposal_rate = rates.rate(&trade_currency, currency, &disposal.disposed)?;
+    let proceeds = disposal.proceeds * disposal_rate;
+    let fees = disposal.fee * disposal_rate;
+    let cost_basis = disposal.cost_basis * acquisition_rate;
+    Ok(RealizedGain {
+        sale_id: disposal.sale_id,
+        lot_id: disposal.lot_id,
+        account_id: disposal.account_id,
+        account,
+        ticker: disposal.ticker,
+        acquired: disposal.acquired,
+        disposed: disposal.disposed,
+        shares: disposal.shares,
+        proceeds,
+        cost_basis,
+        fees,
+        gain: proceeds - fees - cost_basis,
+        term,
+        holding_days: (disposed - acquired).num_days(),
+        trade_currency,
+        acquisition_rate,
+        disposal_rate,
+    })
+}
+
+fn realized_gains(
+    conn: &Connection,
+    query: &RealizedGainsQuery,
+) -> Result<RealizedGainsReport, String> {
+    let currency = query
+        .currency
+        .as_deref()
+        .map(|c| c.trim().to_uppercase())
+        .filter(|c| !c.is_empty())
+        .unwrap_or_else(|| "USD".to_string());
+    let from = query.date_from.as_deref().map(parse_date).transpose()?;
+    let to = query.date_to.as_deref().map(parse_date).transpose()?;
+
+    let names = account_names(conn)?;
+    let mut rates = RateTable {
+        conn,
+        cache: HashMap::new(),
+    };
+    let in_range =
+        |date: NaiveDate| from.is_none_or(|from| date >= from) && to.is_none_or(|to| date <= to);
+    let mut gains = Vec::new();
+    // Lots depend on every earlier trade, so the whole history is replayed before filtering
+    let Replay {
+        disposals,
+        oversales,
+        ..
+    } = replay(conn, query.account_id)?;
+    for disposal in disposals {
+        // A trade dated in another format elsewhere leaves that disposal out, not the whole report
+        let (Ok(acquired), Ok(disposed)) = (
+            parse_date(&disposal.acquired),
+            parse_date(&disposal.disposed),
+        ) else {
+            continue;
+        };
+        if !in_range(disposed) {
+            continue;
+        }
+        let account = names.get(&disposal.account_id).cloned().unwrap_or_default();
+        gains.push(realized_gain(
+            disposal, acquired, disposed, account, &currency, &mut rates,
+        )?);
+    }
+    let unmatched = oversales
+        .into_iter()
+        .filter(|o| parse_date(&o.disposed).is_ok_and(in_range))
+        .map(|o| UnmatchedSale {
+            sale_id: o.sale_id,
+            account_id: o.account_id,
+            account: names.get(&o.account_id).cloned().unwrap_or_default(),
+            ticker: o.ticker,
+            disposed: o.disposed,
+            shares: o.shares,
+        })
+        .collect();
+
+    let total = |term: Option<HoldingTerm>| {
+        gains
+            .iter()
+            .filter(|g| term.is_none_or(|t| g.term == t))
+            .map(|g| g.gain)
+            .sum::<f64>()
+    };
+    Ok(RealizedGainsReport {
+        proceeds: gains.iter().map(|g| g.proceeds).sum(),
+        cost_basis: gains.iter().map(|g| g.cost_basis).sum(),
+        fees: gains.iter().map(|g| g.fees).sum(),
+        short_term_gain: total(Some(HoldingTerm::Short)),
+        long_term_gain: total(Some(HoldingTerm::Long)),
+        total_gain: total(None),
+        currency,
+        gains,
+        unmatched,
+    })
+}
+
+pub fn get_realized_gains_db(
+    db_path: &PathBuf,
+    query: RealizedGainsQuery,
+) -> Result<RealizedGainsReport, String> {
+    let conn = Connection::open(db_path).map_err(|e| e.to_string())?;
+    realized_gains(&conn, &query)
+}
+
+// One row per lot closed, ready for a tax return; returns the number of rows written
+pub fn export_realized_gains_db(
+    db_path: &PathBuf,
+    file_path: &Path,
+    query: RealizedGainsQuery,
+) -> Result<usize, String> {
+    let report = get_realized_gains_db(db_path, query)?;
+    let mut writer = csv::Writer::from_path(file_path).map_err(|e| e.to_string())?;
+    writer
+        .write_record([
+            "Account",
+            "Ticker",
+            "Shares",
+            "Acquired",
+            "Disposed",
+            "Term",
+            "Proceeds",
+            "Cost Basis",
+            "Fees",
+            "Gain",
+            "Currency",
+        ])
+        .map_err(|e| e.to_string())?;
+    for gain in &report.gains {
+        writer
+            .write_record([
+                sanitize_cell(&gain.account).into_owned(),
+                sanitize_cell(&gain.ticker).into_owned(),
+                gain.shares.to_string(),
+                gain.acquired.clone(),
+                gain.disposed.clone(),
+                gain.term.label().to_string(),
+                format!("{:.2}", gain.proceeds),
+                format!("{:.2}", gain.cost_basis),
+                format!("{:.2}", gain.fees),
+                format!("{:.2}", gain.gain),
+                report.currency.clone(),
+            ])
+            .map_err(|e| e.to_string())?;
+    }
+    writer.flush().map_err(|e| e.to_string())?;
+    Ok(report.gains.len())
+}
@@ -0,0 +1,208 @@
+use super::replay;
+use chrono::{Months, NaiveDate};
+use rusqlite::{params, Connection};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+// Categories of cash paid by a holding, shared with the broker importers
+pub const DIVIDENDS: &str = "Dividends";
+pub const INTEREST: &str = "Interest";
+pub const DISTRIBUTIONS: &str = "Distributions";
+// Tax withheld on a payment, when a statement books it on its own line
+pub const TAXES: &str = "Taxes";
+
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum IncomeKind {
+    Dividend,
+    Interest,
+    Distribution,
+}
+
+impl IncomeKind {
+    pub fn category(self) -> &'static str {
+        match self {
+            IncomeKind::Dividend => DIVIDENDS,
+            IncomeKind::Interest => INTEREST,
+            IncomeKind::Distribution => DISTRIBUTIONS,
+        }
+    }
+
+    pub fn label(self) -> &'static str {
+        match self {
+            IncomeKind::Dividend => "Dividend",
+            IncomeKind::Interest => "Interest",
+            IncomeKind::Distribution => "Distribution",
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct IncomeQuery {
+    // Payment dates, both ends included
+    pub date_from: Option<String>,
+    pub date_to: Option<String>,
+    pub account_id: Option<i32>,
+    // End of the trailing twelve months; defaults to today
+    pub as_of: Option<String>,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct HoldingIncome {
+    pub account_id: i32,
+    pub ticker: String,
+    pub currency: Option<String>,
+    // Payments between the query dates
+    pub gross: f64,
+    pub withholding: f64,
+    pub net: f64,
+    pub payments: usize,
+    pub last_paid: Option<String>,
+    // Gross income over the twelve months up to `as_of`
+    pub trailing_12_months: f64,
+    // Of the shares still held
+    pub shares: f64,
+    pub cost_basis: f64,
+    // Trailing income over cost basis, in percent; None once the position is closed
+    pub yield_on_cost: Option<f64>,
+}
+
+// A payment or withholding line tied to a ticker
+struct IncomeRow {
+    account_id: i32,
+    date: NaiveDate,
+    ticker: String,
+    category: String,
+    amount: f64,
+    fee: f64,
+    currency: Option<String>,
+}
+
+fn parse_date(date: &str) -> Result<NaiveDate, String> {
+    NaiveDate::parse_from_str(date.trim(), "%Y-%m-%d")
+        .map_err(|_| format!("Invalid date '{}', expected YYYY-MM-DD", date))
+}
+
+fn load_income(conn: &Connection, account_id: Option<i32>) -> Result<Vec<IncomeRow>, String> {
+    let mut stmt = conn
+        .prepare(
+            "SELECT account_id, date, ticker, category, amount, COALESCE(fee, 0), currency
+             FROM transactions
+             WHERE ticker IS NOT NULL AND TRIM(ticker) <> '' AND (shares IS NULL OR shares = 0)
+               AND category IN (?1, ?2, ?3, ?4) AND (?5 IS NULL OR account_id = ?5)
+             ORDER BY date ASC, id ASC",
+        )
+        .map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map(
+            params![DIVIDENDS, INTEREST, DISTRIBUTIONS, TAXES, account_id],
+            |row| {
+                Ok((
+                    row.get::<_, i32>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, String>(2)?,
+                    row.get::<_, String>(3)?,
+                    row.get::<_, f64>(4)?,
+                    row.get::<_, f64>(5)?,
+                    row.get::<_, Option<String>>(6)?,
+                ))
+            },
+        )
+        .map_err(|e| e.to_string())?;
+    let mut income = Vec::new();
+    for row in rows {
+        let (account_id, date, ticker, category, amount, fee, currency) =
+            row.map_err(|e| e.to_string())?;
+        // A date typed in another format elsewhere leaves that payment out, not the whole report
+        let Ok(date) = parse_date(&date) else {
+            continue;
+        };
+        income.push(IncomeRow {
+            account_id,
+            date,
+            ticker,
+            category,
+            amount,
+            fee,
+            currency,
+        });
+    }
+    Ok(income)
+}
+
+// Income per holding. Payments entered with `create_investment_income` carry their withholding
+// in `fee`, so their gross is amount plus fee; imported statements book withholding as separate
+// Taxes lines on the same ticker instead. Payments without a ticker are left out.
+pub fn get_investment_income_db(
+    db_path: &PathBuf,
+    query: IncomeQuery,
+) -> Result<Vec<HoldingIncome>, String> {
+    let from = query.date_from.as_deref().map(parse_date).transpose()?;
+    let to = query.date_to.as_deref().map(parse_date).transpose()?;
+    let as_of = match query.as_of.as_deref() {
+        Some(date) => parse_date(date)?,
+        None => chrono::Local::now().date_naive(),
+    };
+    let trailing_from = as_of
+        .checked_sub_months(Months::new(12))
+        .ok_or_else(|| format!("Invalid date '{}'", as_of))?;
+
+    let conn = Connection::open(db_path).map_err(|e| e.to_string())?;
+    let positions = replay(&conn, query.account_id)?.positions;
+    let mut holdings: BTreeMap<(i32, String), HoldingIncome> = BTreeMap::new();
+    for row in load_income(&conn, query.account_id)? {
+        let key = (row.account_id, row.ticker.clone());
+        let holding = holdings.entry(key).or_insert_with(|| HoldingIncome {
+            account_id: row.account_id,
+            ticker: row.ticker.clone(),
+            currency: None,
+            gross: 0.0,
+            withholding: 0.0,
+            net: 0.0,
+            payments: 0,
+            last_paid: None,
+            trailing_12_months: 0.0,
+            shares: 0.0,
+            cost_basis: 0.0,
+            yield_on_cost: None,
+        });
+        let (gross, withholding) = match row.category == TAXES {
+            true => (0.0, -row.amount),
+            false => (row.amount + row.fee, row.fee),
+        };
+        if row.date > trailing_from && row.date <= as_of {
+            holding.trailing_12_months += gross;
+        }
+        if from.is_some_and(|from| row.date < from) || to.is_some_and(|to| row.date > to) {
+            continue;
+        }
+        holding.gross += gross;
+        holding.withholding += withholding;
+        holding.net += gross - withholding;
+        if row.currency.is_some() {
+            holding.currency = row.currency;
+        }
+        if row.category != TAXES {
+            holding.payments += 1;
+            holding.last_paid = Some(row.date.format("%Y-%m-%d").to_string());
+        }
+    }
+
+    let mut result = Vec::new();
+    for ((account_id, ticker), mut holding) in holdings {
+        if let Some(position) = positions.get(&(account_id, ticker)) {
+            holding.shares = position.lots.iter().map(|l| l.shares).sum();
+            holding.cost_basis = position.lots.iter().map(|l| l.cost_basis).sum();
+            if holding.currency.is_none() {
+                holding.currency = position.currency.clone();
+            }
+        }
+        holding.yield_on_cost = Some(holding.cost_basis)
+            .filter(|cost| *cost > 0.0)
+            .map(|cost| holding.trailing_12_months / cost * 100.0);
+        result.push(holding);
+    }
+    Ok(result)
+}
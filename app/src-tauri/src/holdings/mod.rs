@@ -0,0 +1,521 @@
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::PathBuf;
+
+pub mod gains;
+pub mod income;
+
+// Share counts below this are rounding left over from closed lots
+const EPSILON: f64 = 1e-9;
+
+// How the sales of an account pick the lots they close
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum CostBasisMethod {
+    // Oldest lots first
+    #[default]
+    Fifo,
+    // Newest lots first
+    Lifo,
+    // Every share held costs the same at the time of a sale; lots still close oldest first
+    Average,
+    // Lots picked with `select_lots`, then the oldest ones for shares left unpicked
+    SpecificLot,
+}
+
+impl CostBasisMethod {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            CostBasisMethod::Fifo => "fifo",
+            CostBasisMethod::Lifo => "lifo",
+            CostBasisMethod::Average => "average",
+            CostBasisMethod::SpecificLot => "specificLot",
+        }
+    }
+
+    pub fn parse(value: &str) -> Self {
+        match value {
+            "lifo" => CostBasisMethod::Lifo,
+            "average" => CostBasisMethod::Average,
+            "specificLot" => CostBasisMethod::SpecificLot,
+            _ => CostBasisMethod::Fifo,
+        }
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct Lot {
+    // The buy that opened the lot
+    pub transaction_id: i32,
+    pub acquired: String,
+    pub original_shares: f64,
+    pub shares: f64,
+    // Of the shares still held, buy fee included
+    pub cost_basis: f64,
+    pub cost_per_share: f64,
+    pub market_value: Option<f64>,
+    pub unrealized_gain: Option<f64>,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct Holding {
+    pub account_id: i32,
+    pub ticker: String,
+    pub currency: Option<String>,
+    pub method: CostBasisMethod,
+    pub shares: f64,
+    pub cost_basis: f64,
+    pub average_cost: f64,
+    // Latest known quote; None when the ticker was never priced
+    pub price: Option<f64>,
+    pub market_value: Option<f64>,
+    pub unrealized_gain: Option<f64>,
+    pub unrealized_gain_percent: Option<f64>,
+    // Shares sold beyond what the account held, which points at buys missing from the ledger
+    pub oversold_shares: f64,
+    // Oldest first
+    pub lots: Vec<Lot>,
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct LotPick {
+    // Id of the buy that opened the lot
+    pub lot_id: i32,
+    pub shares: f64,
+}
+
+// A buy (positive shares) or sell (negative shares)
+struct Trade {
+    id: i32,
+    account_id: i32,
+    date: String,
+    ticker: String,
+    shares: f64,
+    price_per_share: f64,
+    fee: f64,
+    currency: Option<String>,
+}
+
+// Shares of one lot closed by a sale, in the currency of the trades
+struct Disposal {
+    sale_id: i32,
+    lot_id: i32,
+    account_id: i32,
+    ticker: String,
+    currency: Option<String>,
+    acquired: String,
+    disposed: String,
+    shares: f64,
+    proceeds: f64,
+    // The part of the sale fee that falls on these shares
+    fee: f64,
+    cost_basis: f64,
+}
+
+// The open lots of one ticker in one account
+struct Position {
+    method: CostBasisMethod,
+    currency: Option<String>,
+    lots: Vec<Lot>,
+    // Shares sold that no lot could cover
+    oversold: f64,
+}
+
+impl Position {
+    fn buy(&mut self, trade: &Trade) {
+        let cost = trade.shares * trade.price_per_share + trade.fee;
+        self.lots.push(Lot {
+            transaction_id: trade.id,
+            acquired: trade.date.clone(),
+            original_shares: trade.shares,
+            shares: trade.shares,
+            cost_basis: cost,
+            cost_per_share: cost / trade.shares,
+            market_value: None,
+            unrealized_gain: None,
+        });
+    }
+
+    // Closes lots for the shares sold, as (lot index, shares, cost basis); shares sold beyond
+    // what is held are added to `oversold`
+    fn sell(&mut self, trade: &Trade, picks: &[(i32, f64)]) -> Vec<(usize, f64, f64)> {
+        let mut remaining = -trade.shares;
+        let mut closed = Vec::new();
+        if self.method == CostBasisMethod::Average {
+            self.pool();
+        }
+        if self.method == CostBasisMethod::SpecificLot {
+            for (lot_id, shares) in picks {
+                if let Some(index) = self.lots.iter().position(|l| l.transaction_id == *lot_id) {
+                    remaining -= self.take(index, shares.min(remaining), &mut closed);
+                }
+            }
+        }
+        let order: Vec<usize> = match self.method {
+            CostBasisMethod::Lifo => (0..self.lots.len()).rev().collect(),
+            _ => (0..self.lots.len()).collect(),
+        };
+        for index in order {
+            if remaining <= EPSILON {
+                break;
+            }
+            remaining -= self.take(index, remaining, &mut closed);
+        }
+        if remaining > EPSILON {
+            self.oversold += remaining;
+        }
+        closed
+    }
+
+    fn take(&mut self, index: usize, shares: f64, closed: &mut Vec<(usize, f64, f64)>) -> f64 {
+        let lot = &mut self.lots[index];
+        let taken = shares.min(lot.shares).max(0.0);
+        if taken > EPSILON {
+            lot.shares -= taken;
+            lot.cost_basis = lot.shares * lot.cost_per_share;
+            closed.push((index, taken, taken * lot.cost_per_share));
+        }
+        taken
+    }
+
+    // Spreads the cost of the position evenly over its shares
+    fn pool(&mut self) {
+        let shares: f64 = self.lots.iter().map(|l| l.shares).sum();
+        if shares <= EPSILON {
+            return;
+        }
+        let average = self.lots.iter().map(|l| l.cost_basis).sum::<f64>() / shares;
+        for lot in &mut self.lots {
+            lot.cost_per_share = average;
+            lot.cost_basis = lot.shares * average;
+        }
+    }
+}
+
+fn load_trades(conn: &Connection, account_id: Option<i32>) -> Result<Vec<Trade>, String> {
+    let mut stmt = conn
+        .prepare(
+            "SELECT id, account_id, date, ticker, shares, COALESCE(price_per_share, 0), COALESCE(fee, 0), currency
+             FROM transactions
+             WHERE ticker IS NOT NULL AND TRIM(ticker) <> '' AND shares IS NOT NULL AND shares <> 0
+               AND (?1 IS NULL OR account_id = ?1)
+             ORDER BY date ASC, id ASC",
+        )
+        .map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map(params![account_id], |row| {
+            Ok(Trade {
+                id: row.get(0)?,
+                account_id: row.get(1)?,
+                date: row.get(2)?,
+                ticker: row.get(3)?,
+                shares: row.get(4)?,
+                price_per_share: row.get(5)?,
+                fee: row.get(6)?,
+                currency: row.get(7)?,
+            })
+        })
+        .map_err(|e| e.to_string())?;
+    rows.collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())
+}
+
+fn load_accounts(
+    conn: &Connection,
+) -> Result<HashMap<i32, (CostBasisMethod, Option<String>)>, String> {
+    let mut stmt = conn
+        .prepare("SELECT id, cost_basis_method, currency FROM accounts")
+        .map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map([], |row| {
+            Ok((
+                row.get::<_, i32>(0)?,
+                (
+                    CostBasisMethod::parse(&row.get::<_, String>(1)?),
+                    row.get(2)?,
+                ),
+            ))
+        })
+        .map_err(|e| e.to_string())?;
+    rows.collect::<Result<HashMap<_, _>, _>>()
+        .map_err(|e| e.to_string())
+}
+
+// Lots picked for each sale, as (lot id, shares)
+fn load_picks(conn: &Connection) -> Result<HashMap<i32, Vec<(i32, f64)>>, String> {
+    let mut stmt = conn
+        .prepare("SELECT sale_id, lot_id, shares FROM lot_selections ORDER BY sale_id, lot_id")
+        .map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map([], |row| {
+            Ok((
+                row.get::<_, i32>(0)?,
+                row.get::<_, i32>(1)?,
+                row.get::<_, f64>(2)?,
+            ))
+        })
+        .map_err(|e| e.to_string())?;
+    let mut picks: HashMap<i32, Vec<(i32, f64)>> = HashMap::new();
+    for row in rows {
+        let (sale_id, lot_id, shares) = row.map_err(|e| e.to_string())?;
+        picks.entry(sale_id).or_default().push((lot_id, shares));
+    }
+    Ok(picks)
+}
+
+// Shares of a sale that no lot covered
+struct Oversale {
+    sale_id: i32,
+    account_id: i32,
+    ticker: String,
+    disposed: String,
+    shares: f64,
+}
+
+// Every trade replayed in date order: the positions left open, keyed by account and ticker,
+// what each sale closed and what no lot covered
+struct Replay {
+    positions: BTreeMap<(i32, String), Position>,
+    disposals: Vec<Disposal>,
+    oversales: Vec<Oversale>,
+}
+
+fn replay(conn: &Connection, account_id: Option<i32>) -> Result<Replay, String> {
+    let accounts = load_accounts(conn)?;
+    let picks = load_picks(conn)?;
+    let mut positions: BTreeMap<(i32, String), Position> = BTreeMap::new();
+    let mut disposals = Vec::new();
+    let mut oversales = Vec::new();
+    for trade in load_trades(conn, account_id)? {
+        let (method, account_currency) =
+            accounts.get(&trade.account_id).cloned().unwrap_or_default();
+        let currency = trade.currency.clone().or(account_currency);
+        let position = positions
+            .entry((trade.account_id, trade.ticker.clone()))
+            .or_insert_with(|| Position {
+                method,
+                currency: None,
+                lots: Vec::new(),
+                oversold: 0.0,
+            });
+        position.currency = currency.clone();
+        if trade.shares > 0.0 {
+            position.buy(&trade);
+            continue;
+        }
+        let sold = -trade.shares;
+        let oversold = position.oversold;
+        let closed = position.sell(
+            &trade,
+            picks.get(&trade.id).map(Vec::as_slice).unwrap_or_default(),
+        );
+        for (index, shares, cost_basis) in closed {
+            let lot = &position.lots[index];
+            disposals.push(Disposal {
+                sale_id: trade.id,
+                lot_id: lot.transaction_id,
+                account_id: trade.account_id,
+                ticker: trade.ticker.clone(),
+                currency: currency.clone(),
+                acquired: lot.acquired.clone(),
+                disposed: trade.date.clone(),
+                shares,
+                proceeds: shares * trade.price_per_share,
+                fee: trade.fee * shares / sold,
+                cost_basis,
+            });
+        }
+        if position.oversold - oversold > EPSILON {
+            oversales.push(Oversale {
+                sale_id: trade.id,
+                account_id: trade.account_id,
+                ticker: trade.ticker.clone(),
+                disposed: trade.date.clone(),
+                shares: position.oversold - oversold,
+            });
+        }
+        position.lots.retain(|lot| lot.shares > EPSILON);
+    }
+    Ok(Replay {
+        positions,
+        disposals,
+        oversales,
+    })
+}
+
+// The cached quote, or the last daily close when there is none
+fn latest_price(conn: &Connection, ticker: &str) -> Result<Option<f64>, String> {
+    let quote: Option<f64> = conn
+        .query_row(
+            "SELECT price FROM stock_prices WHERE ticker = ?1 COLLATE NOCASE",
+            params![ticker],
+            |row| row.get(0),
+        )
+        .optional()
+        .map_err(|e| e.to_string())?;
+    if quote.is_some() {
+        return Ok(quote);
+    }
+    conn.query_row(
+        "SELECT price FROM daily_stock_prices WHERE ticker = ?1 ORDER BY date DESC LIMIT 1",
+        params![ticker],
+        |row| row.get(0),
+    )
+    .optional()
+    .map_err(|e| e.to_string())
+}
+
+// Open positions with their lots, valued at the latest known price. Each account keeps its own
+// lots and cost basis method; `account_id` limits the result to one account.
+pub fn get_holdings_db(db_path: &PathBuf, account_id: Option<i32>) -> Result<Vec<Holding>, String> {
+    let conn = Connection::open(db_path).map_err(|e| e.to_string())?;
+    let mut holdings = Vec::new();
+    for ((account_id, ticker), position) in replay(&conn, account_id)?.positions {
+        if position.lots.is_empty() && position.oversold <= EPSILON {
+            continue;
+        }
+        let price = latest_price(&conn, &ticker)?;
+        let mut lots = position.lots;
+        for lot in &mut lots {
+            lot.market_value = price.map(|p| p * lot.shares);
+            lot.unrealized_gain = lot.market_value.map(|v| v - lot.cost_basis);
+        }
+        let shares: f64 = lots.iter().map(|l| l.shares).sum();
+        let cost_basis: f64 = lots.iter().map(|l| l.cost_basis).sum();
+        let market_value = price.map(|p| p * shares);
+        let unrealized_gain = market_value.map(|v| v - cost_basis);
+        holdings.push(Holding {
+            account_id,
+            ticker,
+            currency: position.currency,
+            method: position.method,
+            shares,
+            cost_basis,
+            average_cost: if shares > EPSILON {
+                cost_basis / shares
+            } else {
+                0.0
+            },
+            price,
+            market_value,
+            unrealized_gain,
+            unrealized_gain_percent: unrealized_gain
+                .filter(|_| cost_basis > 0.0)
+                .map(|g| g / cost_basis * 100.0),
+            oversold_shares: position.oversold,
+            lots,
+        });
+    }
+    Ok(holdings)
+}
+
+pub fn get_cost_basis_method_db(
+    db_path: &PathBuf,
+    account_id: i32,
+) -> Result<CostBasisMethod, String> {
+    let conn = Connection::open(db_path).map_err(|e| e.to_string())?;
+    conn.query_row(
+        "SELECT cost_basis_method FROM accounts WHERE id = ?1",
+        params![account_id],
+        |row| row.get::<_, String>(0),
+    )
+    .optional()
+    .map_err(|e| e.to_string())?
+    .map(|method| CostBasisMethod::parse(&method))
+    .ok_or_else(|| format!("Account {} not found", account_id))
+}
+
+// Takes effect on every sale of the account, past ones included, as lots are rebuilt each time
+pub fn set_cost_basis_method_db(
+    db_path: &PathBuf,
+    account_id: i32,
+    method: CostBasisMethod,
+) -> Result<(), String> {
+    let conn = Connection::open(db_path).map_err(|e| e.to_string())?;
+    let updated = conn
+        .execute(
+            "UPDATE accounts SET cost_basis_method = ?1 WHERE id = ?2",
+            params![method.as_str(), account_id],
+        )
+        .map_err(|e| e.to_string())?;
+    if updated == 0 {
+        return Err(format!("Account {} not found", account_id));
+    }
+    Ok(())
+}
+
+// Account, date, ticker and shares of a transaction
+fn load_trade(
+    conn: &Connection,
+    id: i32,
+) -> Result<(i32, String, Option<String>, Option<f64>), String> {
+    conn.query_row(
+        "SELECT account_id, date, ticker, shares FROM transactions WHERE id = ?1",
+        params![id],
+        |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)),
+    )
+    .optional()
+    .map_err(|e| e.to_string())?
+    .ok_or_else(|| format!("Transaction {} not found", id))
+}
+
+// Picks the lots a sale closes, replacing any earlier picks; an empty list clears them. Only
+// used by accounts on specific-lot identification. Shares a lot no longer has when the sale
+// happens, because earlier sales closed them, are taken from the oldest lots instead.
+pub fn select_lots_db(db_path: &PathBuf, sale_id: i32, picks: Vec<LotPick>) -> Result<(), String> {
+    let mut conn = Connection::open(db_path).map_err(|e| e.to_string())?;
+    let (account_id, date, ticker, shares) = load_trade(&conn, sale_id)?;
+    let (Some(ticker), Some(sold)) = (ticker, shares.filter(|s| *s < 0.0).map(|s| -s)) else {
+        return Err(format!("Transaction {} is not a sale", sale_id));
+    };
+
+    let mut seen = HashSet::new();
+    for pick in &picks {
+        if !seen.insert(pick.lot_id) {
+            return Err(format!("Lot {} is picked twice", pick.lot_id));
+        }
+        if pick.shares <= 0.0 {
+            return Err("Picked shares must be positive".to_string());
+        }
+        let (lot_account, lot_date, lot_ticker, lot_shares) = load_trade(&conn, pick.lot_id)?;
+        let bought = lot_shares.filter(|s| *s > 0.0).unwrap_or_default();
+        if lot_account != account_id
+            || lot_ticker.as_deref() != Some(ticker.as_str())
+            || lot_date > date
+            || bought <= 0.0
+        {
+            return Err(format!(
+                "Transaction {} is not a lot this sale can close",
+                pick.lot_id
+            ));
+        }
+        if pick.shares > bought + EPSILON {
+            return Err(format!("Lot {} only has {} shares", pick.lot_id, bought));
+        }
+    }
+    let picked: f64 = picks.iter().map(|p| p.shares).sum();
+    if picked > sold + EPSILON {
+        return Err(format!(
+            "Picked {} shares but the sale is {} shares",
+            picked, sold
+        ));
+    }
+
+    let tx = conn.transaction().map_err(|e| e.to_string())?;
+    tx.execute(
+        "DELETE FROM lot_selections WHERE sale_id = ?1",
+        params![sale_id],
+    )
+    .map_err(|e| e.to_string())?;
+    for pick in &picks {
+        tx.execute(
+            "INSERT INTO lot_selections (sale_id, lot_id, shares) VALUES (?1, ?2, ?3)",
+            params![sale_id, pick.lot_id, pick.shares],
+        )
+        .map_err(|e| e.to_string())?;
+    }
+    tx.commit().map_err(|e| e.to_string())?;
+    Ok(())
+}
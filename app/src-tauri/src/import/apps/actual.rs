@@ -0,0 +1,169 @@
+use super::{category_path, transfer_row, Migration, SourceDatabase};
+use crate::import::ImportRow;
+use std::collections::{HashMap, HashSet};
+
+struct ActualTransaction {
+    id: String,
+    account: Option<String>,
+    date: Option<i64>,
+    amount: i64,
+    payee: Option<String>,
+    notes: Option<String>,
+    category: Option<String>,
+    is_parent: bool,
+    is_child: bool,
+    parent_id: Option<String>,
+    transferred_id: Option<String>,
+    starting_balance: bool,
+}
+
+// Merged payees and categories leave their old ids behind, pointing at the survivor
+fn mapping(
+    db: &SourceDatabase,
+    table: &str,
+    target: &str,
+) -> Result<HashMap<String, String>, String> {
+    if !db.has_table(table)? {
+        return Ok(HashMap::new());
+    }
+    db.query(&format!("SELECT id, {} FROM {}", target, table), |r| {
+        Ok((r.get(0)?, r.get(1)?))
+    })
+    .map(|pairs| pairs.into_iter().collect())
+}
+
+// Actual stores dates as integers like 20240115
+fn actual_date(value: i64) -> String {
+    format!(
+        "{:04}-{:02}-{:02}",
+        value / 10_000,
+        value / 100 % 100,
+        value % 100
+    )
+}
+
+// Reads the `db.sqlite` of an Actual Budget export. Split parents are replaced by their lines,
+// and transfers, which Actual stores on both accounts, are kept once.
+pub fn parse_actual(db: &SourceDatabase) -> Result<Migration, String> {
+    db.require_tables(
+        "Actual Budget",
+        &["accounts", "transactions", "payees", "categories"],
+    )?;
+
+    let account_list: Vec<(String, String)> = db.query(
+        "SELECT id, name FROM accounts WHERE tombstone = 0 ORDER BY sort_order, id",
+        |r| Ok((r.get(0)?, r.get(1)?)),
+    )?;
+    let accounts: HashMap<&String, &String> = account_list.iter().map(|(id, n)| (id, n)).collect();
+    let categories: HashMap<String, String> = db
+        .query(
+            "SELECT c.id, g.name, c.name FROM categories c LEFT JOIN category_groups g ON g.id = c.cat_group",
+            |r| {
+                let group: Option<String> = r.get(1)?;
+                let name: Option<String> = r.get(2)?;
+                Ok((r.get(0)?, category_path(group.as_deref(), name.as_deref())))
+            },
+        )?
+        .into_iter()
+        .filter_map(|(id, path)| path.map(|p| (id, p)))
+        .collect();
+    let payees: HashMap<String, (Option<String>, Option<String>)> = db
+        .query("SELECT id, name, transfer_acct FROM payees", |r| {
+            Ok((r.get(0)?, (r.get(1)?, r.get(2)?)))
+        })?
+        .into_iter()
+        .collect();
+    let category_mapping = mapping(db, "category_mapping", "transferId")?;
+    let payee_mapping = mapping(db, "payee_mapping", "targetId")?;
+
+    let split_parents: HashSet<String> = db
+        .query(
+            "SELECT DISTINCT parent_id FROM transactions WHERE isChild = 1 AND tombstone = 0 AND parent_id IS NOT NULL",
+            |r| r.get(0),
+        )?
+        .into_iter()
+        .collect();
+    let transactions = db.query(
+        "SELECT id, acct, date, amount, description, notes, category, isParent, isChild, parent_id, transferred_id, starting_balance_flag FROM transactions WHERE tombstone = 0 ORDER BY date, id",
+        |r| {
+            Ok(ActualTransaction {
+                id: r.get(0)?,
+                account: r.get(1)?,
+                date: r.get(2)?,
+                amount: r.get::<_, Option<i64>>(3)?.unwrap_or(0),
+                payee: r.get(4)?,
+                notes: r.get(5)?,
+                category: r.get(6)?,
+                is_parent: r.get::<_, Option<i64>>(7)?.unwrap_or(0) == 1,
+                is_child: r.get::<_, Option<i64>>(8)?.unwrap_or(0) == 1,
+                parent_id: r.get(9)?,
+                transferred_id: r.get(10)?,
+                starting_balance: r.get::<_, Option<i64>>(11)?.unwrap_or(0) == 1,
+            })
+        },
+    )?;
+
+    let mut migration = Migration::default();
+    for (_, name) in &account_list {
+        migration.declare_account(name, None);
+    }
+    let mut emitted: HashSet<String> = HashSet::new();
+    for tx in transactions {
+        let row = migration.next_row();
+        if tx.is_parent && split_parents.contains(&tx.id) {
+            continue;
+        }
+        let Some(account) = tx.account.as_ref().and_then(|id| accounts.get(id)) else {
+            migration.skip(row, "Skipped transaction of a deleted account");
+            continue;
+        };
+        let Some(date) = tx.date else {
+            migration.skip(row, "Skipped transaction without a date");
+            continue;
+        };
+
+        let payee_id = tx
+            .payee
+            .as_ref()
+            .map(|id| payee_mapping.get(id).unwrap_or(id));
+        let (payee_name, transfer_account) = payee_id
+            .and_then(|id| payees.get(id))
+            .map(|(name, target)| (name.clone(), target.as_ref().and_then(|t| accounts.get(t))))
+            .unwrap_or_default();
+        let category = tx
+            .category
+            .as_ref()
+            .map(|id| category_mapping.get(id).unwrap_or(id))
+            .and_then(|id| categories.get(id))
+            .cloned();
+
+        let base = ImportRow {
+            row,
+            account_name: Some(account.to_string()),
+            date: actual_date(date),
+            payee: payee_name.unwrap_or_default(),
+            notes: tx.notes.filter(|n| !n.trim().is_empty()),
+            category: if tx.starting_balance { None } else { category },
+            amount: tx.amount as f64 / 100.0,
+            external_id: Some(tx.id.clone()),
+            ..Default::default()
+        };
+        let mut line = match transfer_account {
+            Some(other) => {
+                // The other side was already emitted and will be linked to this one
+                if tx.transferred_id.is_some_and(|id| emitted.contains(&id)) {
+                    continue;
+                }
+                emitted.insert(tx.id);
+                transfer_row(base, other)
+            }
+            None => base,
+        };
+        if tx.is_child {
+            let parent = tx.parent_id.unwrap_or_default();
+            migration.split_line(&mut line, &parent);
+        }
+        migration.push(line);
+    }
+    Ok(migration)
+}
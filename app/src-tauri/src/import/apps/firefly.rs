@@ -0,0 +1,169 @@
+use super::{transfer_row, Migration, OPENING_BALANCE};
+use crate::import::broker::{cell, non_empty, number, optional_number, read_csv, Columns};
+use crate::import::{parse_date, ImportRow};
+use csv::StringRecord;
+use std::collections::HashMap;
+
+// Firefly account types that are ledgers of their own; everything else is a counterparty
+const OWN_ACCOUNT_TYPES: [&str; 5] = [
+    "Asset account",
+    "Default account",
+    "Loan",
+    "Debt",
+    "Mortgage",
+];
+
+struct FireflyColumns {
+    journal: Option<usize>,
+    group: Option<usize>,
+    group_title: Option<usize>,
+    amount: usize,
+    foreign_amount: Option<usize>,
+    currency: Option<usize>,
+    foreign_currency: Option<usize>,
+    description: Option<usize>,
+    date: usize,
+    source: usize,
+    source_type: usize,
+    destination: usize,
+    destination_type: usize,
+    category: Option<usize>,
+    notes: Option<usize>,
+}
+
+impl FireflyColumns {
+    fn new(columns: &Columns) -> Result<Self, String> {
+        Ok(FireflyColumns {
+            journal: columns.find(&["journal_id"]),
+            group: columns.find(&["group_id"]),
+            group_title: columns.find(&["group_title"]),
+            amount: columns.require(&["amount"])?,
+            foreign_amount: columns.find(&["foreign_amount"]),
+            currency: columns.find(&["currency_code"]),
+            foreign_currency: columns.find(&["foreign_currency_code"]),
+            description: columns.find(&["description"]),
+            date: columns.require(&["date"])?,
+            source: columns.require(&["source_name"])?,
+            source_type: columns.require(&["source_type"])?,
+            destination: columns.require(&["destination_name"])?,
+            destination_type: columns.require(&["destination_type"])?,
+            category: columns.find(&["category"]),
+            notes: columns.find(&["notes"]),
+        })
+    }
+
+    fn row(
+        &self,
+        record: &StringRecord,
+        row: usize,
+        splits: usize,
+        date_format: Option<&str>,
+    ) -> Result<ImportRow, String> {
+        let get = |index: usize| cell(record, Some(index));
+        // The export's sign convention changed between versions; the direction comes from the
+        // account types instead
+        let amount = number(get(self.amount))?.abs();
+        let currency = non_empty(cell(record, self.currency));
+        let source = get(self.source);
+        let destination = get(self.destination);
+        let source_own = OWN_ACCOUNT_TYPES.contains(&get(self.source_type));
+        let destination_own = OWN_ACCOUNT_TYPES.contains(&get(self.destination_type));
+
+        let (account, counterparty, counterparty_type, signed) = match (source_own, destination_own)
+        {
+            (true, _) => (source, destination, get(self.destination_type), -amount),
+            (false, true) => (destination, source, get(self.source_type), amount),
+            (false, false) => {
+                return Err(format!(
+                    "Skipped transaction between '{}' and '{}': neither is an asset account",
+                    source, destination
+                ))
+            }
+        };
+
+        let description = non_empty(cell(record, self.description));
+        let payee = match counterparty_type {
+            "Initial balance account" => OPENING_BALANCE,
+            "Reconciliation account" => "Reconciliation",
+            _ => non_empty(counterparty)
+                .filter(|name| *name != "(no name)")
+                .or(description)
+                .unwrap_or_default(),
+        };
+        // Split journals share a group; its title says what the whole transaction was
+        let title = non_empty(cell(record, self.group_title)).filter(|_| splits > 1);
+        let notes: Vec<&str> = [title, description.filter(|d| *d != payee)]
+            .into_iter()
+            .chain([non_empty(cell(record, self.notes))])
+            .flatten()
+            .collect();
+
+        let base = ImportRow {
+            row,
+            account_name: Some(account.to_string()),
+            date: parse_date(get(self.date), date_format)?,
+            payee: payee.to_string(),
+            notes: (!notes.is_empty()).then(|| notes.join(" - ")),
+            category: non_empty(cell(record, self.category)).map(str::to_string),
+            amount: signed,
+            currency: currency.map(str::to_string),
+            external_id: non_empty(cell(record, self.journal)).map(str::to_string),
+            ..Default::default()
+        };
+        if !(source_own && destination_own) {
+            return Ok(base);
+        }
+
+        let mut row = transfer_row(base, destination);
+        let foreign_currency = non_empty(cell(record, self.foreign_currency));
+        if foreign_currency.is_some() && foreign_currency != currency {
+            row.transfer_amount = optional_number(cell(record, self.foreign_amount))?.map(f64::abs);
+        }
+        Ok(row)
+    }
+}
+
+// Parses the transaction CSV from Firefly III's data export. Every journal is one line; the lines
+// of a split transaction share a `group_id`.
+pub fn parse_firefly(content: &str, date_format: Option<&str>) -> Result<Migration, String> {
+    let (columns, records) = read_csv(content)?;
+    let columns = FireflyColumns::new(&columns)?;
+    let mut group_sizes: HashMap<&str, usize> = HashMap::new();
+    for record in &records {
+        *group_sizes.entry(cell(record, columns.group)).or_insert(0) += 1;
+    }
+
+    let mut migration = Migration::default();
+    for record in &records {
+        let row = migration.next_row();
+        let group = cell(record, columns.group);
+        let splits = if group.is_empty() {
+            1
+        } else {
+            group_sizes[group]
+        };
+        match columns.row(record, row, splits, date_format) {
+            Ok(mut parsed) => {
+                if splits > 1 {
+                    migration.split_line(&mut parsed, group);
+                }
+                let own = |kind| OWN_ACCOUNT_TYPES.contains(&cell(record, Some(kind)));
+                let currency = parsed.currency.as_deref();
+                if own(columns.source_type) {
+                    migration.declare_account(cell(record, Some(columns.source)), currency);
+                }
+                if own(columns.destination_type) {
+                    // The receiving side of a transfer between currencies holds the foreign one
+                    let currency = match own(columns.source_type) {
+                        true => non_empty(cell(record, columns.foreign_currency)).or(currency),
+                        false => currency,
+                    };
+                    migration.declare_account(cell(record, Some(columns.destination)), currency);
+                }
+                migration.push(parsed);
+            }
+            Err(message) => migration.skip(row, message),
+        }
+    }
+    Ok(migration)
+}
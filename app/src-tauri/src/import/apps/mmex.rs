@@ -0,0 +1,328 @@
+use super::{transfer_row, Migration, SourceDatabase, OPENING_BALANCE};
+use crate::import::{parse_date, ImportRow};
+use std::collections::HashMap;
+
+// MMEX 1.6 nests categories through `PARENTID`; older databases have a separate subcategory
+// table and a `SUBCATEGID` next to every `CATEGID`
+struct Categories {
+    names: HashMap<i64, (String, i64)>,
+    subcategories: HashMap<i64, (String, i64)>,
+}
+
+impl Categories {
+    fn load(db: &SourceDatabase) -> Result<Self, String> {
+        let parent = match db.has_column("CATEGORY_V1", "PARENTID")? {
+            true => "PARENTID",
+            false => "-1",
+        };
+        let names = db
+            .query(
+                &format!("SELECT CATEGID, CATEGNAME, {} FROM CATEGORY_V1", parent),
+                |r| Ok((r.get(0)?, (r.get(1)?, r.get(2)?))),
+            )?
+            .into_iter()
+            .collect();
+        let subcategories = match db.has_table("SUBCATEGORY_V1")? {
+            true => db
+                .query(
+                    "SELECT SUBCATEGID, SUBCATEGNAME, CATEGID FROM SUBCATEGORY_V1",
+                    |r| Ok((r.get(0)?, (r.get(1)?, r.get(2)?))),
+                )?
+                .into_iter()
+                .collect(),
+            false => HashMap::new(),
+        };
+        Ok(Categories {
+            names,
+            subcategories,
+        })
+    }
+
+    fn path(&self, category: Option<i64>, subcategory: Option<i64>) -> Option<String> {
+        let (leaf, mut next) = match subcategory.and_then(|id| self.subcategories.get(&id)) {
+            Some((name, parent)) => (Some(name.as_str()), *parent),
+            None => (None, category?),
+        };
+        let mut parts: Vec<&str> = leaf.into_iter().collect();
+        // Bounded, in case a damaged database has a cycle in its parents
+        for _ in 0..16 {
+            let Some((name, parent)) = self.names.get(&next) else {
+                break;
+            };
+            parts.push(name);
+            next = *parent;
+        }
+        if parts.is_empty() {
+            return None;
+        }
+        parts.reverse();
+        Some(parts.join(":"))
+    }
+}
+
+// Split id, category, subcategory, amount and notes of one line of a split transaction
+type SplitLine = (i64, Option<i64>, Option<i64>, f64, Option<String>);
+
+struct MmexAccount {
+    name: String,
+    currency: Option<String>,
+    initial_balance: f64,
+    initial_date: Option<String>,
+}
+
+struct MmexTransaction {
+    id: i64,
+    account: i64,
+    to_account: Option<i64>,
+    payee: Option<String>,
+    code: String,
+    amount: f64,
+    to_amount: Option<f64>,
+    status: Option<String>,
+    notes: Option<String>,
+    category: Option<i64>,
+    subcategory: Option<i64>,
+    date: String,
+    deleted: Option<String>,
+}
+
+// The column when this schema version has it, NULL otherwise
+fn optional_column(db: &SourceDatabase, table: &str, column: &str) -> Result<String, String> {
+    Ok(match db.has_column(table, column)? {
+        true => format!("t.{}", column),
+        false => "NULL".to_string(),
+    })
+}
+
+// Reads a Money Manager Ex database (`.mmb`). Encrypted `.emb` files have to be saved
+// unencrypted from MMEX first.
+pub fn parse_mmex(db: &SourceDatabase) -> Result<Migration, String> {
+    db.require_tables(
+        "Money Manager Ex",
+        &[
+            "ACCOUNTLIST_V1",
+            "CHECKINGACCOUNT_V1",
+            "CATEGORY_V1",
+            "PAYEE_V1",
+        ],
+    )?;
+    let categories = Categories::load(db)?;
+
+    let initial_date = match db.has_column("ACCOUNTLIST_V1", "INITIALDATE")? {
+        true => "a.INITIALDATE",
+        false => "NULL",
+    };
+    let accounts: HashMap<i64, MmexAccount> = db
+        .query(
+            &format!(
+                "SELECT a.ACCOUNTID, a.ACCOUNTNAME, c.CURRENCY_SYMBOL, a.INITIALBAL, {} FROM ACCOUNTLIST_V1 a LEFT JOIN CURRENCYFORMATS_V1 c ON c.CURRENCYID = a.CURRENCYID ORDER BY a.ACCOUNTID",
+                initial_date
+            ),
+            |r| {
+                Ok((
+                    r.get(0)?,
+                    MmexAccount {
+                        name: r.get(1)?,
+                        currency: r.get(2)?,
+                        initial_balance: r.get::<_, Option<f64>>(3)?.unwrap_or(0.0),
+                        initial_date: r.get(4)?,
+                    },
+                ))
+            },
+        )?
+        .into_iter()
+        .collect();
+
+    let table = "CHECKINGACCOUNT_V1";
+    let transactions = db.query(
+        &format!(
+            "SELECT t.TRANSID, t.ACCOUNTID, t.TOACCOUNTID, p.PAYEENAME, t.TRANSCODE, t.TRANSAMOUNT, t.TOTRANSAMOUNT, t.STATUS, t.NOTES, t.CATEGID, {}, t.TRANSDATE, {} FROM {} t LEFT JOIN PAYEE_V1 p ON p.PAYEEID = t.PAYEEID ORDER BY t.TRANSDATE, t.TRANSID",
+            optional_column(db, table, "SUBCATEGID")?,
+            optional_column(db, table, "DELETEDTIME")?,
+            table
+        ),
+        |r| {
+            Ok(MmexTransaction {
+                id: r.get(0)?,
+                account: r.get(1)?,
+                to_account: r.get(2)?,
+                payee: r.get(3)?,
+                code: r.get(4)?,
+                amount: r.get::<_, Option<f64>>(5)?.unwrap_or(0.0),
+                to_amount: r.get(6)?,
+                status: r.get(7)?,
+                notes: r.get(8)?,
+                category: r.get(9)?,
+                subcategory: r.get(10)?,
+                date: r.get::<_, Option<String>>(11)?.unwrap_or_default(),
+                deleted: r.get(12)?,
+            })
+        },
+    )?;
+
+    let mut splits: HashMap<i64, Vec<SplitLine>> = HashMap::new();
+    if db.has_table("SPLITTRANSACTIONS_V1")? {
+        let split_table = "SPLITTRANSACTIONS_V1";
+        let lines = db.query(
+            &format!(
+                "SELECT t.TRANSID, t.SPLITTRANSID, t.CATEGID, {}, t.SPLITTRANSAMOUNT, {} FROM {} t ORDER BY t.SPLITTRANSID",
+                optional_column(db, split_table, "SUBCATEGID")?,
+                optional_column(db, split_table, "NOTES")?,
+                split_table
+            ),
+            |r| {
+                Ok((
+                    r.get::<_, i64>(0)?,
+                    (r.get(1)?, r.get(2)?, r.get(3)?, r.get(4)?, r.get(5)?),
+                ))
+            },
+        )?;
+        for (transaction, line) in lines {
+            splits.entry(transaction).or_default().push(line);
+        }
+    }
+
+    let mut migration = Migration::default();
+    let mut first_dates: HashMap<i64, &str> = HashMap::new();
+    for tx in &transactions {
+        first_dates.entry(tx.account).or_insert(tx.date.as_str());
+    }
+    let mut account_ids: Vec<&i64> = accounts.keys().collect();
+    account_ids.sort();
+    for id in account_ids {
+        let account = &accounts[id];
+        migration.declare_account(&account.name, account.currency.as_deref());
+        if account.initial_balance == 0.0 {
+            continue;
+        }
+        let row = migration.next_row();
+        let date = account
+            .initial_date
+            .as_deref()
+            .or(first_dates.get(id).copied())
+            .map(|d| parse_date(d, None))
+            .unwrap_or_else(|| Ok(chrono::Local::now().format("%Y-%m-%d").to_string()));
+        match date {
+            Ok(date) => migration.push(ImportRow {
+                row,
+                account_name: Some(account.name.clone()),
+                date,
+                payee: OPENING_BALANCE.to_string(),
+                amount: account.initial_balance,
+                currency: account.currency.clone(),
+                external_id: Some(format!("initial:{}", id)),
+                ..Default::default()
+            }),
+            Err(message) => migration.skip(row, message),
+        }
+    }
+
+    for tx in transactions {
+        let row = migration.next_row();
+        if tx.deleted.as_deref().is_some_and(|d| !d.trim().is_empty()) {
+            migration.skip(row, "Skipped deleted transaction");
+            continue;
+        }
+        if tx.status.as_deref() == Some("V") {
+            migration.skip(row, "Skipped void transaction");
+            continue;
+        }
+        let Some(account) = accounts.get(&tx.account) else {
+            migration.skip(
+                row,
+                format!("Skipped transaction of unknown account {}", tx.account),
+            );
+            continue;
+        };
+        let date = match parse_date(&tx.date, None) {
+            Ok(date) => date,
+            Err(message) => {
+                migration.skip(row, message);
+                continue;
+            }
+        };
+
+        let base = ImportRow {
+            row,
+            account_name: Some(account.name.clone()),
+            date,
+            payee: tx.payee.clone().unwrap_or_default(),
+            notes: tx.notes.clone().filter(|n| !n.trim().is_empty()),
+            currency: account.currency.clone(),
+            external_id: Some(tx.id.to_string()),
+            ..Default::default()
+        };
+        match tx.code.as_str() {
+            "Transfer" => {
+                let Some(other) = tx.to_account.and_then(|id| accounts.get(&id)) else {
+                    migration.skip(row, "Skipped transfer to an unknown account");
+                    continue;
+                };
+                let mut line = transfer_row(
+                    ImportRow {
+                        amount: -tx.amount,
+                        ..base
+                    },
+                    &other.name,
+                );
+                // Between currencies the receiving side records its own amount
+                line.transfer_amount = tx
+                    .to_amount
+                    .filter(|to| *to != 0.0 && (to - tx.amount).abs() > f64::EPSILON);
+                migration.push(line);
+            }
+            "Withdrawal" | "Deposit" => {
+                let sign = if tx.code == "Withdrawal" { -1.0 } else { 1.0 };
+                match splits.get(&tx.id) {
+                    Some(lines) if !lines.is_empty() => {
+                        for (split_id, category, subcategory, amount, notes) in lines {
+                            let mut line = ImportRow {
+                                category: categories.path(*category, *subcategory),
+                                amount: sign * amount,
+                                notes: notes.clone().or(base.notes.clone()),
+                                external_id: Some(format!("{}:{}", tx.id, split_id)),
+                                ..base.clone()
+                            };
+                            migration.split_line(&mut line, &tx.id.to_string());
+                            migration.push(line);
+                        }
+                    }
+                    _ => migration.push(ImportRow {
+                        category: categories.path(tx.category, tx.subcategory),
+                        amount: sign * tx.amount,
+                        ..base
+                    }),
+                }
+            }
+            other => migration.skip(
+                row,
+                format!("Skipped transaction of unknown type '{}'", other),
+            ),
+        }
+    }
+
+    if db.has_table("STOCK_V1")? {
+        let stocks = db.query("SELECT HELDAT, SYMBOL, NUMSHARES FROM STOCK_V1", |r| {
+            Ok((
+                r.get::<_, i64>(0)?,
+                r.get::<_, String>(1)?,
+                r.get::<_, f64>(2)?,
+            ))
+        })?;
+        for (account, symbol, shares) in stocks {
+            let row = migration.next_row();
+            let held_at = accounts
+                .get(&account)
+                .map(|a| a.name.as_str())
+                .unwrap_or("?");
+            migration.skip(
+                row,
+                format!(
+                    "Skipped {} shares of {} held in '{}': stock holdings are not imported",
+                    shares, symbol, held_at
+                ),
+            );
+        }
+    }
+    Ok(migration)
+}
@@ -0,0 +1,271 @@
+use super::broker::non_empty;
+use super::{DeclaredAccount, ImportReport, ImportRow, ParsedImport, RowError};
+use crate::rules::RuleSelection;
+use rusqlite::{Connection, OpenFlags, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+pub mod actual;
+pub mod firefly;
+pub mod mmex;
+pub mod ynab;
+
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum SourceApp {
+    Ynab,
+    Actual,
+    Firefly,
+    Mmex,
+}
+
+pub const TRANSFER: &str = "Transfer";
+pub const OPENING_BALANCE: &str = "Opening Balance";
+
+// What a migration found in the source file, next to the usual import report
+#[derive(Serialize, Deserialize, Debug, Default, PartialEq)]
+pub struct MigrationSummary {
+    pub accounts: usize,
+    pub categories: usize,
+    pub payees: usize,
+    pub transactions: usize,
+    pub transfers: usize,
+    pub split_lines: usize,
+    pub skipped: usize,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct MigrationReport {
+    #[serde(flatten)]
+    pub import: ImportReport,
+    pub summary: MigrationSummary,
+}
+
+// Accumulates the rows of a migration. Every source row gets a number, including the ones that
+// are skipped, so the report can point back at them.
+#[derive(Debug, Default)]
+pub struct Migration {
+    pub parsed: ParsedImport,
+    pub split_lines: usize,
+    // Split group of each source transaction, by the source's key for it
+    split_groups: HashMap<String, usize>,
+}
+
+impl Migration {
+    pub fn next_row(&mut self) -> usize {
+        self.parsed.total_rows += 1;
+        self.parsed.total_rows
+    }
+
+    pub fn declare_account(&mut self, name: &str, currency: Option<&str>) {
+        let name = name.trim();
+        if name.is_empty()
+            || self
+                .parsed
+                .accounts
+                .iter()
+                .any(|a| a.name.eq_ignore_ascii_case(name))
+        {
+            return;
+        }
+        self.parsed.accounts.push(DeclaredAccount {
+            name: name.to_string(),
+            currency: currency.map(str::to_string),
+        });
+    }
+
+    pub fn push(&mut self, row: ImportRow) {
+        self.parsed.rows.push(row);
+    }
+
+    // Counts a split line and puts it in one group with the other lines of the same source
+    // transaction, so they are committed as one split. A group is numbered after its first row.
+    pub fn split_line(&mut self, line: &mut ImportRow, key: &str) {
+        self.split_lines += 1;
+        let group = *self.split_groups.entry(key.to_string()).or_insert(line.row);
+        line.split_group = Some(group);
+    }
+
+    pub fn skip(&mut self, row: usize, message: impl Into<String>) {
+        self.parsed.errors.push(RowError {
+            row,
+            message: message.into(),
+        });
+    }
+
+    pub fn summary(&self) -> MigrationSummary {
+        let rows = &self.parsed.rows;
+        let is_transfer = |r: &&ImportRow| r.category.as_deref() == Some(TRANSFER);
+        let categories: HashSet<&str> = rows
+            .iter()
+            .filter(|r| !is_transfer(r))
+            .filter_map(|r| r.category.as_deref())
+            .collect();
+        let payees: HashSet<&str> = rows
+            .iter()
+            .filter(|r| !is_transfer(r) && !r.payee.is_empty())
+            .map(|r| r.payee.as_str())
+            .collect();
+        MigrationSummary {
+            accounts: self.parsed.accounts.len(),
+            categories: categories.len(),
+            payees: payees.len(),
+            transactions: rows.len(),
+            transfers: rows.iter().filter(is_transfer).count(),
+            split_lines: self.split_lines,
+            skipped: self.parsed.errors.len(),
+        }
+    }
+}
+
+// Turns a row into one side of a transfer; the importer links it to `other` when committing
+pub fn transfer_row(mut row: ImportRow, other: &str) -> ImportRow {
+    row.payee = other.trim().to_string();
+    row.category = Some(TRANSFER.to_string());
+    row
+}
+
+// `Group:Category`, the same shape QIF and Beancount categories are imported with
+pub fn category_path(group: Option<&str>, name: Option<&str>) -> Option<String> {
+    match (group.and_then(non_empty), name.and_then(non_empty)) {
+        (Some(group), Some(name)) => Some(format!("{}:{}", group, name)),
+        (None, Some(name)) => Some(name.to_string()),
+        _ => None,
+    }
+}
+
+// Removes an extracted file again once the import is done with it
+struct TempFile(PathBuf);
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.0);
+    }
+}
+
+const SQLITE_MAGIC: &[u8] = b"SQLite format 3\0";
+const ZIP_MAGIC: &[u8] = b"PK\x03\x04";
+
+// A SQLite database read from an app export. Zipped exports are extracted to a temporary file
+// first; fields drop in order, so the connection is closed before that file is removed.
+pub struct SourceDatabase {
+    pub conn: Connection,
+    _extracted: Option<TempFile>,
+}
+
+impl SourceDatabase {
+    // Opens `path` read-only. Zip archives are accepted when the app exports one, in which case
+    // `entry` names the database inside it.
+    pub fn open(path: &Path, entry: Option<&str>) -> Result<Self, String> {
+        let mut header = [0u8; 16];
+        let read = std::fs::File::open(path)
+            .and_then(|mut f| f.read(&mut header))
+            .map_err(|e| e.to_string())?;
+        let header = &header[..read];
+
+        let (db_path, extracted) =
+            if let Some(entry) = entry.filter(|_| header.starts_with(ZIP_MAGIC)) {
+                let file = std::fs::File::open(path).map_err(|e| e.to_string())?;
+                let mut archive = zip::ZipArchive::new(file).map_err(|e| e.to_string())?;
+                let mut inner = archive
+                    .by_name(entry)
+                    .map_err(|_| format!("Archive does not contain '{}'", entry))?;
+                let nanos = chrono::Utc::now().timestamp_nanos_opt().unwrap_or_default();
+                let temp = TempFile(std::env::temp_dir().join(format!(
+                    "honeybear-import-{}-{}.sqlite",
+                    std::process::id(),
+                    nanos
+                )));
+                let mut out = std::fs::File::create(&temp.0).map_err(|e| e.to_string())?;
+                std::io::copy(&mut inner, &mut out).map_err(|e| e.to_string())?;
+                (temp.0.clone(), Some(temp))
+            } else if header.starts_with(SQLITE_MAGIC) {
+                (path.to_path_buf(), None)
+            } else {
+                return Err("Not a SQLite database".to_string());
+            };
+
+        let conn = Connection::open_with_flags(&db_path, OpenFlags::SQLITE_OPEN_READ_ONLY)
+            .map_err(|e| e.to_string())?;
+        Ok(SourceDatabase {
+            conn,
+            _extracted: extracted,
+        })
+    }
+
+    pub fn has_table(&self, table: &str) -> Result<bool, String> {
+        self.conn
+            .query_row(
+                "SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = ?1",
+                [table],
+                |_| Ok(()),
+            )
+            .optional()
+            .map(|found| found.is_some())
+            .map_err(|e| e.to_string())
+    }
+
+    pub fn has_column(&self, table: &str, column: &str) -> Result<bool, String> {
+        let columns = self.query(&format!("PRAGMA table_info({})", table), |row| {
+            row.get::<_, String>(1)
+        })?;
+        Ok(columns.iter().any(|c| c.eq_ignore_ascii_case(column)))
+    }
+
+    pub fn query<T, F>(&self, sql: &str, f: F) -> Result<Vec<T>, String>
+    where
+        F: FnMut(&rusqlite::Row<'_>) -> rusqlite::Result<T>,
+    {
+        let mut stmt = self.conn.prepare(sql).map_err(|e| e.to_string())?;
+        let rows = stmt.query_map([], f).map_err(|e| e.to_string())?;
+        rows.collect::<Result<Vec<_>, _>>()
+            .map_err(|e| e.to_string())
+    }
+
+    // Fails with a readable message when the file is not the expected app's database
+    pub fn require_tables(&self, app: &str, tables: &[&str]) -> Result<(), String> {
+        for table in tables {
+            if !self.has_table(table)? {
+                return Err(format!(
+                    "Not a {} database: table '{}' is missing",
+                    app, table
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+pub fn parse_app(
+    file_path: &Path,
+    app: SourceApp,
+    date_format: Option<&str>,
+) -> Result<Migration, String> {
+    match app {
+        SourceApp::Ynab => ynab::parse_ynab(&super::read_text_file(file_path)?, date_format),
+        SourceApp::Firefly => {
+            firefly::parse_firefly(&super::read_text_file(file_path)?, date_format)
+        }
+        SourceApp::Actual => {
+            actual::parse_actual(&SourceDatabase::open(file_path, Some("db.sqlite"))?)
+        }
+        SourceApp::Mmex => mmex::parse_mmex(&SourceDatabase::open(file_path, None)?),
+    }
+}
+
+// Migrations bring their own accounts, which are created when missing
+pub fn import_app_db(
+    db_path: &PathBuf,
+    file_path: &Path,
+    app: SourceApp,
+    date_format: Option<String>,
+    dry_run: bool,
+    rules: &RuleSelection,
+) -> Result<MigrationReport, String> {
+    let migration = parse_app(file_path, app, date_format.as_deref())?;
+    let summary = migration.summary();
+    let import = super::finish_import(db_path, file_path, migration.parsed, true, dry_run, rules)?;
+    Ok(MigrationReport { import, summary })
+}
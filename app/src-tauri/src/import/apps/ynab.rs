@@ -0,0 +1,132 @@
+use super::{category_path, transfer_row, Migration};
+use crate::import::broker::{cell, non_empty, optional_number, read_csv};
+use crate::import::{parse_date, ImportRow, TransferLedger};
+use chrono::NaiveDate;
+
+// Money moved to be budgeted rather than spent on a category
+const INFLOW_GROUPS: [&str; 2] = ["Inflow", "Income"];
+
+// YNAB writes dates in the budget's display format, which defaults to month-first
+fn ynab_date(value: &str, format: Option<&str>) -> Result<String, String> {
+    if format.is_some() || !value.contains('/') {
+        return parse_date(value, format);
+    }
+    NaiveDate::parse_from_str(value.trim(), "%m/%d/%Y")
+        .map(|d| d.format("%Y-%m-%d").to_string())
+        .or_else(|_| parse_date(value, None))
+}
+
+// `Transfer : Savings` names the other account of a transfer
+fn transfer_target(payee: &str) -> Option<&str> {
+    payee
+        .strip_prefix("Transfer")
+        .and_then(|rest| rest.trim_start().strip_prefix(':'))
+        .map(str::trim)
+        .filter(|name| !name.is_empty())
+}
+
+// Split lines carry `Split (1/3)` (YNAB) or `(Split 1/3)` (YNAB 4) in front of their memo.
+// Returns the line's place in the split and the rest of the memo.
+fn split_memo(memo: &str) -> Option<(&str, &str)> {
+    memo.strip_prefix("Split (")
+        .or_else(|| memo.strip_prefix("(Split "))
+        .and_then(|rest| rest.split_once(')'))
+        .map(|(place, rest)| (place.trim(), rest.trim()))
+}
+
+// Parses a register export from YNAB or YNAB 4. Both sides of a transfer are listed; only the
+// first one is kept and the import links it to the other account.
+pub fn parse_ynab(content: &str, date_format: Option<&str>) -> Result<Migration, String> {
+    let (columns, records) = read_csv(content)?;
+    let account = columns.require(&["Account"])?;
+    let date = columns.require(&["Date"])?;
+    let payee = columns.require(&["Payee"])?;
+    let outflow = columns.require(&["Outflow"])?;
+    let inflow = columns.require(&["Inflow"])?;
+    let memo = columns.find(&["Memo"]);
+    let group = columns.find(&["Category Group", "Master Category"]);
+    let category = columns
+        .find(&["Sub Category"])
+        .or_else(|| columns.find(&["Category"]));
+    let combined = columns.find(&["Category Group/Category"]);
+
+    let mut migration = Migration::default();
+    let mut transfers = TransferLedger::default();
+    // The register has no id for a split; its lines follow each other, starting at `1/n`
+    let mut split_start = 0;
+    for record in &records {
+        let row = migration.next_row();
+        let account_name = cell(record, Some(account));
+        if account_name.is_empty() {
+            migration.skip(row, "No account for row");
+            continue;
+        }
+        migration.declare_account(account_name, None);
+
+        let amount = |index| optional_number(cell(record, Some(index))).map(|v| v.unwrap_or(0.0));
+        let parsed = ynab_date(cell(record, Some(date)), date_format)
+            .and_then(|date| Ok((date, amount(inflow)? - amount(outflow)?)));
+        let (date, amount) = match parsed {
+            Ok(values) => values,
+            Err(message) => {
+                migration.skip(row, message);
+                continue;
+            }
+        };
+
+        let mut notes = non_empty(cell(record, memo));
+        let mut split = None;
+        if let Some((place, rest)) = notes.and_then(split_memo) {
+            if place.starts_with("1/") || split_start == 0 {
+                split_start = row;
+            }
+            split = Some(format!("{}:{}", account_name, split_start));
+            notes = non_empty(rest);
+        }
+        let (group_name, category_name) = match (group, combined) {
+            (Some(_), _) => (
+                non_empty(cell(record, group)),
+                non_empty(cell(record, category)),
+            ),
+            (None, Some(_)) => match cell(record, combined).split_once(':') {
+                Some((g, c)) => (non_empty(g), non_empty(c)),
+                None => (None, non_empty(cell(record, combined))),
+            },
+            (None, None) => (None, non_empty(cell(record, category))),
+        };
+        let payee_name = cell(record, Some(payee));
+        let category = if payee_name == "Starting Balance" {
+            None
+        } else if group_name.is_some_and(|g| INFLOW_GROUPS.contains(&g)) {
+            Some("Income".to_string())
+        } else {
+            category_path(group_name, category_name)
+        };
+
+        let base = ImportRow {
+            row,
+            account_name: Some(account_name.to_string()),
+            date,
+            payee: payee_name.to_string(),
+            notes: notes.map(str::to_string),
+            category,
+            amount,
+            ..Default::default()
+        };
+        let mut line = match transfer_target(payee_name) {
+            Some(other) => {
+                migration.declare_account(other, None);
+                if transfers.consume_or_expect(account_name, other, &base.date, amount) {
+                    continue;
+                }
+                transfer_row(base, other)
+            }
+            None => base,
+        };
+        if let Some(key) = split {
+            migration.split_line(&mut line, &key);
+        }
+        migration.push(line);
+    }
+    Ok(migration)
+}
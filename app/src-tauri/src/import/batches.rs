@@ -0,0 +1,175 @@
+use rusqlite::{params, Connection, OptionalExtension, Transaction};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+
+// One committed import. Transactions it inserted, including transfer counterparts, carry its id
+// in `transactions.import_batch_id`.
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct ImportBatch {
+    pub id: i64,
+    pub file_name: String,
+    pub file_hash: String,
+    pub imported_at: String,
+    pub total_rows: usize,
+    pub imported_rows: usize,
+    pub duplicate_rows: usize,
+    pub error_rows: usize,
+    // Transactions of the batch still in the ledger
+    pub transactions: usize,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct RollbackReport {
+    pub batch: ImportBatch,
+    pub removed_transactions: usize,
+}
+
+// Row counts of the import, known before anything is inserted
+pub struct BatchCounts {
+    pub total_rows: usize,
+    pub duplicate_rows: usize,
+    pub error_rows: usize,
+}
+
+// File name and SHA-256 of the imported file, so the same statement can be recognized later
+pub fn describe_file(file_path: &Path) -> Result<(String, String), String> {
+    let bytes = std::fs::read(file_path).map_err(|e| e.to_string())?;
+    let name = file_path
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_else(|| file_path.to_string_lossy().into_owned());
+    Ok((name, format!("{:x}", Sha256::digest(&bytes))))
+}
+
+pub fn create_batch(
+    tx: &Transaction,
+    file_path: &Path,
+    counts: &BatchCounts,
+) -> Result<i64, String> {
+    let (file_name, file_hash) = describe_file(file_path)?;
+    tx.execute(
+        "INSERT INTO import_batches (file_name, file_hash, imported_at, total_rows, imported_rows, duplicate_rows, error_rows) VALUES (?1, ?2, ?3, ?4, 0, ?5, ?6)",
+        params![
+            file_name,
+            file_hash,
+            chrono::Local::now().format("%Y-%m-%d %H:%M:%S").to_string(),
+            counts.total_rows as i64,
+            counts.duplicate_rows as i64,
+            counts.error_rows as i64
+        ],
+    )
+    .map_err(|e| e.to_string())?;
+    Ok(tx.last_insert_rowid())
+}
+
+// Tags a transaction and its transfer counterpart with the batch
+pub fn tag_transaction(tx: &Transaction, batch_id: i64, transaction_id: i32) -> Result<(), String> {
+    tx.execute(
+        "UPDATE transactions SET import_batch_id = ?1 WHERE id = ?2 OR id = (SELECT linked_tx_id FROM transactions WHERE id = ?2)",
+        params![batch_id, transaction_id],
+    )
+    .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+pub fn finish_batch(tx: &Transaction, batch_id: i64, imported_rows: usize) -> Result<(), String> {
+    tx.execute(
+        "UPDATE import_batches SET imported_rows = ?1 WHERE id = ?2",
+        params![imported_rows as i64, batch_id],
+    )
+    .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+const BATCH_QUERY: &str = "SELECT b.id, b.file_name, b.file_hash, b.imported_at, b.total_rows, b.imported_rows, b.duplicate_rows, b.error_rows, (SELECT COUNT(*) FROM transactions t WHERE t.import_batch_id = b.id) FROM import_batches b";
+
+fn batch_from_row(row: &rusqlite::Row<'_>) -> rusqlite::Result<ImportBatch> {
+    Ok(ImportBatch {
+        id: row.get(0)?,
+        file_name: row.get(1)?,
+        file_hash: row.get(2)?,
+        imported_at: row.get(3)?,
+        total_rows: row.get::<_, i64>(4)? as usize,
+        imported_rows: row.get::<_, i64>(5)? as usize,
+        duplicate_rows: row.get::<_, i64>(6)? as usize,
+        error_rows: row.get::<_, i64>(7)? as usize,
+        transactions: row.get::<_, i64>(8)? as usize,
+    })
+}
+
+pub fn list_import_batches_db(db_path: &PathBuf) -> Result<Vec<ImportBatch>, String> {
+    let conn = Connection::open(db_path).map_err(|e| e.to_string())?;
+    let mut stmt = conn
+        .prepare(&format!("{} ORDER BY b.id DESC", BATCH_QUERY))
+        .map_err(|e| e.to_string())?;
+    let batches = stmt
+        .query_map([], batch_from_row)
+        .map_err(|e| e.to_string())?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())?;
+    Ok(batches)
+}
+
+// Removes every transaction of the batch, with the counterparts of its transfers, and reverts the
+// account balances. Accounts the import created are kept. All or nothing.
+pub fn rollback_import_batch_db(
+    db_path: &PathBuf,
+    batch_id: i64,
+) -> Result<RollbackReport, String> {
+    let mut conn = Connection::open(db_path).map_err(|e| e.to_string())?;
+    let tx = conn.transaction().map_err(|e| e.to_string())?;
+
+    let batch = tx
+        .query_row(
+            &format!("{} WHERE b.id = ?1", BATCH_QUERY),
+            params![batch_id],
+            batch_from_row,
+        )
+        .optional()
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| format!("Import batch {} not found", batch_id))?;
+
+    let mut ids: BTreeSet<i32> = BTreeSet::new();
+    let mut removed_transactions = 0;
+    {
+        let mut stmt = tx
+            .prepare("SELECT id, linked_tx_id FROM transactions WHERE import_batch_id = ?1")
+            .map_err(|e| e.to_string())?;
+        let rows = stmt
+            .query_map(params![batch_id], |r| {
+                Ok((r.get::<_, i32>(0)?, r.get::<_, Option<i32>>(1)?))
+            })
+            .map_err(|e| e.to_string())?;
+        for row in rows {
+            let (id, linked) = row.map_err(|e| e.to_string())?;
+            ids.insert(id);
+            ids.extend(linked);
+        }
+    }
+
+    for id in &ids {
+        if crate::remove_transaction(&tx, *id)?.is_none() {
+            continue;
+        }
+        removed_transactions += 1;
+        // A transaction outside the batch must not keep pointing at a removed one
+        tx.execute(
+            "UPDATE transactions SET linked_tx_id = NULL WHERE linked_tx_id = ?1",
+            params![id],
+        )
+        .map_err(|e| e.to_string())?;
+    }
+    tx.execute(
+        "DELETE FROM import_batches WHERE id = ?1",
+        params![batch_id],
+    )
+    .map_err(|e| e.to_string())?;
+
+    tx.commit().map_err(|e| e.to_string())?;
+    Ok(RollbackReport {
+        batch,
+        removed_transactions,
+    })
+}
@@ -0,0 +1,597 @@
+use super::{
+    DeclaredAccount, ImportReport, ImportRow, ParsedImport, PricePoint, RowError, StatementBalance,
+};
+use crate::rules::RuleSelection;
+use chrono::NaiveDate;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone)]
+struct Posting {
+    account: String,
+    units: Option<(f64, String)>,
+    // Per-unit lot cost from `{...}` (or `{{total}}` divided by the units)
+    cost: Option<(f64, String)>,
+    // Per-unit price from `@`, or `@@` divided by the units
+    price: Option<(f64, String)>,
+}
+
+impl Posting {
+    // Value of the posting in the currency it balances in
+    fn weight(&self) -> Option<(f64, String)> {
+        let (units, commodity) = self.units.clone()?;
+        Some(match (&self.cost, &self.price) {
+            (Some((cost, currency)), _) => (units * cost, currency.clone()),
+            (None, Some((price, currency))) => (units * price, currency.clone()),
+            (None, None) => (units, commodity),
+        })
+    }
+
+    fn is_holding(&self) -> bool {
+        matches!(
+            self.account.split(':').next(),
+            Some("Assets") | Some("Liabilities")
+        )
+    }
+}
+
+struct RawTransaction {
+    line: usize,
+    date: String,
+    payee: String,
+    narration: String,
+    postings: Vec<Posting>,
+}
+
+impl RawTransaction {
+    fn description(&self) -> Option<String> {
+        let parts: Vec<&str> = [self.payee.as_str(), self.narration.as_str()]
+            .into_iter()
+            .filter(|p| !p.is_empty())
+            .collect();
+        Some(parts.join(" - ")).filter(|d| !d.is_empty())
+    }
+}
+
+// Splits a line into tokens, keeping double-quoted strings (with `\"` escapes) together
+fn tokenize(line: &str) -> Vec<String> {
+    let mut tokens = Vec::new();
+    let mut chars = line.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if c == ';' {
+            break;
+        } else if c == '"' {
+            chars.next();
+            let mut text = String::from("\"");
+            while let Some(c) = chars.next() {
+                match c {
+                    '\\' => {
+                        if let Some(escaped) = chars.next() {
+                            text.push(escaped);
+                        }
+                    }
+                    '"' => break,
+                    _ => text.push(c),
+                }
+            }
+            tokens.push(text);
+        } else {
+            let mut word = String::new();
+            while let Some(&c) = chars.peek() {
+                if c.is_whitespace() || c == '"' {
+                    break;
+                }
+                word.push(c);
+                chars.next();
+            }
+            tokens.push(word);
+        }
+    }
+    tokens
+}
+
+fn strings(tokens: &[String]) -> Vec<String> {
+    tokens
+        .iter()
+        .filter_map(|t| t.strip_prefix('"').map(str::to_string))
+        .collect()
+}
+
+fn parse_number(value: &str) -> Result<f64, String> {
+    value
+        .replace(',', "")
+        .parse::<f64>()
+        .map_err(|_| format!("Unsupported amount '{}'", value))
+}
+
+// `ACCOUNT [NUMBER COMMODITY] [{COST CUR}|{{TOTAL CUR}}] [@ PRICE CUR|@@ TOTAL CUR]`
+fn parse_posting(line: &str) -> Result<Posting, String> {
+    let line = line.split(';').next().unwrap_or("").trim();
+    let line = line
+        .strip_prefix(['!', '*'])
+        .map(str::trim_start)
+        .unwrap_or(line);
+    let (account, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+
+    let (amount_part, rest) = match rest.find(['{', '@']) {
+        Some(idx) => (rest[..idx].trim(), &rest[idx..]),
+        None => (rest.trim(), ""),
+    };
+    let units = if amount_part.is_empty() {
+        None
+    } else {
+        let (number, commodity) = amount_part
+            .rsplit_once(char::is_whitespace)
+            .ok_or_else(|| format!("Unsupported amount '{}'", amount_part))?;
+        Some((parse_number(number.trim())?, commodity.trim().to_string()))
+    };
+    let quantity = units.as_ref().map(|u| u.0.abs()).unwrap_or(0.0);
+
+    let mut cost = None;
+    let mut rest = rest.trim();
+    if rest.starts_with('{') {
+        let total = rest.starts_with("{{");
+        let end = rest.find('}').ok_or("Unclosed cost")?;
+        let inner = rest[..end].trim_start_matches('{');
+        // Lot dates and labels after the cost are not needed here
+        let spec = inner.split(',').next().unwrap_or("").trim();
+        if let Some((number, currency)) = spec.split_once(char::is_whitespace) {
+            let value = parse_number(number.trim())?;
+            let per_unit = if total && quantity > 0.0 {
+                value / quantity
+            } else {
+                value
+            };
+            cost = Some((per_unit, currency.trim().to_string()));
+        }
+        rest = rest[end..].trim_start_matches('}').trim();
+    }
+
+    let mut price = None;
+    if let Some(total) = rest.strip_prefix("@@") {
+        let (number, currency) = total
+            .trim()
+            .split_once(char::is_whitespace)
+            .ok_or("Incomplete price")?;
+        let value = parse_number(number)?;
+        price = Some((
+            if quantity > 0.0 {
+                value / quantity
+            } else {
+                value
+            },
+            currency.trim().to_string(),
+        ));
+    } else if let Some(unit) = rest.strip_prefix('@') {
+        let (number, currency) = unit
+            .trim()
+            .split_once(char::is_whitespace)
+            .ok_or("Incomplete price")?;
+        price = Some((parse_number(number)?, currency.trim().to_string()));
+    }
+
+    Ok(Posting {
+        account: account.to_string(),
+        units,
+        cost,
+        price,
+    })
+}
+
+// `Assets:Bank:Checking` becomes the HoneyBear account `Bank:Checking`, and
+// `Expenses:Food:Groceries` the category `Food:Groceries`
+fn strip_root(account: &str) -> String {
+    account
+        .split_once(':')
+        .map(|(_, rest)| rest.to_string())
+        .unwrap_or_else(|| account.to_string())
+}
+
+struct Mapper {
+    // Currencies named by `open` directives
+    currencies: HashSet<String>,
+    parsed: ParsedImport,
+}
+
+impl Mapper {
+    // Securities are held at a cost, or priced in something that is not one of the ledger's
+    // currencies (a priced currency amount is a conversion instead)
+    fn is_security(&self, posting: &Posting) -> bool {
+        let Some((_, commodity)) = &posting.units else {
+            return false;
+        };
+        posting.is_holding()
+            && (posting.cost.is_some()
+                || (posting.price.is_some() && !self.currencies.contains(commodity)))
+    }
+
+    fn base_row(&self, raw: &RawTransaction, account: &str) -> ImportRow {
+        let (payee, notes) = match (raw.payee.is_empty(), raw.narration.is_empty()) {
+            (false, true) => (raw.payee.clone(), None),
+            (false, false) => (raw.payee.clone(), Some(raw.narration.clone())),
+            (true, false) => (raw.narration.clone(), None),
+            (true, true) => ("Unknown".to_string(), None),
+        };
+        ImportRow {
+            row: raw.line,
+            account_name: Some(strip_root(account)),
+            date: raw.date.clone(),
+            payee,
+            notes,
+            ..Default::default()
+        }
+    }
+
+    fn map(&mut self, mut raw: RawTransaction) -> Result<(), String> {
+        // Fill in the one posting allowed to omit its amount
+        let missing: Vec<usize> = raw
+            .postings
+            .iter()
+            .enumerate()
+            .filter(|(_, p)| p.units.is_none())
+            .map(|(i, _)| i)
+            .collect();
+        if missing.len() > 1 {
+            return Err("More than one posting without an amount".to_string());
+        }
+        let mut sums: HashMap<String, f64> = HashMap::new();
+        for (value, currency) in raw.postings.iter().filter_map(Posting::weight) {
+            *sums.entry(currency).or_insert(0.0) += value;
+        }
+        if let Some(&idx) = missing.first() {
+            let open: Vec<(String, f64)> = sums
+                .iter()
+                .filter(|(_, v)| v.abs() >= 0.005)
+                .map(|(c, v)| (c.clone(), *v))
+                .collect();
+            match open.as_slice() {
+                [(currency, value)] => raw.postings[idx].units = Some((-value, currency.clone())),
+                [] => {
+                    let currency = sums.keys().next().cloned().unwrap_or_default();
+                    raw.postings[idx].units = Some((0.0, currency));
+                }
+                _ => {
+                    return Err(
+                        "Cannot infer an elided amount across several currencies".to_string()
+                    )
+                }
+            }
+        }
+
+        let securities: Vec<&Posting> = raw
+            .postings
+            .iter()
+            .filter(|p| self.is_security(p))
+            .collect();
+        if !securities.is_empty() {
+            return self.map_trade(&raw);
+        }
+
+        let holdings: Vec<&Posting> = raw.postings.iter().filter(|p| p.is_holding()).collect();
+        let others: Vec<&Posting> = raw.postings.iter().filter(|p| !p.is_holding()).collect();
+        match (holdings.as_slice(), others.as_slice()) {
+            ([holding], categories) if !categories.is_empty() => {
+                let (amount, currency) = holding.units.clone().unwrap_or_default();
+                if let [category] = categories {
+                    let mut row = self.base_row(&raw, &holding.account);
+                    row.amount = amount;
+                    row.currency = Some(currency);
+                    row.category = Some(strip_root(&category.account));
+                    self.parsed.rows.push(row);
+                    return Ok(());
+                }
+                // Split: one row per category, in the asset's currency, grouped by the
+                // transaction's line
+                for category in categories {
+                    let (value, category_currency) = category.weight().unwrap_or_default();
+                    if category_currency != currency {
+                        return Err(
+                            "Split postings in different currencies are not supported".to_string()
+                        );
+                    }
+                    let mut row = self.base_row(&raw, &holding.account);
+                    row.amount = -value;
+                    row.currency = Some(currency.clone());
+                    row.category = Some(strip_root(&category.account));
+                    row.split_group = Some(raw.line);
+                    self.parsed.rows.push(row);
+                }
+                Ok(())
+            }
+            ([from, to], []) => {
+                let (amount, currency) = from.units.clone().unwrap_or_default();
+                let (received, received_currency) = to.units.clone().unwrap_or_default();
+                let mut row = self.base_row(&raw, &from.account);
+                // The app links transfers through the payee naming the other account
+                row.notes = raw.description();
+                row.payee = strip_root(&to.account);
+                row.amount = amount;
+                row.currency = Some(currency.clone());
+                row.category = Some("Transfer".to_string());
+                if received_currency != currency || (received + amount).abs() >= 0.005 {
+                    row.transfer_amount = Some(received);
+                }
+                self.parsed.rows.push(row);
+                Ok(())
+            }
+            _ => Err(format!(
+                "Cannot map a transaction with {} asset/liability and {} other postings",
+                holdings.len(),
+                others.len()
+            )),
+        }
+    }
+
+    // One trade per security posting (lots of the same sale are merged). Cash coming from a
+    // different account becomes a transfer into the brokerage account; gains postings are left
+    // to the app, which derives them from the lots.
+    fn map_trade(&mut self, raw: &RawTransaction) -> Result<(), String> {
+        let mut trades: Vec<(String, String, f64, f64, String)> = Vec::new();
+        for posting in raw.postings.iter().filter(|p| self.is_security(p)) {
+            let (units, ticker) = posting.units.clone().unwrap_or_default();
+            let (price, currency) = posting
+                .price
+                .clone()
+                .or(posting.cost.clone())
+                .ok_or("Security posting without cost or price")?;
+            match trades
+                .iter_mut()
+                .find(|t| t.0 == posting.account && t.1 == ticker && (t.2 > 0.0) == (units > 0.0))
+            {
+                Some(trade) => {
+                    trade.3 = (trade.3 * trade.2 + price * units) / (trade.2 + units);
+                    trade.2 += units;
+                }
+                None => trades.push((posting.account.clone(), ticker, units, price, currency)),
+            }
+        }
+
+        let fee: f64 = raw
+            .postings
+            .iter()
+            .filter(|p| p.account.starts_with("Expenses:"))
+            .filter_map(|p| p.units.as_ref().map(|u| u.0))
+            .sum();
+
+        let trade_accounts: Vec<String> = trades.iter().map(|t| t.0.clone()).collect();
+        for (i, (account, ticker, units, price, currency)) in trades.iter().enumerate() {
+            let mut row = self.base_row(raw, account);
+            row.payee = if *units > 0.0 { "Buy" } else { "Sell" }.to_string();
+            row.category = Some("Investment".to_string());
+            row.ticker = Some(ticker.clone());
+            row.shares = Some(units.abs());
+            row.price_per_share = Some(*price);
+            // Fees are charged once, on the first trade of the entry
+            row.fee = Some(if i == 0 { fee.abs() } else { 0.0 });
+            row.amount = if *units > 0.0 {
+                -(units * price + row.fee.unwrap_or(0.0))
+            } else {
+                units.abs() * price - row.fee.unwrap_or(0.0)
+            };
+            row.currency = Some(currency.clone());
+            row.is_buy = Some(*units > 0.0);
+            self.parsed.rows.push(row);
+        }
+
+        let funding: Vec<&Posting> = raw
+            .postings
+            .iter()
+            .filter(|p| {
+                p.is_holding() && !self.is_security(p) && !trade_accounts.contains(&p.account)
+            })
+            .collect();
+        for posting in funding {
+            let (amount, currency) = posting.units.clone().unwrap_or_default();
+            let mut row = self.base_row(raw, &posting.account);
+            row.payee = strip_root(&trade_accounts[0]);
+            row.notes = raw.description();
+            row.amount = amount;
+            row.currency = Some(currency);
+            row.category = Some("Transfer".to_string());
+            self.parsed.rows.push(row);
+        }
+        Ok(())
+    }
+}
+
+// Parses a Beancount file. `open` directives become accounts, transactions become rows, `price`
+// directives become daily prices and `balance` assertions are checked after import. Anything
+// else is reported as an error line rather than silently dropped.
+pub fn parse_beancount(content: &str) -> Result<ParsedImport, String> {
+    let mut currencies: HashSet<String> = HashSet::new();
+    let mut declared: Vec<DeclaredAccount> = Vec::new();
+    let mut transactions: Vec<RawTransaction> = Vec::new();
+    let mut errors: Vec<RowError> = Vec::new();
+    let mut balances: Vec<StatementBalance> = Vec::new();
+    let mut prices: Vec<PricePoint> = Vec::new();
+    let mut saw_directive = false;
+    let mut current: Option<usize> = None;
+
+    for (idx, raw_line) in content.lines().enumerate() {
+        let line_no = idx + 1;
+        let line = raw_line.trim_end();
+        if line.trim().is_empty() {
+            current = None;
+            continue;
+        }
+        let trimmed = line.trim_start();
+        // Comments and org-mode section headings
+        if trimmed.starts_with(';')
+            || trimmed.starts_with('#')
+            || (trimmed.starts_with('*') && line == trimmed)
+        {
+            continue;
+        }
+
+        if line.starts_with([' ', '\t']) {
+            let Some(tx_idx) = current else {
+                continue;
+            };
+            // Metadata lines (`key: value`) start with a lowercase key
+            if trimmed.starts_with(|c: char| c.is_ascii_lowercase()) {
+                continue;
+            }
+            match parse_posting(trimmed) {
+                Ok(posting) => transactions[tx_idx].postings.push(posting),
+                Err(message) => errors.push(RowError {
+                    row: line_no,
+                    message,
+                }),
+            }
+            continue;
+        }
+        current = None;
+
+        let tokens = tokenize(line);
+        let Some(first) = tokens.first() else {
+            continue;
+        };
+        let Ok(date) = NaiveDate::parse_from_str(first, "%Y-%m-%d") else {
+            if !matches!(
+                first.as_str(),
+                "option" | "plugin" | "include" | "pushtag" | "poptag"
+            ) {
+                errors.push(RowError {
+                    row: line_no,
+                    message: format!("Unrecognized line '{}'", trimmed),
+                });
+            }
+            continue;
+        };
+        saw_directive = true;
+        let date = date.format("%Y-%m-%d").to_string();
+        let kind = tokens.get(1).map(String::as_str).unwrap_or("");
+
+        match kind {
+            "open" => {
+                let Some(account) = tokens.get(2) else {
+                    continue;
+                };
+                // Currency constraints (`USD,AAPL`); the first one becomes the account currency
+                let currency = tokens[3..]
+                    .iter()
+                    .take_while(|t| !t.starts_with('"'))
+                    .flat_map(|t| t.split(','))
+                    .map(str::trim)
+                    .find(|c| !c.is_empty())
+                    .map(str::to_string);
+                currencies.extend(currency.clone());
+                if matches!(
+                    account.split(':').next(),
+                    Some("Assets") | Some("Liabilities")
+                ) {
+                    declared.push(DeclaredAccount {
+                        name: strip_root(account),
+                        currency,
+                    });
+                }
+            }
+            "*" | "!" | "txn" => {
+                let texts = strings(&tokens[2..]);
+                let (payee, narration) = match texts.as_slice() {
+                    [narration] => (String::new(), narration.clone()),
+                    [payee, narration, ..] => (payee.clone(), narration.clone()),
+                    [] => (String::new(), String::new()),
+                };
+                transactions.push(RawTransaction {
+                    line: line_no,
+                    date,
+                    payee,
+                    narration,
+                    postings: Vec::new(),
+                });
+                current = Some(transactions.len() - 1);
+            }
+            "price" => match (tokens.get(2), tokens.get(3)) {
+                (Some(ticker), Some(value)) => match parse_number(value) {
+                    Ok(price) => prices.push(PricePoint {
+                        ticker: ticker.clone(),
+                        date,
+                        price,
+                    }),
+                    Err(message) => errors.push(RowError {
+                        row: line_no,
+                        message,
+                    }),
+                },
+                _ => errors.push(RowError {
+                    row: line_no,
+                    message: "Incomplete price directive".to_string(),
+                }),
+            },
+            "balance" => {
+                // Balance assertions hold at the start of the day, i.e. after the previous one
+                let account = tokens.get(2).cloned().unwrap_or_default();
+                let amount = tokens.get(3).map(|v| parse_number(v));
+                let as_of = NaiveDate::parse_from_str(&date, "%Y-%m-%d")
+                    .ok()
+                    .and_then(|d| d.pred_opt())
+                    .map(|d| d.format("%Y-%m-%d").to_string());
+                match (amount, as_of) {
+                    (Some(Ok(amount)), Some(as_of)) => balances.push(StatementBalance {
+                        account_id: None,
+                        account_name: Some(strip_root(&account)),
+                        date: as_of,
+                        amount,
+                        opening_amount: None,
+                    }),
+                    _ => errors.push(RowError {
+                        row: line_no,
+                        message: format!("Could not read balance assertion for {}", account),
+                    }),
+                }
+            }
+            "close" | "commodity" => {}
+            other => errors.push(RowError {
+                row: line_no,
+                message: format!("Skipped unsupported '{}' directive", other),
+            }),
+        }
+    }
+
+    if !saw_directive {
+        return Err("Not a Beancount file: no dated directives found".to_string());
+    }
+
+    // Anything used to price or cost a holding is a currency too
+    for posting in transactions.iter().flat_map(|t| t.postings.iter()) {
+        for (_, currency) in posting.cost.iter().chain(posting.price.iter()) {
+            currencies.insert(currency.clone());
+        }
+    }
+
+    let mut mapper = Mapper {
+        currencies,
+        parsed: ParsedImport {
+            total_rows: transactions.len(),
+            accounts: declared,
+            balances,
+            prices,
+            errors,
+            ..Default::default()
+        },
+    };
+    for raw in transactions {
+        let line = raw.line;
+        let before = mapper.parsed.rows.len();
+        if let Err(message) = mapper.map(raw) {
+            mapper.parsed.rows.truncate(before);
+            mapper.parsed.errors.push(RowError { row: line, message });
+        }
+    }
+    mapper.parsed.errors.sort_by_key(|e| e.row);
+    Ok(mapper.parsed)
+}
+
+pub fn import_beancount_db(
+    db_path: &PathBuf,
+    file_path: &Path,
+    dry_run: bool,
+    rules: &RuleSelection,
+) -> Result<ImportReport, String> {
+    let content = super::read_text_file(file_path)?;
+    let parsed = parse_beancount(&content)?;
+    super::finish_import(db_path, file_path, parsed, true, dry_run, rules)
+}
@@ -0,0 +1,818 @@
+use super::{
+    ensure_single_source_account, parse_amount, parse_date, ImportReport, ImportRow, ParsedImport,
+    RowError,
+};
+use crate::holdings::income::{DIVIDENDS, INTEREST, TAXES};
+use crate::rules::RuleSelection;
+use csv::StringRecord;
+use roxmltree::{Document, Node};
+use serde::Deserialize;
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum BrokerFormat {
+    // Flex Query exported as XML, with Trades and Cash Transactions sections
+    InteractiveBrokers,
+    // Either the Transactions export (trades) or the Account statement (cash movements)
+    Degiro,
+    // History export CSV
+    Trading212,
+}
+
+const FEES: &str = "Fees";
+const EXCHANGE: &str = "Currency Exchange";
+
+// Fields shared by every row produced from one statement line
+struct Source {
+    row: usize,
+    account_id: i32,
+    date: String,
+    currency: Option<String>,
+    external_id: Option<String>,
+    notes: Option<String>,
+}
+
+impl Source {
+    fn cash(&self, payee: &str, category: Option<&str>, amount: f64) -> ImportRow {
+        ImportRow {
+            row: self.row,
+            account_id: Some(self.account_id),
+            date: self.date.clone(),
+            payee: payee.to_string(),
+            notes: self.notes.clone(),
+            category: category.map(str::to_string),
+            amount,
+            currency: self.currency.clone(),
+            external_id: self.external_id.clone(),
+            ..Default::default()
+        }
+    }
+
+    // One leg of a multi-row line (FX pairs, fees in another currency) with its own id suffix
+    fn leg(
+        &self,
+        suffix: &str,
+        payee: &str,
+        category: &str,
+        amount: f64,
+        currency: &str,
+    ) -> ImportRow {
+        ImportRow {
+            currency: Some(currency.to_string()),
+            external_id: self
+                .external_id
+                .as_ref()
+                .map(|id| format!("{}:{}", id, suffix)),
+            ..self.cash(payee, Some(category), amount)
+        }
+    }
+
+    // `shares` is signed (negative for sells) and `fee` is a positive cost. A fee charged in
+    // another currency than the trade becomes its own row.
+    fn trade(
+        &self,
+        ticker: &str,
+        shares: f64,
+        price: f64,
+        fee: f64,
+        fee_currency: Option<&str>,
+    ) -> Vec<ImportRow> {
+        let is_buy = shares > 0.0;
+        let separate_fee =
+            fee != 0.0 && fee_currency.is_some_and(|c| Some(c) != self.currency.as_deref());
+        let trade_fee = if separate_fee { 0.0 } else { fee };
+        let gross = shares.abs() * price;
+        let mut rows = vec![ImportRow {
+            payee: if is_buy { "Buy" } else { "Sell" }.to_string(),
+            category: Some("Investment".to_string()),
+            amount: if is_buy {
+                -(gross + trade_fee)
+            } else {
+                gross - trade_fee
+            },
+            ticker: Some(ticker.to_string()),
+            shares: Some(shares),
+            price_per_share: Some(price),
+            fee: Some(trade_fee),
+            is_buy: Some(is_buy),
+            ..self.cash("", None, 0.0)
+        }];
+        if separate_fee {
+            rows.push(self.leg("fee", ticker, FEES, -fee, fee_currency.unwrap_or_default()));
+        }
+        rows
+    }
+}
+
+// Broker exports use either decimal separator depending on the account locale
+pub(super) fn number(value: &str) -> Result<f64, String> {
+    let trimmed = value.trim();
+    let decimal = match (trimmed.rfind('.'), trimmed.rfind(',')) {
+        (Some(dot), Some(comma)) if comma > dot => ',',
+        (None, Some(_)) => ',',
+        _ => '.',
+    };
+    let thousands = if decimal == ',' { '.' } else { ',' };
+    parse_amount(trimmed, decimal, Some(thousands))
+}
+
+pub(super) fn optional_number(value: &str) -> Result<Option<f64>, String> {
+    if value.trim().is_empty() {
+        Ok(None)
+    } else {
+        number(value).map(Some)
+    }
+}
+
+pub(super) fn non_empty(value: &str) -> Option<&str> {
+    Some(value.trim()).filter(|v| !v.is_empty())
+}
+
+fn push_result(parsed: &mut ParsedImport, row: usize, result: Result<Vec<ImportRow>, String>) {
+    match result {
+        Ok(rows) => parsed.rows.extend(rows),
+        Err(message) => parsed.errors.push(RowError { row, message }),
+    }
+}
+
+// ---------- Interactive Brokers Flex XML ----------
+
+fn attr<'a>(node: Node<'a, '_>, name: &str) -> Option<&'a str> {
+    node.attribute(name).and_then(non_empty)
+}
+
+// Flex dates are `20240115`, optionally followed by `;153012`
+fn ibkr_date(node: Node, names: &[&str]) -> Result<String, String> {
+    let value = names
+        .iter()
+        .find_map(|n| attr(node, n))
+        .ok_or("Missing date")?;
+    parse_date(value.split(';').next().unwrap_or(value), None)
+}
+
+fn ibkr_number(node: Node, name: &str) -> Result<f64, String> {
+    attr(node, name).map_or(Ok(0.0), |v| {
+        v.parse::<f64>()
+            .map_err(|_| format!("Invalid {} '{}'", name, v))
+    })
+}
+
+fn ibkr_trade(node: Node, row: usize, account_id: i32) -> Result<Vec<ImportRow>, String> {
+    let symbol = attr(node, "symbol").ok_or("Trade without symbol")?;
+    let quantity = ibkr_number(node, "quantity")?;
+    let commission = ibkr_number(node, "ibCommission")?;
+    let commission_currency = attr(node, "ibCommissionCurrency");
+    let source = Source {
+        row,
+        account_id,
+        date: ibkr_date(node, &["tradeDate", "dateTime"])?,
+        currency: attr(node, "currency").map(str::to_string),
+        external_id: attr(node, "tradeID")
+            .or(attr(node, "transactionID"))
+            .map(str::to_string),
+        notes: attr(node, "description").map(str::to_string),
+    };
+
+    // Forex trades are quoted as BASE.QUOTE: the quantity is in the base currency and the
+    // proceeds in the quote currency
+    if attr(node, "assetCategory") == Some("CASH") {
+        let (base, quote) = symbol
+            .split_once('.')
+            .ok_or_else(|| format!("Unrecognized currency pair '{}'", symbol))?;
+        let proceeds = ibkr_number(node, "proceeds")?;
+        let mut rows = vec![
+            source.leg("base", symbol, EXCHANGE, quantity, base),
+            source.leg("quote", symbol, EXCHANGE, proceeds, quote),
+        ];
+        if commission != 0.0 {
+            rows.push(source.leg(
+                "fee",
+                symbol,
+                FEES,
+                commission,
+                commission_currency.unwrap_or(quote),
+            ));
+        }
+        return Ok(rows);
+    }
+
+    if quantity == 0.0 {
+        return Err(format!("Trade {} has no quantity", symbol));
+    }
+    let price = ibkr_number(node, "tradePrice")?;
+    Ok(source.trade(
+        symbol,
+        quantity,
+        price,
+        commission.abs(),
+        commission_currency,
+    ))
+}
+
+fn ibkr_cash(node: Node, row: usize, account_id: i32) -> Result<Vec<ImportRow>, String> {
+    let kind = attr(node, "type").unwrap_or_default();
+    let amount = ibkr_number(node, "amount")?;
+    let symbol = attr(node, "symbol");
+    let source = Source {
+        row,
+        account_id,
+        date: ibkr_date(node, &["dateTime", "settleDate", "reportDate"])?,
+        currency: attr(node, "currency").map(str::to_string),
+        external_id: attr(node, "transactionID").map(str::to_string),
+        notes: attr(node, "description").map(str::to_string),
+    };
+    let payee = symbol
+        .or(attr(node, "description"))
+        .unwrap_or("Interactive Brokers");
+
+    let mut cash = match kind {
+        "Dividends" | "Payment In Lieu Of Dividends" => source.cash(payee, Some(DIVIDENDS), amount),
+        "Withholding Tax" => source.cash(payee, Some(TAXES), amount),
+        "Other Fees" | "Commission Adjustments" => source.cash(payee, Some(FEES), amount),
+        "Broker Interest Received" | "Broker Interest Paid" | "Bond Interest Received" => {
+            source.cash("Interactive Brokers", Some(INTEREST), amount)
+        }
+        "Deposits/Withdrawals" | "Deposits & Withdrawals" => source.cash(
+            if amount < 0.0 {
+                "Withdrawal"
+            } else {
+                "Deposit"
+            },
+            None,
+            amount,
+        ),
+        other => return Err(format!("Skipped unsupported cash transaction '{}'", other)),
+    };
+    if matches!(cash.category.as_deref(), Some(DIVIDENDS) | Some(TAXES)) {
+        cash.ticker = symbol.map(str::to_string);
+    }
+    Ok(vec![cash])
+}
+
+pub fn parse_ibkr_flex(content: &str, account_id: i32) -> Result<ParsedImport, String> {
+    let doc = Document::parse(content).map_err(|e| format!("Invalid Flex XML: {}", e))?;
+    let statements: Vec<Node> = doc
+        .descendants()
+        .filter(|n| n.has_tag_name("FlexStatement"))
+        .collect();
+    if statements.is_empty() {
+        return Err("No FlexStatement found; export the Flex query as XML".to_string());
+    }
+    ensure_single_source_account(
+        statements
+            .iter()
+            .filter_map(|s| attr(*s, "accountId"))
+            .collect(),
+    )?;
+
+    let mut parsed = ParsedImport::default();
+    for node in doc.descendants() {
+        let is_trade = node.has_tag_name("Trade");
+        if !is_trade && !node.has_tag_name("CashTransaction") {
+            continue;
+        }
+        // Queries run at order or summary level repeat the executions they aggregate
+        if attr(node, "levelOfDetail").is_some_and(|l| l != "EXECUTION" && l != "DETAIL") {
+            continue;
+        }
+        parsed.total_rows += 1;
+        let row = parsed.total_rows;
+        let result = if is_trade {
+            ibkr_trade(node, row, account_id)
+        } else {
+            ibkr_cash(node, row, account_id)
+        };
+        push_result(&mut parsed, row, result);
+    }
+    Ok(parsed)
+}
+
+// ---------- CSV helpers ----------
+
+pub(super) struct Columns(Vec<String>);
+
+impl Columns {
+    pub(super) fn find(&self, names: &[&str]) -> Option<usize> {
+        names.iter().find_map(|name| {
+            self.0
+                .iter()
+                .position(|h| h.trim().eq_ignore_ascii_case(name))
+        })
+    }
+
+    fn starting_with(&self, prefix: &str) -> Option<usize> {
+        let prefix = prefix.to_lowercase();
+        self.0
+            .iter()
+            .position(|h| h.trim().to_lowercase().starts_with(&prefix))
+    }
+
+    pub(super) fn require(&self, names: &[&str]) -> Result<usize, String> {
+        self.find(names)
+            .ok_or_else(|| format!("Missing column '{}'", names[0]))
+    }
+}
+
+pub(super) fn cell(record: &StringRecord, index: Option<usize>) -> &str {
+    index
+        .and_then(|i| record.get(i))
+        .map(str::trim)
+        .unwrap_or_default()
+}
+
+// DEGIRO puts the currency of an amount in the unnamed column right after it
+fn next(index: Option<usize>) -> Option<usize> {
+    index.map(|i| i + 1)
+}
+
+pub(super) fn read_csv(content: &str) -> Result<(Columns, Vec<StringRecord>), String> {
+    let mut reader = csv::ReaderBuilder::new()
+        .flexible(true)
+        .from_reader(content.as_bytes());
+    let headers = reader
+        .headers()
+        .map_err(|e| e.to_string())?
+        .iter()
+        .map(str::to_string)
+        .collect();
+    let mut records = Vec::new();
+    for record in reader.records() {
+        let record = record.map_err(|e| e.to_string())?;
+        if record.iter().all(|f| f.trim().is_empty()) {
+            continue;
+        }
+        records.push(record);
+    }
+    Ok((Columns(headers), records))
+}
+
+// ---------- DEGIRO ----------
+
+fn degiro_date(value: &str) -> Result<String, String> {
+    parse_date(value, Some("%d-%m-%Y"))
+}
+
+struct DegiroTrades {
+    date: usize,
+    time: Option<usize>,
+    product: Option<usize>,
+    isin: usize,
+    quantity: usize,
+    price: Option<usize>,
+    value: Option<usize>,
+    rate: Option<usize>,
+    fee: Option<usize>,
+    order: Option<usize>,
+}
+
+impl DegiroTrades {
+    fn new(columns: &Columns) -> Result<Self, String> {
+        Ok(DegiroTrades {
+            date: columns.require(&["Date", "Datum"])?,
+            time: columns.find(&["Time", "Tijd"]),
+            product: columns.find(&["Product"]),
+            isin: columns.require(&["ISIN"])?,
+            quantity: columns.require(&["Quantity", "Aantal"])?,
+            price: Some(columns.require(&["Price", "Koers"])?),
+            value: columns.find(&["Value", "Waarde"]),
+            rate: columns.find(&["Exchange rate", "Wisselkoers"]),
+            fee: columns.starting_with("Transaction"),
+            order: columns.find(&["Order ID", "Order Id"]),
+        })
+    }
+
+    fn rows(
+        &self,
+        record: &StringRecord,
+        row: usize,
+        account_id: i32,
+        fills: &mut HashMap<String, usize>,
+    ) -> Result<Vec<ImportRow>, String> {
+        let isin = non_empty(cell(record, Some(self.isin))).ok_or("Missing ISIN")?;
+        let shares = number(cell(record, Some(self.quantity)))?;
+        if shares == 0.0 {
+            return Err("Trade has no quantity".to_string());
+        }
+        let local_price = number(cell(record, self.price))?;
+        let local_currency = non_empty(cell(record, next(self.price)));
+        let fee = optional_number(cell(record, self.fee))?
+            .unwrap_or(0.0)
+            .abs();
+        let fee_currency = non_empty(cell(record, next(self.fee)));
+        let value = optional_number(cell(record, self.value))?;
+        let value_currency = non_empty(cell(record, next(self.value)));
+        let date = cell(record, Some(self.date));
+
+        // Orders filled in several parts share the order id
+        let order_id = non_empty(cell(record, self.order))
+            .map(str::to_string)
+            .unwrap_or_else(|| format!("{} {} {}", date, cell(record, self.time), isin));
+        let fill = fills.entry(order_id.clone()).or_insert(0);
+        *fill += 1;
+        let name = non_empty(cell(record, self.product)).unwrap_or(isin);
+
+        // Trades in a foreign currency are settled in the account currency (AutoFX), so the
+        // price is expressed in what actually left the account
+        let (price, currency, notes) = match (value, value_currency) {
+            (Some(v), Some(c)) if v != 0.0 && Some(c) != local_currency => (
+                v.abs() / shares.abs(),
+                Some(c),
+                format!(
+                    "{} ({} @ {} {}, rate {})",
+                    name,
+                    shares.abs(),
+                    local_price,
+                    local_currency.unwrap_or_default(),
+                    cell(record, self.rate)
+                ),
+            ),
+            _ => (local_price, local_currency, name.to_string()),
+        };
+        let source = Source {
+            row,
+            account_id,
+            date: degiro_date(date)?,
+            currency: currency.map(str::to_string),
+            external_id: Some(format!("{}:{}", order_id, fill)),
+            notes: Some(notes),
+        };
+        Ok(source.trade(isin, shares, price, fee, fee_currency))
+    }
+}
+
+fn is_degiro_trade(description: &str) -> bool {
+    let d = description.to_lowercase();
+    d.starts_with("buy ") || d.starts_with("sell ")
+}
+
+struct DegiroAccount {
+    date: usize,
+    time: Option<usize>,
+    product: Option<usize>,
+    isin: Option<usize>,
+    description: usize,
+    change: Option<usize>,
+}
+
+impl DegiroAccount {
+    fn new(columns: &Columns) -> Result<Self, String> {
+        Ok(DegiroAccount {
+            date: columns.require(&["Date", "Datum"])?,
+            time: columns.find(&["Time", "Tijd"]),
+            product: columns.find(&["Product"]),
+            isin: columns.find(&["ISIN"]),
+            description: columns.require(&["Description", "Omschrijving"])?,
+            change: Some(columns.require(&["Change", "Mutatie"])?),
+        })
+    }
+
+    fn moment(&self, record: &StringRecord) -> (String, String) {
+        (
+            cell(record, Some(self.date)).to_string(),
+            cell(record, self.time).to_string(),
+        )
+    }
+
+    fn rows(
+        &self,
+        record: &StringRecord,
+        row: usize,
+        account_id: i32,
+        trade_times: &HashSet<(String, String)>,
+    ) -> Result<Vec<ImportRow>, String> {
+        let text = cell(record, Some(self.description));
+        let lower = text.to_lowercase();
+        let currency = non_empty(cell(record, self.change));
+        let amount = number(cell(record, next(self.change)))?;
+        if amount == 0.0 {
+            return Ok(Vec::new());
+        }
+        if is_degiro_trade(text) {
+            return Err("Trades are imported from the DEGIRO Transactions export".to_string());
+        }
+        if lower.contains("transaction and/or third party fees")
+            || lower.contains("transaction fee")
+        {
+            return Err(
+                "Trade fees are imported with the trade from the Transactions export".to_string(),
+            );
+        }
+
+        let moment = self.moment(record);
+        let source = Source {
+            row,
+            account_id,
+            date: degiro_date(&moment.0)?,
+            currency: currency.map(str::to_string),
+            external_id: Some(format!(
+                "{} {}:{}:{}:{}",
+                moment.0,
+                moment.1,
+                text,
+                amount,
+                currency.unwrap_or_default()
+            )),
+            notes: Some(text.to_string()),
+        };
+        let payee = non_empty(cell(record, self.product)).unwrap_or("DEGIRO");
+        let ticker = non_empty(cell(record, self.isin)).map(str::to_string);
+
+        let row = if lower.starts_with("fx ") || lower.contains("currency conversion") {
+            // Conversions booked at the same moment as a trade are DEGIRO's AutoFX
+            if trade_times.contains(&moment) {
+                return Err(
+                    "Automatic currency conversion of a trade; the trade is imported in the account currency"
+                        .to_string(),
+                );
+            }
+            source.cash("DEGIRO", Some(EXCHANGE), amount)
+        } else if lower.contains("dividend tax") {
+            ImportRow {
+                ticker,
+                ..source.cash(payee, Some(TAXES), amount)
+            }
+        } else if lower.contains("dividend") {
+            ImportRow {
+                ticker,
+                ..source.cash(payee, Some(DIVIDENDS), amount)
+            }
+        } else if lower.contains("deposit") {
+            source.cash("Deposit", None, amount)
+        } else if lower.contains("withdrawal") {
+            source.cash("Withdrawal", None, amount)
+        } else if lower.contains("interest") {
+            source.cash("DEGIRO", Some(INTEREST), amount)
+        } else if lower.contains("fee") {
+            source.cash("DEGIRO", Some(FEES), amount)
+        } else {
+            return Err(format!("Skipped unsupported entry '{}'", text));
+        };
+        Ok(vec![row])
+    }
+}
+
+pub fn parse_degiro(content: &str, account_id: i32) -> Result<ParsedImport, String> {
+    let (columns, records) = read_csv(content)?;
+    let mut parsed = ParsedImport::default();
+
+    if columns.find(&["Description", "Omschrijving"]).is_some() {
+        let layout = DegiroAccount::new(&columns)?;
+        let trade_times: HashSet<(String, String)> = records
+            .iter()
+            .filter(|r| is_degiro_trade(cell(r, Some(layout.description))))
+            .map(|r| layout.moment(r))
+            .collect();
+        for (i, record) in records.iter().enumerate() {
+            parsed.total_rows += 1;
+            let result = layout.rows(record, i + 1, account_id, &trade_times);
+            push_result(&mut parsed, i + 1, result);
+        }
+    } else if columns.find(&["Quantity", "Aantal"]).is_some() {
+        let layout = DegiroTrades::new(&columns)?;
+        let mut fills = HashMap::new();
+        for (i, record) in records.iter().enumerate() {
+            parsed.total_rows += 1;
+            let result = layout.rows(record, i + 1, account_id, &mut fills);
+            push_result(&mut parsed, i + 1, result);
+        }
+    } else {
+        return Err("Not a DEGIRO Transactions or Account export".to_string());
+    }
+    Ok(parsed)
+}
+
+// ---------- Trading 212 ----------
+
+const T212_FEES: [&str; 6] = [
+    "Currency conversion fee",
+    "Transaction fee",
+    "Finra fee",
+    "Stamp duty reserve tax",
+    "Stamp duty",
+    "French transaction tax",
+];
+
+// Notes of conversion rows read like "10.00 EUR -> 10.85 USD"
+fn conversion_legs(notes: &str) -> Option<((f64, String), (f64, String))> {
+    let (from, to) = notes.split_once("->")?;
+    let leg = |s: &str| {
+        let mut parts = s.split_whitespace();
+        let amount = number(parts.next()?).ok()?;
+        let currency = parts.next()?.to_string();
+        Some((amount, currency))
+    };
+    Some((leg(from)?, leg(to)?))
+}
+
+struct Trading212 {
+    action: usize,
+    time: usize,
+    isin: Option<usize>,
+    ticker: Option<usize>,
+    name: Option<usize>,
+    shares: Option<usize>,
+    price: Option<usize>,
+    price_currency: Option<usize>,
+    rate: Option<usize>,
+    total: usize,
+    total_currency: Option<usize>,
+    withholding: Option<usize>,
+    withholding_currency: Option<usize>,
+    notes: Option<usize>,
+    id: Option<usize>,
+    fees: Vec<usize>,
+}
+
+impl Trading212 {
+    fn new(columns: &Columns) -> Result<Self, String> {
+        Ok(Trading212 {
+            action: columns.require(&["Action"])?,
+            time: columns.require(&["Time"])?,
+            isin: columns.find(&["ISIN"]),
+            ticker: columns.find(&["Ticker"]),
+            name: columns.find(&["Name"]),
+            shares: columns.find(&["No. of shares"]),
+            price: columns.find(&["Price / share"]),
+            price_currency: columns.find(&["Currency (Price / share)"]),
+            rate: columns.find(&["Exchange rate"]),
+            total: columns.require(&["Total"])?,
+            total_currency: columns.find(&["Currency (Total)"]),
+            withholding: columns.find(&["Withholding tax"]),
+            withholding_currency: columns.find(&["Currency (Withholding tax)"]),
+            notes: columns.find(&["Notes"]),
+            id: columns.find(&["ID"]),
+            fees: T212_FEES
+                .iter()
+                .filter_map(|f| columns.find(&[f]))
+                .collect(),
+        })
+    }
+
+    fn rows(
+        &self,
+        record: &StringRecord,
+        row: usize,
+        account_id: i32,
+    ) -> Result<Vec<ImportRow>, String> {
+        let action = cell(record, Some(self.action));
+        let kind = action.to_lowercase();
+        let time = cell(record, Some(self.time));
+        let amount = number(cell(record, Some(self.total)))?;
+        let currency = non_empty(cell(record, self.total_currency));
+        let symbol = non_empty(cell(record, self.ticker)).or(non_empty(cell(record, self.isin)));
+        let mut fee = 0.0;
+        for index in &self.fees {
+            fee += optional_number(cell(record, Some(*index)))?
+                .unwrap_or(0.0)
+                .abs();
+        }
+        let source = Source {
+            row,
+            account_id,
+            date: parse_date(time, None)?,
+            currency: currency.map(str::to_string),
+            external_id: Some(
+                non_empty(cell(record, self.id))
+                    .map(str::to_string)
+                    .unwrap_or_else(|| {
+                        format!(
+                            "{}:{}:{}:{}",
+                            time,
+                            kind,
+                            symbol.unwrap_or_default(),
+                            amount
+                        )
+                    }),
+            ),
+            notes: non_empty(cell(record, self.name)).map(str::to_string),
+        };
+
+        if kind.ends_with(" buy") || kind.ends_with(" sell") {
+            return self.trade(record, source, symbol, kind.ends_with(" buy"), amount, fee);
+        }
+
+        let row = if kind.starts_with("dividend") {
+            let tax = optional_number(cell(record, self.withholding))?;
+            let notes = tax.filter(|t| *t != 0.0).map(|t| {
+                format!(
+                    "Withholding tax {} {}",
+                    t.abs(),
+                    cell(record, self.withholding_currency)
+                )
+            });
+            ImportRow {
+                ticker: symbol.map(str::to_string),
+                notes,
+                ..source.cash(symbol.unwrap_or("Trading 212"), Some(DIVIDENDS), amount)
+            }
+        } else if kind == "deposit" {
+            source.cash("Deposit", None, amount.abs())
+        } else if kind == "withdrawal" {
+            source.cash("Withdrawal", None, -amount.abs())
+        } else if kind.contains("interest") {
+            source.cash("Trading 212", Some(INTEREST), amount)
+        } else if kind == "currency conversion" {
+            let ((from, from_currency), (to, to_currency)) =
+                conversion_legs(cell(record, self.notes))
+                    .ok_or("Currency conversion without 'amount CUR -> amount CUR' notes")?;
+            let mut rows = vec![
+                source.leg("from", "Trading 212", EXCHANGE, -from.abs(), &from_currency),
+                source.leg("to", "Trading 212", EXCHANGE, to.abs(), &to_currency),
+            ];
+            if fee != 0.0 {
+                let fee_currency = currency.unwrap_or(&from_currency);
+                rows.push(source.leg("fee", "Trading 212", FEES, -fee, fee_currency));
+            }
+            return Ok(rows);
+        } else {
+            return Err(format!("Skipped unsupported action '{}'", action));
+        };
+        Ok(vec![row])
+    }
+
+    fn trade(
+        &self,
+        record: &StringRecord,
+        source: Source,
+        symbol: Option<&str>,
+        is_buy: bool,
+        total: f64,
+        fee: f64,
+    ) -> Result<Vec<ImportRow>, String> {
+        let symbol = symbol.ok_or("Trade without ticker")?;
+        let count = number(cell(record, self.shares))?.abs();
+        if count == 0.0 {
+            return Err("Trade has no quantity".to_string());
+        }
+        let local_price = number(cell(record, self.price))?;
+        let local_currency = non_empty(cell(record, self.price_currency));
+        let currency = source.currency.as_deref();
+
+        // Foreign shares are paid in the account currency: derive the price from the total,
+        // which includes the fees
+        let (price, notes) = match (local_currency, currency) {
+            (Some(local), Some(account)) if local != account => {
+                let net = if is_buy {
+                    total.abs() - fee
+                } else {
+                    total.abs() + fee
+                };
+                (
+                    net / count,
+                    Some(format!(
+                        "{} ({} @ {} {}, rate {})",
+                        source.notes.as_deref().unwrap_or(symbol),
+                        count,
+                        local_price,
+                        local,
+                        cell(record, self.rate)
+                    )),
+                )
+            }
+            _ => (local_price, source.notes.clone()),
+        };
+        let fee_currency = source.currency.clone();
+        let source = Source { notes, ..source };
+        let signed = if is_buy { count } else { -count };
+        Ok(source.trade(symbol, signed, price, fee, fee_currency.as_deref()))
+    }
+}
+
+pub fn parse_trading212(content: &str, account_id: i32) -> Result<ParsedImport, String> {
+    let (columns, records) = read_csv(content)?;
+    let layout = Trading212::new(&columns)?;
+    let mut parsed = ParsedImport::default();
+    for (i, record) in records.iter().enumerate() {
+        parsed.total_rows += 1;
+        let result = layout.rows(record, i + 1, account_id);
+        push_result(&mut parsed, i + 1, result);
+    }
+    Ok(parsed)
+}
+
+pub fn parse_broker(
+    content: &str,
+    format: BrokerFormat,
+    account_id: i32,
+) -> Result<ParsedImport, String> {
+    match format {
+        BrokerFormat::InteractiveBrokers => parse_ibkr_flex(content, account_id),
+        BrokerFormat::Degiro => parse_degiro(content, account_id),
+        BrokerFormat::Trading212 => parse_trading212(content, account_id),
+    }
+}
+
+pub fn import_broker_db(
+    db_path: &PathBuf,
+    file_path: &Path,
+    format: BrokerFormat,
+    account_id: i32,
+    dry_run: bool,
+    rules: &RuleSelection,
+) -> Result<ImportReport, String> {
+    let content = super::read_text_file(file_path)?;
+    let parsed = parse_broker(&content, format, account_id)?;
+    super::finish_import(db_path, file_path, parsed, false, dry_run, rules)
+}
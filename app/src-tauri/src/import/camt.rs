@@ -0,0 +1,254 @@
+use super::{
+    parse_amount, parse_date, ImportReport, ImportRow, ParsedImport, RowError, StatementBalance,
+};
+use crate::rules::RuleSelection;
+use roxmltree::{Document, Node};
+use std::path::{Path, PathBuf};
+
+// camt.053 versions differ in namespace and in a few wrappers (`Pty` around party names since
+// .08), so elements are matched on local names only.
+fn child<'a, 'i>(node: Node<'a, 'i>, name: &str) -> Option<Node<'a, 'i>> {
+    node.children()
+        .find(|c| c.is_element() && c.tag_name().name() == name)
+}
+
+fn children<'a, 'i: 'a>(
+    node: Node<'a, 'i>,
+    name: &'a str,
+) -> impl Iterator<Item = Node<'a, 'i>> + 'a {
+    node.children()
+        .filter(move |c| c.is_element() && c.tag_name().name() == name)
+}
+
+fn text_at(node: Node, path: &[&str]) -> Option<String> {
+    let mut current = node;
+    for name in path {
+        current = child(current, name)?;
+    }
+    current
+        .text()
+        .map(str::trim)
+        .filter(|t| !t.is_empty())
+        .map(str::to_string)
+}
+
+// `<Dt><Dt>2024-01-05</Dt></Dt>` or `<Dt><DtTm>2024-01-05T10:00:00</DtTm></Dt>`
+fn date_at(node: Node, name: &str) -> Option<String> {
+    let wrapper = child(node, name)?;
+    text_at(wrapper, &["Dt"])
+        .or_else(|| text_at(wrapper, &["DtTm"]))
+        .and_then(|d| parse_date(&d, None).ok())
+}
+
+// Amounts are unsigned; the credit/debit indicator carries the direction
+fn signed_amount(node: Node) -> Result<Option<f64>, String> {
+    let Some(value) = text_at(node, &["Amt"]) else {
+        return Ok(None);
+    };
+    let amount = parse_amount(&value, '.', None)?.abs();
+    Ok(Some(match text_at(node, &["CdtDbtInd"]).as_deref() {
+        Some("DBIT") => -amount,
+        _ => amount,
+    }))
+}
+
+fn party_name(party: Node) -> Option<String> {
+    text_at(party, &["Nm"]).or_else(|| text_at(party, &["Pty", "Nm"]))
+}
+
+fn account_iban(account: Node) -> Option<String> {
+    text_at(account, &["Id", "IBAN"]).or_else(|| text_at(account, &["Id", "Othr", "Id"]))
+}
+
+// Usable reference, skipping the `NOTPROVIDED` placeholder SEPA uses for missing ones
+fn reference(node: Node, path: &[&str]) -> Option<String> {
+    text_at(node, path).filter(|r| !r.eq_ignore_ascii_case("NOTPROVIDED"))
+}
+
+fn entry_rows(
+    entry: Node,
+    account_id: i32,
+    currency: Option<&str>,
+    row: usize,
+) -> Result<Vec<ImportRow>, String> {
+    let date = date_at(entry, "BookgDt")
+        .or_else(|| date_at(entry, "ValDt"))
+        .ok_or("Missing booking date")?;
+    let value_date = date_at(entry, "ValDt").filter(|d| *d != date);
+    let entry_amount = signed_amount(entry)?.ok_or("Missing amount")?;
+    let entry_currency = child(entry, "Amt")
+        .and_then(|a| a.attribute("Ccy"))
+        .or(currency)
+        .map(str::to_string);
+
+    // A batch entry lists each underlying payment in its own TxDtls
+    let details: Vec<Node> = child(entry, "NtryDtls")
+        .map(|d| children(d, "TxDtls").collect())
+        .unwrap_or_default();
+    let incoming = entry_amount >= 0.0;
+
+    // `detail` is the position of the payment in a batch entry
+    let build = |tx: Option<Node>, detail: Option<usize>, amount: f64| -> ImportRow {
+        let (party, party_account) = if incoming {
+            ("Dbtr", "DbtrAcct")
+        } else {
+            ("Cdtr", "CdtrAcct")
+        };
+        let parties = tx.and_then(|t| child(t, "RltdPties"));
+        let name = parties.and_then(|p| child(p, party)).and_then(party_name);
+        let iban = parties
+            .and_then(|p| child(p, party_account))
+            .and_then(account_iban);
+        let end_to_end = tx.and_then(|t| reference(t, &["Refs", "EndToEndId"]));
+        let remittance: Vec<String> = tx
+            .and_then(|t| child(t, "RmtInf"))
+            .map(|r| {
+                children(r, "Ustrd")
+                    .filter_map(|u| u.text().map(|t| t.trim().to_string()))
+                    .filter(|t| !t.is_empty())
+                    .collect()
+            })
+            .unwrap_or_default();
+        let additional = text_at(entry, &["AddtlNtryInf"]);
+
+        let payee = name
+            .clone()
+            .or_else(|| iban.clone())
+            .or_else(|| additional.clone())
+            .unwrap_or_else(|| "Unknown".to_string());
+
+        let mut notes = Vec::new();
+        if !remittance.is_empty() {
+            notes.push(remittance.join(" "));
+        } else if let Some(info) = additional.filter(|i| *i != payee) {
+            notes.push(info);
+        }
+        if let (Some(_), Some(iban)) = (&name, &iban) {
+            notes.push(format!("IBAN {}", iban));
+        }
+        if let Some(e2e) = &end_to_end {
+            notes.push(format!("Ref {}", e2e));
+        }
+
+        // The end-to-end reference comes first, then the bank's own. A reference of the whole
+        // batch entry is shared by its payments, which are told apart by their position.
+        let external_id = end_to_end
+            .as_deref()
+            .map(|e2e| super::payment_key(e2e, amount))
+            .or_else(|| tx.and_then(|t| reference(t, &["Refs", "AcctSvcrRef"])))
+            .or_else(|| {
+                reference(entry, &["AcctSvcrRef"])
+                    .or_else(|| reference(entry, &["NtryRef"]))
+                    .map(|r| match detail {
+                        Some(i) => format!("{}/{}", r, i + 1),
+                        None => r,
+                    })
+            });
+
+        ImportRow {
+            row,
+            account_id: Some(account_id),
+            date: date.clone(),
+            value_date: value_date.clone(),
+            payee,
+            notes: if notes.is_empty() {
+                None
+            } else {
+                Some(notes.join("; "))
+            },
+            amount,
+            currency: entry_currency.clone(),
+            external_id,
+            ..Default::default()
+        }
+    };
+
+    if details.len() <= 1 {
+        return Ok(vec![build(details.first().copied(), None, entry_amount)]);
+    }
+    let mut rows = Vec::new();
+    for (i, tx) in details.into_iter().enumerate() {
+        let amount =
+            match text_at(tx, &["Amt"]).or_else(|| text_at(tx, &["AmtDtls", "TxAmt", "Amt"])) {
+                Some(v) => parse_amount(&v, '.', None)?.abs() * entry_amount.signum(),
+                None => return Err("Batch entry detail without amount".to_string()),
+            };
+        rows.push(build(Some(tx), Some(i), amount));
+    }
+    Ok(rows)
+}
+
+// Parses every statement (`Stmt`) of a camt.053 document into rows for `account_id`. The closing
+// booked balance of each statement is verified after import.
+pub fn parse_camt053(content: &str, account_id: i32) -> Result<ParsedImport, String> {
+    let doc = Document::parse(content).map_err(|e| format!("Invalid camt.053 XML: {}", e))?;
+    let statements: Vec<Node> = doc
+        .descendants()
+        .filter(|n| n.is_element() && n.tag_name().name() == "Stmt")
+        .collect();
+    if statements.is_empty() {
+        return Err("No camt.053 statement (Stmt) found in file".to_string());
+    }
+
+    super::ensure_single_source_account(
+        statements
+            .iter()
+            .filter_map(|s| child(*s, "Acct").and_then(account_iban))
+            .collect(),
+    )?;
+
+    let mut parsed = ParsedImport::default();
+    for statement in statements {
+        let currency = child(statement, "Acct").and_then(|a| text_at(a, &["Ccy"]));
+
+        let mut opening = None;
+        let mut closing = None;
+        for balance in children(statement, "Bal") {
+            let code = text_at(balance, &["Tp", "CdOrPrtry", "Cd"]);
+            let amount = signed_amount(balance)?;
+            let date = date_at(balance, "Dt");
+            match code.as_deref() {
+                Some("OPBD") | Some("PRCD") => opening = amount,
+                Some("CLBD") => closing = amount.zip(date),
+                _ => {}
+            }
+        }
+
+        for entry in children(statement, "Ntry") {
+            // Pending entries are not booked yet and would be imported twice later on
+            let status = text_at(entry, &["Sts"]).or_else(|| text_at(entry, &["Sts", "Cd"]));
+            if status.as_deref() == Some("PDNG") {
+                continue;
+            }
+            parsed.total_rows += 1;
+            let row = parsed.total_rows;
+            match entry_rows(entry, account_id, currency.as_deref(), row) {
+                Ok(rows) => parsed.rows.extend(rows),
+                Err(message) => parsed.errors.push(RowError { row, message }),
+            }
+        }
+
+        if let Some((amount, date)) = closing {
+            parsed.balances.push(StatementBalance {
+                account_id: Some(account_id),
+                account_name: None,
+                date,
+                amount,
+                opening_amount: opening,
+            });
+        }
+    }
+    Ok(parsed)
+}
+
+pub fn import_camt053_db(
+    db_path: &PathBuf,
+    file_path: &Path,
+    account_id: i32,
+    dry_run: bool,
+    rules: &RuleSelection,
+) -> Result<ImportReport, String> {
+    let content = super::read_text_file(file_path)?;
+    let parsed = parse_camt053(&content, account_id)?;
+    super::finish_import(db_path, file_path, parsed, false, dry_run, rules)
+}
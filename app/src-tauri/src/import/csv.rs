@@ -0,0 +1,338 @@
+use super::{parse_amount, parse_date, ImportReport, ImportRow, ParsedImport, RowError};
+use crate::rules::RuleSelection;
+use ::csv::{ReaderBuilder, StringRecord};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum SignConvention {
+    // Positive amounts are money coming in (checking account exports)
+    #[default]
+    PositiveIsIncome,
+    // Positive amounts are spending (most credit card exports)
+    PositiveIsExpense,
+}
+
+// Column mapping for a CSV import. Column references are header names (case-insensitive) or,
+// for files without a header row, 1-based column numbers.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase", default)]
+pub struct CsvMapping {
+    pub date: Option<String>,
+    pub payee: Option<String>,
+    pub amount: Option<String>,
+    pub debit: Option<String>,
+    pub credit: Option<String>,
+    pub category: Option<String>,
+    pub notes: Option<String>,
+    pub account: Option<String>,
+    pub ticker: Option<String>,
+    pub shares: Option<String>,
+    pub price: Option<String>,
+    pub fee: Option<String>,
+    pub currency: Option<String>,
+    pub reference: Option<String>,
+    pub date_format: Option<String>,
+    pub decimal_separator: char,
+    pub thousands_separator: Option<char>,
+    pub delimiter: Option<char>,
+    pub has_header: bool,
+    pub skip_rows: usize,
+    pub sign_convention: SignConvention,
+    pub account_id: Option<i32>,
+    pub create_missing_accounts: bool,
+}
+
+impl Default for CsvMapping {
+    fn default() -> Self {
+        CsvMapping {
+            date: None,
+            payee: None,
+            amount: None,
+            debit: None,
+            credit: None,
+            category: None,
+            notes: None,
+            account: None,
+            ticker: None,
+            shares: None,
+            price: None,
+            fee: None,
+            currency: None,
+            reference: None,
+            date_format: None,
+            decimal_separator: '.',
+            thousands_separator: None,
+            delimiter: None,
+            has_header: true,
+            skip_rows: 0,
+            sign_convention: SignConvention::PositiveIsIncome,
+            account_id: None,
+            create_missing_accounts: false,
+        }
+    }
+}
+
+// Picks the most frequent of the usual delimiters on the first line
+fn sniff_delimiter(content: &str) -> u8 {
+    let first_line = content.lines().find(|l| !l.trim().is_empty()).unwrap_or("");
+    [b',', b';', b'\t', b'|']
+        .into_iter()
+        .max_by_key(|d| first_line.bytes().filter(|b| b == d).count())
+        .filter(|d| first_line.as_bytes().contains(d))
+        .unwrap_or(b',')
+}
+
+fn column_index(headers: &[String], reference: &Option<String>) -> Result<Option<usize>, String> {
+    let Some(reference) = reference
+        .as_deref()
+        .map(str::trim)
+        .filter(|r| !r.is_empty())
+    else {
+        return Ok(None);
+    };
+    if let Some(idx) = headers
+        .iter()
+        .position(|h| h.trim().eq_ignore_ascii_case(reference))
+    {
+        return Ok(Some(idx));
+    }
+    match reference.parse::<usize>() {
+        Ok(n) if n >= 1 && n <= headers.len() => Ok(Some(n - 1)),
+        _ => Err(format!("Column '{}' not found in file", reference)),
+    }
+}
+
+struct ColumnIndexes {
+    date: usize,
+    payee: Option<usize>,
+    amount: Option<usize>,
+    debit: Option<usize>,
+    credit: Option<usize>,
+    category: Option<usize>,
+    notes: Option<usize>,
+    account: Option<usize>,
+    ticker: Option<usize>,
+    shares: Option<usize>,
+    price: Option<usize>,
+    fee: Option<usize>,
+    currency: Option<usize>,
+    reference: Option<usize>,
+}
+
+impl ColumnIndexes {
+    fn resolve(headers: &[String], mapping: &CsvMapping) -> Result<Self, String> {
+        let date = column_index(headers, &mapping.date)?
+            .ok_or_else(|| "A date column is required".to_string())?;
+        let amount = column_index(headers, &mapping.amount)?;
+        let debit = column_index(headers, &mapping.debit)?;
+        let credit = column_index(headers, &mapping.credit)?;
+        if amount.is_none() && debit.is_none() && credit.is_none() {
+            return Err("An amount column or debit/credit columns are required".to_string());
+        }
+        if mapping.account_id.is_none() && mapping.account.is_none() {
+            return Err("Choose a default account or map an account column".to_string());
+        }
+        Ok(ColumnIndexes {
+            date,
+            payee: column_index(headers, &mapping.payee)?,
+            amount,
+            debit,
+            credit,
+            category: column_index(headers, &mapping.category)?,
+            notes: column_index(headers, &mapping.notes)?,
+            account: column_index(headers, &mapping.account)?,
+            ticker: column_index(headers, &mapping.ticker)?,
+            shares: column_index(headers, &mapping.shares)?,
+            price: column_index(headers, &mapping.price)?,
+            fee: column_index(headers, &mapping.fee)?,
+            currency: column_index(headers, &mapping.currency)?,
+            reference: column_index(headers, &mapping.reference)?,
+        })
+    }
+}
+
+fn cell(record: &StringRecord, idx: Option<usize>) -> Option<String> {
+    idx.and_then(|i| record.get(i))
+        .map(|v| v.trim().to_string())
+        .filter(|v| !v.is_empty())
+}
+
+// Drops the lines above the header and works out the delimiter
+fn prepare(content: &str, mapping: &CsvMapping) -> Result<(String, u8), String> {
+    let content: String = content
+        .lines()
+        .skip(mapping.skip_rows)
+        .collect::<Vec<_>>()
+        .join("\n");
+    let delimiter = match mapping.delimiter {
+        Some(d) if d.is_ascii() => d as u8,
+        Some(d) => return Err(format!("Unsupported delimiter '{}'", d)),
+        None => sniff_delimiter(&content),
+    };
+    Ok((content, delimiter))
+}
+
+// Identifies the layout of a bank's export: the normalized header row, or the column count for
+// files without one. Import profiles are recognized by it.
+pub fn header_fingerprint(content: &str, mapping: &CsvMapping) -> Result<String, String> {
+    let (content, delimiter) = prepare(content, mapping)?;
+    let first = ReaderBuilder::new()
+        .delimiter(delimiter)
+        .has_headers(false)
+        .flexible(true)
+        .from_reader(content.as_bytes())
+        .records()
+        .next()
+        .transpose()
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| "The file is empty".to_string())?;
+    if !mapping.has_header {
+        return Ok(format!("{} columns", first.len()));
+    }
+    Ok(first
+        .iter()
+        .map(|h| h.trim().to_lowercase())
+        .collect::<Vec<_>>()
+        .join("|"))
+}
+
+// Parses CSV text into normalized rows, collecting per-row errors. Mapping problems (e.g.
+// unknown columns) fail the whole parse.
+pub fn parse_csv(content: &str, mapping: &CsvMapping) -> Result<ParsedImport, String> {
+    let (content, delimiter) = prepare(content, mapping)?;
+
+    let mut reader = ReaderBuilder::new()
+        .delimiter(delimiter)
+        .has_headers(mapping.has_header)
+        .flexible(true)
+        .from_reader(content.as_bytes());
+
+    // Without a header row this is the first record, which `records()` still yields
+    let first = reader.headers().map_err(|e| e.to_string())?;
+    let headers: Vec<String> = if mapping.has_header {
+        first.iter().map(|h| h.trim().to_string()).collect()
+    } else {
+        (1..=first.len()).map(|i| format!("Column {}", i)).collect()
+    };
+    let columns = ColumnIndexes::resolve(&headers, mapping)?;
+
+    let mut rows = Vec::new();
+    let mut errors = Vec::new();
+    let mut total = 0;
+
+    for record in reader.records() {
+        let record = match record {
+            Ok(r) => r,
+            Err(e) => {
+                total += 1;
+                let line = e.position().map(|p| p.line() as usize).unwrap_or(total);
+                errors.push(RowError {
+                    row: line + mapping.skip_rows,
+                    message: e.to_string(),
+                });
+                continue;
+            }
+        };
+        if record.iter().all(|f| f.trim().is_empty()) {
+            continue;
+        }
+        total += 1;
+        let line = record
+            .position()
+            .map(|p| p.line() as usize)
+            .unwrap_or(total)
+            + mapping.skip_rows;
+
+        match parse_record(&record, &columns, mapping, line) {
+            Ok(row) => rows.push(row),
+            Err(message) => errors.push(RowError { row: line, message }),
+        }
+    }
+
+    Ok(ParsedImport {
+        rows,
+        errors,
+        total_rows: total,
+        balances: Vec::new(),
+        accounts: Vec::new(),
+        prices: Vec::new(),
+    })
+}
+
+fn parse_record(
+    record: &StringRecord,
+    columns: &ColumnIndexes,
+    mapping: &CsvMapping,
+    line: usize,
+) -> Result<ImportRow, String> {
+    let number = |idx: Option<usize>| -> Result<Option<f64>, String> {
+        cell(record, idx)
+            .map(|v| parse_amount(&v, mapping.decimal_separator, mapping.thousands_separator))
+            .transpose()
+    };
+
+    let date = parse_date(
+        &cell(record, Some(columns.date)).unwrap_or_default(),
+        mapping.date_format.as_deref(),
+    )?;
+
+    let mut amount = match (
+        number(columns.amount)?,
+        number(columns.debit)?,
+        number(columns.credit)?,
+    ) {
+        (Some(a), _, _) => a,
+        (None, None, None) => return Err("Missing amount".to_string()),
+        // Debit/credit columns usually hold unsigned values; debits are money out
+        (None, debit, credit) => credit.unwrap_or(0.0).abs() - debit.unwrap_or(0.0).abs(),
+    };
+    if mapping.sign_convention == SignConvention::PositiveIsExpense {
+        amount = -amount;
+    }
+
+    let account_name = cell(record, columns.account);
+    let account_id = if account_name.is_some() {
+        None
+    } else {
+        mapping.account_id
+    };
+
+    Ok(ImportRow {
+        row: line,
+        account_id,
+        account_name,
+        date,
+        payee: cell(record, columns.payee).unwrap_or_else(|| "Unknown".to_string()),
+        notes: cell(record, columns.notes),
+        category: cell(record, columns.category),
+        amount,
+        ticker: cell(record, columns.ticker),
+        shares: number(columns.shares)?,
+        price_per_share: number(columns.price)?,
+        fee: number(columns.fee)?,
+        currency: cell(record, columns.currency),
+        external_id: cell(record, columns.reference),
+        ..Default::default()
+    })
+}
+
+pub fn import_csv_db(
+    db_path: &PathBuf,
+    file_path: &Path,
+    mapping: CsvMapping,
+    dry_run: bool,
+    rules: &RuleSelection,
+) -> Result<ImportReport, String> {
+    let content = super::read_text_file(file_path)?;
+    let parsed = parse_csv(&content, &mapping)?;
+    super::finish_import(
+        db_path,
+        file_path,
+        parsed,
+        mapping.create_missing_accounts,
+        dry_run,
+        rules,
+    )
+}
@@ -0,0 +1,757 @@
+use crate::payees::PayeeCleaner;
+use crate::rules::{
+    split_amounts, transfer_payee, DraftTransaction, RuleEngine, RuleSelection, SplitPart,
+};
+use chrono::NaiveDate;
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+pub mod apps;
+pub mod batches;
+pub mod beancount;
+pub mod broker;
+pub mod camt;
+pub mod csv;
+pub mod mt940;
+pub mod ofx;
+pub mod profiles;
+pub mod qif;
+
+// A statement line normalized to the shape of `CreateTransactionArgs`, shared by every importer.
+// `account_name` is kept alongside `account_id` so a preview can show accounts that will only be
+// created when the import is committed.
+#[derive(Clone, Serialize, Deserialize, Debug, Default, PartialEq)]
+pub struct ImportRow {
+    pub row: usize,
+    pub account_id: Option<i32>,
+    pub account_name: Option<String>,
+    pub date: String,
+    pub payee: String,
+    pub notes: Option<String>,
+    pub category: Option<String>,
+    pub amount: f64,
+    pub ticker: Option<String>,
+    pub shares: Option<f64>,
+    pub price_per_share: Option<f64>,
+    pub fee: Option<f64>,
+    pub currency: Option<String>,
+    // Identifier assigned by the source (OFX FITID, bank reference) used to skip re-imports
+    pub external_id: Option<String>,
+    // Set for brokerage trades, which go through the investment transaction path
+    pub is_buy: Option<bool>,
+    // Value date from bank statements, when it differs from the booking date in `date`
+    pub value_date: Option<String>,
+    // For transfers between currencies, the amount that lands in the other account
+    pub transfer_amount: Option<f64>,
+    // Rules that changed the row, recorded on the inserted transaction
+    #[serde(default)]
+    pub rule_ids: Vec<i32>,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    // Payee as the statement had it, before cleaning and rules
+    #[serde(default)]
+    pub raw_payee: Option<String>,
+    // Set by a split rule; the row becomes one row per part once duplicates are screened
+    #[serde(skip)]
+    pub splits: Vec<SplitPart>,
+    // Rows that are parts of one split statement line share this, and are stored as one split
+    #[serde(default)]
+    pub split_group: Option<usize>,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct RowError {
+    pub row: usize,
+    pub message: String,
+}
+
+// Balance reported by the statement itself, used to verify the ledger after import
+#[derive(Clone, Serialize, Deserialize, Debug, Default, PartialEq)]
+pub struct StatementBalance {
+    pub account_id: Option<i32>,
+    pub account_name: Option<String>,
+    pub date: String,
+    pub amount: f64,
+    // Opening balance of the same statement, shown next to the check
+    pub opening_amount: Option<f64>,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct BalanceCheck {
+    pub account_id: i32,
+    pub date: String,
+    pub opening_balance: Option<f64>,
+    pub statement_balance: f64,
+    pub computed_balance: f64,
+    pub matches: bool,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct DeclaredAccount {
+    pub name: String,
+    pub currency: Option<String>,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct PricePoint {
+    pub ticker: String,
+    pub date: String,
+    pub price: f64,
+}
+
+// Output of a format parser, before anything touches the database
+#[derive(Debug, Default)]
+pub struct ParsedImport {
+    pub rows: Vec<ImportRow>,
+    pub errors: Vec<RowError>,
+    pub total_rows: usize,
+    pub balances: Vec<StatementBalance>,
+    // Accounts declared by the file (e.g. transfer targets) that must exist even without rows
+    pub accounts: Vec<DeclaredAccount>,
+    // Security prices carried by the file, stored in `daily_stock_prices`
+    pub prices: Vec<PricePoint>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Default)]
+pub struct ImportReport {
+    pub dry_run: bool,
+    pub total_rows: usize,
+    pub rows: Vec<ImportRow>,
+    pub errors: Vec<RowError>,
+    // Rows skipped because their external id was already imported into the account, or because
+    // they exactly match a transaction that is already there
+    pub duplicates: Vec<ImportRow>,
+    // Rows that will be imported but look like a transaction already in the ledger
+    pub possible_duplicates: Vec<crate::duplicates::PossibleDuplicate>,
+    pub balance_checks: Vec<BalanceCheck>,
+    pub imported: usize,
+    pub created_accounts: Vec<String>,
+    pub prices: usize,
+    // Batch the committed rows belong to, for `rollback_import_batch`
+    pub batch_id: Option<i64>,
+}
+
+const BALANCE_TOLERANCE: f64 = 0.005;
+
+// Reads an import file as text. Bank exports are frequently Latin-1/Windows-1252 rather than
+// UTF-8, so invalid UTF-8 falls back to a byte-per-char decode instead of failing the import.
+pub fn read_text_file(path: &Path) -> Result<String, String> {
+    let bytes = std::fs::read(path).map_err(|e| e.to_string())?;
+    let text = match String::from_utf8(bytes) {
+        Ok(s) => s,
+        Err(e) => e.into_bytes().iter().map(|&b| b as char).collect(),
+    };
+    Ok(text.trim_start_matches('\u{feff}').to_string())
+}
+
+const FALLBACK_DATE_FORMATS: [&str; 8] = [
+    "%Y-%m-%d", "%Y/%m/%d", "%d/%m/%Y", "%d-%m-%Y", "%d.%m.%Y", "%Y%m%d", "%d/%m/%y", "%d.%m.%y",
+];
+
+// Parses a date into the `YYYY-MM-DD` form stored in `transactions.date`. Without an explicit
+// format, day-first forms win over month-first, matching the frontend importer's fallback.
+pub fn parse_date(value: &str, format: Option<&str>) -> Result<String, String> {
+    let trimmed = value.trim();
+    if trimmed.is_empty() {
+        return Err("Missing date".to_string());
+    }
+
+    if let Some(fmt) = format.filter(|f| !f.trim().is_empty()) {
+        return NaiveDate::parse_from_str(trimmed, fmt)
+            .map(|d| d.format("%Y-%m-%d").to_string())
+            .map_err(|_| format!("Date '{}' does not match format '{}'", trimmed, fmt));
+    }
+
+    // ISO timestamps: keep only the date part
+    let candidate = if trimmed.len() > 10
+        && trimmed
+            .as_bytes()
+            .get(10)
+            .is_some_and(|b| *b == b'T' || *b == b' ')
+    {
+        &trimmed[..10]
+    } else {
+        trimmed
+    };
+
+    FALLBACK_DATE_FORMATS
+        .iter()
+        .find_map(|fmt| NaiveDate::parse_from_str(candidate, fmt).ok())
+        .map(|d| d.format("%Y-%m-%d").to_string())
+        .ok_or_else(|| format!("Unrecognized date '{}'", trimmed))
+}
+
+// Parses a localized amount such as "1.234,56", "(12.00)", "12.00-", "€ 1 234,50" or
+// "12.00 EUR".
+pub fn parse_amount(
+    value: &str,
+    decimal_separator: char,
+    thousands_separator: Option<char>,
+) -> Result<f64, String> {
+    let trimmed = value.trim();
+    if trimmed.is_empty() {
+        return Err("Missing amount".to_string());
+    }
+
+    let mut negative = false;
+    let mut body = trimmed;
+    if body.starts_with('(') && body.ends_with(')') {
+        negative = true;
+        body = &body[1..body.len() - 1];
+    }
+    if let Some(stripped) = body.strip_suffix('-') {
+        negative = !negative;
+        body = stripped;
+    }
+
+    let chars: Vec<char> = body.chars().collect();
+    let mut normalized = String::with_capacity(body.len());
+    for (i, &c) in chars.iter().enumerate() {
+        // An exponent only counts between digits, so "EUR" or "SEK" is not read as one
+        let exponent = (c == 'e' || c == 'E')
+            && i > 0
+            && chars[i - 1].is_ascii_digit()
+            && chars
+                .get(i + 1)
+                .is_some_and(|n| n.is_ascii_digit() || *n == '-' || *n == '+');
+        if c == decimal_separator {
+            normalized.push('.');
+        } else if Some(c) == thousands_separator || c.is_whitespace() || c == '\u{a0}' {
+            continue;
+        } else if c.is_ascii_digit() || c == '-' || c == '+' || exponent {
+            normalized.push(c);
+        } else if c.is_alphabetic() || "$€£¥₹'".contains(c) {
+            // Currency symbols/codes around the number
+            continue;
+        } else {
+            return Err(format!("Invalid amount '{}'", trimmed));
+        }
+    }
+
+    let parsed: f64 = normalized
+        .parse()
+        .map_err(|_| format!("Invalid amount '{}'", trimmed))?;
+    if !parsed.is_finite() {
+        return Err(format!("Invalid amount '{}'", trimmed));
+    }
+    Ok(if negative { -parsed } else { parsed })
+}
+
+// Fills in `account_id` for rows that only carry an account name. Unknown names are an error
+// unless the caller allows creating the account at commit time.
+pub fn resolve_accounts(
+    conn: &Connection,
+    rows: Vec<ImportRow>,
+    create_missing: bool,
+    errors: &mut Vec<RowError>,
+) -> Result<Vec<ImportRow>, String> {
+    let mut resolved = Vec::with_capacity(rows.len());
+    for mut row in rows {
+        if row.account_id.is_none() {
+            match row.account_name.as_deref().map(str::trim) {
+                Some(name) if !name.is_empty() => {
+                    row.account_id = find_account_id(conn, name)?;
+                    if row.account_id.is_none() && !create_missing {
+                        errors.push(RowError {
+                            row: row.row,
+                            message: format!("Account '{}' does not exist", name),
+                        });
+                        continue;
+                    }
+                }
+                _ => {
+                    errors.push(RowError {
+                        row: row.row,
+                        message: "No account for row".to_string(),
+                    });
+                    continue;
+                }
+            }
+        }
+        resolved.push(row);
+    }
+    Ok(resolved)
+}
+
+fn find_account_id(conn: &Connection, name: &str) -> Result<Option<i32>, String> {
+    conn.query_row(
+        "SELECT id FROM accounts WHERE LOWER(name) = LOWER(?1) LIMIT 1",
+        params![name.trim()],
+        |row| row.get(0),
+    )
+    .optional()
+    .map_err(|e| e.to_string())
+}
+
+// Looks an account up by name (case-insensitive), creating it when missing. `known` caches
+// lookups for the duration of one import.
+fn account_for_name(
+    tx: &Connection,
+    name: &str,
+    currency: Option<&str>,
+    known: &mut HashMap<String, i32>,
+    created: &mut Vec<String>,
+) -> Result<i32, String> {
+    let key = name.to_lowercase();
+    if let Some(id) = known.get(&key) {
+        return Ok(*id);
+    }
+    let id = match find_account_id(tx, name)? {
+        Some(id) => id,
+        None => {
+            tx.execute(
+                "INSERT INTO accounts (name, balance, currency) VALUES (?1, 0, ?2)",
+                params![name, currency],
+            )
+            .map_err(|e| e.to_string())?;
+            created.push(name.to_string());
+            tx.last_insert_rowid() as i32
+        }
+    };
+    known.insert(key, id);
+    Ok(id)
+}
+
+// Inserts all rows in a single SQLite transaction, creating any accounts referenced by name
+// that do not exist yet, and records them as one import batch. Any failure rolls the whole
+// import back.
+pub fn commit_rows(
+    db_path: &PathBuf,
+    file_path: &Path,
+    counts: &batches::BatchCounts,
+    rows: &[ImportRow],
+    accounts: &[DeclaredAccount],
+    prices: &[PricePoint],
+) -> Result<(Vec<crate::Transaction>, Vec<String>, i64), String> {
+    let mut conn = Connection::open(db_path).map_err(|e| e.to_string())?;
+    let tx = conn.transaction().map_err(|e| e.to_string())?;
+    let batch_id = batches::create_batch(&tx, file_path, counts)?;
+
+    let mut created_accounts = Vec::new();
+    let mut known_accounts: HashMap<String, i32> = HashMap::new();
+    let mut inserted = Vec::with_capacity(rows.len());
+    let mut splits: HashMap<usize, Vec<i32>> = HashMap::new();
+
+    // Declared accounts first, so transfers between them are detected
+    for account in accounts.iter().filter(|a| !a.name.trim().is_empty()) {
+        account_for_name(
+            &tx,
+            account.name.trim(),
+            account.currency.as_deref(),
+            &mut known_accounts,
+            &mut created_accounts,
+        )?;
+    }
+
+    for row in rows {
+        let account_id = match row.account_id {
+            Some(id) => id,
+            None => {
+                let name = row
+                    .account_name
+                    .as_deref()
+                    .map(str::trim)
+                    .filter(|n| !n.is_empty())
+                    .ok_or_else(|| format!("Row {}: no account for row", row.row))?;
+                account_for_name(
+                    &tx,
+                    name,
+                    row.currency.as_deref(),
+                    &mut known_accounts,
+                    &mut created_accounts,
+                )?
+            }
+        };
+
+        let transaction = match row.is_buy {
+            Some(is_buy) => crate::insert_investment_transaction(
+                &tx,
+                crate::CreateInvestmentTransactionArgs {
+                    account_id,
+                    date: row.date.clone(),
+                    ticker: row
+                        .ticker
+                        .clone()
+                        .ok_or_else(|| format!("Row {}: trade without ticker", row.row))?,
+                    shares: row.shares.unwrap_or(0.0).abs(),
+                    price_per_share: row.price_per_share.unwrap_or(0.0),
+                    fee: row.fee.unwrap_or(0.0),
+                    is_buy,
+                    currency: row.currency.clone(),
+                },
+            ),
+            None => crate::insert_transaction(
+                &tx,
+                crate::CreateTransactionArgs {
+                    account_id,
+                    date: row.date.clone(),
+                    payee: row.payee.clone(),
+                    notes: row.notes.clone(),
+                    category: row.category.clone(),
+                    amount: row.amount,
+                    ticker: row.ticker.clone(),
+                    shares: row.shares,
+                    price_per_share: row.price_per_share,
+                    fee: row.fee,
+                    currency: row.currency.clone(),
+                },
+            ),
+        }
+        .map_err(|e| format!("Row {}: {}", row.row, e))?;
+
+        crate::rules::record_rule_ids(&tx, transaction.id, &row.rule_ids)?;
+        crate::rules::record_tags(&tx, transaction.id, &row.tags)?;
+        if let Some(ref raw_payee) = row.raw_payee {
+            crate::payees::record_raw_payee(&tx, transaction.id, raw_payee, &row.payee)?;
+        }
+        if row.external_id.is_some() || row.value_date.is_some() {
+            tx.execute(
+                "UPDATE transactions SET external_id = ?1, value_date = ?2 WHERE id = ?3",
+                params![row.external_id, row.value_date, transaction.id],
+            )
+            .map_err(|e| e.to_string())?;
+        }
+        // The counterpart was created as the exact opposite; correct it to the converted amount
+        if let Some(received) = row.transfer_amount {
+            let linked: Option<(i32, i32, f64)> = tx
+                .query_row(
+                    "SELECT l.id, l.account_id, l.amount FROM transactions t JOIN transactions l ON l.id = t.linked_tx_id WHERE t.id = ?1",
+                    params![transaction.id],
+                    |r| Ok((r.get(0)?, r.get(1)?, r.get(2)?)),
+                )
+                .optional()
+                .map_err(|e| e.to_string())?;
+            if let Some((linked_id, linked_account, linked_amount)) = linked {
+                tx.execute(
+                    "UPDATE transactions SET amount = ?1 WHERE id = ?2",
+                    params![received, linked_id],
+                )
+                .map_err(|e| e.to_string())?;
+                tx.execute(
+                    "UPDATE accounts SET balance = balance + ?1 WHERE id = ?2",
+                    params![received - linked_amount, linked_account],
+                )
+                .map_err(|e| e.to_string())?;
+            }
+        }
+        batches::tag_transaction(&tx, batch_id, transaction.id)?;
+        if let Some(group) = row.split_group {
+            splits.entry(group).or_default().push(transaction.id);
+        }
+        inserted.push(transaction);
+    }
+    for ids in splits.values() {
+        crate::rules::record_split(&tx, ids)?;
+    }
+    batches::finish_batch(&tx, batch_id, inserted.len())?;
+
+    for price in prices {
+        tx.execute(
+            "INSERT OR REPLACE INTO daily_stock_prices (ticker, date, price) VALUES (?1, ?2, ?3)",
+            params![price.ticker, price.date, price.price],
+        )
+        .map_err(|e| e.to_string())?;
+    }
+
+    tx.commit().map_err(|e| e.to_string())?;
+    Ok((inserted, created_accounts, batch_id))
+}
+
+// Splits off rows whose external id was already imported into the same account, or that repeat
+// an earlier row of the same file.
+fn split_known_external_ids(
+    conn: &Connection,
+    rows: Vec<ImportRow>,
+) -> Result<(Vec<ImportRow>, Vec<ImportRow>), String> {
+    let mut stmt = conn
+        .prepare("SELECT 1 FROM transactions WHERE account_id = ?1 AND external_id = ?2 LIMIT 1")
+        .map_err(|e| e.to_string())?;
+    let mut seen = HashSet::new();
+    let mut fresh = Vec::with_capacity(rows.len());
+    let mut duplicates = Vec::new();
+
+    for row in rows {
+        let Some(ref external_id) = row.external_id else {
+            fresh.push(row);
+            continue;
+        };
+        let account_key = match row.account_id {
+            Some(id) => id.to_string(),
+            None => row.account_name.clone().unwrap_or_default().to_lowercase(),
+        };
+        let known = match row.account_id {
+            Some(id) => stmt
+                .exists(params![id, external_id])
+                .map_err(|e| e.to_string())?,
+            None => false,
+        };
+        if known || !seen.insert((account_key, external_id.clone())) {
+            duplicates.push(row);
+        } else {
+            fresh.push(row);
+        }
+    }
+    Ok((fresh, duplicates))
+}
+
+// Both sides of a transfer usually appear in multi-account exports (QIF, YNAB registers). The
+// first side creates the linked pair; the mirrored line in the other account is consumed here.
+#[derive(Default)]
+pub struct TransferLedger {
+    expected: HashMap<(String, String, String, i64), usize>,
+}
+
+impl TransferLedger {
+    fn key(account: &str, other: &str, date: &str, amount: f64) -> (String, String, String, i64) {
+        (
+            account.to_lowercase(),
+            other.to_lowercase(),
+            date.to_string(),
+            (amount * 100.0).round() as i64,
+        )
+    }
+
+    // Returns true when this line is the mirror of a transfer that was already emitted
+    pub fn consume_or_expect(
+        &mut self,
+        account: &str,
+        other: &str,
+        date: &str,
+        amount: f64,
+    ) -> bool {
+        let mine = Self::key(account, other, date, amount);
+        if let Some(count) = self.expected.get_mut(&mine) {
+            if *count > 0 {
+                *count -= 1;
+                return true;
+            }
+        }
+        *self
+            .expected
+            .entry(Self::key(other, account, date, -amount))
+            .or_insert(0) += 1;
+        false
+    }
+}
+
+// Key of a statement line found by its end-to-end reference. The reference follows the payment
+// across statements and formats, but a return or reversal brings it back in the other direction,
+// so the direction is part of the key.
+pub fn payment_key(end_to_end: &str, amount: f64) -> String {
+    let direction = if amount < 0.0 { "DBIT" } else { "CRDT" };
+    format!("{}:{}", end_to_end, direction)
+}
+
+// Statement files are imported into one chosen account, so they must not mix source accounts
+pub fn ensure_single_source_account<S: AsRef<str> + Ord>(
+    mut source_accounts: Vec<S>,
+) -> Result<(), String> {
+    source_accounts.sort_unstable();
+    source_accounts.dedup();
+    if source_accounts.len() > 1 {
+        let names: Vec<&str> = source_accounts.iter().map(|a| a.as_ref()).collect();
+        return Err(format!(
+            "File contains statements for several accounts ({}); import them one at a time",
+            names.join(", ")
+        ));
+    }
+    Ok(())
+}
+
+// Compares each statement balance with the ledger sum up to the statement date. `pending` holds
+// rows that are not in the database yet (dry runs) and are counted as if they were.
+fn check_balances(
+    conn: &Connection,
+    balances: &[StatementBalance],
+    pending: &[ImportRow],
+) -> Result<Vec<BalanceCheck>, String> {
+    let mut checks = Vec::new();
+    for balance in balances {
+        let account_id = match (balance.account_id, balance.account_name.as_deref()) {
+            (Some(id), _) => Some(id),
+            (None, Some(name)) => find_account_id(conn, name)?,
+            (None, None) => None,
+        };
+        let Some(account_id) = account_id else {
+            continue;
+        };
+
+        let stored: f64 = conn
+            .query_row(
+                "SELECT COALESCE(SUM(amount), 0) FROM transactions WHERE account_id = ?1 AND date <= ?2",
+                params![account_id, balance.date],
+                |row| row.get(0),
+            )
+            .map_err(|e| e.to_string())?;
+        let pending_sum: f64 = pending
+            .iter()
+            .filter(|r| r.account_id == Some(account_id) && r.date <= balance.date)
+            .map(|r| r.amount)
+            .sum();
+        let computed_balance = stored + pending_sum;
+
+        checks.push(BalanceCheck {
+            account_id,
+            date: balance.date.clone(),
+            opening_balance: balance.opening_amount,
+            statement_balance: balance.amount,
+            computed_balance,
+            matches: (computed_balance - balance.amount).abs() < BALANCE_TOLERANCE,
+        });
+    }
+    Ok(checks)
+}
+
+// Strips card numbers, references and locations from statement payees before rules see them.
+// Payees naming an account of the file are kept for transfer linking.
+fn clean_payees(
+    conn: &Connection,
+    rows: &mut [ImportRow],
+    accounts: Vec<String>,
+) -> Result<(), String> {
+    let mut cleaner = PayeeCleaner::load(conn)?;
+    cleaner.keep_accounts(accounts);
+    for row in rows.iter_mut().filter(|r| r.is_buy.is_none()) {
+        let cleaned = cleaner.clean(&row.payee);
+        row.raw_payee = Some(std::mem::replace(&mut row.payee, cleaned));
+    }
+    Ok(())
+}
+
+// Runs the selected rules on every cash row. Trades keep what the broker reported.
+fn apply_rules(
+    conn: &Connection,
+    rows: &mut [ImportRow],
+    selection: &RuleSelection,
+) -> Result<(), String> {
+    let engine = RuleEngine::load(conn, selection)?;
+    if engine.is_empty() {
+        return Ok(());
+    }
+    for row in rows.iter_mut().filter(|r| r.is_buy.is_none()) {
+        let mut draft = DraftTransaction {
+            account_id: row.account_id,
+            date: std::mem::take(&mut row.date),
+            payee: std::mem::take(&mut row.payee),
+            notes: row.notes.take(),
+            category: row.category.take(),
+            amount: row.amount,
+            ticker: row.ticker.take(),
+            shares: row.shares,
+            price_per_share: row.price_per_share,
+            fee: row.fee,
+            ..Default::default()
+        };
+        row.rule_ids = engine.apply(&mut draft);
+        row.date = draft.date;
+        row.payee = match transfer_payee(conn, draft.transfer_to)? {
+            Some(account) => account,
+            None => draft.payee,
+        };
+        row.notes = draft.notes;
+        row.category = draft.category;
+        row.amount = draft.amount;
+        row.ticker = draft.ticker;
+        row.shares = draft.shares;
+        row.price_per_share = draft.price_per_share;
+        row.fee = draft.fee;
+        row.tags = draft.tags;
+        row.splits = draft.splits;
+    }
+    Ok(())
+}
+
+// Replaces each split row by one row per part. Done after duplicate screening, which compares
+// the statement line as a whole.
+fn expand_splits(rows: Vec<ImportRow>) -> Vec<ImportRow> {
+    let mut expanded = Vec::with_capacity(rows.len());
+    for mut row in rows {
+        if row.splits.is_empty() {
+            expanded.push(row);
+            continue;
+        }
+        let splits = std::mem::take(&mut row.splits);
+        for (amount, part) in split_amounts(row.amount, &splits).into_iter().zip(splits) {
+            expanded.push(ImportRow {
+                amount,
+                category: Some(part.category),
+                split_group: Some(row.row),
+                ..row.clone()
+            });
+        }
+    }
+    expanded
+}
+
+// Shared tail of every file importer: resolve accounts and drop already-imported lines, then
+// either return the preview or commit the valid rows. Rows with errors are never inserted.
+pub fn finish_import(
+    db_path: &PathBuf,
+    file_path: &Path,
+    parsed: ParsedImport,
+    create_missing_accounts: bool,
+    dry_run: bool,
+    rules: &RuleSelection,
+) -> Result<ImportReport, String> {
+    let ParsedImport {
+        rows,
+        mut errors,
+        total_rows,
+        balances,
+        accounts,
+        prices,
+    } = parsed;
+
+    let conn = Connection::open(db_path).map_err(|e| e.to_string())?;
+    let account_names = accounts
+        .iter()
+        .map(|a| a.name.clone())
+        .chain(rows.iter().filter_map(|r| r.account_name.clone()))
+        .collect();
+    let mut rows = resolve_accounts(&conn, rows, create_missing_accounts, &mut errors)?;
+    clean_payees(&conn, &mut rows, account_names)?;
+    apply_rules(&conn, &mut rows, rules)?;
+    let (rows, mut duplicates) = split_known_external_ids(&conn, rows)?;
+    let (rows, fingerprint_duplicates, possible_duplicates) =
+        crate::duplicates::screen_rows(&conn, rows, crate::duplicates::DEFAULT_WINDOW_DAYS)?;
+    let rows = expand_splits(rows);
+    duplicates.extend(fingerprint_duplicates);
+    duplicates.sort_by_key(|r| r.row);
+    errors.sort_by_key(|e| e.row);
+
+    let mut report = ImportReport {
+        dry_run,
+        total_rows,
+        rows,
+        errors,
+        duplicates,
+        possible_duplicates,
+        ..Default::default()
+    };
+    if dry_run {
+        report.balance_checks = check_balances(&conn, &balances, &report.rows)?;
+        return Ok(report);
+    }
+
+    let counts = batches::BatchCounts {
+        total_rows,
+        duplicate_rows: report.duplicates.len(),
+        error_rows: report.errors.len(),
+    };
+    let (inserted, created_accounts, batch_id) = commit_rows(
+        db_path,
+        file_path,
+        &counts,
+        &report.rows,
+        &accounts,
+        &prices,
+    )?;
+    report.imported = inserted.len();
+    report.batch_id = Some(batch_id);
+    report.prices = prices.len();
+    report.created_accounts = created_accounts;
+    report.balance_checks = check_balances(&conn, &balances, &[])?;
+    Ok(report)
+}
@@ -0,0 +1,363 @@
+use super::{parse_amount, ImportReport, ImportRow, ParsedImport, RowError, StatementBalance};
+use crate::rules::RuleSelection;
+use chrono::{Datelike, NaiveDate};
+use std::path::{Path, PathBuf};
+
+// `:TAG:value` fields of one statement, with continuation lines folded into the value
+type Fields = Vec<(String, String)>;
+
+// Splits the file into statements (each starting at `:20:`), dropping SWIFT envelope lines
+fn split_statements(content: &str) -> Vec<Fields> {
+    let mut statements: Vec<Fields> = Vec::new();
+    for line in content.lines() {
+        let line = line.trim_end();
+        let trimmed = line.trim_start();
+        if trimmed.is_empty()
+            || trimmed.starts_with('{')
+            || trimmed.starts_with("-}")
+            || trimmed == "-"
+        {
+            continue;
+        }
+
+        let tag = trimmed
+            .strip_prefix(':')
+            .and_then(|rest| rest.split_once(':'))
+            .filter(|(tag, _)| {
+                (2..=3).contains(&tag.len())
+                    && tag.chars().next().is_some_and(|c| c.is_ascii_digit())
+            });
+        match tag {
+            Some((tag, value)) => {
+                if tag == "20" || statements.is_empty() {
+                    statements.push(Vec::new());
+                }
+                if let Some(statement) = statements.last_mut() {
+                    statement.push((tag.to_string(), value.to_string()));
+                }
+            }
+            None => {
+                if let Some((_, value)) = statements.last_mut().and_then(|s| s.last_mut()) {
+                    value.push('\n');
+                    value.push_str(line);
+                }
+            }
+        }
+    }
+    statements
+}
+
+fn parse_yymmdd(value: &str) -> Result<NaiveDate, String> {
+    NaiveDate::parse_from_str(value, "%y%m%d").map_err(|_| format!("Invalid date '{}'", value))
+}
+
+fn parse_mt940_amount(value: &str) -> Result<f64, String> {
+    parse_amount(value, ',', None)
+}
+
+// `:60F:` / `:62F:` balance: D/C mark, YYMMDD, currency, amount (`C240131EUR1234,56`)
+fn parse_balance(value: &str) -> Result<(String, String, f64), String> {
+    let value = value.trim();
+    if value.len() < 11 || !value.is_ascii() {
+        return Err(format!("Invalid balance '{}'", value));
+    }
+    let (mark, rest) = value.split_at(1);
+    let date = parse_yymmdd(&rest[..6])?.format("%Y-%m-%d").to_string();
+    let currency = rest[6..9].to_string();
+    let amount = parse_mt940_amount(&rest[9..])?;
+    let signed = if mark == "D" { -amount } else { amount };
+    Ok((date, currency, signed))
+}
+
+struct StatementLine {
+    value_date: NaiveDate,
+    booking_date: NaiveDate,
+    amount: f64,
+    customer_reference: Option<String>,
+    bank_reference: Option<String>,
+}
+
+// `:61:` statement line: value date, optional MMDD booking date, D/C/RD/RC mark, optional funds
+// code, amount, 4-character transaction type, customer reference and optional `//` bank reference
+fn parse_statement_line(value: &str) -> Result<StatementLine, String> {
+    let first = value.lines().next().unwrap_or("").trim();
+    let invalid = || format!("Invalid statement line '{}'", first);
+    if first.len() < 12 || !first.is_ascii() {
+        return Err(invalid());
+    }
+    let value_date = parse_yymmdd(&first[..6])?;
+    let mut rest = &first[6..];
+
+    let mut booking_date = value_date;
+    if rest.len() >= 4 && rest[..4].chars().all(|c| c.is_ascii_digit()) {
+        let month: u32 = rest[..2].parse().map_err(|_| invalid())?;
+        let day: u32 = rest[2..4].parse().map_err(|_| invalid())?;
+        // The booking date has no year; it can fall just across a year end from the value date
+        let year = match (value_date.month(), month) {
+            (1, 12) => value_date.year() - 1,
+            (12, 1) => value_date.year() + 1,
+            _ => value_date.year(),
+        };
+        booking_date = NaiveDate::from_ymd_opt(year, month, day).ok_or_else(invalid)?;
+        rest = &rest[4..];
+    }
+
+    let (mark, after_mark) = if let Some(r) = rest.strip_prefix("RD") {
+        ("RD", r)
+    } else if let Some(r) = rest.strip_prefix("RC") {
+        ("RC", r)
+    } else if let Some(r) = rest.strip_prefix('D') {
+        ("D", r)
+    } else if let Some(r) = rest.strip_prefix('C') {
+        ("C", r)
+    } else {
+        return Err(invalid());
+    };
+    // Optional funds code: third letter of the currency code
+    let after_funds = match after_mark.chars().next() {
+        Some(c) if c.is_ascii_alphabetic() => &after_mark[1..],
+        _ => after_mark,
+    };
+    let amount_len = after_funds
+        .find(|c: char| !(c.is_ascii_digit() || c == ','))
+        .unwrap_or(after_funds.len());
+    let amount = parse_mt940_amount(&after_funds[..amount_len])?;
+    // Reversals flip the direction of the original booking
+    let amount = match mark {
+        "D" | "RC" => -amount,
+        _ => amount,
+    };
+
+    let references = after_funds[amount_len..].get(4..).unwrap_or("");
+    let (customer, bank) = match references.split_once("//") {
+        Some((c, b)) => (c, Some(b)),
+        None => (references, None),
+    };
+    let clean = |r: &str| {
+        Some(r.trim().to_string()).filter(|r| !r.is_empty() && !r.eq_ignore_ascii_case("NONREF"))
+    };
+
+    Ok(StatementLine {
+        value_date,
+        booking_date,
+        amount,
+        customer_reference: clean(customer),
+        bank_reference: bank.and_then(clean),
+    })
+}
+
+#[derive(Default)]
+struct Narrative {
+    name: Option<String>,
+    iban: Option<String>,
+    end_to_end: Option<String>,
+    remittance: Option<String>,
+}
+
+// `:86:` comes in two structured flavours: German `?NN` subfields and `/KEY/value` pairs. Anything
+// else is kept as free text.
+fn parse_narrative(value: &str) -> Narrative {
+    let joined: String = value.lines().map(str::trim_end).collect();
+    let mut narrative = Narrative::default();
+
+    if joined.contains("?2") || joined.contains("?32") {
+        let mut name = String::new();
+        let mut remittance = String::new();
+        for part in joined.split('?').skip(1) {
+            let code = part.get(..2).unwrap_or("");
+            let text = part.get(2..).unwrap_or("");
+            match code {
+                "20" | "21" | "22" | "23" | "24" | "25" | "26" | "27" | "28" | "29" | "60"
+                | "61" | "62" | "63" => remittance.push_str(text),
+                "31" => narrative.iban = Some(text.trim().to_string()).filter(|t| !t.is_empty()),
+                "32" | "33" => name.push_str(text),
+                _ => {}
+            }
+        }
+        // SEPA remittance is tagged (`EREF+`, `SVWZ+`...); the end-to-end reference runs to the
+        // next tag
+        if let Some(start) = remittance.find("EREF+") {
+            let tail = &remittance[start + 5..];
+            let end = [
+                "KREF+", "MREF+", "CRED+", "DEBT+", "SVWZ+", "ABWA+", "ABWE+",
+            ]
+            .iter()
+            .filter_map(|t| tail.find(t))
+            .min()
+            .unwrap_or(tail.len());
+            narrative.end_to_end = Some(tail[..end].trim().to_string())
+                .filter(|r| !r.is_empty() && !r.eq_ignore_ascii_case("NOTPROVIDED"));
+        }
+        let text = match remittance.find("SVWZ+") {
+            Some(start) => remittance[start + 5..].to_string(),
+            None => remittance,
+        };
+        narrative.name = Some(name.trim().to_string()).filter(|n| !n.is_empty());
+        narrative.remittance = Some(text.trim().to_string()).filter(|t| !t.is_empty());
+    } else if joined.starts_with('/') {
+        let parts: Vec<&str> = joined.split('/').skip(1).collect();
+        let known = [
+            "EREF", "IBAN", "NAME", "REMI", "BIC", "TRTP", "CSID", "MARF", "ORDP", "BENM", "ID",
+        ];
+        let mut i = 0;
+        while i < parts.len() {
+            let key = parts[i];
+            let mut value = Vec::new();
+            i += 1;
+            // Values may themselves contain slashes, so read up to the next known key
+            while i < parts.len() && !known.contains(&parts[i]) {
+                value.push(parts[i]);
+                i += 1;
+            }
+            let value = Some(value.join("/").trim().to_string()).filter(|v| !v.is_empty());
+            match key {
+                "EREF" => {
+                    narrative.end_to_end = value.filter(|r| !r.eq_ignore_ascii_case("NOTPROVIDED"))
+                }
+                "IBAN" => narrative.iban = value,
+                "NAME" => narrative.name = value,
+                "REMI" => narrative.remittance = value,
+                _ => {}
+            }
+        }
+    } else {
+        narrative.remittance = Some(value.lines().map(str::trim).collect::<Vec<_>>().join(" "))
+            .filter(|t| !t.is_empty());
+    }
+    narrative
+}
+
+fn statement_row(
+    line: &StatementLine,
+    narrative: Narrative,
+    account_id: i32,
+    currency: Option<&str>,
+    row: usize,
+) -> ImportRow {
+    let payee = narrative
+        .name
+        .clone()
+        .or_else(|| narrative.iban.clone())
+        .or_else(|| narrative.remittance.clone())
+        .unwrap_or_else(|| "Unknown".to_string());
+
+    let mut notes = Vec::new();
+    if let Some(text) = narrative.remittance.filter(|t| *t != payee) {
+        notes.push(text);
+    }
+    if let (Some(_), Some(iban)) = (&narrative.name, &narrative.iban) {
+        notes.push(format!("IBAN {}", iban));
+    }
+    if let Some(e2e) = &narrative.end_to_end {
+        notes.push(format!("Ref {}", e2e));
+    }
+
+    let date = line.booking_date.format("%Y-%m-%d").to_string();
+    let value_date = line.value_date.format("%Y-%m-%d").to_string();
+    ImportRow {
+        row,
+        account_id: Some(account_id),
+        value_date: Some(value_date).filter(|d| *d != date),
+        date,
+        payee,
+        notes: if notes.is_empty() {
+            None
+        } else {
+            Some(notes.join("; "))
+        },
+        amount: line.amount,
+        currency: currency.map(str::to_string),
+        external_id: narrative
+            .end_to_end
+            .map(|e2e| super::payment_key(&e2e, line.amount))
+            .or_else(|| line.bank_reference.clone())
+            .or_else(|| line.customer_reference.clone()),
+        ..Default::default()
+    }
+}
+
+// Parses every statement of an MT940 file into rows for `account_id`. The closing balance
+// (`:62F:`/`:62M:`) of each statement is verified after import.
+pub fn parse_mt940(content: &str, account_id: i32) -> Result<ParsedImport, String> {
+    let statements = split_statements(content);
+    if !statements.iter().any(|s| {
+        s.iter()
+            .any(|(tag, _)| tag == "61" || tag.starts_with("62"))
+    }) {
+        return Err("No MT940 statement found in file".to_string());
+    }
+
+    super::ensure_single_source_account(
+        statements
+            .iter()
+            .filter_map(|s| s.iter().find(|(tag, _)| tag == "25"))
+            .map(|(_, v)| v.trim())
+            .collect(),
+    )?;
+
+    let mut parsed = ParsedImport::default();
+    for fields in &statements {
+        let mut currency: Option<String> = None;
+        let mut opening = None;
+        let mut pending: Option<(usize, Result<StatementLine, String>)> = None;
+
+        let flush = |parsed: &mut ParsedImport,
+                     pending: &mut Option<(usize, Result<StatementLine, String>)>,
+                     narrative: Option<&str>,
+                     currency: Option<&str>| {
+            if let Some((row, line)) = pending.take() {
+                match line {
+                    Ok(line) => {
+                        let narrative = narrative.map(parse_narrative).unwrap_or_default();
+                        parsed
+                            .rows
+                            .push(statement_row(&line, narrative, account_id, currency, row));
+                    }
+                    Err(message) => parsed.errors.push(RowError { row, message }),
+                }
+            }
+        };
+
+        for (tag, value) in fields {
+            match tag.as_str() {
+                "60F" | "60M" => {
+                    let (_, ccy, amount) = parse_balance(value)?;
+                    currency = Some(ccy);
+                    opening = Some(amount);
+                }
+                "61" => {
+                    flush(&mut parsed, &mut pending, None, currency.as_deref());
+                    parsed.total_rows += 1;
+                    pending = Some((parsed.total_rows, parse_statement_line(value)));
+                }
+                "86" => flush(&mut parsed, &mut pending, Some(value), currency.as_deref()),
+                "62F" | "62M" => {
+                    flush(&mut parsed, &mut pending, None, currency.as_deref());
+                    let (date, _, amount) = parse_balance(value)?;
+                    parsed.balances.push(StatementBalance {
+                        account_id: Some(account_id),
+                        account_name: None,
+                        date,
+                        amount,
+                        opening_amount: opening,
+                    });
+                }
+                _ => {}
+            }
+        }
+        flush(&mut parsed, &mut pending, None, currency.as_deref());
+    }
+    Ok(parsed)
+}
+
+pub fn import_mt940_db(
+    db_path: &PathBuf,
+    file_path: &Path,
+    account_id: i32,
+    dry_run: bool,
+    rules: &RuleSelection,
+) -> Result<ImportReport, String> {
+    let content = super::read_text_file(file_path)?;
+    let parsed = parse_mt940(&content, account_id)?;
+    super::finish_import(db_path, file_path, parsed, false, dry_run, rules)
+}
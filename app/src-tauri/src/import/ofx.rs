@@ -0,0 +1,434 @@
+use super::{parse_amount, ImportReport, ImportRow, ParsedImport, RowError, StatementBalance};
+use crate::rules::RuleSelection;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+// One OFX element. Leaf elements carry a value; aggregates carry children. OFX 1.x (SGML) leaves
+// have no closing tag while OFX 2.x (XML) closes every element, so both are read into this shape.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct OfxNode {
+    pub name: String,
+    pub value: Option<String>,
+    pub children: Vec<OfxNode>,
+}
+
+impl OfxNode {
+    fn child(&self, name: &str) -> Option<&OfxNode> {
+        self.children.iter().find(|c| c.name == name)
+    }
+
+    // Value of a leaf reached by following `path` from this node
+    fn text(&self, path: &[&str]) -> Option<&str> {
+        let mut node = self;
+        for name in path {
+            node = node.child(name)?;
+        }
+        node.value.as_deref().filter(|v| !v.is_empty())
+    }
+
+    fn collect<'a>(&'a self, names: &[&str], out: &mut Vec<&'a OfxNode>) {
+        for c in &self.children {
+            if names.contains(&c.name.as_str()) {
+                out.push(c);
+            } else {
+                c.collect(names, out);
+            }
+        }
+    }
+}
+
+fn decode_entities(value: &str) -> String {
+    if !value.contains('&') {
+        return value.to_string();
+    }
+    let mut out = String::with_capacity(value.len());
+    let mut rest = value;
+    while let Some(pos) = rest.find('&') {
+        out.push_str(&rest[..pos]);
+        rest = &rest[pos..];
+        let Some(end) = rest.find(';').filter(|e| *e <= 8) else {
+            out.push('&');
+            rest = &rest[1..];
+            continue;
+        };
+        let entity = &rest[1..end];
+        let decoded = match entity {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            _ => entity
+                .strip_prefix('#')
+                .and_then(|n| match n.strip_prefix('x') {
+                    Some(hex) => u32::from_str_radix(hex, 16).ok(),
+                    None => n.parse().ok(),
+                })
+                .and_then(char::from_u32),
+        };
+        match decoded {
+            Some(c) => {
+                out.push(c);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                out.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+// Moves a node that was never explicitly closed into its parent. An SGML leaf with an empty value
+// looks like an aggregate while parsing, so its "children" are really its following siblings.
+fn close_implicit(parent: &mut OfxNode, mut node: OfxNode) {
+    let children = std::mem::take(&mut node.children);
+    parent.children.push(node);
+    parent.children.extend(children);
+}
+
+// Parses the body of an OFX 1.x or 2.x document (everything from `<OFX>` on) into a tree.
+pub fn parse_ofx_tree(content: &str) -> Result<OfxNode, String> {
+    let start = content
+        .to_ascii_uppercase()
+        .find("<OFX>")
+        .ok_or_else(|| "Not an OFX file: missing <OFX> element".to_string())?;
+    let body = &content[start..];
+
+    let mut stack: Vec<OfxNode> = vec![OfxNode::default()];
+    let mut pos = 0;
+    while let Some(open) = body[pos..].find('<') {
+        let open = pos + open;
+        let close = body[open..]
+            .find('>')
+            .map(|c| open + c)
+            .ok_or_else(|| "Malformed OFX: unterminated tag".to_string())?;
+        let tag = body[open + 1..close].trim();
+        let next = body[close + 1..]
+            .find('<')
+            .map(|n| close + 1 + n)
+            .unwrap_or(body.len());
+        let text = body[close + 1..next].trim();
+        pos = close + 1;
+
+        if tag.starts_with('?') || tag.starts_with('!') || tag.is_empty() {
+            continue;
+        }
+
+        if let Some(name) = tag.strip_prefix('/') {
+            let name = name.trim().to_ascii_uppercase();
+            // Closing tag of an XML-style leaf (`<NAME>value</NAME>`) has no aggregate to pop
+            if let Some(idx) = stack.iter().rposition(|n| n.name == name) {
+                if idx == 0 {
+                    continue;
+                }
+                while stack.len() > idx + 1 {
+                    let node = stack.pop().unwrap();
+                    close_implicit(stack.last_mut().unwrap(), node);
+                }
+                let node = stack.pop().unwrap();
+                stack.last_mut().unwrap().children.push(node);
+            }
+            continue;
+        }
+
+        let self_closing = tag.ends_with('/');
+        let name = tag
+            .trim_end_matches('/')
+            .split_whitespace()
+            .next()
+            .unwrap_or("")
+            .to_ascii_uppercase();
+
+        if self_closing || !text.is_empty() {
+            stack.last_mut().unwrap().children.push(OfxNode {
+                name,
+                value: Some(decode_entities(text)),
+                children: Vec::new(),
+            });
+        } else {
+            stack.push(OfxNode {
+                name,
+                value: None,
+                children: Vec::new(),
+            });
+        }
+    }
+
+    while stack.len() > 1 {
+        let node = stack.pop().unwrap();
+        close_implicit(stack.last_mut().unwrap(), node);
+    }
+    let root = stack.pop().unwrap();
+    root.child("OFX")
+        .cloned()
+        .ok_or_else(|| "Malformed OFX: no <OFX> element".to_string())
+}
+
+// OFX dates are `YYYYMMDD[HHMMSS[.XXX]][[TZ]]`; only the calendar date is kept.
+fn parse_ofx_date(value: &str) -> Result<String, String> {
+    let digits: String = value.chars().take(8).collect();
+    chrono::NaiveDate::parse_from_str(&digits, "%Y%m%d")
+        .map(|d| d.format("%Y-%m-%d").to_string())
+        .map_err(|_| format!("Invalid OFX date '{}'", value))
+}
+
+// The spec mandates a dot decimal point, but some European banks emit a comma
+fn parse_ofx_amount(value: &str) -> Result<f64, String> {
+    let decimal = if value.contains(',') && !value.contains('.') {
+        ','
+    } else {
+        '.'
+    };
+    parse_amount(value, decimal, None)
+}
+
+fn security_tickers(ofx: &OfxNode) -> HashMap<String, String> {
+    let mut infos = Vec::new();
+    ofx.collect(&["SECINFO"], &mut infos);
+    infos
+        .into_iter()
+        .filter_map(|info| {
+            let id = info.text(&["SECID", "UNIQUEID"])?;
+            let label = info.text(&["TICKER"]).or(info.text(&["SECNAME"]))?;
+            Some((id.to_string(), label.to_string()))
+        })
+        .collect()
+}
+
+fn bank_row(
+    trn: &OfxNode,
+    account_id: i32,
+    currency: Option<&str>,
+    row: usize,
+) -> Result<ImportRow, String> {
+    let date = parse_ofx_date(trn.text(&["DTPOSTED"]).ok_or("Missing DTPOSTED")?)?;
+    let amount = parse_ofx_amount(trn.text(&["TRNAMT"]).ok_or("Missing TRNAMT")?)?;
+    let name = trn.text(&["NAME"]).or(trn.text(&["PAYEE", "NAME"]));
+    let memo = trn.text(&["MEMO"]);
+
+    Ok(ImportRow {
+        row,
+        account_id: Some(account_id),
+        date,
+        payee: name.or(memo).unwrap_or("Unknown").to_string(),
+        notes: if name.is_some() {
+            memo.map(str::to_string)
+        } else {
+            None
+        },
+        amount,
+        currency: trn
+            .text(&["CURRENCY", "CURSYM"])
+            .or(trn.text(&["ORIGCURRENCY", "CURSYM"]))
+            .or(currency)
+            .map(str::to_string),
+        external_id: trn.text(&["FITID"]).map(str::to_string),
+        ..Default::default()
+    })
+}
+
+fn trade_row(
+    trade: &OfxNode,
+    is_buy: bool,
+    account_id: i32,
+    currency: Option<&str>,
+    tickers: &HashMap<String, String>,
+    row: usize,
+) -> Result<ImportRow, String> {
+    let detail = trade
+        .child(if is_buy { "INVBUY" } else { "INVSELL" })
+        .ok_or_else(|| format!("Missing {}", if is_buy { "INVBUY" } else { "INVSELL" }))?;
+    let date = parse_ofx_date(
+        detail
+            .text(&["INVTRAN", "DTTRADE"])
+            .ok_or("Missing DTTRADE")?,
+    )?;
+    let security = detail.text(&["SECID", "UNIQUEID"]).ok_or("Missing SECID")?;
+    let ticker = tickers
+        .get(security)
+        .cloned()
+        .unwrap_or_else(|| security.to_string());
+    let shares = parse_ofx_amount(detail.text(&["UNITS"]).ok_or("Missing UNITS")?)?.abs();
+    let price = parse_ofx_amount(detail.text(&["UNITPRICE"]).ok_or("Missing UNITPRICE")?)?;
+    let fee: f64 = ["COMMISSION", "FEES", "TAXES"]
+        .iter()
+        .filter_map(|f| detail.text(&[f]))
+        .map(parse_ofx_amount)
+        .collect::<Result<Vec<_>, _>>()?
+        .iter()
+        .map(|v| v.abs())
+        .sum();
+
+    let total = shares * price;
+    Ok(ImportRow {
+        row,
+        account_id: Some(account_id),
+        date,
+        payee: if is_buy { "Buy" } else { "Sell" }.to_string(),
+        notes: detail.text(&["INVTRAN", "MEMO"]).map(str::to_string),
+        category: Some("Investment".to_string()),
+        amount: if is_buy { -(total + fee) } else { total - fee },
+        ticker: Some(ticker),
+        shares: Some(shares),
+        price_per_share: Some(price),
+        fee: Some(fee),
+        currency: detail
+            .text(&["CURRENCY", "CURSYM"])
+            .or(detail.text(&["ORIGCURRENCY", "CURSYM"]))
+            .or(currency)
+            .map(str::to_string),
+        external_id: detail.text(&["INVTRAN", "FITID"]).map(str::to_string),
+        is_buy: Some(is_buy),
+        ..Default::default()
+    })
+}
+
+fn income_row(
+    income: &OfxNode,
+    account_id: i32,
+    currency: Option<&str>,
+    tickers: &HashMap<String, String>,
+    row: usize,
+) -> Result<ImportRow, String> {
+    let date = parse_ofx_date(
+        income
+            .text(&["INVTRAN", "DTTRADE"])
+            .ok_or("Missing DTTRADE")?,
+    )?;
+    let amount = parse_ofx_amount(income.text(&["TOTAL"]).ok_or("Missing TOTAL")?)?;
+    let security = income.text(&["SECID", "UNIQUEID"]).unwrap_or_default();
+    let ticker = tickers
+        .get(security)
+        .map(String::as_str)
+        .unwrap_or(security);
+    let kind = match income.text(&["INCOMETYPE"]) {
+        Some("DIV") => "Dividend",
+        Some("INTEREST") => "Interest",
+        Some("CGLONG") | Some("CGSHORT") => "Capital gains distribution",
+        _ => "Investment income",
+    };
+
+    Ok(ImportRow {
+        row,
+        account_id: Some(account_id),
+        date,
+        payee: format!("{} {}", kind, ticker).trim().to_string(),
+        notes: income.text(&["INVTRAN", "MEMO"]).map(str::to_string),
+        amount,
+        currency: income
+            .text(&["CURRENCY", "CURSYM"])
+            .or(currency)
+            .map(str::to_string),
+        external_id: income.text(&["INVTRAN", "FITID"]).map(str::to_string),
+        ..Default::default()
+    })
+}
+
+// Maps every bank, credit card and investment statement in the file onto `account_id`.
+pub fn parse_ofx(content: &str, account_id: i32) -> Result<ParsedImport, String> {
+    let ofx = parse_ofx_tree(content)?;
+    let tickers = security_tickers(&ofx);
+
+    let mut statements = Vec::new();
+    ofx.collect(&["STMTRS", "CCSTMTRS", "INVSTMTRS"], &mut statements);
+    if statements.is_empty() {
+        return Err("No bank, credit card or investment statement found in file".to_string());
+    }
+
+    let source_accounts: Vec<&str> = statements
+        .iter()
+        .filter_map(|s| {
+            s.text(&["BANKACCTFROM", "ACCTID"])
+                .or(s.text(&["CCACCTFROM", "ACCTID"]))
+                .or(s.text(&["INVACCTFROM", "ACCTID"]))
+        })
+        .collect();
+    super::ensure_single_source_account(source_accounts)?;
+
+    let mut parsed = ParsedImport::default();
+    let push = |parsed: &mut ParsedImport, result: Result<ImportRow, String>| {
+        parsed.total_rows += 1;
+        match result {
+            Ok(r) => parsed.rows.push(r),
+            Err(message) => parsed.errors.push(RowError {
+                row: parsed.total_rows,
+                message,
+            }),
+        }
+    };
+
+    for statement in statements {
+        let currency = statement.text(&["CURDEF"]);
+
+        if statement.name == "INVSTMTRS" {
+            if let Some(list) = statement.child("INVTRANLIST") {
+                for item in &list.children {
+                    let row = parsed.total_rows + 1;
+                    let result = match item.name.as_str() {
+                        n if n.starts_with("BUY") => {
+                            trade_row(item, true, account_id, currency, &tickers, row)
+                        }
+                        n if n.starts_with("SELL") => {
+                            trade_row(item, false, account_id, currency, &tickers, row)
+                        }
+                        "INCOME" => income_row(item, account_id, currency, &tickers, row),
+                        "INVBANKTRAN" => match item.child("STMTTRN") {
+                            Some(trn) => bank_row(trn, account_id, currency, row),
+                            None => Err("INVBANKTRAN without STMTTRN".to_string()),
+                        },
+                        _ => continue,
+                    };
+                    push(&mut parsed, result);
+                }
+            }
+            if let (Some(cash), Some(as_of)) = (
+                statement.text(&["INVBAL", "AVAILCASH"]),
+                statement.text(&["DTASOF"]),
+            ) {
+                parsed.balances.push(StatementBalance {
+                    account_id: Some(account_id),
+                    account_name: None,
+                    date: parse_ofx_date(as_of)?,
+                    amount: parse_ofx_amount(cash)?,
+                    opening_amount: None,
+                });
+            }
+        } else {
+            if let Some(list) = statement.child("BANKTRANLIST") {
+                for trn in list.children.iter().filter(|c| c.name == "STMTTRN") {
+                    let row = parsed.total_rows + 1;
+                    push(&mut parsed, bank_row(trn, account_id, currency, row));
+                }
+            }
+            if let (Some(amount), Some(as_of)) = (
+                statement.text(&["LEDGERBAL", "BALAMT"]),
+                statement.text(&["LEDGERBAL", "DTASOF"]),
+            ) {
+                parsed.balances.push(StatementBalance {
+                    account_id: Some(account_id),
+                    account_name: None,
+                    date: parse_ofx_date(as_of)?,
+                    amount: parse_ofx_amount(amount)?,
+                    opening_amount: None,
+                });
+            }
+        }
+    }
+
+    Ok(parsed)
+}
+
+pub fn import_ofx_db(
+    db_path: &PathBuf,
+    file_path: &Path,
+    account_id: i32,
+    dry_run: bool,
+    rules: &RuleSelection,
+) -> Result<ImportReport, String> {
+    let content = super::read_text_file(file_path)?;
+    let parsed = parse_ofx(&content, account_id)?;
+    super::finish_import(db_path, file_path, parsed, false, dry_run, rules)
+}
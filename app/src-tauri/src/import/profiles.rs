@@ -0,0 +1,154 @@
+use super::csv::{header_fingerprint, import_csv_db, CsvMapping};
+use super::{read_text_file, ImportReport};
+use crate::rules::RuleSelection;
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+// A saved CSV mapping for one bank's export, recognized again by the file's header row
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct ImportProfile {
+    pub id: i32,
+    pub name: String,
+    pub header_fingerprint: String,
+    pub mapping: CsvMapping,
+    // Rules to run on the imported rows; `None` runs every rule
+    pub rule_ids: Option<Vec<i32>>,
+}
+
+// Mapping and rule ids are stored as JSON text
+fn json<T: DeserializeOwned>(idx: usize, text: &str) -> rusqlite::Result<T> {
+    serde_json::from_str(text).map_err(|e| {
+        rusqlite::Error::FromSqlConversionFailure(idx, rusqlite::types::Type::Text, Box::new(e))
+    })
+}
+
+fn profile_from_row(row: &rusqlite::Row<'_>) -> rusqlite::Result<ImportProfile> {
+    Ok(ImportProfile {
+        id: row.get(0)?,
+        name: row.get(1)?,
+        header_fingerprint: row.get(2)?,
+        mapping: json(3, &row.get::<_, String>(3)?)?,
+        rule_ids: row
+            .get::<_, Option<String>>(4)?
+            .map(|ids| json(4, &ids))
+            .transpose()?,
+    })
+}
+
+const PROFILE_COLUMNS: &str = "id, name, header_fingerprint, mapping, rule_ids";
+
+// Saves a mapping under `name`, replacing an existing profile of the same name. The header
+// fingerprint is taken from a sample file of that bank.
+pub fn create_import_profile_db(
+    db_path: &PathBuf,
+    name: String,
+    mapping: CsvMapping,
+    rule_ids: Option<Vec<i32>>,
+    sample_path: &Path,
+) -> Result<ImportProfile, String> {
+    let name = name.trim().to_string();
+    if name.is_empty() {
+        return Err("Profile name cannot be empty".to_string());
+    }
+    let content = read_text_file(sample_path)?;
+    let fingerprint = header_fingerprint(&content, &mapping)?;
+    let mapping_json = serde_json::to_string(&mapping).map_err(|e| e.to_string())?;
+    let rules_json = rule_ids
+        .as_ref()
+        .map(serde_json::to_string)
+        .transpose()
+        .map_err(|e| e.to_string())?;
+
+    let conn = Connection::open(db_path).map_err(|e| e.to_string())?;
+    conn.execute(
+        "INSERT INTO import_profiles (name, header_fingerprint, mapping, rule_ids) VALUES (?1, ?2, ?3, ?4)
+         ON CONFLICT(name) DO UPDATE SET header_fingerprint = excluded.header_fingerprint, mapping = excluded.mapping, rule_ids = excluded.rule_ids",
+        params![name, fingerprint, mapping_json, rules_json],
+    )
+    .map_err(|e| e.to_string())?;
+    conn.query_row(
+        &format!(
+            "SELECT {} FROM import_profiles WHERE name = ?1",
+            PROFILE_COLUMNS
+        ),
+        params![name],
+        profile_from_row,
+    )
+    .map_err(|e| e.to_string())
+}
+
+pub fn list_import_profiles_db(db_path: &PathBuf) -> Result<Vec<ImportProfile>, String> {
+    let conn = Connection::open(db_path).map_err(|e| e.to_string())?;
+    let mut stmt = conn
+        .prepare(&format!(
+            "SELECT {} FROM import_profiles ORDER BY name COLLATE NOCASE",
+            PROFILE_COLUMNS
+        ))
+        .map_err(|e| e.to_string())?;
+    let profiles = stmt
+        .query_map([], profile_from_row)
+        .map_err(|e| e.to_string())?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())?;
+    Ok(profiles)
+}
+
+pub fn delete_import_profile_db(db_path: &PathBuf, id: i32) -> Result<(), String> {
+    let conn = Connection::open(db_path).map_err(|e| e.to_string())?;
+    let deleted = conn
+        .execute("DELETE FROM import_profiles WHERE id = ?1", params![id])
+        .map_err(|e| e.to_string())?;
+    if deleted == 0 {
+        return Err(format!("Import profile {} not found", id));
+    }
+    Ok(())
+}
+
+fn get_import_profile(conn: &Connection, id: i32) -> Result<ImportProfile, String> {
+    conn.query_row(
+        &format!(
+            "SELECT {} FROM import_profiles WHERE id = ?1",
+            PROFILE_COLUMNS
+        ),
+        params![id],
+        profile_from_row,
+    )
+    .optional()
+    .map_err(|e| e.to_string())?
+    .ok_or_else(|| format!("Import profile {} not found", id))
+}
+
+// Finds the profile whose header fingerprint matches the file. Each profile reads the header
+// with its own skip rows and delimiter, so banks with a preamble above the header still match.
+pub fn detect_import_profile_db(
+    db_path: &PathBuf,
+    file_path: &Path,
+) -> Result<Option<ImportProfile>, String> {
+    let content = read_text_file(file_path)?;
+    let profiles = list_import_profiles_db(db_path)?;
+    Ok(profiles.into_iter().find(|profile| {
+        header_fingerprint(&content, &profile.mapping)
+            .is_ok_and(|fingerprint| fingerprint == profile.header_fingerprint)
+    }))
+}
+
+// Imports a CSV with a saved profile, detecting it from the header when none is given
+pub fn import_with_profile_db(
+    db_path: &PathBuf,
+    file_path: &Path,
+    profile_id: Option<i32>,
+    dry_run: bool,
+) -> Result<ImportReport, String> {
+    let profile = match profile_id {
+        Some(id) => {
+            let conn = Connection::open(db_path).map_err(|e| e.to_string())?;
+            get_import_profile(&conn, id)?
+        }
+        None => detect_import_profile_db(db_path, file_path)?
+            .ok_or_else(|| "No import profile matches this file".to_string())?,
+    };
+    let rules = RuleSelection::from(profile.rule_ids);
+    import_csv_db(db_path, file_path, profile.mapping, dry_run, &rules)
+}
@@ -0,0 +1,384 @@
+use super::{
+    parse_amount, parse_date, DeclaredAccount, ImportReport, ImportRow, ParsedImport, RowError,
+    TransferLedger,
+};
+use crate::rules::RuleSelection;
+use chrono::NaiveDate;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Section {
+    Bank,
+    Investment,
+    // Account lists, category lists, memorized payees... are skipped
+    Other,
+}
+
+// One `^`-terminated QIF record: field code and value per line, in file order
+type Record = Vec<(char, String)>;
+
+// Quicken writes US dates, optionally with an apostrophe before two-digit years (`1/5'24`)
+fn parse_qif_date(value: &str, format: Option<&str>) -> Result<String, String> {
+    if format.is_some() {
+        return parse_date(value, format);
+    }
+    let normalized: String = value
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .map(|c| if c == '\'' { '/' } else { c })
+        .collect();
+    // `%Y` would happily read `24` as year 24, so pick the year width from the text
+    let short_year = normalized
+        .rsplit('/')
+        .next()
+        .is_some_and(|y| normalized.contains('/') && y.len() <= 2);
+    let us_format = if short_year { "%m/%d/%y" } else { "%m/%d/%Y" };
+    [us_format, "%Y-%m-%d", "%d.%m.%Y"]
+        .iter()
+        .find_map(|fmt| NaiveDate::parse_from_str(&normalized, fmt).ok())
+        .map(|d| d.format("%Y-%m-%d").to_string())
+        .ok_or_else(|| format!("Unrecognized date '{}'", value.trim()))
+}
+
+fn parse_qif_amount(value: &str) -> Result<f64, String> {
+    parse_amount(value, '.', Some(','))
+}
+
+// `[Savings]` in a category field means a transfer to the Savings account
+fn transfer_target(category: &str) -> Option<&str> {
+    let trimmed = category.trim();
+    trimmed
+        .strip_prefix('[')
+        .and_then(|r| r.split(']').next())
+        .map(str::trim)
+        .filter(|n| !n.is_empty())
+}
+
+// Drops the `/Class` suffix Quicken appends to categories
+fn category_name(category: &str) -> Option<String> {
+    let name = category.split('/').next().unwrap_or("").trim();
+    if name.is_empty() {
+        None
+    } else {
+        Some(name.to_string())
+    }
+}
+
+fn field(record: &Record, code: char) -> Option<&str> {
+    record
+        .iter()
+        .find(|(c, _)| *c == code)
+        .map(|(_, v)| v.as_str())
+        .filter(|v| !v.trim().is_empty())
+}
+
+struct QifReader<'a> {
+    date_format: Option<&'a str>,
+    default_account_id: Option<i32>,
+    current_account: Option<String>,
+    transfers: TransferLedger,
+    parsed: ParsedImport,
+}
+
+impl QifReader<'_> {
+    fn base_row(&self, row: usize, date: &str) -> ImportRow {
+        ImportRow {
+            row,
+            account_id: if self.current_account.is_some() {
+                None
+            } else {
+                self.default_account_id
+            },
+            account_name: self.current_account.clone(),
+            date: date.to_string(),
+            ..Default::default()
+        }
+    }
+
+    fn declare_account(&mut self, name: &str) {
+        if !self
+            .parsed
+            .accounts
+            .iter()
+            .any(|a| a.name.eq_ignore_ascii_case(name))
+        {
+            self.parsed.accounts.push(DeclaredAccount {
+                name: name.to_string(),
+                currency: None,
+            });
+        }
+    }
+
+    // A cash line: either categorized, or a transfer when the category is `[Account]`
+    fn push_cash_line(
+        &mut self,
+        mut row: ImportRow,
+        payee: Option<&str>,
+        memo: Option<&str>,
+        category: Option<&str>,
+        amount: f64,
+    ) {
+        row.amount = amount;
+        row.payee = payee.unwrap_or("Unknown").to_string();
+        row.notes = memo.map(str::to_string);
+
+        let own_account = self.current_account.clone().unwrap_or_default();
+        match category.and_then(transfer_target) {
+            // Quicken marks the opening balance as a transfer to the account itself
+            Some(target) if target.eq_ignore_ascii_case(&own_account) => {
+                row.category = None;
+            }
+            Some(target) => {
+                self.declare_account(target);
+                if self
+                    .transfers
+                    .consume_or_expect(&own_account, target, &row.date, amount)
+                {
+                    return;
+                }
+                // The app models transfers as a payee naming the other account
+                if payee.is_some_and(|p| !p.eq_ignore_ascii_case(target)) && row.notes.is_none() {
+                    row.notes = payee.map(str::to_string);
+                }
+                row.payee = target.to_string();
+                row.category = Some("Transfer".to_string());
+            }
+            None => row.category = category.and_then(category_name),
+        }
+        self.parsed.rows.push(row);
+    }
+
+    fn bank_record(&mut self, record: &Record, row: usize) -> Result<(), String> {
+        let date = parse_qif_date(field(record, 'D').ok_or("Missing date")?, self.date_format)?;
+        let amount = parse_qif_amount(
+            field(record, 'T')
+                .or(field(record, 'U'))
+                .ok_or("Missing amount")?,
+        )?;
+        let payee = field(record, 'P');
+        let memo = field(record, 'M');
+        let category = field(record, 'L');
+
+        // Split lines: S category, E memo, $ amount, repeated
+        let mut splits: Vec<(Option<&str>, Option<&str>, f64)> = Vec::new();
+        for (code, value) in record {
+            match code {
+                'S' => splits.push((Some(value.as_str()), None, 0.0)),
+                'E' => {
+                    if let Some(last) = splits.last_mut() {
+                        last.1 = Some(value.as_str()).filter(|v| !v.trim().is_empty());
+                    }
+                }
+                '$' => {
+                    if let Some(last) = splits.last_mut() {
+                        last.2 = parse_qif_amount(value)?;
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        let mut base = self.base_row(row, &date);
+        if splits.is_empty() {
+            self.push_cash_line(base, payee, memo, category, amount);
+            return Ok(());
+        }
+        base.split_group = Some(row);
+
+        let split_total: f64 = splits.iter().map(|s| s.2).sum();
+        for (split_category, split_memo, split_amount) in splits {
+            self.push_cash_line(
+                base.clone(),
+                payee,
+                split_memo.or(memo),
+                split_category,
+                split_amount,
+            );
+        }
+        // Keep the account balance right even when the splits do not add up
+        let remainder = amount - split_total;
+        if remainder.abs() >= 0.005 {
+            self.push_cash_line(base, payee, memo, category, remainder);
+        }
+        Ok(())
+    }
+
+    fn investment_record(&mut self, record: &Record, row: usize) -> Result<(), String> {
+        let date = parse_qif_date(field(record, 'D').ok_or("Missing date")?, self.date_format)?;
+        let action = field(record, 'N').unwrap_or("Cash").trim().to_string();
+        let ticker = field(record, 'Y').map(|t| t.trim().to_string());
+        let number = |code: char| field(record, code).map(parse_qif_amount).transpose();
+        let total = number('T')?.or(number('U')?).unwrap_or(0.0).abs();
+        let memo = field(record, 'M');
+        let transfer = field(record, 'L');
+
+        let trade = |reader: &Self, is_buy: bool| -> Result<ImportRow, String> {
+            let shares = number('Q')?.ok_or("Missing share quantity")?.abs();
+            let price = match number('I')? {
+                Some(p) => p,
+                None if shares > 0.0 => total / shares,
+                None => 0.0,
+            };
+            let fee = number('O')?.unwrap_or(0.0).abs();
+            let gross = shares * price;
+            let mut r = reader.base_row(row, &date);
+            r.payee = if is_buy { "Buy" } else { "Sell" }.to_string();
+            r.category = Some("Investment".to_string());
+            r.amount = if is_buy { -(gross + fee) } else { gross - fee };
+            r.ticker = Some(ticker.clone().ok_or("Missing security")?);
+            r.shares = Some(shares);
+            r.price_per_share = Some(price);
+            r.fee = Some(fee);
+            r.is_buy = Some(is_buy);
+            Ok(r)
+        };
+
+        let lower = action.to_ascii_lowercase();
+        match lower.as_str() {
+            "buy" | "buyx" | "sell" | "sellx" => {
+                let is_buy = lower.starts_with("buy");
+                let t = trade(self, is_buy)?;
+                let cash = t.amount;
+                self.parsed.rows.push(t);
+                // The X variants move the cash through another account
+                if lower.ends_with('x') && transfer.is_some() {
+                    let base = self.base_row(row, &date);
+                    self.push_cash_line(base, None, memo, transfer, -cash);
+                }
+            }
+            "reinvdiv" | "reinvint" | "reinvlg" | "reinvsh" => {
+                let label = investment_income_label(&lower);
+                let mut income = self.base_row(row, &date);
+                income.amount = total;
+                income.payee = format!("{} {}", label, ticker.clone().unwrap_or_default())
+                    .trim()
+                    .to_string();
+                income.notes = memo.map(str::to_string);
+                self.parsed.rows.push(income);
+                let t = trade(self, true)?;
+                self.parsed.rows.push(t);
+            }
+            "div" | "divx" | "intinc" | "intincx" | "cglong" | "cglongx" | "cgshort"
+            | "cgshortx" | "miscinc" | "miscincx" => {
+                let label = investment_income_label(&lower);
+                let base = self.base_row(row, &date);
+                let payee = format!("{} {}", label, ticker.clone().unwrap_or_default());
+                self.push_cash_line(base.clone(), Some(payee.trim()), memo, None, total);
+                if lower.ends_with('x') && transfer.is_some() {
+                    self.push_cash_line(base, None, memo, transfer, -total);
+                }
+            }
+            "xin" | "xout" | "cash" | "contribx" | "withdrwx" | "margint" | "miscexp" => {
+                let signed = match lower.as_str() {
+                    "xin" | "contribx" => total,
+                    "xout" | "withdrwx" | "margint" | "miscexp" => -total,
+                    // Cash lines carry their own sign
+                    _ => number('T')?.or(number('U')?).unwrap_or(0.0),
+                };
+                let base = self.base_row(row, &date);
+                self.push_cash_line(base, field(record, 'P'), memo, transfer, signed);
+            }
+            _ => return Err(format!("Unsupported investment action '{}'", action)),
+        }
+        Ok(())
+    }
+}
+
+fn investment_income_label(action: &str) -> &'static str {
+    match action.trim_start_matches("reinv").trim_end_matches('x') {
+        "div" => "Dividend",
+        "int" | "intinc" => "Interest",
+        "lg" | "cglong" => "Long-term capital gain",
+        "sh" | "cgshort" => "Short-term capital gain",
+        _ => "Investment income",
+    }
+}
+
+// Parses a QIF file. Sections without a preceding `!Account` header go to `default_account_id`.
+pub fn parse_qif(
+    content: &str,
+    default_account_id: Option<i32>,
+    date_format: Option<&str>,
+) -> Result<ParsedImport, String> {
+    let mut reader = QifReader {
+        date_format,
+        default_account_id,
+        current_account: None,
+        transfers: TransferLedger::default(),
+        parsed: ParsedImport::default(),
+    };
+    let mut section = Section::Other;
+    let mut in_account_block = false;
+    let mut saw_header = false;
+    let mut record: Record = Vec::new();
+
+    for raw in content.lines() {
+        let line = raw.trim_end();
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        if let Some(header) = line.strip_prefix('!') {
+            let header = header.trim().to_ascii_lowercase();
+            saw_header = true;
+            record.clear();
+            in_account_block = header == "account";
+            if let Some(kind) = header.strip_prefix("type:") {
+                section = match kind.trim() {
+                    "bank" | "cash" | "ccard" | "oth a" | "oth l" => Section::Bank,
+                    "invst" => Section::Investment,
+                    _ => Section::Other,
+                };
+            } else if !in_account_block {
+                section = Section::Other;
+            }
+            continue;
+        }
+
+        if !line.starts_with('^') {
+            let mut chars = line.chars();
+            if let Some(code) = chars.next() {
+                record.push((code, chars.as_str().to_string()));
+            }
+            continue;
+        }
+
+        // End of record
+        if in_account_block {
+            if let Some(name) = field(&record, 'N') {
+                let name = name.trim().to_string();
+                reader.declare_account(&name);
+                reader.current_account = Some(name);
+            }
+        } else if section != Section::Other && !record.is_empty() {
+            reader.parsed.total_rows += 1;
+            let row = reader.parsed.total_rows;
+            let result = match section {
+                Section::Bank => reader.bank_record(&record, row),
+                _ => reader.investment_record(&record, row),
+            };
+            if let Err(message) = result {
+                reader.parsed.errors.push(RowError { row, message });
+            }
+        }
+        record.clear();
+    }
+
+    if !saw_header {
+        return Err("Not a QIF file: no !Type or !Account header found".to_string());
+    }
+    Ok(reader.parsed)
+}
+
+pub fn import_qif_db(
+    db_path: &PathBuf,
+    file_path: &Path,
+    account_id: Option<i32>,
+    date_format: Option<String>,
+    dry_run: bool,
+    rules: &RuleSelection,
+) -> Result<ImportReport, String> {
+    let content = super::read_text_file(file_path)?;
+    let parsed = parse_qif(&content, account_id, date_format.as_deref())?;
+    super::finish_import(db_path, file_path, parsed, true, dry_run, rules)
+}
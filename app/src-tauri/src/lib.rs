@@ -6,6 +6,14 @@ use std::fs;
 use std::path::PathBuf;
 use tauri::{AppHandle, Manager};
 
+mod categorize;
+mod duplicates;
+mod export;
+mod holdings;
+mod import;
+mod payees;
+mod rules;
+
 #[derive(Serialize, Deserialize, Debug)]
 struct YahooQuote {
     symbol: String,
@@ -113,6 +121,13 @@ struct Rule {
     match_pattern: String,
     action_field: String,
     action_value: String,
+    #[serde(default)]
+    match_join: rules::MatchJoin,
+    // Empty for rules saved with `create_rule` only, which match `match_field` exactly
+    #[serde(default)]
+    conditions: Vec<rules::RuleCondition>,
+    #[serde(default)]
+    actions: Vec<rules::RuleAction>,
 }
 
 #[derive(Debug)]
@@ -190,9 +205,54 @@ fn get_db_path(app_handle: &AppHandle) -> Result<PathBuf, String> {
     Ok(app_dir.join("honeybear.db"))
 }
 
+// Whether the database already has the table
+fn table_exists(conn: &Connection, table: &str) -> Result<bool, String> {
+    conn.query_row(
+        "SELECT EXISTS(SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = ?1)",
+        params![table],
+        |row| row.get(0),
+    )
+    .map_err(|e| e.to_string())
+}
+
+// Adds a nullable column to an existing table when an older database does not have it yet.
+// Concurrent runs may attempt this simultaneously; duplicate-column errors are ignored.
+fn ensure_column(
+    conn: &Connection,
+    table: &str,
+    column: &str,
+    definition: &str,
+) -> Result<(), String> {
+    let mut stmt = conn
+        .prepare(&format!("PRAGMA table_info({})", table))
+        .map_err(|e| e.to_string())?;
+    let exists = stmt
+        .query_map([], |row| row.get::<_, String>(1))
+        .map_err(|e| e.to_string())?
+        .flatten()
+        .any(|name| name == column);
+    if exists {
+        return Ok(());
+    }
+    match conn.execute(
+        &format!("ALTER TABLE {} ADD COLUMN {} {}", table, column, definition),
+        [],
+    ) {
+        Ok(_) => Ok(()),
+        Err(e) => {
+            let s = e.to_string();
+            if s.contains("duplicate column name") || s.contains("already exists") {
+                Ok(())
+            } else {
+                Err(s)
+            }
+        }
+    }
+}
+
 fn init_db(app_handle: &AppHandle) -> Result<(), String> {
     let db_path = get_db_path(app_handle)?;
-    let conn = Connection::open(db_path).map_err(|e| e.to_string())?;
+    let mut conn = Connection::open(db_path).map_err(|e| e.to_string())?;
 
     conn.execute(
         "CREATE TABLE IF NOT EXISTS accounts (
@@ -312,6 +372,63 @@ fn init_db(app_handle: &AppHandle) -> Result<(), String> {
         }
     }
 
+    // Statement identifiers (e.g. OFX FITID) used to skip already-imported lines
+    ensure_column(&conn, "transactions", "external_id", "TEXT")?;
+    conn.execute(
+        "CREATE INDEX IF NOT EXISTS idx_transactions_external_id ON transactions (account_id, external_id)",
+        [],
+    )
+    .map_err(|e| e.to_string())?;
+    // Duplicate screening looks transactions up by account and date range
+    conn.execute(
+        "CREATE INDEX IF NOT EXISTS idx_transactions_account_date ON transactions (account_id, date)",
+        [],
+    )
+    .map_err(|e| e.to_string())?;
+    // Committed imports, so a bad one can be rolled back as a whole
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS import_batches (
+            id INTEGER PRIMARY KEY,
+            file_name TEXT NOT NULL,
+            file_hash TEXT NOT NULL,
+            imported_at TEXT NOT NULL,
+            total_rows INTEGER NOT NULL DEFAULT 0,
+            imported_rows INTEGER NOT NULL DEFAULT 0,
+            duplicate_rows INTEGER NOT NULL DEFAULT 0,
+            error_rows INTEGER NOT NULL DEFAULT 0
+        )",
+        [],
+    )
+    .map_err(|e| e.to_string())?;
+    ensure_column(&conn, "transactions", "import_batch_id", "INTEGER")?;
+    // JSON list of the rules that modified a transaction when it was created
+    ensure_column(&conn, "transactions", "rule_ids", "TEXT")?;
+    // JSON list of tags added by rules
+    ensure_column(&conn, "transactions", "tags", "TEXT")?;
+    // Payee as the bank or the user wrote it, before cleaning; NULL when it was kept as is
+    ensure_column(&conn, "transactions", "raw_payee", "TEXT")?;
+    // Value date from bank statements, when it differs from the booking date
+    ensure_column(&conn, "transactions", "value_date", "TEXT")?;
+    // Id of the first part of a split transaction, set on every part so the split stays grouped
+    ensure_column(&conn, "transactions", "split_id", "INTEGER")?;
+    conn.execute(
+        "CREATE INDEX IF NOT EXISTS idx_transactions_import_batch ON transactions (import_batch_id)",
+        [],
+    )
+    .map_err(|e| e.to_string())?;
+    // Saved CSV mappings, one per bank export layout
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS import_profiles (
+            id INTEGER PRIMARY KEY,
+            name TEXT NOT NULL UNIQUE,
+            header_fingerprint TEXT NOT NULL,
+            mapping TEXT NOT NULL,
+            rule_ids TEXT
+        )",
+        [],
+    )
+    .map_err(|e| e.to_string())?;
+
     conn.execute(
         "CREATE TABLE IF NOT EXISTS stock_prices (
             ticker TEXT PRIMARY KEY,
@@ -345,6 +462,110 @@ fn init_db(app_handle: &AppHandle) -> Result<(), String> {
         [],
     )
     .map_err(|e| e.to_string())?;
+    ensure_column(&conn, "rules", "match_join", "TEXT NOT NULL DEFAULT 'and'")?;
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS rule_conditions (
+            id INTEGER PRIMARY KEY,
+            rule_id INTEGER NOT NULL,
+            position INTEGER NOT NULL DEFAULT 0,
+            field TEXT NOT NULL,
+            operator TEXT NOT NULL,
+            value TEXT NOT NULL,
+            value_to TEXT,
+            case_sensitive INTEGER NOT NULL DEFAULT 0,
+            FOREIGN KEY(rule_id) REFERENCES rules(id)
+        )",
+        [],
+    )
+    .map_err(|e| e.to_string())?;
+    // Each rule's list of actions, in order
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS rule_actions (
+            id INTEGER PRIMARY KEY,
+            rule_id INTEGER NOT NULL,
+            position INTEGER NOT NULL DEFAULT 0,
+            kind TEXT NOT NULL,
+            field TEXT,
+            value TEXT NOT NULL,
+            FOREIGN KEY(rule_id) REFERENCES rules(id)
+        )",
+        [],
+    )
+    .map_err(|e| e.to_string())?;
+    // Rules saved before actions existed carry their one action in `action_field`/`action_value`
+    conn.execute(
+        "INSERT INTO rule_actions (rule_id, position, kind, field, value)
+         SELECT id, 0, 'setField', action_field, action_value FROM rules
+         WHERE id NOT IN (SELECT rule_id FROM rule_actions)",
+        [],
+    )
+    .map_err(|e| e.to_string())?;
+    // Undo records of rules applied to existing transactions
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS rule_runs (
+            id INTEGER PRIMARY KEY,
+            applied_at TEXT NOT NULL,
+            changes TEXT NOT NULL
+        )",
+        [],
+    )
+    .map_err(|e| e.to_string())?;
+    // Naive Bayes category model: what was learned from each transaction, and the feature counts
+    let model_exists = table_exists(&conn, "category_training")?;
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS category_training (
+            transaction_id INTEGER PRIMARY KEY,
+            category TEXT NOT NULL,
+            features TEXT NOT NULL
+        )",
+        [],
+    )
+    .map_err(|e| e.to_string())?;
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS category_features (
+            category TEXT NOT NULL,
+            feature TEXT NOT NULL,
+            count INTEGER NOT NULL,
+            PRIMARY KEY (category, feature)
+        )",
+        [],
+    )
+    .map_err(|e| e.to_string())?;
+    // Write paths keep the model current from here on; older ledgers learn their history once
+    if !model_exists {
+        categorize::sync_model(&mut conn, true)?;
+    }
+    // User-defined payee rewrites, applied in order after the built-in cleaners
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS payee_rewrites (
+            id INTEGER PRIMARY KEY,
+            position INTEGER NOT NULL DEFAULT 0,
+            pattern TEXT NOT NULL,
+            replacement TEXT NOT NULL,
+            is_regex INTEGER NOT NULL DEFAULT 0
+        )",
+        [],
+    )
+    .map_err(|e| e.to_string())?;
+    // How sales in an investment account pick the lots they close: fifo, lifo, average or
+    // specificLot
+    ensure_column(
+        &conn,
+        "accounts",
+        "cost_basis_method",
+        "TEXT NOT NULL DEFAULT 'fifo'",
+    )?;
+    // Lots picked by hand for a sale, by the id of the buy that opened them
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS lot_selections (
+            sale_id INTEGER NOT NULL,
+            lot_id INTEGER NOT NULL,
+            shares REAL NOT NULL,
+            PRIMARY KEY (sale_id, lot_id)
+        )",
+        [],
+    )
+    .map_err(|e| e.to_string())?;
 
     conn.execute(
         "CREATE TABLE IF NOT EXISTS custom_exchange_rates (
@@ -451,6 +672,7 @@ fn create_account_db(
             ],
         )
         .map_err(|e| e.to_string())?;
+        categorize::refresh(&tx, tx.last_insert_rowid() as i32)?;
     }
 
     tx.commit().map_err(|e| e.to_string())?;
@@ -600,12 +822,33 @@ fn delete_account_db(db_path: &PathBuf, id: i32) -> Result<(), String> {
 
     let tx = conn.transaction().map_err(|e| e.to_string())?;
 
+    let removed: Vec<i32> = {
+        let mut stmt = tx
+            .prepare("SELECT id FROM transactions WHERE account_id = ?1")
+            .map_err(|e| e.to_string())?;
+        let ids = stmt
+            .query_map(params![id], |row| row.get(0))
+            .map_err(|e| e.to_string())?
+            .collect::<Result<_, _>>()
+            .map_err(|e| e.to_string())?;
+        ids
+    };
+
+    tx.execute(
+        "DELETE FROM lot_selections WHERE sale_id IN (SELECT id FROM transactions WHERE account_id = ?1) OR lot_id IN (SELECT id FROM transactions WHERE account_id = ?1)",
+        params![id],
+    )
+    .map_err(|e| e.to_string())?;
     // Delete all transactions for this account
     tx.execute(
         "DELETE FROM transactions WHERE account_id = ?1",
         params![id],
     )
     .map_err(|e| e.to_string())?;
+    // The category model forgets what it learned from them
+    for transaction_id in removed {
+        categorize::refresh(&tx, transaction_id)?;
+    }
 
     // Delete the account
     tx.execute("DELETE FROM accounts WHERE id = ?1", params![id])
@@ -893,7 +1136,7 @@ async fn get_accounts(
     Ok(accounts)
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, Clone)]
 #[serde(rename_all = "camelCase")]
 struct CreateTransactionArgs {
     account_id: i32,
@@ -912,11 +1155,45 @@ struct CreateTransactionArgs {
 fn create_transaction_db(
     db_path: &PathBuf,
     args: CreateTransactionArgs,
+) -> Result<Transaction, String> {
+    create_transaction_with_rules_db(db_path, args, &rules::RuleSelection::All)
+}
+
+// Cleans the payee and runs the selected rules on the new transaction before inserting it, and
+// records which of them changed it. When a rule splits it, every part is inserted and the first one is returned.
+fn create_transaction_with_rules_db(
+    db_path: &PathBuf,
+    args: CreateTransactionArgs,
+    selection: &rules::RuleSelection,
 ) -> Result<Transaction, String> {
     let mut conn = Connection::open(db_path).map_err(|e| e.to_string())?;
 
     let tx = conn.transaction().map_err(|e| e.to_string())?;
+    let mut args = args;
+    let raw_payee = std::mem::take(&mut args.payee);
+    args.payee = payees::PayeeCleaner::load(&tx)?.clean(&raw_payee);
+    let applied = rules::RuleEngine::load(&tx, selection)?.apply_to_args(&tx, args)?;
+    let mut inserted = Vec::with_capacity(applied.transactions.len());
+    for args in applied.transactions {
+        let transaction = insert_transaction(&tx, args)?;
+        rules::record_rule_ids(&tx, transaction.id, &applied.rule_ids)?;
+        rules::record_tags(&tx, transaction.id, &applied.tags)?;
+        payees::record_raw_payee(&tx, transaction.id, &raw_payee, &transaction.payee)?;
+        inserted.push(transaction);
+    }
+    let ids: Vec<i32> = inserted.iter().map(|t| t.id).collect();
+    rules::record_split(&tx, &ids)?;
+    tx.commit().map_err(|e| e.to_string())?;
+
+    inserted
+        .into_iter()
+        .next()
+        .ok_or_else(|| "No transaction was created".to_string())
+}
 
+// Inserts a transaction (plus its transfer counterpart when the payee names another account)
+// on an open connection, so bulk paths such as imports can share one SQLite transaction.
+fn insert_transaction(tx: &Connection, args: CreateTransactionArgs) -> Result<Transaction, String> {
     // Check if payee matches another account for Transfer detection
     let target_account_info: Option<i32> = tx
         .query_row(
@@ -982,8 +1259,7 @@ fn create_transaction_db(
         )
         .map_err(|e| e.to_string())?;
     }
-
-    tx.commit().map_err(|e| e.to_string())?;
+    categorize::refresh(tx, id)?;
 
     Ok(Transaction {
         id,
@@ -1005,9 +1281,306 @@ fn create_transaction_db(
 fn create_transaction(
     app_handle: AppHandle,
     args: CreateTransactionArgs,
+    apply_rules: Option<rules::RuleSelection>,
 ) -> Result<Transaction, String> {
     let db_path = get_db_path(&app_handle)?;
-    create_transaction_db(&db_path, args)
+    match apply_rules {
+        Some(selection) => create_transaction_with_rules_db(&db_path, args, &selection),
+        None => create_transaction_db(&db_path, args),
+    }
+}
+
+#[tauri::command]
+fn import_ofx(
+    app_handle: AppHandle,
+    path: String,
+    account_id: i32,
+    dry_run: bool,
+    apply_rules: Option<rules::RuleSelection>,
+) -> Result<import::ImportReport, String> {
+    let db_path = get_db_path(&app_handle)?;
+    import::ofx::import_ofx_db(
+        &db_path,
+        std::path::Path::new(&path),
+        account_id,
+        dry_run,
+        &apply_rules.unwrap_or_default(),
+    )
+}
+
+#[tauri::command]
+fn import_camt053(
+    app_handle: AppHandle,
+    path: String,
+    account_id: i32,
+    dry_run: bool,
+    apply_rules: Option<rules::RuleSelection>,
+) -> Result<import::ImportReport, String> {
+    let db_path = get_db_path(&app_handle)?;
+    import::camt::import_camt053_db(
+        &db_path,
+        std::path::Path::new(&path),
+        account_id,
+        dry_run,
+        &apply_rules.unwrap_or_default(),
+    )
+}
+
+#[tauri::command]
+fn import_broker(
+    app_handle: AppHandle,
+    path: String,
+    format: import::broker::BrokerFormat,
+    account_id: i32,
+    dry_run: bool,
+    apply_rules: Option<rules::RuleSelection>,
+) -> Result<import::ImportReport, String> {
+    let db_path = get_db_path(&app_handle)?;
+    import::broker::import_broker_db(
+        &db_path,
+        std::path::Path::new(&path),
+        format,
+        account_id,
+        dry_run,
+        &apply_rules.unwrap_or_default(),
+    )
+}
+
+#[tauri::command]
+fn find_duplicates(
+    app_handle: AppHandle,
+    window_days: Option<i64>,
+    account_id: Option<i32>,
+) -> Result<Vec<duplicates::DuplicatePair>, String> {
+    let db_path = get_db_path(&app_handle)?;
+    duplicates::find_duplicates_db(
+        &db_path,
+        window_days.unwrap_or(duplicates::DEFAULT_WINDOW_DAYS),
+        account_id,
+    )
+}
+
+#[tauri::command]
+fn merge_duplicates(
+    app_handle: AppHandle,
+    keep_id: i32,
+    duplicate_id: i32,
+) -> Result<duplicates::LedgerEntry, String> {
+    let db_path = get_db_path(&app_handle)?;
+    duplicates::merge_duplicates_db(&db_path, keep_id, duplicate_id)
+}
+
+#[tauri::command]
+fn list_import_batches(app_handle: AppHandle) -> Result<Vec<import::batches::ImportBatch>, String> {
+    let db_path = get_db_path(&app_handle)?;
+    import::batches::list_import_batches_db(&db_path)
+}
+
+#[tauri::command]
+fn rollback_import_batch(
+    app_handle: AppHandle,
+    batch_id: i64,
+) -> Result<import::batches::RollbackReport, String> {
+    let db_path = get_db_path(&app_handle)?;
+    import::batches::rollback_import_batch_db(&db_path, batch_id)
+}
+
+#[tauri::command]
+fn create_import_profile(
+    app_handle: AppHandle,
+    name: String,
+    mapping: import::csv::CsvMapping,
+    rule_ids: Option<Vec<i32>>,
+    sample_path: String,
+) -> Result<import::profiles::ImportProfile, String> {
+    let db_path = get_db_path(&app_handle)?;
+    import::profiles::create_import_profile_db(
+        &db_path,
+        name,
+        mapping,
+        rule_ids,
+        std::path::Path::new(&sample_path),
+    )
+}
+
+#[tauri::command]
+fn list_import_profiles(
+    app_handle: AppHandle,
+) -> Result<Vec<import::profiles::ImportProfile>, String> {
+    let db_path = get_db_path(&app_handle)?;
+    import::profiles::list_import_profiles_db(&db_path)
+}
+
+#[tauri::command]
+fn delete_import_profile(app_handle: AppHandle, id: i32) -> Result<(), String> {
+    let db_path = get_db_path(&app_handle)?;
+    import::profiles::delete_import_profile_db(&db_path, id)
+}
+
+#[tauri::command]
+fn detect_import_profile(
+    app_handle: AppHandle,
+    path: String,
+) -> Result<Option<import::profiles::ImportProfile>, String> {
+    let db_path = get_db_path(&app_handle)?;
+    import::profiles::detect_import_profile_db(&db_path, std::path::Path::new(&path))
+}
+
+#[tauri::command]
+fn import_with_profile(
+    app_handle: AppHandle,
+    path: String,
+    profile_id: Option<i32>,
+    dry_run: bool,
+) -> Result<import::ImportReport, String> {
+    let db_path = get_db_path(&app_handle)?;
+    import::profiles::import_with_profile_db(
+        &db_path,
+        std::path::Path::new(&path),
+        profile_id,
+        dry_run,
+    )
+}
+
+#[tauri::command]
+fn import_from_app(
+    app_handle: AppHandle,
+    path: String,
+    app: import::apps::SourceApp,
+    date_format: Option<String>,
+    dry_run: bool,
+    apply_rules: Option<rules::RuleSelection>,
+) -> Result<import::apps::MigrationReport, String> {
+    let db_path = get_db_path(&app_handle)?;
+    import::apps::import_app_db(
+        &db_path,
+        std::path::Path::new(&path),
+        app,
+        date_format,
+        dry_run,
+        &apply_rules.unwrap_or_default(),
+    )
+}
+
+#[tauri::command]
+fn import_mt940(
+    app_handle: AppHandle,
+    path: String,
+    account_id: i32,
+    dry_run: bool,
+    apply_rules: Option<rules::RuleSelection>,
+) -> Result<import::ImportReport, String> {
+    let db_path = get_db_path(&app_handle)?;
+    import::mt940::import_mt940_db(
+        &db_path,
+        std::path::Path::new(&path),
+        account_id,
+        dry_run,
+        &apply_rules.unwrap_or_default(),
+    )
+}
+
+#[tauri::command]
+fn import_beancount(
+    app_handle: AppHandle,
+    path: String,
+    dry_run: bool,
+    apply_rules: Option<rules::RuleSelection>,
+) -> Result<import::ImportReport, String> {
+    let db_path = get_db_path(&app_handle)?;
+    import::beancount::import_beancount_db(
+        &db_path,
+        std::path::Path::new(&path),
+        dry_run,
+        &apply_rules.unwrap_or_default(),
+    )
+}
+
+#[tauri::command]
+fn import_qif(
+    app_handle: AppHandle,
+    path: String,
+    account_id: Option<i32>,
+    date_format: Option<String>,
+    dry_run: bool,
+    apply_rules: Option<rules::RuleSelection>,
+) -> Result<import::ImportReport, String> {
+    let db_path = get_db_path(&app_handle)?;
+    import::qif::import_qif_db(
+        &db_path,
+        std::path::Path::new(&path),
+        account_id,
+        date_format,
+        dry_run,
+        &apply_rules.unwrap_or_default(),
+    )
+}
+
+#[tauri::command]
+fn export_qif(
+    app_handle: AppHandle,
+    path: String,
+    account_ids: Option<Vec<i32>>,
+) -> Result<usize, String> {
+    let db_path = get_db_path(&app_handle)?;
+    export::qif::export_qif_db(&db_path, std::path::Path::new(&path), account_ids)
+}
+
+#[tauri::command]
+fn export_journal(
+    app_handle: AppHandle,
+    path: String,
+    format: export::journal::JournalFormat,
+) -> Result<usize, String> {
+    let db_path = get_db_path(&app_handle)?;
+    export::journal::export_journal_db(&db_path, std::path::Path::new(&path), format)
+}
+
+#[tauri::command]
+fn export_ledger(
+    app_handle: AppHandle,
+    path: String,
+    options: export::ledger::LedgerExportOptions,
+) -> Result<usize, String> {
+    let db_path = get_db_path(&app_handle)?;
+    export::ledger::export_ledger_db(&db_path, std::path::Path::new(&path), options)
+}
+
+#[tauri::command]
+fn export_snapshot(
+    app_handle: AppHandle,
+    path: String,
+) -> Result<export::snapshot::SnapshotReport, String> {
+    let db_path = get_db_path(&app_handle)?;
+    export::snapshot::export_snapshot_db(&db_path, std::path::Path::new(&path))
+}
+
+#[tauri::command]
+fn import_snapshot(
+    app_handle: AppHandle,
+    path: String,
+    mode: export::snapshot::SnapshotMode,
+) -> Result<export::snapshot::SnapshotReport, String> {
+    let db_path = get_db_path(&app_handle)?;
+    export::snapshot::import_snapshot_db(&db_path, std::path::Path::new(&path), mode)
+}
+
+#[tauri::command]
+fn import_transactions(
+    app_handle: AppHandle,
+    path: String,
+    mapping: import::csv::CsvMapping,
+    dry_run: bool,
+    apply_rules: Option<rules::RuleSelection>,
+) -> Result<import::ImportReport, String> {
+    let db_path = get_db_path(&app_handle)?;
+    import::csv::import_csv_db(
+        &db_path,
+        std::path::Path::new(&path),
+        mapping,
+        dry_run,
+        &apply_rules.unwrap_or_default(),
+    )
 }
 
 fn get_transactions_db(db_path: &PathBuf, account_id: i32) -> Result<Vec<Transaction>, String> {
@@ -1113,6 +1686,20 @@ struct UpdateTransactionArgs {
 fn create_investment_transaction_db(
     db_path: &PathBuf,
     args: CreateInvestmentTransactionArgs,
+) -> Result<Transaction, String> {
+    let mut conn = Connection::open(db_path).map_err(|e| e.to_string())?;
+
+    let tx = conn.transaction().map_err(|e| e.to_string())?;
+    let transaction = insert_investment_transaction(&tx, args)?;
+    tx.commit().map_err(|e| e.to_string())?;
+
+    Ok(transaction)
+}
+
+// Connection-level counterpart of `create_investment_transaction_db` for bulk paths
+fn insert_investment_transaction(
+    tx: &Connection,
+    args: CreateInvestmentTransactionArgs,
 ) -> Result<Transaction, String> {
     let CreateInvestmentTransactionArgs {
         account_id,
@@ -1125,10 +1712,6 @@ fn create_investment_transaction_db(
         currency,
     } = args;
 
-    let mut conn = Connection::open(db_path).map_err(|e| e.to_string())?;
-
-    let tx = conn.transaction().map_err(|e| e.to_string())?;
-
     let total_price = shares * price_per_share;
 
     // Investment Transaction Amount on the unified account
@@ -1167,8 +1750,6 @@ fn create_investment_transaction_db(
     )
     .map_err(|e| e.to_string())?;
 
-    tx.commit().map_err(|e| e.to_string())?;
-
     Ok(Transaction {
         id,
         account_id,
@@ -1203,6 +1784,137 @@ fn create_investment_transaction(
     create_investment_transaction_db(&db_path, args)
 }
 
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct CreateInvestmentIncomeArgs {
+    account_id: i32,
+    date: String,
+    ticker: String,
+    kind: holdings::income::IncomeKind,
+    gross_amount: f64,
+    withholding_tax: Option<f64>,
+    // Dividend reinvestment: shares bought with the payment, on the same day
+    reinvest_shares: Option<f64>,
+    reinvest_price: Option<f64>,
+    currency: Option<String>,
+}
+
+#[derive(Serialize, Debug)]
+struct InvestmentIncome {
+    income: Transaction,
+    reinvestment: Option<Transaction>,
+}
+
+// Records a payment from a holding as a cash transaction tied to its ticker: the amount is what
+// was received after withholding, which is kept in `fee`. A reinvestment adds a buy that opens a
+// new lot.
+fn create_investment_income_db(
+    db_path: &PathBuf,
+    args: CreateInvestmentIncomeArgs,
+) -> Result<InvestmentIncome, String> {
+    let ticker = args.ticker.trim().to_string();
+    if ticker.is_empty() {
+        return Err("A ticker is required".to_string());
+    }
+    if NaiveDate::parse_from_str(&args.date, "%Y-%m-%d").is_err() {
+        return Err(format!("Invalid date '{}', expected YYYY-MM-DD", args.date));
+    }
+    if args.gross_amount <= 0.0 {
+        return Err("Gross amount must be positive".to_string());
+    }
+    let withholding = args.withholding_tax.unwrap_or(0.0);
+    if withholding < 0.0 || withholding > args.gross_amount {
+        return Err("Withholding tax must be between 0 and the gross amount".to_string());
+    }
+    let reinvestment = match (args.reinvest_shares, args.reinvest_price) {
+        (None, None) => None,
+        (Some(shares), Some(price)) if shares > 0.0 && price > 0.0 => Some((shares, price)),
+        _ => return Err("Reinvestment needs positive shares and price".to_string()),
+    };
+
+    let mut conn = Connection::open(db_path).map_err(|e| e.to_string())?;
+    let tx = conn.transaction().map_err(|e| e.to_string())?;
+    let amount = args.gross_amount - withholding;
+    let notes = format!("{} from {}", args.kind.label(), ticker);
+    let category = args.kind.category();
+    tx.execute(
+        "INSERT INTO transactions (account_id, date, payee, notes, category, amount, ticker, fee, currency) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+        params![
+            args.account_id,
+            args.date,
+            ticker,
+            notes,
+            category,
+            amount,
+            ticker,
+            withholding,
+            args.currency
+        ],
+    )
+    .map_err(|e| e.to_string())?;
+    let id = tx.last_insert_rowid() as i32;
+    tx.execute(
+        "UPDATE accounts SET balance = balance + ?1 WHERE id = ?2",
+        params![amount, args.account_id],
+    )
+    .map_err(|e| e.to_string())?;
+    categorize::refresh(&tx, id)?;
+
+    let reinvestment = match reinvestment {
+        Some((shares, price_per_share)) => Some(insert_investment_transaction(
+            &tx,
+            CreateInvestmentTransactionArgs {
+                account_id: args.account_id,
+                date: args.date.clone(),
+                ticker: ticker.clone(),
+                shares,
+                price_per_share,
+                fee: 0.0,
+                is_buy: true,
+                currency: args.currency.clone(),
+            },
+        )?),
+        None => None,
+    };
+    tx.commit().map_err(|e| e.to_string())?;
+
+    Ok(InvestmentIncome {
+        income: Transaction {
+            id,
+            account_id: args.account_id,
+            date: args.date,
+            payee: ticker.clone(),
+            notes: Some(notes),
+            category: Some(category.to_string()),
+            amount,
+            ticker: Some(ticker),
+            shares: None,
+            price_per_share: None,
+            fee: Some(withholding),
+            currency: args.currency,
+        },
+        reinvestment,
+    })
+}
+
+#[tauri::command]
+fn create_investment_income(
+    app_handle: AppHandle,
+    args: CreateInvestmentIncomeArgs,
+) -> Result<InvestmentIncome, String> {
+    let db_path = get_db_path(&app_handle)?;
+    create_investment_income_db(&db_path, args)
+}
+
+#[tauri::command]
+fn get_investment_income(
+    app_handle: AppHandle,
+    query: holdings::income::IncomeQuery,
+) -> Result<Vec<holdings::income::HoldingIncome>, String> {
+    let db_path = get_db_path(&app_handle)?;
+    holdings::income::get_investment_income_db(&db_path, query)
+}
+
 fn update_transaction_db(
     db_path: &PathBuf,
     args: UpdateTransactionArgs,
@@ -1336,8 +2048,10 @@ fn update_transaction_db(
                 )
                 .map_err(|e| e.to_string())?;
             }
+            categorize::refresh(&tx, counterpart_id)?;
         }
     }
+    categorize::refresh(&tx, id)?;
 
     tx.commit().map_err(|e| e.to_string())?;
 
@@ -1519,69 +2233,128 @@ fn update_investment_transaction(
     update_investment_transaction_db(&db_path, args)
 }
 
-fn delete_transaction_db(db_path: &PathBuf, id: i32) -> Result<(), String> {
-    let mut conn = Connection::open(db_path).map_err(|e| e.to_string())?;
+#[tauri::command]
+fn get_holdings(
+    app_handle: AppHandle,
+    account_id: Option<i32>,
+) -> Result<Vec<holdings::Holding>, String> {
+    let db_path = get_db_path(&app_handle)?;
+    holdings::get_holdings_db(&db_path, account_id)
+}
 
-    let tx = conn.transaction().map_err(|e| e.to_string())?;
+#[tauri::command]
+fn get_cost_basis_method(
+    app_handle: AppHandle,
+    account_id: i32,
+) -> Result<holdings::CostBasisMethod, String> {
+    let db_path = get_db_path(&app_handle)?;
+    holdings::get_cost_basis_method_db(&db_path, account_id)
+}
+
+#[tauri::command]
+fn set_cost_basis_method(
+    app_handle: AppHandle,
+    account_id: i32,
+    method: holdings::CostBasisMethod,
+) -> Result<(), String> {
+    let db_path = get_db_path(&app_handle)?;
+    holdings::set_cost_basis_method_db(&db_path, account_id, method)
+}
+
+#[tauri::command]
+fn select_lots(
+    app_handle: AppHandle,
+    sale_id: i32,
+    picks: Vec<holdings::LotPick>,
+) -> Result<(), String> {
+    let db_path = get_db_path(&app_handle)?;
+    holdings::select_lots_db(&db_path, sale_id, picks)
+}
 
-    // Get amount, account_id, notes and linked_tx_id (if any)
-    let (amount, account_id, notes, linked): (f64, i32, Option<String>, Option<i32>) = tx
+#[tauri::command]
+fn get_realized_gains(
+    app_handle: AppHandle,
+    query: holdings::gains::RealizedGainsQuery,
+) -> Result<holdings::gains::RealizedGainsReport, String> {
+    let db_path = get_db_path(&app_handle)?;
+    holdings::gains::get_realized_gains_db(&db_path, query)
+}
+
+#[tauri::command]
+fn export_realized_gains(
+    app_handle: AppHandle,
+    path: String,
+    query: holdings::gains::RealizedGainsQuery,
+) -> Result<usize, String> {
+    let db_path = get_db_path(&app_handle)?;
+    holdings::gains::export_realized_gains_db(&db_path, std::path::Path::new(&path), query)
+}
+
+// Removes one transaction row with what hangs off it (picked lots, what the category model
+// learned from it) and takes its amount off the account balance. Returns the account and amount
+// of the removed row, or None when there was no such row. Transfer counterparts are left to the
+// caller.
+fn remove_transaction(tx: &Connection, id: i32) -> Result<Option<(i32, f64)>, String> {
+    let removed: Option<(i32, f64)> = tx
         .query_row(
-            "SELECT amount, account_id, notes, linked_tx_id FROM transactions WHERE id = ?1",
+            "SELECT account_id, amount FROM transactions WHERE id = ?1",
             params![id],
-            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)),
+            |row| Ok((row.get(0)?, row.get(1)?)),
         )
+        .optional()
         .map_err(|e| e.to_string())?;
-
-    // Delete the requested transaction
+    let Some((account_id, amount)) = removed else {
+        return Ok(None);
+    };
     tx.execute("DELETE FROM transactions WHERE id = ?1", params![id])
         .map_err(|e| e.to_string())?;
-
+    tx.execute(
+        "DELETE FROM lot_selections WHERE sale_id = ?1 OR lot_id = ?1",
+        params![id],
+    )
+    .map_err(|e| e.to_string())?;
+    categorize::refresh(tx, id)?;
     tx.execute(
         "UPDATE accounts SET balance = balance - ?1 WHERE id = ?2",
         params![amount, account_id],
     )
     .map_err(|e| e.to_string())?;
+    Ok(Some((account_id, amount)))
+}
+
+fn delete_transaction_db(db_path: &PathBuf, id: i32) -> Result<(), String> {
+    let mut conn = Connection::open(db_path).map_err(|e| e.to_string())?;
+
+    let tx = conn.transaction().map_err(|e| e.to_string())?;
+
+    // Get notes and linked_tx_id (if any)
+    let (notes, linked): (Option<String>, Option<i32>) = tx
+        .query_row(
+            "SELECT notes, linked_tx_id FROM transactions WHERE id = ?1",
+            params![id],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+        .map_err(|e| e.to_string())?;
+
+    // Delete the requested transaction
+    remove_transaction(&tx, id)?;
 
     // If there's a linked counterpart, delete it and update its account balance
     if let Some(linked_id) = linked {
-        if let Some((ctr_amount, ctr_account_id)) = tx
-            .query_row(
-                "SELECT amount, account_id FROM transactions WHERE id = ?1",
-                params![linked_id],
-                |row| Ok((row.get::<_, f64>(0)?, row.get::<_, i32>(1)?)),
-            )
-            .optional()
-            .map_err(|e| e.to_string())?
-        {
-            tx.execute("DELETE FROM transactions WHERE id = ?1", params![linked_id])
-                .map_err(|e| e.to_string())?;
-
-            tx.execute(
-                "UPDATE accounts SET balance = balance - ?1 WHERE id = ?2",
-                params![ctr_amount, ctr_account_id],
-            )
-            .map_err(|e| e.to_string())?;
-        }
+        remove_transaction(&tx, linked_id)?;
     } else if let Some(ref n) = notes {
-        // fallback: try to find counterpart by notes
-        if let Some((found_id, ctr_amount, ctr_account_id)) = tx
+        // fallback: try to find counterpart by notes, among transfers that are not linked to
+        // another transaction
+        if let Some(found_id) = tx
             .query_row(
-                "SELECT id, amount, account_id FROM transactions WHERE notes = ?1 AND category = 'Transfer' LIMIT 1",
+                "SELECT id FROM transactions WHERE notes = ?1 AND category = 'Transfer' AND linked_tx_id IS NULL LIMIT 1",
                 params![n],
-                |row| Ok((row.get::<_, i32>(0)?, row.get::<_, f64>(1)?, row.get::<_, i32>(2)?)),
+                |row| row.get::<_, i32>(0),
             )
             .optional()
             .map_err(|e| e.to_string())?
         {
-            tx.execute("DELETE FROM transactions WHERE id = ?1", params![found_id])
-                .map_err(|e| e.to_string())?;
-
-            tx.execute(
-                "UPDATE accounts SET balance = balance - ?1 WHERE id = ?2",
-                params![ctr_amount, ctr_account_id],
-            )
-            .map_err(|e| e.to_string())?;
+            remove_transaction(&tx, found_id)?;
         }
     }
 
@@ -1598,29 +2371,7 @@ fn delete_transaction(app_handle: AppHandle, id: i32) -> Result<(), String> {
 
 fn get_rules_db(db_path: &PathBuf) -> Result<Vec<Rule>, String> {
     let conn = Connection::open(db_path).map_err(|e| e.to_string())?;
-
-    let mut stmt = conn
-        .prepare("SELECT id, priority, match_field, match_pattern, action_field, action_value FROM rules ORDER BY priority DESC, id ASC")
-        .map_err(|e| e.to_string())?;
-
-    let rule_iter = stmt
-        .query_map([], |row| {
-            Ok(Rule {
-                id: row.get(0)?,
-                priority: row.get(1)?,
-                match_field: row.get(2)?,
-                match_pattern: row.get(3)?,
-                action_field: row.get(4)?,
-                action_value: row.get(5)?,
-            })
-        })
-        .map_err(|e| e.to_string())?;
-
-    let mut rules = Vec::new();
-    for rule in rule_iter {
-        rules.push(rule.map_err(|e| e.to_string())?);
-    }
-    Ok(rules)
+    rules::load_rules(&conn)
 }
 
 #[tauri::command]
@@ -1646,6 +2397,14 @@ fn create_rule_db(
     .map_err(|e| e.to_string())?;
 
     let id = conn.last_insert_rowid() as i32;
+    rules::write_actions(
+        &conn,
+        id,
+        &[rules::RuleAction::SetField {
+            field: action_field,
+            value: action_value,
+        }],
+    )?;
     Ok(id)
 }
 
@@ -1678,13 +2437,27 @@ fn update_rule_db(
     action_field: String,
     action_value: String,
 ) -> Result<(), String> {
-    let conn = Connection::open(db_path).map_err(|e| e.to_string())?;
+    let mut conn = Connection::open(db_path).map_err(|e| e.to_string())?;
+    let tx = conn.transaction().map_err(|e| e.to_string())?;
+    let rule = rules::load_rules(&tx)?
+        .into_iter()
+        .find(|r| r.id == id)
+        .ok_or_else(|| format!("Rule {} not found", id))?;
 
-    conn.execute(
+    tx.execute(
         "UPDATE rules SET priority = ?1, match_field = ?2, match_pattern = ?3, action_field = ?4, action_value = ?5 WHERE id = ?6",
         params![priority, match_field, match_pattern, action_field, action_value, id],
     )
     .map_err(|e| e.to_string())?;
+    rules::replace_first(
+        &tx,
+        &rule,
+        &match_field,
+        &match_pattern,
+        &action_field,
+        &action_value,
+    )?;
+    tx.commit().map_err(|e| e.to_string())?;
 
     Ok(())
 }
@@ -1712,10 +2485,14 @@ fn update_rule(
 }
 
 fn delete_rule_db(db_path: &PathBuf, id: i32) -> Result<(), String> {
-    let conn = Connection::open(db_path).map_err(|e| e.to_string())?;
+    let mut conn = Connection::open(db_path).map_err(|e| e.to_string())?;
+    let tx = conn.transaction().map_err(|e| e.to_string())?;
 
-    conn.execute("DELETE FROM rules WHERE id = ?1", params![id])
+    rules::write_conditions(&tx, id, &[])?;
+    rules::write_actions(&tx, id, &[])?;
+    tx.execute("DELETE FROM rules WHERE id = ?1", params![id])
         .map_err(|e| e.to_string())?;
+    tx.commit().map_err(|e| e.to_string())?;
 
     Ok(())
 }
@@ -1752,6 +2529,156 @@ fn update_rules_order(app_handle: AppHandle, rule_ids: Vec<i32>) -> Result<(), S
     update_rules_order_db(&db_path, rule_ids)
 }
 
+#[tauri::command]
+fn test_rules(app_handle: AppHandle) -> Result<rules::coverage::RuleTestReport, String> {
+    let db_path = get_db_path(&app_handle)?;
+    rules::coverage::test_rules_db(&db_path)
+}
+
+#[tauri::command]
+fn set_rule_conditions(
+    app_handle: AppHandle,
+    rule_id: i32,
+    match_join: rules::MatchJoin,
+    conditions: Vec<rules::RuleCondition>,
+) -> Result<(), String> {
+    let db_path = get_db_path(&app_handle)?;
+    rules::set_rule_conditions_db(&db_path, rule_id, match_join, conditions)
+}
+
+#[tauri::command]
+fn suggest_category(
+    app_handle: AppHandle,
+    payee: String,
+    notes: Option<String>,
+    amount: f64,
+    limit: Option<usize>,
+) -> Result<Vec<categorize::CategorySuggestion>, String> {
+    let db_path = get_db_path(&app_handle)?;
+    categorize::suggest_category_db(&db_path, &payee, notes.as_deref(), amount, limit)
+}
+
+#[tauri::command]
+fn retrain_category_model(
+    app_handle: AppHandle,
+    rebuild: Option<bool>,
+) -> Result<categorize::ModelSyncReport, String> {
+    let db_path = get_db_path(&app_handle)?;
+    categorize::retrain_category_model_db(&db_path, rebuild.unwrap_or(false))
+}
+
+#[tauri::command]
+fn set_rule_actions(
+    app_handle: AppHandle,
+    rule_id: i32,
+    actions: Vec<rules::RuleAction>,
+) -> Result<(), String> {
+    let db_path = get_db_path(&app_handle)?;
+    rules::set_rule_actions_db(&db_path, rule_id, actions)
+}
+
+#[tauri::command]
+fn apply_rules(
+    app_handle: AppHandle,
+    draft: rules::DraftTransaction,
+) -> Result<rules::RuleOutcome, String> {
+    let db_path = get_db_path(&app_handle)?;
+    rules::apply_rules_db(&db_path, draft)
+}
+
+#[tauri::command]
+fn apply_rules_to_existing(
+    app_handle: AppHandle,
+    apply_rules: Option<rules::RuleSelection>,
+    filter: rules::retroactive::TransactionFilter,
+    dry_run: bool,
+    approved: Option<Vec<i32>>,
+) -> Result<rules::retroactive::RuleRunReport, String> {
+    let db_path = get_db_path(&app_handle)?;
+    rules::retroactive::apply_rules_to_existing_db(
+        &db_path,
+        &apply_rules.unwrap_or_default(),
+        &filter,
+        dry_run,
+        approved,
+    )
+}
+
+#[tauri::command]
+fn undo_rule_run(
+    app_handle: AppHandle,
+    run_id: i64,
+) -> Result<rules::retroactive::UndoRuleRunReport, String> {
+    let db_path = get_db_path(&app_handle)?;
+    rules::retroactive::undo_rule_run_db(&db_path, run_id)
+}
+
+#[tauri::command]
+fn suggest_rules(
+    app_handle: AppHandle,
+    min_occurrences: Option<usize>,
+    min_consistency: Option<f64>,
+) -> Result<Vec<rules::suggest::RuleSuggestion>, String> {
+    let db_path = get_db_path(&app_handle)?;
+    rules::suggest::suggest_rules_db(&db_path, min_occurrences, min_consistency)
+}
+
+#[tauri::command]
+fn export_rules(
+    app_handle: AppHandle,
+    path: String,
+    format: Option<rules::sharing::RuleSetFormat>,
+    rule_ids: Option<Vec<i32>>,
+) -> Result<usize, String> {
+    let db_path = get_db_path(&app_handle)?;
+    rules::sharing::export_rules_db(&db_path, std::path::Path::new(&path), format, rule_ids)
+}
+
+#[tauri::command]
+fn import_rules(
+    app_handle: AppHandle,
+    path: String,
+    format: Option<rules::sharing::RuleSetFormat>,
+    strategy: Option<rules::sharing::ConflictStrategy>,
+) -> Result<rules::sharing::RuleImportReport, String> {
+    let db_path = get_db_path(&app_handle)?;
+    rules::sharing::import_rules_db(
+        &db_path,
+        std::path::Path::new(&path),
+        format,
+        strategy.unwrap_or_default(),
+    )
+}
+
+#[tauri::command]
+fn list_payee_rewrites(app_handle: AppHandle) -> Result<Vec<payees::PayeeRewrite>, String> {
+    let db_path = get_db_path(&app_handle)?;
+    payees::list_payee_rewrites_db(&db_path)
+}
+
+#[tauri::command]
+fn create_payee_rewrite(
+    app_handle: AppHandle,
+    pattern: String,
+    replacement: String,
+    is_regex: Option<bool>,
+) -> Result<payees::PayeeRewrite, String> {
+    let db_path = get_db_path(&app_handle)?;
+    payees::create_payee_rewrite_db(&db_path, pattern, replacement, is_regex.unwrap_or(false))
+}
+
+#[tauri::command]
+fn delete_payee_rewrite(app_handle: AppHandle, id: i32) -> Result<(), String> {
+    let db_path = get_db_path(&app_handle)?;
+    payees::delete_payee_rewrite_db(&db_path, id)
+}
+
+#[tauri::command]
+fn clean_payee(app_handle: AppHandle, payee: String) -> Result<String, String> {
+    let db_path = get_db_path(&app_handle)?;
+    payees::clean_payee_db(&db_path, &payee)
+}
+
 fn get_payees_db(db_path: &PathBuf) -> Result<Vec<String>, String> {
     let conn = Connection::open(db_path).map_err(|e| e.to_string())?;
 
@@ -2497,6 +3424,51 @@ pub fn run() {
             update_rule,
             delete_rule,
             update_rules_order,
+            import_transactions,
+            import_ofx,
+            import_camt053,
+            import_mt940,
+            import_qif,
+            export_qif,
+            export_journal,
+            export_ledger,
+            export_snapshot,
+            import_snapshot,
+            import_beancount,
+            import_broker,
+            import_from_app,
+            find_duplicates,
+            merge_duplicates,
+            list_import_batches,
+            rollback_import_batch,
+            create_import_profile,
+            list_import_profiles,
+            delete_import_profile,
+            detect_import_profile,
+            import_with_profile,
+            set_rule_conditions,
+            set_rule_actions,
+            suggest_category,
+            retrain_category_model,
+            apply_rules,
+            apply_rules_to_existing,
+            undo_rule_run,
+            suggest_rules,
+            export_rules,
+            import_rules,
+            test_rules,
+            list_payee_rewrites,
+            create_payee_rewrite,
+            delete_payee_rewrite,
+            clean_payee,
+            get_holdings,
+            get_cost_basis_method,
+            set_cost_basis_method,
+            select_lots,
+            get_realized_gains,
+            export_realized_gains,
+            create_investment_income,
+            get_investment_income,
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
@@ -0,0 +1,236 @@
+use regex::{NoExpand, Regex, RegexBuilder};
+use rusqlite::{params, Connection};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::path::PathBuf;
+use std::sync::LazyLock;
+
+// Two-letter US state and territory codes that end many card descriptors
+const STATES: &str = "AL|AK|AZ|AR|CA|CO|CT|DC|DE|FL|GA|HI|ID|IL|IN|IA|KS|KY|LA|ME|MD|MA|MI|MN|MS|MO|MT|NE|NV|NH|NJ|NM|NY|NC|ND|OH|OK|OR|PA|PR|RI|SC|SD|TN|TX|UT|VT|VA|WA|WV|WI|WY";
+
+// Cleaners that apply to any descriptor: card numbers and the words announcing them, reference
+// codes and numbers marked with `#`. Other digits may be part of the name ("1800 Flowers"), so
+// they stay. Compiled once, as every new transaction goes through them.
+static BUILT_IN: LazyLock<Vec<Regex>> = LazyLock::new(|| {
+    [
+        r"^(?:pos|card|debit|credit|visa|mastercard|contactless)(?:\s+(?:purchase|payment|card))*[\s:-]+[x*]*\d{4}\b",
+        r"^pos\b[\s:-]*",
+        r"\b\d{4}(?:[ -]?\d{4}){3}\b",
+        r"[x*]{4,}\d{4}\b",
+        r"\*\s*[a-z0-9]*\d[a-z0-9]*",
+        r"\b(?:ref|reference|auth|txn|trace)\b[\s:.#-]*[a-z0-9]*\d[a-z0-9]*",
+        r"#\s*\d+",
+    ]
+    .iter()
+    .map(|pattern| case_insensitive(pattern).expect("built-in payee cleaner"))
+    .collect()
+});
+
+// City and state, or country, at the end of an all-capitals descriptor. At least one word must
+// remain in front of them.
+static LOCATION: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(&format!(
+        r"^(.*?\S)\s+(?:(?:SAN|SANTA|LOS|LAS|NEW|EL|FORT|FT|ST|SAINT|SALT LAKE)\s+)?[A-Z][A-Z'.-]+\s+(?:{})$",
+        STATES
+    ))
+    .expect("location cleaner")
+});
+// Store number at the end of an all-capitals descriptor, as in "WALGREENS 1234"
+static STORE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^(.*\S)\s+\d{3,}$").expect("store number cleaner"));
+static COUNTRY: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^(.*\S)\s+(?:US|USA)$").expect("country cleaner"));
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct PayeeRewrite {
+    pub id: i32,
+    pub position: i32,
+    pub pattern: String,
+    pub replacement: String,
+    // Plain patterns match case-insensitively anywhere in the payee; regexes may use `$1` in the
+    // replacement
+    pub is_regex: bool,
+}
+
+fn case_insensitive(pattern: &str) -> Result<Regex, String> {
+    RegexBuilder::new(pattern)
+        .case_insensitive(true)
+        .build()
+        .map_err(|e| format!("Invalid regex '{}': {}", pattern, e))
+}
+
+fn rewrite_regex(rewrite: &PayeeRewrite) -> Result<Regex, String> {
+    match rewrite.is_regex {
+        true => case_insensitive(&rewrite.pattern),
+        false => case_insensitive(&regex::escape(&rewrite.pattern)),
+    }
+}
+
+fn squash(text: &str) -> String {
+    text.split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .trim_matches(|c: char| c.is_whitespace() || "*#:,.-/".contains(c))
+        .to_string()
+}
+
+// Turns raw bank descriptors such as "CARD 1234 AMZN MKTP US*2K3H7 SEATTLE WA" into "AMZN MKTP".
+// Payees naming an account are left alone, so transfer detection still sees them.
+pub struct PayeeCleaner {
+    rewrites: Vec<(PayeeRewrite, Regex)>,
+    accounts: HashSet<String>,
+}
+
+impl PayeeCleaner {
+    pub fn load(conn: &Connection) -> Result<Self, String> {
+        let mut rewrites = Vec::new();
+        for rewrite in list_payee_rewrites(conn)? {
+            // Checked when saved; a pattern that stopped compiling is skipped
+            if let Ok(regex) = rewrite_regex(&rewrite) {
+                rewrites.push((rewrite, regex));
+            }
+        }
+        let mut stmt = conn
+            .prepare("SELECT name FROM accounts")
+            .map_err(|e| e.to_string())?;
+        let accounts = stmt
+            .query_map([], |row| row.get::<_, String>(0))
+            .map_err(|e| e.to_string())?
+            .collect::<Result<HashSet<_>, _>>()
+            .map_err(|e| e.to_string())?;
+
+        Ok(PayeeCleaner { rewrites, accounts })
+    }
+
+    // Leaves these names alone as well: accounts an import declares exist only once it commits
+    pub fn keep_accounts(&mut self, names: impl IntoIterator<Item = String>) {
+        self.accounts.extend(names);
+    }
+
+    pub fn clean(&self, raw: &str) -> String {
+        if self.accounts.contains(raw.trim()) {
+            return raw.to_string();
+        }
+        let mut payee = raw.to_string();
+        for regex in BUILT_IN.iter() {
+            payee = regex.replace_all(&payee, " ").into_owned();
+        }
+        payee = squash(&payee);
+        if !raw.chars().any(char::is_lowercase) {
+            payee = LOCATION.replace(&payee, "$1").into_owned();
+            payee = COUNTRY.replace(&payee, "$1").into_owned();
+            payee = STORE.replace(&payee, "$1").into_owned();
+        }
+        for (rewrite, regex) in &self.rewrites {
+            payee = match rewrite.is_regex {
+                true => regex.replace_all(&payee, rewrite.replacement.as_str()),
+                false => regex.replace_all(&payee, NoExpand(&rewrite.replacement)),
+            }
+            .into_owned();
+        }
+        match squash(&payee) {
+            cleaned if cleaned.is_empty() => raw.trim().to_string(),
+            cleaned => cleaned,
+        }
+    }
+}
+
+// Keeps the payee the transaction came in with when cleaning or rules changed it
+pub fn record_raw_payee(
+    conn: &Connection,
+    transaction_id: i32,
+    raw: &str,
+    payee: &str,
+) -> Result<(), String> {
+    if raw == payee {
+        return Ok(());
+    }
+    conn.execute(
+        "UPDATE transactions SET raw_payee = ?1 WHERE id = ?2",
+        params![raw, transaction_id],
+    )
+    .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+pub fn list_payee_rewrites(conn: &Connection) -> Result<Vec<PayeeRewrite>, String> {
+    let mut stmt = conn
+        .prepare("SELECT id, position, pattern, replacement, is_regex FROM payee_rewrites ORDER BY position, id")
+        .map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map([], |row| {
+            Ok(PayeeRewrite {
+                id: row.get(0)?,
+                position: row.get(1)?,
+                pattern: row.get(2)?,
+                replacement: row.get(3)?,
+                is_regex: row.get(4)?,
+            })
+        })
+        .map_err(|e| e.to_string())?;
+    rows.collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())
+}
+
+pub fn list_payee_rewrites_db(db_path: &PathBuf) -> Result<Vec<PayeeRewrite>, String> {
+    let conn = Connection::open(db_path).map_err(|e| e.to_string())?;
+    list_payee_rewrites(&conn)
+}
+
+// Adds a rewrite after the existing ones
+pub fn create_payee_rewrite_db(
+    db_path: &PathBuf,
+    pattern: String,
+    replacement: String,
+    is_regex: bool,
+) -> Result<PayeeRewrite, String> {
+    if pattern.trim().is_empty() {
+        return Err("A pattern is required".to_string());
+    }
+    let mut rewrite = PayeeRewrite {
+        id: 0,
+        position: 0,
+        pattern,
+        replacement,
+        is_regex,
+    };
+    rewrite_regex(&rewrite)?;
+
+    let conn = Connection::open(db_path).map_err(|e| e.to_string())?;
+    rewrite.position = conn
+        .query_row(
+            "SELECT COALESCE(MAX(position), -1) + 1 FROM payee_rewrites",
+            [],
+            |row| row.get(0),
+        )
+        .map_err(|e| e.to_string())?;
+    conn.execute(
+        "INSERT INTO payee_rewrites (position, pattern, replacement, is_regex) VALUES (?1, ?2, ?3, ?4)",
+        params![
+            rewrite.position,
+            rewrite.pattern,
+            rewrite.replacement,
+            rewrite.is_regex
+        ],
+    )
+    .map_err(|e| e.to_string())?;
+    rewrite.id = conn.last_insert_rowid() as i32;
+    Ok(rewrite)
+}
+
+pub fn delete_payee_rewrite_db(db_path: &PathBuf, id: i32) -> Result<(), String> {
+    let conn = Connection::open(db_path).map_err(|e| e.to_string())?;
+    let deleted = conn
+        .execute("DELETE FROM payee_rewrites WHERE id = ?1", params![id])
+        .map_err(|e| e.to_string())?;
+    if deleted == 0 {
+        return Err(format!("Payee rewrite {} not found", id));
+    }
+    Ok(())
+}
+
+// What a payee would be stored as, for previews while editing rewrites
+pub fn clean_payee_db(db_path: &PathBuf, payee: &str) -> Result<String, String> {
+    let conn = Connection::open(db_path).map_err(|e| e.to_string())?;
+    Ok(PayeeCleaner::load(&conn)?.clean(payee))
+}
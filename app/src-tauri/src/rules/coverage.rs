@@ -0,0 +1,177 @@
+use super::{
+    apply_action, legacy_condition, load_rules, CompiledRule, DraftTransaction, Matcher, RuleAction,
+};
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::path::PathBuf;
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct RuleCoverage {
+    pub rule_id: i32,
+    pub priority: i32,
+    // Transactions the conditions match, after the rules above have run
+    pub matched: usize,
+    // Matched transactions the rule would change
+    pub changed: usize,
+    // Matched transactions where a rule above already wrote a field this rule sets
+    pub shadowed: usize,
+    pub shadowed_by: Vec<i32>,
+    // Why the rule cannot run, e.g. an invalid regex or a transfer to a deleted account; such a
+    // rule is skipped by the engine
+    pub error: Option<String>,
+    pub never_matched: bool,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct RuleTestReport {
+    pub transactions: usize,
+    // In priority order, as `update_rules_order` lists them
+    pub rules: Vec<RuleCoverage>,
+}
+
+// The lock an action takes when it applies, as `apply_action` names it
+fn lock(action: &RuleAction) -> Option<&str> {
+    match action {
+        RuleAction::SetField { field, .. } => Some(field),
+        RuleAction::TransferTo { .. } => Some("transfer"),
+        RuleAction::Split { .. } => Some("split"),
+        RuleAction::AppendNote { .. } | RuleAction::AddTag { .. } => None,
+    }
+}
+
+fn load_history(conn: &Connection) -> Result<Vec<DraftTransaction>, String> {
+    let mut stmt = conn
+        .prepare(
+            "SELECT account_id, date, payee, notes, category, amount, ticker, shares, price_per_share, fee, tags
+             FROM transactions WHERE linked_tx_id IS NULL AND shares IS NULL
+             ORDER BY date ASC, id ASC",
+        )
+        .map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map([], |row| {
+            Ok((
+                DraftTransaction {
+                    account_id: Some(row.get(0)?),
+                    date: row.get(1)?,
+                    payee: row.get(2)?,
+                    notes: row.get(3)?,
+                    category: row.get(4)?,
+                    amount: row.get(5)?,
+                    ticker: row.get(6)?,
+                    shares: row.get(7)?,
+                    price_per_share: row.get(8)?,
+                    fee: row.get(9)?,
+                    ..Default::default()
+                },
+                row.get::<_, Option<String>>(10)?,
+            ))
+        })
+        .map_err(|e| e.to_string())?;
+    let mut history = Vec::new();
+    for row in rows {
+        let (mut draft, tags) = row.map_err(|e| e.to_string())?;
+        if let Some(tags) = tags {
+            draft.tags = serde_json::from_str(&tags).map_err(|e| e.to_string())?;
+        }
+        history.push(draft);
+    }
+    Ok(history)
+}
+
+// Runs every rule over the whole history the way the engine would, without writing anything.
+// Transfers and trades are left out, as rules never rewrite them.
+pub fn test_rules_db(db_path: &PathBuf) -> Result<RuleTestReport, String> {
+    let conn = Connection::open(db_path).map_err(|e| e.to_string())?;
+    let rules = load_rules(&conn)?;
+    let history = load_history(&conn)?;
+
+    let mut report: Vec<RuleCoverage> = Vec::new();
+    let mut compiled = Vec::new();
+    for rule in &rules {
+        let conditions = match rule.conditions.is_empty() {
+            true => vec![legacy_condition(rule)],
+            false => rule.conditions.clone(),
+        };
+        let matchers = conditions
+            .iter()
+            .map(Matcher::compile)
+            .collect::<Result<Vec<_>, _>>();
+        let error = match &matchers {
+            Err(e) => Some(e.clone()),
+            Ok(_) => rule
+                .actions
+                .iter()
+                .map(|action| action.validate(&conn))
+                .find_map(Result::err),
+        };
+        if let (Ok(matchers), None) = (matchers, &error) {
+            compiled.push((
+                report.len(),
+                CompiledRule {
+                    id: rule.id,
+                    join: rule.match_join,
+                    matchers,
+                    actions: rule.actions.clone(),
+                },
+            ));
+        }
+        report.push(RuleCoverage {
+            rule_id: rule.id,
+            priority: rule.priority,
+            matched: 0,
+            changed: 0,
+            shadowed: 0,
+            shadowed_by: Vec::new(),
+            error,
+            never_matched: false,
+        });
+    }
+
+    let mut shadowed_by: HashMap<usize, BTreeSet<i32>> = HashMap::new();
+    for transaction in &history {
+        let mut draft = transaction.clone();
+        let mut locked: HashSet<&str> = HashSet::new();
+        let mut owners: HashMap<&str, i32> = HashMap::new();
+        for (index, rule) in &compiled {
+            if !rule.matches(&draft) {
+                continue;
+            }
+            let coverage = &mut report[*index];
+            coverage.matched += 1;
+            let blockers: BTreeSet<i32> = rule
+                .actions
+                .iter()
+                .filter_map(|action| lock(action).and_then(|key| owners.get(key)).copied())
+                .collect();
+            if !blockers.is_empty() {
+                coverage.shadowed += 1;
+                shadowed_by.entry(*index).or_default().extend(blockers);
+            }
+            let before = draft.clone();
+            for action in &rule.actions {
+                apply_action(&mut draft, action, &mut locked);
+                // The first rule to take a field owns it, whether or not it changed the value
+                if let Some(key) = lock(action).filter(|key| locked.contains(key)) {
+                    owners.entry(key).or_insert(rule.id);
+                }
+            }
+            if draft != before {
+                coverage.changed += 1;
+            }
+        }
+    }
+
+    for (index, coverage) in report.iter_mut().enumerate() {
+        coverage.shadowed_by = shadowed_by
+            .remove(&index)
+            .unwrap_or_default()
+            .into_iter()
+            .collect();
+        coverage.never_matched = coverage.error.is_none() && coverage.matched == 0;
+    }
+    Ok(RuleTestReport {
+        transactions: history.len(),
+        rules: report,
+    })
+}
@@ -0,0 +1,985 @@
+use chrono::NaiveDate;
+use regex::{Regex, RegexBuilder};
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::path::PathBuf;
+
+pub mod coverage;
+pub mod retroactive;
+pub mod sharing;
+pub mod suggest;
+
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum MatchOperator {
+    Equals,
+    Contains,
+    StartsWith,
+    EndsWith,
+    Regex,
+    // `value` and `value_to` are the bounds; either may be empty for an open range
+    AmountBetween,
+    // `value` is `positive` or `negative`
+    AmountSign,
+    // Inclusive YYYY-MM-DD bounds in `value` and `value_to`; either may be empty
+    DateRange,
+    // `value` is an account id
+    AccountIs,
+}
+
+impl MatchOperator {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            MatchOperator::Equals => "equals",
+            MatchOperator::Contains => "contains",
+            MatchOperator::StartsWith => "startsWith",
+            MatchOperator::EndsWith => "endsWith",
+            MatchOperator::Regex => "regex",
+            MatchOperator::AmountBetween => "amountBetween",
+            MatchOperator::AmountSign => "amountSign",
+            MatchOperator::DateRange => "dateRange",
+            MatchOperator::AccountIs => "accountIs",
+        }
+    }
+
+    fn parse(value: &str) -> Option<Self> {
+        serde_json::from_value(serde_json::Value::String(value.to_string())).ok()
+    }
+}
+
+// How the conditions of a rule combine
+#[derive(Clone, Copy, Serialize, Deserialize, Debug, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum MatchJoin {
+    #[default]
+    And,
+    Or,
+}
+
+impl MatchJoin {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            MatchJoin::And => "and",
+            MatchJoin::Or => "or",
+        }
+    }
+
+    pub fn parse(value: &str) -> Self {
+        match value {
+            "or" => MatchJoin::Or,
+            _ => MatchJoin::And,
+        }
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct RuleCondition {
+    pub field: String,
+    pub operator: MatchOperator,
+    pub value: String,
+    #[serde(default)]
+    pub value_to: Option<String>,
+    #[serde(default)]
+    pub case_sensitive: bool,
+}
+
+// The fields of a transaction that rules read and write
+#[derive(Clone, Serialize, Deserialize, Debug, Default, PartialEq)]
+#[serde(rename_all = "camelCase", default)]
+pub struct DraftTransaction {
+    pub account_id: Option<i32>,
+    pub date: String,
+    pub payee: String,
+    pub notes: Option<String>,
+    pub category: Option<String>,
+    pub amount: f64,
+    pub ticker: Option<String>,
+    pub shares: Option<f64>,
+    pub price_per_share: Option<f64>,
+    pub fee: Option<f64>,
+    pub tags: Vec<String>,
+    // Account a rule made this a transfer to
+    pub transfer_to: Option<i32>,
+    // Categories a rule split the amount across; empty when not split
+    pub splits: Vec<SplitPart>,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct SplitPart {
+    pub category: String,
+    pub percent: f64,
+}
+
+// What a matching rule does to the transaction
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+#[serde(tag = "type", rename_all = "camelCase")]
+pub enum RuleAction {
+    SetField {
+        field: String,
+        value: String,
+    },
+    AppendNote {
+        text: String,
+    },
+    AddTag {
+        tag: String,
+    },
+    // The create and import paths turn this into a linked transfer by naming the account as payee
+    TransferTo {
+        #[serde(rename = "accountId")]
+        account_id: i32,
+    },
+    // Percentages must add up to 100; each part becomes its own transaction
+    Split {
+        parts: Vec<SplitPart>,
+    },
+}
+
+impl RuleAction {
+    // Stored as a kind plus the `field` and `value` columns of `rule_actions`
+    fn to_row(&self) -> Result<(&'static str, Option<&str>, String), String> {
+        Ok(match self {
+            RuleAction::SetField { field, value } => ("setField", Some(field), value.clone()),
+            RuleAction::AppendNote { text } => ("appendNote", None, text.clone()),
+            RuleAction::AddTag { tag } => ("addTag", None, tag.clone()),
+            RuleAction::TransferTo { account_id } => ("transferTo", None, account_id.to_string()),
+            RuleAction::Split { parts } => (
+                "split",
+                None,
+                serde_json::to_string(parts).map_err(|e| e.to_string())?,
+            ),
+        })
+    }
+
+    fn from_row(kind: &str, field: Option<String>, value: String) -> Result<Self, String> {
+        Ok(match kind {
+            "setField" => RuleAction::SetField {
+                field: field.unwrap_or_default(),
+                value,
+            },
+            "appendNote" => RuleAction::AppendNote { text: value },
+            "addTag" => RuleAction::AddTag { tag: value },
+            "transferTo" => RuleAction::TransferTo {
+                account_id: value.parse::<i32>().map_err(|e| e.to_string())?,
+            },
+            "split" => RuleAction::Split {
+                parts: serde_json::from_str(&value).map_err(|e| e.to_string())?,
+            },
+            other => return Err(format!("Unknown rule action '{}'", other)),
+        })
+    }
+
+    // The field and value shown in the rules list: the field set, or the kind of action
+    pub fn summary(&self) -> Result<(String, String), String> {
+        Ok(match self.to_row()? {
+            (_, Some(field), value) => (field.to_string(), value),
+            (kind, None, value) => (kind.to_string(), value),
+        })
+    }
+
+    fn validate(&self, conn: &Connection) -> Result<(), String> {
+        match self {
+            RuleAction::SetField { field, value } => {
+                if !set_field(&mut DraftTransaction::default(), field, value) {
+                    return Err(format!("Invalid value '{}' for field '{}'", value, field));
+                }
+            }
+            RuleAction::AppendNote { text: value } | RuleAction::AddTag { tag: value } => {
+                if value.trim().is_empty() {
+                    return Err("Notes and tags cannot be empty".to_string());
+                }
+            }
+            RuleAction::TransferTo { account_id } => {
+                if transfer_payee(conn, Some(*account_id))?.is_none() {
+                    return Err(format!("Account {} not found", account_id));
+                }
+            }
+            RuleAction::Split { parts } => {
+                let total: f64 = parts.iter().map(|p| p.percent).sum();
+                if parts.len() < 2
+                    || parts
+                        .iter()
+                        .any(|p| p.percent <= 0.0 || p.category.trim().is_empty())
+                    || (total - 100.0).abs() > 1e-6
+                {
+                    return Err(
+                        "A split needs two or more categories with percentages adding up to 100"
+                            .to_string(),
+                    );
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+// Which rules a create or import call runs
+#[derive(Clone, Serialize, Deserialize, Debug, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum RuleSelection {
+    #[default]
+    All,
+    Only(Vec<i32>),
+    Disabled,
+}
+
+impl From<Option<Vec<i32>>> for RuleSelection {
+    fn from(rule_ids: Option<Vec<i32>>) -> Self {
+        match rule_ids {
+            Some(ids) => RuleSelection::Only(ids),
+            None => RuleSelection::All,
+        }
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct RuleOutcome {
+    pub transaction: DraftTransaction,
+    // Rules that changed the transaction, in the order they ran
+    pub rule_ids: Vec<i32>,
+}
+
+// Text of a field as a condition sees it; None for fields rules cannot match on
+fn field_text(draft: &DraftTransaction, field: &str) -> Option<String> {
+    let number = |v: Option<f64>| v.map(|n| n.to_string()).unwrap_or_default();
+    Some(match field {
+        "payee" => draft.payee.clone(),
+        "category" => draft.category.clone().unwrap_or_default(),
+        "notes" => draft.notes.clone().unwrap_or_default(),
+        "ticker" => draft.ticker.clone().unwrap_or_default(),
+        "date" => draft.date.clone(),
+        "amount" => draft.amount.to_string(),
+        "shares" => number(draft.shares),
+        "price" => number(draft.price_per_share),
+        "fee" => number(draft.fee),
+        "tags" => draft.tags.join(", "),
+        _ => return None,
+    })
+}
+
+// Writes an action value into the draft. Returns false when the field is unknown or the value
+// does not fit it (e.g. text for the amount).
+fn set_field(draft: &mut DraftTransaction, field: &str, value: &str) -> bool {
+    let number = || value.trim().parse::<f64>().ok();
+    let text = || Some(value.to_string()).filter(|v| !v.is_empty());
+    match field {
+        "payee" => draft.payee = value.to_string(),
+        "category" => draft.category = text(),
+        "notes" => draft.notes = text(),
+        "ticker" => draft.ticker = text(),
+        "date" => match NaiveDate::parse_from_str(value.trim(), "%Y-%m-%d") {
+            Ok(_) => draft.date = value.trim().to_string(),
+            Err(_) => return false,
+        },
+        "amount" => match number() {
+            Some(n) => draft.amount = n,
+            None => return false,
+        },
+        "shares" => match number() {
+            Some(n) => draft.shares = Some(n),
+            None => return false,
+        },
+        "price" => match number() {
+            Some(n) => draft.price_per_share = Some(n),
+            None => return false,
+        },
+        "fee" => match number() {
+            Some(n) => draft.fee = Some(n),
+            None => return false,
+        },
+        _ => return false,
+    }
+    true
+}
+
+fn bound<T: std::str::FromStr>(value: Option<&str>) -> Result<Option<T>, String> {
+    match value.map(str::trim).filter(|v| !v.is_empty()) {
+        Some(v) => v
+            .parse()
+            .map(Some)
+            .map_err(|_| format!("Invalid value '{}'", v)),
+        None => Ok(None),
+    }
+}
+
+enum Matcher {
+    Text {
+        field: String,
+        operator: MatchOperator,
+        value: String,
+        case_sensitive: bool,
+    },
+    Regex {
+        field: String,
+        regex: Regex,
+    },
+    AmountBetween(Option<f64>, Option<f64>),
+    AmountSign(bool),
+    DateRange(Option<NaiveDate>, Option<NaiveDate>),
+    AccountIs(i32),
+}
+
+impl Matcher {
+    fn compile(condition: &RuleCondition) -> Result<Self, String> {
+        let value_to = condition.value_to.as_deref();
+        let text = matches!(
+            condition.operator,
+            MatchOperator::Equals
+                | MatchOperator::Contains
+                | MatchOperator::StartsWith
+                | MatchOperator::EndsWith
+                | MatchOperator::Regex
+        );
+        if text && field_text(&DraftTransaction::default(), &condition.field).is_none() {
+            return Err(format!("Unknown field '{}'", condition.field));
+        }
+        Ok(match condition.operator {
+            MatchOperator::Equals
+            | MatchOperator::Contains
+            | MatchOperator::StartsWith
+            | MatchOperator::EndsWith => Matcher::Text {
+                field: condition.field.clone(),
+                operator: condition.operator,
+                value: if condition.case_sensitive {
+                    condition.value.clone()
+                } else {
+                    condition.value.to_lowercase()
+                },
+                case_sensitive: condition.case_sensitive,
+            },
+            MatchOperator::Regex => Matcher::Regex {
+                field: condition.field.clone(),
+                regex: RegexBuilder::new(&condition.value)
+                    .case_insensitive(!condition.case_sensitive)
+                    .build()
+                    .map_err(|e| format!("Invalid regex '{}': {}", condition.value, e))?,
+            },
+            MatchOperator::AmountBetween => {
+                Matcher::AmountBetween(bound(Some(&condition.value))?, bound(value_to)?)
+            }
+            MatchOperator::AmountSign => match condition.value.trim() {
+                "positive" => Matcher::AmountSign(true),
+                "negative" => Matcher::AmountSign(false),
+                other => return Err(format!("Invalid amount sign '{}'", other)),
+            },
+            MatchOperator::DateRange => {
+                let date = |v: Option<&str>| -> Result<Option<NaiveDate>, String> {
+                    match v.map(str::trim).filter(|v| !v.is_empty()) {
+                        Some(v) => NaiveDate::parse_from_str(v, "%Y-%m-%d")
+                            .map(Some)
+                            .map_err(|_| format!("Invalid date '{}'", v)),
+                        None => Ok(None),
+                    }
+                };
+                Matcher::DateRange(date(Some(&condition.value))?, date(value_to)?)
+            }
+            MatchOperator::AccountIs => Matcher::AccountIs(
+                bound(Some(&condition.value))?
+                    .ok_or_else(|| "An account is required".to_string())?,
+            ),
+        })
+    }
+
+    fn matches(&self, draft: &DraftTransaction) -> bool {
+        match self {
+            Matcher::Text {
+                field,
+                operator,
+                value,
+                case_sensitive,
+            } => {
+                let Some(text) = field_text(draft, field) else {
+                    return false;
+                };
+                let text = if *case_sensitive {
+                    text
+                } else {
+                    text.to_lowercase()
+                };
+                match operator {
+                    MatchOperator::Equals => text == *value,
+                    MatchOperator::Contains => text.contains(value.as_str()),
+                    MatchOperator::StartsWith => text.starts_with(value.as_str()),
+                    _ => text.ends_with(value.as_str()),
+                }
+            }
+            Matcher::Regex { field, regex } => {
+                field_text(draft, field).is_some_and(|text| regex.is_match(&text))
+            }
+            Matcher::AmountBetween(min, max) => {
+                min.is_none_or(|m| draft.amount >= m) && max.is_none_or(|m| draft.amount <= m)
+            }
+            Matcher::AmountSign(positive) => match positive {
+                true => draft.amount > 0.0,
+                false => draft.amount < 0.0,
+            },
+            Matcher::DateRange(from, to) => {
+                let Ok(date) = NaiveDate::parse_from_str(&draft.date, "%Y-%m-%d") else {
+                    return false;
+                };
+                from.is_none_or(|f| date >= f) && to.is_none_or(|t| date <= t)
+            }
+            Matcher::AccountIs(id) => draft.account_id == Some(*id),
+        }
+    }
+}
+
+// Applies one action of a matching rule. A field set by a higher-priority rule is locked;
+// appending a note or adding a tag overwrites nothing, so those apply whenever they are new.
+fn apply_action<'a>(
+    draft: &mut DraftTransaction,
+    action: &'a RuleAction,
+    locked: &mut HashSet<&'a str>,
+) -> bool {
+    match action {
+        // The field is taken even when it already had the value, so lower rules keep off it
+        RuleAction::SetField { field, value } => {
+            if locked.contains(field.as_str()) {
+                return false;
+            }
+            let before = draft.clone();
+            if !set_field(draft, field, value) {
+                return false;
+            }
+            locked.insert(field.as_str());
+            return *draft != before;
+        }
+        RuleAction::AppendNote { text } => match draft.notes.as_deref() {
+            Some(notes) if notes.contains(text.as_str()) => return false,
+            Some(notes) if !notes.is_empty() => draft.notes = Some(format!("{} {}", notes, text)),
+            _ => draft.notes = Some(text.clone()),
+        },
+        RuleAction::AddTag { tag } => {
+            if draft.tags.contains(tag) {
+                return false;
+            }
+            draft.tags.push(tag.clone());
+        }
+        RuleAction::TransferTo { account_id } => {
+            if !locked.insert("transfer") {
+                return false;
+            }
+            draft.transfer_to = Some(*account_id);
+        }
+        RuleAction::Split { parts } => {
+            if !locked.insert("split") {
+                return false;
+            }
+            draft.splits = parts.clone();
+        }
+    }
+    true
+}
+
+// Amount of each split part, rounded to cents. The last part takes the remainder so the parts
+// add up to the original amount.
+pub fn split_amounts(amount: f64, parts: &[SplitPart]) -> Vec<f64> {
+    let cents = |v: f64| (v * 100.0).round() / 100.0;
+    let mut remaining = amount;
+    parts
+        .iter()
+        .enumerate()
+        .map(|(idx, part)| {
+            if idx + 1 == parts.len() {
+                return cents(remaining);
+            }
+            let share = cents(amount * part.percent / 100.0);
+            remaining -= share;
+            share
+        })
+        .collect()
+}
+
+// Name of the account a transfer action points at, used as the payee so the transfer is linked
+pub fn transfer_payee(
+    conn: &Connection,
+    account_id: Option<i32>,
+) -> Result<Option<String>, String> {
+    let Some(id) = account_id else {
+        return Ok(None);
+    };
+    conn.query_row(
+        "SELECT name FROM accounts WHERE id = ?1",
+        params![id],
+        |row| row.get(0),
+    )
+    .optional()
+    .map_err(|e| e.to_string())
+}
+
+// Rules saved before conditions existed match their field by exact equality
+fn legacy_condition(rule: &crate::Rule) -> RuleCondition {
+    RuleCondition {
+        field: rule.match_field.clone(),
+        operator: MatchOperator::Equals,
+        value: rule.match_pattern.clone(),
+        value_to: None,
+        case_sensitive: true,
+    }
+}
+
+// Rules saved before actions existed set `action_field` to `action_value`
+fn legacy_action(rule: &crate::Rule) -> RuleAction {
+    RuleAction::SetField {
+        field: rule.action_field.clone(),
+        value: rule.action_value.clone(),
+    }
+}
+
+struct CompiledRule {
+    id: i32,
+    join: MatchJoin,
+    matchers: Vec<Matcher>,
+    actions: Vec<RuleAction>,
+}
+
+impl CompiledRule {
+    fn matches(&self, draft: &DraftTransaction) -> bool {
+        match self.join {
+            MatchJoin::And => self.matchers.iter().all(|m| m.matches(draft)),
+            MatchJoin::Or => self.matchers.iter().any(|m| m.matches(draft)),
+        }
+    }
+}
+
+// The saved rules, compiled once and run in priority order
+pub struct RuleEngine {
+    rules: Vec<CompiledRule>,
+}
+
+impl RuleEngine {
+    // Rules whose conditions no longer compile (e.g. a regex saved by an older version) are left
+    // out rather than failing every transaction
+    pub fn new(rules: &[crate::Rule]) -> Self {
+        let rules = rules
+            .iter()
+            .filter_map(|rule| {
+                let conditions = match rule.conditions.is_empty() {
+                    true => vec![legacy_condition(rule)],
+                    false => rule.conditions.clone(),
+                };
+                let matchers = conditions
+                    .iter()
+                    .map(Matcher::compile)
+                    .collect::<Result<Vec<_>, _>>()
+                    .ok()?;
+                Some(CompiledRule {
+                    id: rule.id,
+                    join: rule.match_join,
+                    matchers,
+                    actions: match rule.actions.is_empty() {
+                        true => vec![legacy_action(rule)],
+                        false => rule.actions.clone(),
+                    },
+                })
+            })
+            .collect();
+        RuleEngine { rules }
+    }
+
+    pub fn load(conn: &Connection, selection: &RuleSelection) -> Result<Self, String> {
+        let rules = match selection {
+            RuleSelection::All => load_rules(conn)?,
+            RuleSelection::Only(ids) => load_rules(conn)?
+                .into_iter()
+                .filter(|rule| ids.contains(&rule.id))
+                .collect(),
+            RuleSelection::Disabled => Vec::new(),
+        };
+        // An action that no longer validates, such as a transfer to a deleted account, leaves its
+        // rule out as a broken condition does
+        let rules: Vec<crate::Rule> = rules
+            .into_iter()
+            .filter(|rule| rule.actions.iter().all(|a| a.validate(conn).is_ok()))
+            .collect();
+        Ok(RuleEngine::new(&rules))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    // Runs every rule against the draft. Each rule sees the changes of the rules before it, and a
+    // field set by a higher-priority rule is not overwritten by a lower one. Returns the ids of the
+    // rules that changed something.
+    pub fn apply(&self, draft: &mut DraftTransaction) -> Vec<i32> {
+        let mut fired = Vec::new();
+        let mut locked: HashSet<&str> = HashSet::new();
+        for rule in &self.rules {
+            if !rule.matches(draft) {
+                continue;
+            }
+            let mut changed = false;
+            for action in &rule.actions {
+                changed |= apply_action(draft, action, &mut locked);
+            }
+            if changed {
+                fired.push(rule.id);
+            }
+        }
+        fired
+    }
+
+    // Runs the rules on a new transaction. A split rule turns it into one transaction per part.
+    pub fn apply_to_args(
+        &self,
+        conn: &Connection,
+        args: crate::CreateTransactionArgs,
+    ) -> Result<AppliedRules, String> {
+        let mut draft = DraftTransaction {
+            account_id: Some(args.account_id),
+            date: args.date,
+            payee: args.payee,
+            notes: args.notes,
+            category: args.category,
+            amount: args.amount,
+            ticker: args.ticker,
+            shares: args.shares,
+            price_per_share: args.price_per_share,
+            fee: args.fee,
+            ..Default::default()
+        };
+        let rule_ids = self.apply(&mut draft);
+        if let Some(account) = transfer_payee(conn, draft.transfer_to)? {
+            draft.payee = account;
+        }
+        let base = crate::CreateTransactionArgs {
+            account_id: args.account_id,
+            date: draft.date,
+            payee: draft.payee,
+            notes: draft.notes,
+            category: draft.category,
+            amount: draft.amount,
+            ticker: draft.ticker,
+            shares: draft.shares,
+            price_per_share: draft.price_per_share,
+            fee: draft.fee,
+            currency: args.currency,
+        };
+        let transactions = match draft.splits.is_empty() {
+            true => vec![base],
+            false => split_amounts(base.amount, &draft.splits)
+                .into_iter()
+                .zip(&draft.splits)
+                .map(|(amount, part)| crate::CreateTransactionArgs {
+                    amount,
+                    category: Some(part.category.clone()),
+                    ..base.clone()
+                })
+                .collect(),
+        };
+        Ok(AppliedRules {
+            transactions,
+            rule_ids,
+            tags: draft.tags,
+        })
+    }
+}
+
+// A new transaction after the rules ran, ready to insert
+pub struct AppliedRules {
+    pub transactions: Vec<crate::CreateTransactionArgs>,
+    pub rule_ids: Vec<i32>,
+    pub tags: Vec<String>,
+}
+
+// Stores which rules modified a transaction
+pub fn record_rule_ids(
+    conn: &Connection,
+    transaction_id: i32,
+    rule_ids: &[i32],
+) -> Result<(), String> {
+    if rule_ids.is_empty() {
+        return Ok(());
+    }
+    let ids = serde_json::to_string(rule_ids).map_err(|e| e.to_string())?;
+    conn.execute(
+        "UPDATE transactions SET rule_ids = ?1 WHERE id = ?2",
+        params![ids, transaction_id],
+    )
+    .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+// Groups the parts of a split transaction under the id of the first one
+pub fn record_split(conn: &Connection, transaction_ids: &[i32]) -> Result<(), String> {
+    let Some(first) = transaction_ids.first() else {
+        return Ok(());
+    };
+    if transaction_ids.len() < 2 {
+        return Ok(());
+    }
+    for id in transaction_ids {
+        conn.execute(
+            "UPDATE transactions SET split_id = ?1 WHERE id = ?2",
+            params![first, id],
+        )
+        .map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
+
+// Stores the tags rules added to a transaction
+pub fn record_tags(conn: &Connection, transaction_id: i32, tags: &[String]) -> Result<(), String> {
+    if tags.is_empty() {
+        return Ok(());
+    }
+    let tags = serde_json::to_string(tags).map_err(|e| e.to_string())?;
+    conn.execute(
+        "UPDATE transactions SET tags = ?1 WHERE id = ?2",
+        params![tags, transaction_id],
+    )
+    .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+// All rules with their conditions, highest priority first
+pub fn load_rules(conn: &Connection) -> Result<Vec<crate::Rule>, String> {
+    let mut conditions: HashMap<i32, Vec<RuleCondition>> = HashMap::new();
+    let mut stmt = conn
+        .prepare("SELECT rule_id, field, operator, value, value_to, case_sensitive FROM rule_conditions ORDER BY rule_id, position")
+        .map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map([], |row| {
+            Ok((
+                row.get::<_, i32>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, String>(2)?,
+                row.get::<_, String>(3)?,
+                row.get::<_, Option<String>>(4)?,
+                row.get::<_, bool>(5)?,
+            ))
+        })
+        .map_err(|e| e.to_string())?;
+    for row in rows {
+        let (rule_id, field, operator, value, value_to, case_sensitive) =
+            row.map_err(|e| e.to_string())?;
+        let operator = MatchOperator::parse(&operator)
+            .ok_or_else(|| format!("Unknown match operator '{}'", operator))?;
+        conditions.entry(rule_id).or_default().push(RuleCondition {
+            field,
+            operator,
+            value,
+            value_to,
+            case_sensitive,
+        });
+    }
+
+    let mut actions: HashMap<i32, Vec<RuleAction>> = HashMap::new();
+    let mut stmt = conn
+        .prepare("SELECT rule_id, kind, field, value FROM rule_actions ORDER BY rule_id, position")
+        .map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map([], |row| {
+            Ok((
+                row.get::<_, i32>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, Option<String>>(2)?,
+                row.get::<_, String>(3)?,
+            ))
+        })
+        .map_err(|e| e.to_string())?;
+    for row in rows {
+        let (rule_id, kind, field, value) = row.map_err(|e| e.to_string())?;
+        actions
+            .entry(rule_id)
+            .or_default()
+            .push(RuleAction::from_row(&kind, field, value)?);
+    }
+
+    let mut stmt = conn
+        .prepare("SELECT id, priority, match_field, match_pattern, action_field, action_value, match_join FROM rules ORDER BY priority DESC, id ASC")
+        .map_err(|e| e.to_string())?;
+    let rules = stmt
+        .query_map([], |row| {
+            Ok(crate::Rule {
+                id: row.get(0)?,
+                priority: row.get(1)?,
+                match_field: row.get(2)?,
+                match_pattern: row.get(3)?,
+                action_field: row.get(4)?,
+                action_value: row.get(5)?,
+                match_join: MatchJoin::parse(&row.get::<_, String>(6)?),
+                conditions: Vec::new(),
+                actions: Vec::new(),
+            })
+        })
+        .map_err(|e| e.to_string())?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())?;
+    Ok(rules
+        .into_iter()
+        .map(|mut rule| {
+            rule.conditions = conditions.remove(&rule.id).unwrap_or_default();
+            rule.actions = match actions.remove(&rule.id) {
+                Some(actions) => actions,
+                None => vec![legacy_action(&rule)],
+            };
+            rule
+        })
+        .collect())
+}
+
+pub fn write_conditions(
+    conn: &Connection,
+    rule_id: i32,
+    conditions: &[RuleCondition],
+) -> Result<(), String> {
+    conn.execute(
+        "DELETE FROM rule_conditions WHERE rule_id = ?1",
+        params![rule_id],
+    )
+    .map_err(|e| e.to_string())?;
+    for (position, condition) in conditions.iter().enumerate() {
+        conn.execute(
+            "INSERT INTO rule_conditions (rule_id, position, field, operator, value, value_to, case_sensitive) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            params![
+                rule_id,
+                position as i64,
+                condition.field,
+                condition.operator.as_str(),
+                condition.value,
+                condition.value_to,
+                condition.case_sensitive
+            ],
+        )
+        .map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
+
+// Replaces the conditions of a rule. The first condition is mirrored into `match_field` and
+// `match_pattern`, which the rules list shows.
+pub fn set_rule_conditions_db(
+    db_path: &PathBuf,
+    rule_id: i32,
+    match_join: MatchJoin,
+    conditions: Vec<RuleCondition>,
+) -> Result<(), String> {
+    let first = conditions
+        .first()
+        .ok_or_else(|| "A rule needs at least one condition".to_string())?;
+    for condition in &conditions {
+        Matcher::compile(condition)?;
+    }
+
+    let mut conn = Connection::open(db_path).map_err(|e| e.to_string())?;
+    let tx = conn.transaction().map_err(|e| e.to_string())?;
+    let updated = tx
+        .execute(
+            "UPDATE rules SET match_field = ?1, match_pattern = ?2, match_join = ?3 WHERE id = ?4",
+            params![first.field, first.value, match_join.as_str(), rule_id],
+        )
+        .map_err(|e| e.to_string())?;
+    if updated == 0 {
+        return Err(format!("Rule {} not found", rule_id));
+    }
+    write_conditions(&tx, rule_id, &conditions)?;
+    tx.commit().map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+// The simple rule form edits what the rules list shows: the first condition and the first action.
+// Further conditions and actions are kept, as is the first action when its summary is unchanged.
+pub fn replace_first(
+    conn: &Connection,
+    rule: &crate::Rule,
+    match_field: &str,
+    match_pattern: &str,
+    action_field: &str,
+    action_value: &str,
+) -> Result<(), String> {
+    if let Some(first) = rule.conditions.first() {
+        let mut conditions = rule.conditions.clone();
+        conditions[0] = RuleCondition {
+            field: match_field.to_string(),
+            value: match_pattern.to_string(),
+            ..first.clone()
+        };
+        Matcher::compile(&conditions[0])?;
+        write_conditions(conn, rule.id, &conditions)?;
+    }
+
+    let summary = (action_field.to_string(), action_value.to_string());
+    let mut actions = rule.actions.clone();
+    match actions.first() {
+        Some(first) if first.summary()? == summary => {}
+        _ => {
+            let action = RuleAction::SetField {
+                field: summary.0,
+                value: summary.1,
+            };
+            match actions.is_empty() {
+                true => actions.push(action),
+                false => actions[0] = action,
+            }
+        }
+    }
+    write_actions(conn, rule.id, &actions)
+}
+
+pub fn write_actions(
+    conn: &Connection,
+    rule_id: i32,
+    actions: &[RuleAction],
+) -> Result<(), String> {
+    conn.execute(
+        "DELETE FROM rule_actions WHERE rule_id = ?1",
+        params![rule_id],
+    )
+    .map_err(|e| e.to_string())?;
+    for (position, action) in actions.iter().enumerate() {
+        let (kind, field, value) = action.to_row()?;
+        conn.execute(
+            "INSERT INTO rule_actions (rule_id, position, kind, field, value) VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![rule_id, position as i64, kind, field, value],
+        )
+        .map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
+
+// Replaces the actions of a rule. The first action is mirrored into `action_field` and
+// `action_value`, which the rules list shows.
+pub fn set_rule_actions_db(
+    db_path: &PathBuf,
+    rule_id: i32,
+    actions: Vec<RuleAction>,
+) -> Result<(), String> {
+    let first = actions
+        .first()
+        .ok_or_else(|| "A rule needs at least one action".to_string())?;
+    let mut conn = Connection::open(db_path).map_err(|e| e.to_string())?;
+    for action in &actions {
+        action.validate(&conn)?;
+    }
+    let (action_field, action_value) = first.summary()?;
+
+    let tx = conn.transaction().map_err(|e| e.to_string())?;
+    let updated = tx
+        .execute(
+            "UPDATE rules SET action_field = ?1, action_value = ?2 WHERE id = ?3",
+            params![action_field, action_value, rule_id],
+        )
+        .map_err(|e| e.to_string())?;
+    if updated == 0 {
+        return Err(format!("Rule {} not found", rule_id));
+    }
+    write_actions(&tx, rule_id, &actions)?;
+    tx.commit().map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+pub fn apply_rules_db(db_path: &PathBuf, draft: DraftTransaction) -> Result<RuleOutcome, String> {
+    let conn = Connection::open(db_path).map_err(|e| e.to_string())?;
+    let engine = RuleEngine::load(&conn, &RuleSelection::All)?;
+    let mut transaction = draft;
+    let rule_ids = engine.apply(&mut transaction);
+    Ok(RuleOutcome {
+        transaction,
+        rule_ids,
+    })
+}
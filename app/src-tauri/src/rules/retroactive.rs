@@ -0,0 +1,385 @@
+use super::{field_text, DraftTransaction, RuleEngine, RuleSelection};
+use rusqlite::types::Value;
+use rusqlite::{params, params_from_iter, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+// Fields a rule can change, in the order a diff lists them
+const FIELDS: [&str; 10] = [
+    "date", "payee", "category", "notes", "amount", "ticker", "shares", "price", "fee", "tags",
+];
+
+// Which existing transactions a rule run looks at
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct TransactionFilter {
+    pub account_ids: Option<Vec<i32>>,
+    pub date_from: Option<String>,
+    pub date_to: Option<String>,
+    pub transaction_ids: Option<Vec<i32>>,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct FieldChange {
+    pub field: String,
+    pub old_value: Option<String>,
+    pub new_value: Option<String>,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct TransactionDiff {
+    pub transaction_id: i32,
+    pub account_id: i32,
+    pub date: String,
+    pub payee: String,
+    pub rule_ids: Vec<i32>,
+    pub changes: Vec<FieldChange>,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct RuleRunReport {
+    pub dry_run: bool,
+    pub diffs: Vec<TransactionDiff>,
+    pub applied: usize,
+    // Undo record of the committed changes; None for dry runs or when nothing changed
+    pub run_id: Option<i64>,
+}
+
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq)]
+pub struct UndoRuleRunReport {
+    pub run_id: i64,
+    pub restored: usize,
+}
+
+// A transaction as it was before a rule run changed it
+#[derive(Serialize, Deserialize)]
+struct UndoEntry {
+    transaction_id: i32,
+    before: DraftTransaction,
+    rule_ids: Option<String>,
+    // Fields the run changed; only these are restored
+    fields: Vec<String>,
+}
+
+struct Planned {
+    diff: TransactionDiff,
+    before: DraftTransaction,
+    after: DraftTransaction,
+    rule_ids: Option<String>,
+}
+
+fn value(draft: &DraftTransaction, field: &str) -> Option<String> {
+    field_text(draft, field).filter(|v| !v.is_empty())
+}
+
+// Copies one diff field from `from` into `to`
+fn restore_field(to: &mut DraftTransaction, from: &DraftTransaction, field: &str) {
+    match field {
+        "date" => to.date = from.date.clone(),
+        "payee" => to.payee = from.payee.clone(),
+        "category" => to.category = from.category.clone(),
+        "notes" => to.notes = from.notes.clone(),
+        "amount" => to.amount = from.amount,
+        "ticker" => to.ticker = from.ticker.clone(),
+        "shares" => to.shares = from.shares,
+        "price" => to.price_per_share = from.price_per_share,
+        "fee" => to.fee = from.fee,
+        "tags" => to.tags = from.tags.clone(),
+        _ => {}
+    }
+}
+
+const DRAFT_COLUMNS: &str = "id, account_id, date, payee, notes, category, amount, ticker, shares, price_per_share, fee, rule_ids, tags";
+
+// Reads a row selected with DRAFT_COLUMNS into its id, draft and recorded rule ids
+fn read_draft(row: &rusqlite::Row) -> rusqlite::Result<(i32, DraftTransaction, Option<String>)> {
+    let tags = row
+        .get::<_, Option<String>>(12)?
+        .map(|tags| serde_json::from_str(&tags))
+        .transpose()
+        .map_err(|e| {
+            rusqlite::Error::FromSqlConversionFailure(12, rusqlite::types::Type::Text, Box::new(e))
+        })?
+        .unwrap_or_default();
+    Ok((
+        row.get(0)?,
+        DraftTransaction {
+            account_id: Some(row.get(1)?),
+            date: row.get(2)?,
+            payee: row.get(3)?,
+            notes: row.get(4)?,
+            category: row.get(5)?,
+            amount: row.get(6)?,
+            ticker: row.get(7)?,
+            shares: row.get(8)?,
+            price_per_share: row.get(9)?,
+            fee: row.get(10)?,
+            tags,
+            ..Default::default()
+        },
+        row.get(11)?,
+    ))
+}
+
+fn filter_sql(filter: &TransactionFilter) -> (String, Vec<Value>) {
+    let mut sql = String::new();
+    let mut values = Vec::new();
+    for (column, ids) in [
+        ("account_id", &filter.account_ids),
+        ("id", &filter.transaction_ids),
+    ] {
+        if let Some(ids) = ids.as_deref() {
+            sql.push_str(&format!(
+                " AND {} IN ({})",
+                column,
+                vec!["?"; ids.len()].join(", ")
+            ));
+            values.extend(ids.iter().map(|id| Value::Integer(*id as i64)));
+        }
+    }
+    if let Some(from) = &filter.date_from {
+        sql.push_str(" AND date >= ?");
+        values.push(Value::Text(from.clone()));
+    }
+    if let Some(to) = &filter.date_to {
+        sql.push_str(" AND date <= ?");
+        values.push(Value::Text(to.clone()));
+    }
+    (sql, values)
+}
+
+// Runs the rules over the filtered transactions without writing anything. Transfers and trades
+// are left alone: changing one side of a transfer or a trade's amount would leave the ledger
+// inconsistent.
+fn plan(
+    conn: &Connection,
+    selection: &RuleSelection,
+    filter: &TransactionFilter,
+) -> Result<Vec<Planned>, String> {
+    let engine = RuleEngine::load(conn, selection)?;
+    if engine.is_empty() {
+        return Ok(Vec::new());
+    }
+    let (sql, values) = filter_sql(filter);
+    let mut stmt = conn
+        .prepare(&format!(
+            "SELECT {}
+             FROM transactions
+             WHERE linked_tx_id IS NULL AND shares IS NULL{}
+             ORDER BY date ASC, id ASC",
+            DRAFT_COLUMNS, sql
+        ))
+        .map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map(params_from_iter(values), read_draft)
+        .map_err(|e| e.to_string())?;
+
+    let mut planned = Vec::new();
+    for row in rows {
+        let (id, before, rule_ids) = row.map_err(|e| e.to_string())?;
+        let mut after = before.clone();
+        let fired = engine.apply(&mut after);
+        // Transfers and splits create transactions, which only the create and import paths do
+        after.transfer_to = None;
+        after.splits.clear();
+        let changes: Vec<FieldChange> = FIELDS
+            .iter()
+            .filter_map(|field| {
+                let (old_value, new_value) = (value(&before, field), value(&after, field));
+                (old_value != new_value).then(|| FieldChange {
+                    field: field.to_string(),
+                    old_value,
+                    new_value,
+                })
+            })
+            .collect();
+        if changes.is_empty() {
+            continue;
+        }
+        planned.push(Planned {
+            diff: TransactionDiff {
+                transaction_id: id,
+                account_id: before.account_id.unwrap_or_default(),
+                date: before.date.clone(),
+                payee: before.payee.clone(),
+                rule_ids: fired,
+                changes,
+            },
+            before,
+            after,
+            rule_ids,
+        });
+    }
+    Ok(planned)
+}
+
+// Writes the draft's fields back to the transaction and moves the account balance by the change
+// in amount
+fn write_draft(
+    conn: &Connection,
+    transaction_id: i32,
+    draft: &DraftTransaction,
+    old_amount: f64,
+    rule_ids: Option<&str>,
+) -> Result<(), String> {
+    let tags = match draft.tags.is_empty() {
+        true => None,
+        false => Some(serde_json::to_string(&draft.tags).map_err(|e| e.to_string())?),
+    };
+    conn.execute(
+        "UPDATE transactions SET date = ?1, payee = ?2, notes = ?3, category = ?4, amount = ?5, ticker = ?6, shares = ?7, price_per_share = ?8, fee = ?9, rule_ids = ?10, tags = ?11 WHERE id = ?12",
+        params![
+            draft.date,
+            draft.payee,
+            draft.notes,
+            draft.category,
+            draft.amount,
+            draft.ticker,
+            draft.shares,
+            draft.price_per_share,
+            draft.fee,
+            rule_ids,
+            tags,
+            transaction_id
+        ],
+    )
+    .map_err(|e| e.to_string())?;
+    crate::categorize::refresh(conn, transaction_id)?;
+    let diff = draft.amount - old_amount;
+    if diff.abs() > f64::EPSILON {
+        conn.execute(
+            "UPDATE accounts SET balance = balance + ?1 WHERE id = (SELECT account_id FROM transactions WHERE id = ?2)",
+            params![diff, transaction_id],
+        )
+        .map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
+
+// Adds the rules of this run to those already recorded on the transaction
+fn merge_rule_ids(recorded: Option<&str>, fired: &[i32]) -> Result<String, String> {
+    let mut ids: Vec<i32> = match recorded {
+        Some(json) => serde_json::from_str(json).map_err(|e| e.to_string())?,
+        None => Vec::new(),
+    };
+    for id in fired {
+        if !ids.contains(id) {
+            ids.push(*id);
+        }
+    }
+    serde_json::to_string(&ids).map_err(|e| e.to_string())
+}
+
+// Applies rules to transactions already in the ledger. A dry run returns the field changes per
+// transaction; otherwise the changes to the `approved` transactions (all of them when None) are
+// written in one SQLite transaction together with an undo record.
+pub fn apply_rules_to_existing_db(
+    db_path: &PathBuf,
+    selection: &RuleSelection,
+    filter: &TransactionFilter,
+    dry_run: bool,
+    approved: Option<Vec<i32>>,
+) -> Result<RuleRunReport, String> {
+    let mut conn = Connection::open(db_path).map_err(|e| e.to_string())?;
+    // Planned inside the write transaction so nothing changes between reading and writing
+    let tx = conn
+        .transaction_with_behavior(rusqlite::TransactionBehavior::Immediate)
+        .map_err(|e| e.to_string())?;
+    let mut planned = plan(&tx, selection, filter)?;
+    if let Some(approved) = &approved {
+        planned.retain(|p| approved.contains(&p.diff.transaction_id));
+    }
+    if dry_run || planned.is_empty() {
+        return Ok(RuleRunReport {
+            dry_run,
+            diffs: planned.into_iter().map(|p| p.diff).collect(),
+            applied: 0,
+            run_id: None,
+        });
+    }
+
+    let mut undo = Vec::new();
+    for p in &planned {
+        let rule_ids = merge_rule_ids(p.rule_ids.as_deref(), &p.diff.rule_ids)?;
+        write_draft(
+            &tx,
+            p.diff.transaction_id,
+            &p.after,
+            p.before.amount,
+            Some(&rule_ids),
+        )?;
+        undo.push(UndoEntry {
+            transaction_id: p.diff.transaction_id,
+            before: p.before.clone(),
+            rule_ids: p.rule_ids.clone(),
+            fields: p.diff.changes.iter().map(|c| c.field.clone()).collect(),
+        });
+    }
+    let changes = serde_json::to_string(&undo).map_err(|e| e.to_string())?;
+    tx.execute(
+        "INSERT INTO rule_runs (applied_at, changes) VALUES (?1, ?2)",
+        params![
+            chrono::Local::now().format("%Y-%m-%d %H:%M:%S").to_string(),
+            changes
+        ],
+    )
+    .map_err(|e| e.to_string())?;
+    let run_id = tx.last_insert_rowid();
+    tx.commit().map_err(|e| e.to_string())?;
+
+    Ok(RuleRunReport {
+        dry_run,
+        applied: planned.len(),
+        diffs: planned.into_iter().map(|p| p.diff).collect(),
+        run_id: Some(run_id),
+    })
+}
+
+// Restores the fields a rule run changed. Edits made since to other fields are kept, and
+// transactions deleted since are skipped.
+pub fn undo_rule_run_db(db_path: &PathBuf, run_id: i64) -> Result<UndoRuleRunReport, String> {
+    let mut conn = Connection::open(db_path).map_err(|e| e.to_string())?;
+    let tx = conn.transaction().map_err(|e| e.to_string())?;
+    let changes: String = tx
+        .query_row(
+            "SELECT changes FROM rule_runs WHERE id = ?1",
+            params![run_id],
+            |row| row.get(0),
+        )
+        .optional()
+        .map_err(|e| e.to_string())?
+        .ok_or_else(|| format!("Rule run {} not found", run_id))?;
+    let undo: Vec<UndoEntry> = serde_json::from_str(&changes).map_err(|e| e.to_string())?;
+
+    let mut restored = 0;
+    for entry in &undo {
+        let current = tx
+            .query_row(
+                &format!("SELECT {} FROM transactions WHERE id = ?1", DRAFT_COLUMNS),
+                params![entry.transaction_id],
+                read_draft,
+            )
+            .optional()
+            .map_err(|e| e.to_string())?;
+        let Some((_, current, _)) = current else {
+            continue;
+        };
+        let mut restore = current.clone();
+        for field in &entry.fields {
+            restore_field(&mut restore, &entry.before, field);
+        }
+        write_draft(
+            &tx,
+            entry.transaction_id,
+            &restore,
+            current.amount,
+            entry.rule_ids.as_deref(),
+        )?;
+        restored += 1;
+    }
+    tx.execute("DELETE FROM rule_runs WHERE id = ?1", params![run_id])
+        .map_err(|e| e.to_string())?;
+    tx.commit().map_err(|e| e.to_string())?;
+
+    Ok(UndoRuleRunReport { run_id, restored })
+}
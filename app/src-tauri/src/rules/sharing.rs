@@ -0,0 +1,363 @@
+use super::{
+    legacy_condition, load_rules, write_actions, write_conditions, MatchJoin, MatchOperator,
+    Matcher, RuleAction, RuleCondition,
+};
+use rusqlite::{params, Connection, OptionalExtension};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::path::{Path, PathBuf};
+
+// Bump when the rule set format changes; older files must keep loading
+pub const RULE_SET_VERSION: u32 = 1;
+
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum RuleSetFormat {
+    Json,
+    Toml,
+}
+
+impl RuleSetFormat {
+    // `.toml` files are TOML, anything else JSON
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|e| e.to_str()) {
+            Some(ext) if ext.eq_ignore_ascii_case("toml") => RuleSetFormat::Toml,
+            _ => RuleSetFormat::Json,
+        }
+    }
+}
+
+// What to do with an incoming rule whose priority an existing rule already has
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub enum ConflictStrategy {
+    // Keep the existing rule and leave the incoming one out
+    #[default]
+    Skip,
+    // Delete the existing rule and import the incoming one in its place
+    Replace,
+    // Import the incoming rule at the next free priority below, so existing rules keep precedence
+    Renumber,
+}
+
+// Accounts are referenced by id inside a ledger; the name lets another ledger find its own
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct SharedAccount {
+    pub id: i32,
+    pub name: String,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct SharedRule {
+    pub priority: i32,
+    #[serde(default)]
+    pub match_join: MatchJoin,
+    pub conditions: Vec<RuleCondition>,
+    pub actions: Vec<RuleAction>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct RuleSet {
+    pub version: u32,
+    #[serde(default)]
+    pub exported_at: String,
+    #[serde(default)]
+    pub accounts: Vec<SharedAccount>,
+    #[serde(default)]
+    pub rules: Vec<SharedRule>,
+}
+
+#[derive(Serialize, Debug, Clone, Default, PartialEq)]
+#[serde(rename_all = "camelCase")]
+pub struct RuleImportReport {
+    pub imported: usize,
+    pub replaced: usize,
+    pub renumbered: usize,
+    // Rules left out for a priority conflict or because the ledger already has them
+    pub skipped: usize,
+}
+
+fn account_ids(rule: &SharedRule) -> Vec<i32> {
+    let mut ids = Vec::new();
+    for condition in &rule.conditions {
+        if condition.operator == MatchOperator::AccountIs {
+            ids.extend(condition.value.trim().parse::<i32>().ok());
+        }
+    }
+    for action in &rule.actions {
+        if let RuleAction::TransferTo { account_id } = action {
+            ids.push(*account_id);
+        }
+    }
+    ids
+}
+
+fn shared_rules(conn: &Connection, rule_ids: Option<&[i32]>) -> Result<Vec<SharedRule>, String> {
+    Ok(load_rules(conn)?
+        .into_iter()
+        .filter(|rule| rule_ids.is_none_or(|ids| ids.contains(&rule.id)))
+        .map(|rule| SharedRule {
+            priority: rule.priority,
+            match_join: rule.match_join,
+            conditions: match rule.conditions.is_empty() {
+                true => vec![legacy_condition(&rule)],
+                false => rule.conditions.clone(),
+            },
+            actions: rule.actions,
+        })
+        .collect())
+}
+
+// Fails on a rule that points at a deleted account, as no other ledger could resolve it
+pub fn read_rule_set(conn: &Connection, rule_ids: Option<&[i32]>) -> Result<RuleSet, String> {
+    let rules = shared_rules(conn, rule_ids)?;
+
+    let mut accounts = Vec::new();
+    let referenced: BTreeSet<i32> = rules.iter().flat_map(account_ids).collect();
+    for id in referenced {
+        let name: String = conn
+            .query_row(
+                "SELECT name FROM accounts WHERE id = ?1",
+                params![id],
+                |row| row.get(0),
+            )
+            .optional()
+            .map_err(|e| e.to_string())?
+            .ok_or_else(|| format!("A rule refers to account {}, which no longer exists", id))?;
+        accounts.push(SharedAccount { id, name });
+    }
+
+    Ok(RuleSet {
+        version: RULE_SET_VERSION,
+        exported_at: chrono::Utc::now().to_rfc3339(),
+        accounts,
+        rules,
+    })
+}
+
+pub fn write_rule_set(rule_set: &RuleSet, format: RuleSetFormat) -> Result<String, String> {
+    match format {
+        RuleSetFormat::Json => serde_json::to_string_pretty(rule_set).map_err(|e| e.to_string()),
+        RuleSetFormat::Toml => toml::to_string(rule_set).map_err(|e| e.to_string()),
+    }
+}
+
+pub fn parse_rule_set(text: &str, format: RuleSetFormat) -> Result<RuleSet, String> {
+    let rule_set: RuleSet = match format {
+        RuleSetFormat::Json => serde_json::from_str(text).map_err(|e| e.to_string()),
+        RuleSetFormat::Toml => toml::from_str(text).map_err(|e| e.to_string()),
+    }
+    .map_err(|e| format!("Invalid rule set: {}", e))?;
+    if rule_set.version == 0 || rule_set.version > RULE_SET_VERSION {
+        return Err(format!(
+            "Unsupported rule set version {} (this build reads up to {})",
+            rule_set.version, RULE_SET_VERSION
+        ));
+    }
+    Ok(rule_set)
+}
+
+// Points account references at the accounts of this ledger with the same name
+fn localize(
+    conn: &Connection,
+    rule_set: &RuleSet,
+    rule: &SharedRule,
+    position: usize,
+) -> Result<SharedRule, String> {
+    let mut local: HashMap<i32, i32> = HashMap::new();
+    for id in account_ids(rule) {
+        let name = rule_set
+            .accounts
+            .iter()
+            .find(|a| a.id == id)
+            .map(|a| a.name.as_str())
+            .ok_or_else(|| format!("Rule {}: account {} is not named", position + 1, id))?;
+        let local_id: i32 = conn
+            .query_row(
+                "SELECT id FROM accounts WHERE name = ?1",
+                params![name],
+                |row| row.get(0),
+            )
+            .optional()
+            .map_err(|e| e.to_string())?
+            .ok_or_else(|| format!("Rule {}: account '{}' not found", position + 1, name))?;
+        local.insert(id, local_id);
+    }
+
+    let mut rule = rule.clone();
+    for condition in &mut rule.conditions {
+        if condition.operator == MatchOperator::AccountIs {
+            if let Some(id) = condition
+                .value
+                .trim()
+                .parse::<i32>()
+                .ok()
+                .and_then(|id| local.get(&id))
+            {
+                condition.value = id.to_string();
+            }
+        }
+    }
+    for action in &mut rule.actions {
+        if let RuleAction::TransferTo { account_id } = action {
+            *account_id = local[account_id];
+        }
+    }
+    Ok(rule)
+}
+
+fn validate(conn: &Connection, rule: &SharedRule, position: usize) -> Result<(), String> {
+    let context = |e: String| format!("Rule {}: {}", position + 1, e);
+    if rule.conditions.is_empty() {
+        return Err(context("a rule needs at least one condition".to_string()));
+    }
+    if rule.actions.is_empty() {
+        return Err(context("a rule needs at least one action".to_string()));
+    }
+    for condition in &rule.conditions {
+        Matcher::compile(condition).map_err(context)?;
+    }
+    for action in &rule.actions {
+        action.validate(conn).map_err(context)?;
+    }
+    Ok(())
+}
+
+fn delete_rule(conn: &Connection, rule_id: i32) -> Result<(), String> {
+    for sql in [
+        "DELETE FROM rule_conditions WHERE rule_id = ?1",
+        "DELETE FROM rule_actions WHERE rule_id = ?1",
+        "DELETE FROM rules WHERE id = ?1",
+    ] {
+        conn.execute(sql, params![rule_id])
+            .map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
+
+fn insert_rule(conn: &Connection, rule: &SharedRule) -> Result<(), String> {
+    let first = &rule.conditions[0];
+    let (action_field, action_value) = rule.actions[0].summary()?;
+    conn.execute(
+        "INSERT INTO rules (priority, match_field, match_pattern, action_field, action_value, match_join) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+        params![
+            rule.priority,
+            first.field,
+            first.value,
+            action_field,
+            action_value,
+            rule.match_join.as_str()
+        ],
+    )
+    .map_err(|e| e.to_string())?;
+    let id = conn.last_insert_rowid() as i32;
+    write_conditions(conn, id, &rule.conditions)?;
+    write_actions(conn, id, &rule.actions)?;
+    Ok(())
+}
+
+// Adds the rules of a rule set to the ledger. Every rule is checked (regexes, field names,
+// action values, accounts) before anything is written, and the import happens in one SQLite
+// transaction. Rules the ledger already has, with the same conditions and actions at any
+// priority, are skipped whatever the strategy. Rules of the set that share a priority with one
+// imported before them are not conflicts with the ledger: they move to the next free priority
+// below it.
+pub fn import_rule_set(
+    conn: &mut Connection,
+    rule_set: &RuleSet,
+    strategy: ConflictStrategy,
+) -> Result<RuleImportReport, String> {
+    let mut incoming = Vec::new();
+    for (position, rule) in rule_set.rules.iter().enumerate() {
+        let rule = localize(conn, rule_set, rule, position)?;
+        validate(conn, &rule, position)?;
+        incoming.push(rule);
+    }
+
+    let existing = shared_rules(conn, None)?;
+    let mut owners: HashMap<i32, Vec<i32>> = HashMap::new();
+    for rule in load_rules(conn)? {
+        owners.entry(rule.priority).or_default().push(rule.id);
+    }
+    let mut taken: HashSet<i32> = owners.keys().copied().collect();
+    let mut imported: HashSet<i32> = HashSet::new();
+
+    let tx = conn.transaction().map_err(|e| e.to_string())?;
+    let mut report = RuleImportReport::default();
+    for mut rule in incoming {
+        let same = |r: &SharedRule| {
+            (&r.match_join, &r.conditions, &r.actions)
+                == (&rule.match_join, &rule.conditions, &rule.actions)
+        };
+        if existing.iter().any(same) {
+            report.skipped += 1;
+            continue;
+        }
+        if imported.contains(&rule.priority) {
+            while taken.contains(&rule.priority) {
+                rule.priority -= 1;
+            }
+            report.renumbered += 1;
+        }
+        if taken.contains(&rule.priority) {
+            match strategy {
+                ConflictStrategy::Skip => {
+                    report.skipped += 1;
+                    continue;
+                }
+                ConflictStrategy::Replace => {
+                    for id in owners.remove(&rule.priority).unwrap_or_default() {
+                        delete_rule(&tx, id)?;
+                        report.replaced += 1;
+                    }
+                }
+                ConflictStrategy::Renumber => {
+                    while taken.contains(&rule.priority) {
+                        rule.priority -= 1;
+                    }
+                    report.renumbered += 1;
+                }
+            }
+        }
+        insert_rule(&tx, &rule)?;
+        taken.insert(rule.priority);
+        imported.insert(rule.priority);
+        report.imported += 1;
+    }
+    tx.commit().map_err(|e| e.to_string())?;
+    Ok(report)
+}
+
+pub fn export_rules_db(
+    db_path: &PathBuf,
+    file_path: &Path,
+    format: Option<RuleSetFormat>,
+    rule_ids: Option<Vec<i32>>,
+) -> Result<usize, String> {
+    let conn = Connection::open(db_path).map_err(|e| e.to_string())?;
+    let rule_set = read_rule_set(&conn, rule_ids.as_deref())?;
+    let text = write_rule_set(
+        &rule_set,
+        format.unwrap_or_else(|| RuleSetFormat::from_path(file_path)),
+    )?;
+    std::fs::write(file_path, text).map_err(|e| e.to_string())?;
+    Ok(rule_set.rules.len())
+}
+
+pub fn import_rules_db(
+    db_path: &PathBuf,
+    file_path: &Path,
+    format: Option<RuleSetFormat>,
+    strategy: ConflictStrategy,
+) -> Result<RuleImportReport, String> {
+    let text = std::fs::read_to_string(file_path).map_err(|e| e.to_string())?;
+    let rule_set = parse_rule_set(
+        &text,
+        format.unwrap_or_else(|| RuleSetFormat::from_path(file_path)),
+    )?;
+    let mut conn = Connection::open(db_path).map_err(|e| e.to_string())?;
+    import_rule_set(&mut conn, &rule_set, strategy)
+}
@@ -0,0 +1,258 @@
+use super::{
+    DraftTransaction, MatchJoin, MatchOperator, RuleAction, RuleCondition, RuleEngine,
+    RuleSelection,
+};
+use crate::duplicates::normalize_payee;
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::PathBuf;
+
+pub const DEFAULT_MIN_OCCURRENCES: usize = 3;
+pub const DEFAULT_MIN_CONSISTENCY: f64 = 0.9;
+
+// Payees are grouped by up to this many leading words before giving up on a pattern
+const MAX_PREFIX_WORDS: usize = 3;
+const MAX_EXAMPLES: usize = 5;
+
+// Non-alphanumeric runs and the store numbers between them, which `normalize_payee` drops
+const SEPARATOR: &str = r"[\W_]+(?:\d+[\W_]+)*";
+
+#[derive(Clone, Serialize, Deserialize, Debug)]
+pub struct RuleSuggestion {
+    // Not saved yet, so `id` is 0
+    pub rule: crate::Rule,
+    pub category: String,
+    // Categorized transactions the pattern covers and how many of them have `category`
+    pub transactions: usize,
+    pub occurrences: usize,
+    pub consistency: f64,
+    pub examples: Vec<String>,
+    // Existing transactions whose category the rule would change
+    pub affected: usize,
+}
+
+struct Row {
+    draft: DraftTransaction,
+    words: Vec<String>,
+}
+
+fn load_rows(conn: &Connection) -> Result<Vec<Row>, String> {
+    let mut stmt = conn
+        .prepare(
+            "SELECT account_id, date, payee, notes, category, amount FROM transactions
+             WHERE linked_tx_id IS NULL AND shares IS NULL ORDER BY date ASC, id ASC",
+        )
+        .map_err(|e| e.to_string())?;
+    let rows = stmt
+        .query_map([], |row| {
+            Ok(DraftTransaction {
+                account_id: Some(row.get(0)?),
+                date: row.get(1)?,
+                payee: row.get(2)?,
+                notes: row.get(3)?,
+                category: row.get(4)?,
+                amount: row.get(5)?,
+                ..Default::default()
+            })
+        })
+        .map_err(|e| e.to_string())?;
+    let mut result = Vec::new();
+    for row in rows {
+        let draft = row.map_err(|e| e.to_string())?;
+        let words = normalize_payee(&draft.payee)
+            .split(' ')
+            .filter(|w| !w.is_empty())
+            .map(str::to_string)
+            .collect();
+        result.push(Row { draft, words });
+    }
+    Ok(result)
+}
+
+fn category(row: &Row) -> Option<&str> {
+    row.draft
+        .category
+        .as_deref()
+        .map(str::trim)
+        .filter(|c| !c.is_empty() && *c != "Transfer")
+}
+
+// The most common category of the group, with its count
+fn dominant<'a>(members: &[&'a Row]) -> Option<(&'a str, usize)> {
+    let mut counts: BTreeMap<&str, usize> = BTreeMap::new();
+    for row in members {
+        if let Some(category) = category(row) {
+            *counts.entry(category).or_default() += 1;
+        }
+    }
+    counts
+        .into_iter()
+        .max_by(|a, b| a.1.cmp(&b.1).then_with(|| b.0.cmp(a.0)))
+}
+
+// A `contains` condition when every payee spells the shared words the same way, otherwise a
+// regex that allows any punctuation or store number between them
+fn condition(members: &[&Row], words: &[String]) -> RuleCondition {
+    let phrase = words.join(" ");
+    let (operator, value) = match members
+        .iter()
+        .all(|row| row.draft.payee.to_lowercase().contains(&phrase))
+    {
+        true => (MatchOperator::Contains, phrase),
+        false => (
+            MatchOperator::Regex,
+            format!(
+                r"\b{}",
+                words
+                    .iter()
+                    .map(|w| regex::escape(w))
+                    .collect::<Vec<_>>()
+                    .join(SEPARATOR)
+            ),
+        ),
+    };
+    RuleCondition {
+        field: "payee".to_string(),
+        operator,
+        value,
+        value_to: None,
+        case_sensitive: false,
+    }
+}
+
+fn common_prefix(members: &[&Row]) -> Vec<String> {
+    let first = &members[0].words;
+    let len = members
+        .iter()
+        .map(|row| {
+            first
+                .iter()
+                .zip(&row.words)
+                .take_while(|(a, b)| a == b)
+                .count()
+        })
+        .min()
+        .unwrap_or_default();
+    first[..len].to_vec()
+}
+
+fn suggestion(
+    rows: &[Row],
+    members: &[&Row],
+    category: &str,
+    occurrences: usize,
+) -> Option<RuleSuggestion> {
+    let condition = condition(members, &common_prefix(members));
+    let rule = crate::Rule {
+        id: 0,
+        priority: 0,
+        match_field: "payee".to_string(),
+        match_pattern: condition.value.clone(),
+        action_field: "category".to_string(),
+        action_value: category.to_string(),
+        match_join: MatchJoin::And,
+        conditions: vec![condition],
+        actions: vec![RuleAction::SetField {
+            field: "category".to_string(),
+            value: category.to_string(),
+        }],
+    };
+    let engine = RuleEngine::new(std::slice::from_ref(&rule));
+    // A pattern that misses some of the payees it was built from is not worth proposing
+    let matches = |row: &Row| {
+        !engine
+            .apply(&mut DraftTransaction {
+                category: None,
+                ..row.draft.clone()
+            })
+            .is_empty()
+    };
+    if !members.iter().all(|row| matches(row)) {
+        return None;
+    }
+    let affected = rows
+        .iter()
+        .filter(|row| {
+            let mut draft = row.draft.clone();
+            engine.apply(&mut draft);
+            draft.category != row.draft.category
+        })
+        .count();
+    let examples: BTreeSet<&str> = members.iter().map(|row| row.draft.payee.as_str()).collect();
+    Some(RuleSuggestion {
+        rule,
+        category: category.to_string(),
+        transactions: members.len(),
+        occurrences,
+        consistency: occurrences as f64 / members.len() as f64,
+        examples: examples
+            .into_iter()
+            .take(MAX_EXAMPLES)
+            .map(str::to_string)
+            .collect(),
+        affected,
+    })
+}
+
+// Finds payees that are nearly always given the same category and proposes a rule for each.
+// Payees are grouped by their first word, then by their first two and three words for groups
+// that are not consistent enough. Transactions that an existing rule already categorizes are
+// left out, so a group is judged by the rows no rule covers yet.
+pub fn suggest_rules_db(
+    db_path: &PathBuf,
+    min_occurrences: Option<usize>,
+    min_consistency: Option<f64>,
+) -> Result<Vec<RuleSuggestion>, String> {
+    let min_occurrences = min_occurrences.unwrap_or(DEFAULT_MIN_OCCURRENCES).max(1);
+    let min_consistency = min_consistency.unwrap_or(DEFAULT_MIN_CONSISTENCY);
+    if !(min_consistency > 0.0 && min_consistency <= 1.0) {
+        return Err("Consistency must be between 0 and 1".to_string());
+    }
+    let conn = Connection::open(db_path).map_err(|e| e.to_string())?;
+    let existing = RuleEngine::load(&conn, &RuleSelection::All)?;
+    let rows = load_rows(&conn)?;
+
+    let mut suggestions = Vec::new();
+    let covered = |row: &Row| {
+        let mut draft = DraftTransaction {
+            category: None,
+            ..row.draft.clone()
+        };
+        existing.apply(&mut draft);
+        draft.category.is_some()
+    };
+    let mut pending: Vec<&Row> = rows
+        .iter()
+        .filter(|row| category(row).is_some() && !covered(row))
+        .collect();
+    for depth in 1..=MAX_PREFIX_WORDS {
+        let mut groups: BTreeMap<&[String], Vec<&Row>> = BTreeMap::new();
+        for row in pending {
+            if row.words.len() >= depth {
+                groups.entry(&row.words[..depth]).or_default().push(row);
+            }
+        }
+        pending = Vec::new();
+        for members in groups.into_values() {
+            let Some((category, occurrences)) = dominant(&members) else {
+                continue;
+            };
+            // Narrower groups only get smaller
+            if occurrences < min_occurrences {
+                continue;
+            }
+            if (occurrences as f64 / members.len() as f64) < min_consistency {
+                pending.extend(members);
+                continue;
+            }
+            suggestions.extend(suggestion(&rows, &members, category, occurrences));
+        }
+    }
+    suggestions.sort_by(|a, b| {
+        b.occurrences
+            .cmp(&a.occurrences)
+            .then_with(|| a.rule.match_pattern.cmp(&b.rule.match_pattern))
+    });
+    Ok(suggestions)
+}
@@ -173,6 +173,41 @@ pub(crate) fn init_db_at_path(db_path: &Path) -> Result<(), String> {
         }
     }
 
+    super::ensure_column(&conn, "transactions", "external_id", "TEXT")?;
+    super::ensure_column(&conn, "transactions", "import_batch_id", "INTEGER")?;
+    super::ensure_column(&conn, "transactions", "rule_ids", "TEXT")?;
+    super::ensure_column(&conn, "transactions", "tags", "TEXT")?;
+    super::ensure_column(&conn, "transactions", "raw_payee", "TEXT")?;
+    super::ensure_column(&conn, "transactions", "value_date", "TEXT")?;
+    super::ensure_column(&conn, "transactions", "split_id", "INTEGER")?;
+
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS import_batches (
+            id INTEGER PRIMARY KEY,
+            file_name TEXT NOT NULL,
+            file_hash TEXT NOT NULL,
+            imported_at TEXT NOT NULL,
+            total_rows INTEGER NOT NULL DEFAULT 0,
+            imported_rows INTEGER NOT NULL DEFAULT 0,
+            duplicate_rows INTEGER NOT NULL DEFAULT 0,
+            error_rows INTEGER NOT NULL DEFAULT 0
+        )",
+        [],
+    )
+    .map_err(|e| e.to_string())?;
+
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS import_profiles (
+            id INTEGER PRIMARY KEY,
+            name TEXT NOT NULL UNIQUE,
+            header_fingerprint TEXT NOT NULL,
+            mapping TEXT NOT NULL,
+            rule_ids TEXT
+        )",
+        [],
+    )
+    .map_err(|e| e.to_string())?;
+
     conn.execute(
         "CREATE TABLE IF NOT EXISTS stock_prices (
             ticker TEXT PRIMARY KEY,
@@ -195,6 +230,119 @@ pub(crate) fn init_db_at_path(db_path: &Path) -> Result<(), String> {
     )
     .map_err(|e| e.to_string())?;
 
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS rules (
+            id INTEGER PRIMARY KEY,
+            priority INTEGER NOT NULL DEFAULT 0,
+            match_field TEXT NOT NULL,
+            match_pattern TEXT NOT NULL,
+            action_field TEXT NOT NULL,
+            action_value TEXT NOT NULL
+        )",
+        [],
+    )
+    .map_err(|e| e.to_string())?;
+    super::ensure_column(&conn, "rules", "match_join", "TEXT NOT NULL DEFAULT 'and'")?;
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS rule_conditions (
+            id INTEGER PRIMARY KEY,
+            rule_id INTEGER NOT NULL,
+            position INTEGER NOT NULL DEFAULT 0,
+            field TEXT NOT NULL,
+            operator TEXT NOT NULL,
+            value TEXT NOT NULL,
+            value_to TEXT,
+            case_sensitive INTEGER NOT NULL DEFAULT 0,
+            FOREIGN KEY(rule_id) REFERENCES rules(id)
+        )",
+        [],
+    )
+    .map_err(|e| e.to_string())?;
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS rule_actions (
+            id INTEGER PRIMARY KEY,
+            rule_id INTEGER NOT NULL,
+            position INTEGER NOT NULL DEFAULT 0,
+            kind TEXT NOT NULL,
+            field TEXT,
+            value TEXT NOT NULL,
+            FOREIGN KEY(rule_id) REFERENCES rules(id)
+        )",
+        [],
+    )
+    .map_err(|e| e.to_string())?;
+    conn.execute(
+        "INSERT INTO rule_actions (rule_id, position, kind, field, value)
+         SELECT id, 0, 'setField', action_field, action_value FROM rules
+         WHERE id NOT IN (SELECT rule_id FROM rule_actions)",
+        [],
+    )
+    .map_err(|e| e.to_string())?;
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS rule_runs (
+            id INTEGER PRIMARY KEY,
+            applied_at TEXT NOT NULL,
+            changes TEXT NOT NULL
+        )",
+        [],
+    )
+    .map_err(|e| e.to_string())?;
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS category_training (
+            transaction_id INTEGER PRIMARY KEY,
+            category TEXT NOT NULL,
+            features TEXT NOT NULL
+        )",
+        [],
+    )
+    .map_err(|e| e.to_string())?;
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS category_features (
+            category TEXT NOT NULL,
+            feature TEXT NOT NULL,
+            count INTEGER NOT NULL,
+            PRIMARY KEY (category, feature)
+        )",
+        [],
+    )
+    .map_err(|e| e.to_string())?;
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS payee_rewrites (
+            id INTEGER PRIMARY KEY,
+            position INTEGER NOT NULL DEFAULT 0,
+            pattern TEXT NOT NULL,
+            replacement TEXT NOT NULL,
+            is_regex INTEGER NOT NULL DEFAULT 0
+        )",
+        [],
+    )
+    .map_err(|e| e.to_string())?;
+    super::ensure_column(
+        &conn,
+        "accounts",
+        "cost_basis_method",
+        "TEXT NOT NULL DEFAULT 'fifo'",
+    )?;
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS lot_selections (
+            sale_id INTEGER NOT NULL,
+            lot_id INTEGER NOT NULL,
+            shares REAL NOT NULL,
+            PRIMARY KEY (sale_id, lot_id)
+        )",
+        [],
+    )
+    .map_err(|e| e.to_string())?;
+
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS custom_exchange_rates (
+            currency TEXT PRIMARY KEY,
+            rate REAL NOT NULL
+        )",
+        [],
+    )
+    .map_err(|e| e.to_string())?;
+
     Ok(())
 }
 
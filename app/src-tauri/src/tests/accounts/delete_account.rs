@@ -41,3 +41,33 @@ fn test_delete_account_with_transactions() {
     let txs_after = crate::get_transactions_db(&db_path, account.id).unwrap();
     assert!(txs_after.is_empty());
 }
+
+#[test]
+fn test_delete_account_forgets_its_transactions_in_the_category_model() {
+    let (_dir, db_path) = setup_db();
+    let account = crate::create_account_db(&db_path, "ToDelete".to_string(), 100.0, None).unwrap();
+    crate::create_transaction_db(
+        &db_path,
+        crate::CreateTransactionArgs {
+            account_id: account.id,
+            date: "2023-01-02".to_string(),
+            payee: "Grocer".to_string(),
+            notes: None,
+            category: Some("Food".to_string()),
+            amount: -20.0,
+            ticker: None,
+            shares: None,
+            price_per_share: None,
+            fee: None,
+            currency: None,
+        },
+    )
+    .unwrap();
+
+    crate::delete_account_db(&db_path, account.id).unwrap();
+
+    let conn = rusqlite::Connection::open(&db_path).unwrap();
+    let count = |sql: &str| -> i64 { conn.query_row(sql, [], |r| r.get(0)).unwrap() };
+    assert_eq!(count("SELECT COUNT(*) FROM category_training"), 0);
+    assert_eq!(count("SELECT COUNT(*) FROM category_features"), 0);
+}
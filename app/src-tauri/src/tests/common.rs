@@ -1,3 +1,5 @@
+use crate::import::csv::CsvMapping;
+use crate::rules::{set_rule_conditions_db, MatchJoin, MatchOperator, RuleCondition};
 use rusqlite::Connection;
 use std::path::PathBuf;
 use tempfile::tempdir;
@@ -14,7 +16,8 @@ pub fn setup_db() -> (tempfile::TempDir, PathBuf) {
             name TEXT NOT NULL,
             balance REAL NOT NULL,
             currency TEXT,
-            kind TEXT DEFAULT 'cash'
+            kind TEXT DEFAULT 'cash',
+            cost_basis_method TEXT NOT NULL DEFAULT 'fifo'
         )",
         [],
     )
@@ -35,6 +38,13 @@ pub fn setup_db() -> (tempfile::TempDir, PathBuf) {
             fee REAL,
             currency TEXT,
             linked_tx_id INTEGER,
+            external_id TEXT,
+            import_batch_id INTEGER,
+            rule_ids TEXT,
+            tags TEXT,
+            raw_payee TEXT,
+            value_date TEXT,
+            split_id INTEGER,
             FOREIGN KEY(account_id) REFERENCES accounts(id)
         )",
         [],
@@ -58,11 +68,232 @@ pub fn setup_db() -> (tempfile::TempDir, PathBuf) {
             match_field TEXT NOT NULL,
             match_pattern TEXT NOT NULL,
             action_field TEXT NOT NULL,
-            action_value TEXT NOT NULL
+            action_value TEXT NOT NULL,
+            match_join TEXT NOT NULL DEFAULT 'and'
         )",
         [],
     )
     .unwrap();
 
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS rule_conditions (
+            id INTEGER PRIMARY KEY,
+            rule_id INTEGER NOT NULL,
+            position INTEGER NOT NULL DEFAULT 0,
+            field TEXT NOT NULL,
+            operator TEXT NOT NULL,
+            value TEXT NOT NULL,
+            value_to TEXT,
+            case_sensitive INTEGER NOT NULL DEFAULT 0,
+            FOREIGN KEY(rule_id) REFERENCES rules(id)
+        )",
+        [],
+    )
+    .unwrap();
+
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS rule_actions (
+            id INTEGER PRIMARY KEY,
+            rule_id INTEGER NOT NULL,
+            position INTEGER NOT NULL DEFAULT 0,
+            kind TEXT NOT NULL,
+            field TEXT,
+            value TEXT NOT NULL,
+            FOREIGN KEY(rule_id) REFERENCES rules(id)
+        )",
+        [],
+    )
+    .unwrap();
+
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS rule_runs (
+            id INTEGER PRIMARY KEY,
+            applied_at TEXT NOT NULL,
+            changes TEXT NOT NULL
+        )",
+        [],
+    )
+    .unwrap();
+
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS category_training (
+            transaction_id INTEGER PRIMARY KEY,
+            category TEXT NOT NULL,
+            features TEXT NOT NULL
+        )",
+        [],
+    )
+    .unwrap();
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS category_features (
+            category TEXT NOT NULL,
+            feature TEXT NOT NULL,
+            count INTEGER NOT NULL,
+            PRIMARY KEY (category, feature)
+        )",
+        [],
+    )
+    .unwrap();
+
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS payee_rewrites (
+            id INTEGER PRIMARY KEY,
+            position INTEGER NOT NULL DEFAULT 0,
+            pattern TEXT NOT NULL,
+            replacement TEXT NOT NULL,
+            is_regex INTEGER NOT NULL DEFAULT 0
+        )",
+        [],
+    )
+    .unwrap();
+
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS lot_selections (
+            sale_id INTEGER NOT NULL,
+            lot_id INTEGER NOT NULL,
+            shares REAL NOT NULL,
+            PRIMARY KEY (sale_id, lot_id)
+        )",
+        [],
+    )
+    .unwrap();
+
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS import_batches (
+            id INTEGER PRIMARY KEY,
+            file_name TEXT NOT NULL,
+            file_hash TEXT NOT NULL,
+            imported_at TEXT NOT NULL,
+            total_rows INTEGER NOT NULL DEFAULT 0,
+            imported_rows INTEGER NOT NULL DEFAULT 0,
+            duplicate_rows INTEGER NOT NULL DEFAULT 0,
+            error_rows INTEGER NOT NULL DEFAULT 0
+        )",
+        [],
+    )
+    .unwrap();
+
+    conn.execute(
+        "CREATE TABLE IF NOT EXISTS import_profiles (
+            id INTEGER PRIMARY KEY,
+            name TEXT NOT NULL UNIQUE,
+            header_fingerprint TEXT NOT NULL,
+            mapping TEXT NOT NULL,
+            rule_ids TEXT
+        )",
+        [],
+    )
+    .unwrap();
+
+    (dir, db_path)
+}
+
+// A database set up by the app itself, with every table and migration
+pub fn full_db() -> (tempfile::TempDir, PathBuf) {
+    let dir = tempdir().unwrap();
+    let db_path = dir.path().join("ledger.db");
+    crate::init_db_at_path(&db_path).unwrap();
     (dir, db_path)
 }
+
+// A cash transaction, entered as through the UI so payee cleaning and rules run
+pub fn add_transaction(
+    db_path: &PathBuf,
+    account_id: i32,
+    date: &str,
+    payee: &str,
+    category: Option<&str>,
+    amount: f64,
+) -> crate::Transaction {
+    crate::create_transaction_db(
+        db_path,
+        crate::CreateTransactionArgs {
+            account_id,
+            date: date.to_string(),
+            payee: payee.to_string(),
+            notes: None,
+            category: category.map(str::to_string),
+            amount,
+            ticker: None,
+            shares: None,
+            price_per_share: None,
+            fee: None,
+            currency: None,
+        },
+    )
+    .unwrap()
+}
+
+// A buy for positive shares and a sell for negative ones; returns the trade's id
+pub fn add_trade(
+    db_path: &PathBuf,
+    account_id: i32,
+    date: &str,
+    ticker: &str,
+    shares: f64,
+    price_per_share: f64,
+    fee: f64,
+) -> i32 {
+    crate::create_investment_transaction_db(
+        db_path,
+        crate::CreateInvestmentTransactionArgs {
+            account_id,
+            date: date.to_string(),
+            ticker: ticker.to_string(),
+            shares: shares.abs(),
+            price_per_share,
+            fee,
+            is_buy: shares > 0.0,
+            currency: None,
+        },
+    )
+    .unwrap()
+    .id
+}
+
+// A legacy rule: a payee equal to `payee` sets `action.0` to `action.1`
+pub fn add_rule(db_path: &PathBuf, priority: i32, payee: &str, action: (&str, &str)) -> i32 {
+    crate::create_rule_db(
+        db_path,
+        priority,
+        "payee".to_string(),
+        payee.to_string(),
+        action.0.to_string(),
+        action.1.to_string(),
+    )
+    .unwrap()
+}
+
+// A rule matching payees that contain `pattern`, in any case
+pub fn contains_rule(db_path: &PathBuf, priority: i32, pattern: &str, action: (&str, &str)) -> i32 {
+    let id = add_rule(db_path, priority, "", action);
+    set_rule_conditions_db(
+        db_path,
+        id,
+        MatchJoin::And,
+        vec![RuleCondition {
+            field: "payee".to_string(),
+            operator: MatchOperator::Contains,
+            value: pattern.to_string(),
+            value_to: None,
+            case_sensitive: false,
+        }],
+    )
+    .unwrap();
+    id
+}
+
+// Reads files with a `Date,Payee,Amount` header into one account
+pub fn csv_mapping(account_id: i32) -> CsvMapping {
+    CsvMapping {
+        date: Some("Date".to_string()),
+        payee: Some("Payee".to_string()),
+        amount: Some("Amount".to_string()),
+        account_id: Some(account_id),
+        ..Default::default()
+    }
+}
+
+pub fn close(a: f64, b: f64) -> bool {
+    (a - b).abs() < 1e-9
+}
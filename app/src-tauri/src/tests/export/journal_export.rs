@@ -0,0 +1,287 @@
+use crate::export::journal::{export_journal_db, JournalFormat};
+use crate::tests::common::{add_trade, add_transaction, full_db};
+use rusqlite::Connection;
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+// Minimal double-entry checker in the spirit of `bean-check`: every account is opened before use,
+// every transaction balances per currency (allowing one elided posting), and lot reductions only
+// relieve lots that exist.
+fn check_journal(text: &str, beancount: bool) -> Result<usize, String> {
+    let mut opened: HashMap<String, String> = HashMap::new();
+    let mut lots: HashMap<(String, String, String, String), f64> = HashMap::new();
+    let mut checked = 0;
+    let lines: Vec<&str> = text.lines().collect();
+    let mut i = 0;
+
+    while i < lines.len() {
+        let line = lines[i];
+        i += 1;
+        if line.trim().is_empty() || line.starts_with("option") || line.starts_with("account ") {
+            continue;
+        }
+        if line.starts_with("P ") || line.contains(" price ") {
+            continue;
+        }
+        let date = line
+            .get(..10)
+            .ok_or(format!("bad line {}", line))?
+            .to_string();
+        if line.contains(" open ") {
+            let account = line.split_whitespace().nth(2).unwrap().to_string();
+            let root = account.split(':').next().unwrap();
+            if !["Assets", "Liabilities", "Equity", "Income", "Expenses"].contains(&root) {
+                return Err(format!("invalid root {}", account));
+            }
+            for part in account.split(':') {
+                if !part.starts_with(|c: char| c.is_ascii_uppercase() || c.is_ascii_digit())
+                    || !part.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+                {
+                    return Err(format!("invalid account name {}", account));
+                }
+            }
+            opened.insert(account, date);
+            continue;
+        }
+
+        let mut sums: HashMap<String, f64> = HashMap::new();
+        let mut elided = 0;
+        while i < lines.len() && lines[i].starts_with(' ') {
+            let posting = lines[i].trim();
+            i += 1;
+            let (account, rest) = posting.split_once("  ").unwrap_or((posting, ""));
+            if beancount {
+                match opened.get(account) {
+                    Some(open) if *open <= date => {}
+                    _ => return Err(format!("{} used before it was opened", account)),
+                }
+            }
+            let rest = rest.trim();
+            if rest.is_empty() {
+                elided += 1;
+                continue;
+            }
+            let tokens: Vec<&str> = rest.split_whitespace().collect();
+            let units: f64 = tokens[0]
+                .parse()
+                .map_err(|_| format!("bad number in {}", posting))?;
+            let commodity = tokens[1].trim_matches('"').to_string();
+
+            let weight = if let Some(open) = rest.find('{') {
+                let inner = &rest[open + 1..rest.find('}').unwrap()];
+                let mut parts = inner.split(',');
+                let cost_text = parts.next().unwrap().trim();
+                let (cost, currency) = cost_text.split_once(' ').unwrap();
+                let cost: f64 = cost.parse().unwrap();
+                let lot_date = parts
+                    .next()
+                    .map(|d| d.trim().to_string())
+                    .unwrap_or(date.clone());
+                let key = (
+                    account.to_string(),
+                    commodity.clone(),
+                    cost_text.to_string(),
+                    lot_date,
+                );
+                let held = lots.entry(key).or_insert(0.0);
+                *held += units;
+                if *held < -1e-9 {
+                    return Err(format!("reducing a lot that is not held: {}", posting));
+                }
+                (units * cost, currency.to_string())
+            } else if let Some(pos) = rest.find(" @@ ") {
+                let price: Vec<&str> = rest[pos + 4..].split_whitespace().collect();
+                (
+                    units.signum() * price[0].parse::<f64>().unwrap(),
+                    price[1].trim_matches('"').to_string(),
+                )
+            } else if let Some(pos) = rest.find(" @ ") {
+                let price: Vec<&str> = rest[pos + 3..].split_whitespace().collect();
+                (
+                    units * price[0].parse::<f64>().unwrap(),
+                    price[1].trim_matches('"').to_string(),
+                )
+            } else {
+                (units, commodity)
+            };
+            *sums.entry(weight.1).or_insert(0.0) += weight.0;
+        }
+
+        if elided > 1 {
+            return Err(format!("more than one elided posting on {}", line));
+        }
+        if elided == 0 {
+            for (currency, sum) in &sums {
+                if sum.abs() >= 0.005 {
+                    return Err(format!("{} does not balance: {} {}", line, sum, currency));
+                }
+            }
+        }
+        checked += 1;
+    }
+    Ok(checked)
+}
+
+fn sample_ledger() -> (tempfile::TempDir, PathBuf) {
+    let (dir, db_path) = full_db();
+
+    let checking = crate::create_account_db(
+        &db_path,
+        "Checking".to_string(),
+        500.0,
+        Some("USD".to_string()),
+    )
+    .unwrap();
+    crate::create_account_db(
+        &db_path,
+        "Rainy day fund".to_string(),
+        0.0,
+        Some("USD".to_string()),
+    )
+    .unwrap();
+    crate::create_account_db(
+        &db_path,
+        "Euro Cash".to_string(),
+        0.0,
+        Some("EUR".to_string()),
+    )
+    .unwrap();
+    let broker =
+        crate::create_account_db(&db_path, "Broker".to_string(), 0.0, Some("USD".to_string()))
+            .unwrap();
+
+    add_transaction(
+        &db_path,
+        checking.id,
+        "2024-01-02",
+        "Grocer \"Fresh\"",
+        Some("Food:Groceries"),
+        -42.5,
+    );
+    add_transaction(
+        &db_path,
+        checking.id,
+        "2024-01-03",
+        "ACME",
+        Some("Salary"),
+        3000.0,
+    );
+    add_transaction(
+        &db_path,
+        checking.id,
+        "2024-01-04",
+        "Rainy day fund",
+        Some("Transfer"),
+        -200.0,
+    );
+    add_transaction(
+        &db_path,
+        checking.id,
+        "2024-01-05",
+        "Euro Cash",
+        Some("Transfer"),
+        -100.0,
+    );
+
+    add_trade(&db_path, broker.id, "2024-01-10", "AAPL", 10.0, 100.0, 1.0);
+    add_trade(&db_path, broker.id, "2024-02-10", "AAPL", 5.0, 120.0, 0.0);
+    add_trade(&db_path, broker.id, "2024-03-10", "AAPL", -12.0, 130.0, 1.0);
+
+    let conn = Connection::open(&db_path).unwrap();
+    conn.execute(
+        "INSERT INTO daily_stock_prices (ticker, date, price) VALUES ('AAPL', '2024-03-01', 125.0), ('AAPL', '2024-03-15', 131.5)",
+        [],
+    )
+    .unwrap();
+    (dir, db_path)
+}
+
+#[test]
+fn test_beancount_export_balances_with_fifo_lots() {
+    let (dir, db_path) = sample_ledger();
+    let file = dir.path().join("ledger.beancount");
+    let written = export_journal_db(&db_path, &file, JournalFormat::Beancount).unwrap();
+    let text = std::fs::read_to_string(&file).unwrap();
+
+    assert_eq!(check_journal(&text, true), Ok(written));
+    assert!(text.contains("Assets:Broker  10.00 AAPL {100.00 USD}"));
+    // The sale relieves the whole first lot and part of the second
+    assert!(text.contains("-10.00 AAPL {100.00 USD, 2024-01-10} @ 130.00 USD"));
+    assert!(text.contains("-2.00 AAPL {120.00 USD, 2024-02-10} @ 130.00 USD"));
+    assert!(text.contains("  Income:Capital-Gains\n"));
+    assert!(text.contains("Expenses:Food:Groceries  42.50 USD"));
+    assert!(text.contains("Equity:Opening-Balances  -500.00 USD"));
+    assert!(text.contains("* \"Grocer \\\"Fresh\\\"\" \"\""));
+    assert!(text.contains("2024-03-15 price AAPL 131.50 USD"));
+}
+
+#[test]
+fn test_hledger_export_balances_with_prices() {
+    let (dir, db_path) = sample_ledger();
+    let file = dir.path().join("ledger.journal");
+    let written = export_journal_db(&db_path, &file, JournalFormat::Hledger).unwrap();
+    let text = std::fs::read_to_string(&file).unwrap();
+
+    assert_eq!(check_journal(&text, false), Ok(written));
+    assert!(text.contains("account Assets:Rainy-Day-Fund"));
+    assert!(text.contains("    Assets:Broker  -12.00 AAPL @ 130.00 USD"));
+    assert!(text.contains("P 2024-03-01 AAPL 125.00 USD"));
+    assert!(!text.contains('{'));
+}
+
+#[test]
+fn test_linked_transfers_are_written_once() {
+    let (dir, db_path) = sample_ledger();
+    let file = dir.path().join("ledger.beancount");
+    export_journal_db(&db_path, &file, JournalFormat::Beancount).unwrap();
+    let text = std::fs::read_to_string(&file).unwrap();
+
+    let entries: Vec<&str> = text
+        .split("\n\n")
+        .filter(|e| e.contains("Rainy-Day-Fund  "))
+        .collect();
+    assert_eq!(entries.len(), 1);
+    assert!(entries[0].contains("Assets:Checking  -200.00 USD"));
+    assert!(entries[0].contains("Assets:Rainy-Day-Fund  200.00 USD"));
+
+    // Different currencies on each side are tied together with a total price
+    assert!(text.contains("Assets:Checking  -100.00 USD @@ 100.00 EUR"));
+    assert!(text.contains("Assets:Euro-Cash  100.00 EUR"));
+}
+
+#[test]
+fn test_account_names_are_sanitized_and_unique() {
+    let (dir, db_path) = super::common::setup_db();
+    let conn = Connection::open(&db_path).unwrap();
+    conn.execute(
+        "CREATE TABLE daily_stock_prices (ticker TEXT NOT NULL, date TEXT NOT NULL, price REAL NOT NULL)",
+        [],
+    )
+    .unwrap();
+    let first = crate::create_account_db(&db_path, "main account".to_string(), 0.0, None).unwrap();
+    let second = crate::create_account_db(&db_path, "Main-Account".to_string(), 0.0, None).unwrap();
+    add_transaction(
+        &db_path,
+        first.id,
+        "2024-01-01",
+        "A",
+        Some("Café & Bar"),
+        -1.0,
+    );
+    add_transaction(&db_path, second.id, "2024-01-01", "B", Some(""), 2.0);
+
+    let file = dir.path().join("names.beancount");
+    export_journal_db(&db_path, &file, JournalFormat::Beancount).unwrap();
+    let text = std::fs::read_to_string(&file).unwrap();
+    assert_eq!(check_journal(&text, true), Ok(2));
+    assert!(text.contains("open Assets:Main-Account USD\n"));
+    assert!(text.contains(&format!("open Assets:Main-Account-{} USD\n", second.id)));
+    assert!(text.contains("Expenses:Caf-Bar  1.00 USD"));
+    assert!(text.contains("Income:Uncategorized  -2.00 USD"));
+}
+
+#[test]
+fn test_checker_rejects_unbalanced_journal() {
+    let journal = "2024-01-01 open Assets:Cash\n2024-01-01 open Expenses:Food\n\n2024-01-02 * \"x\" \"\"\n  Assets:Cash  -10.00 USD\n  Expenses:Food  9.00 USD\n";
+    assert!(check_journal(journal, true).is_err());
+}
@@ -0,0 +1,262 @@
+use crate::export::ledger::{
+    export_ledger_db, sanitize_cell, LedgerColumn, LedgerExportOptions, LedgerFormat,
+};
+use crate::tests::common::{add_rule, add_trade, add_transaction, full_db};
+use calamine::{open_workbook, Data, Reader, Xlsx};
+use rusqlite::Connection;
+use std::path::PathBuf;
+
+fn options(format: LedgerFormat) -> LedgerExportOptions {
+    LedgerExportOptions {
+        format,
+        delimiter: None,
+        decimal_separator: None,
+        account_ids: None,
+        date_from: None,
+        date_to: None,
+        columns: None,
+    }
+}
+
+fn sample_ledger() -> (tempfile::TempDir, PathBuf, i32, i32) {
+    let (dir, db_path) = full_db();
+
+    let checking = crate::create_account_db(
+        &db_path,
+        "Checking".to_string(),
+        0.0,
+        Some("EUR".to_string()),
+    )
+    .unwrap();
+    let broker =
+        crate::create_account_db(&db_path, "Broker".to_string(), 0.0, Some("USD".to_string()))
+            .unwrap();
+
+    add_transaction(
+        &db_path,
+        checking.id,
+        "2024-01-05",
+        "Bakery",
+        Some("Food"),
+        -12.5,
+    );
+    add_transaction(
+        &db_path,
+        checking.id,
+        "2024-02-10",
+        "=HYPERLINK(\"x\")",
+        Some("Food"),
+        -3.0,
+    );
+    add_transaction(
+        &db_path,
+        checking.id,
+        "2024-03-01",
+        "Market",
+        Some("Food"),
+        -7.25,
+    );
+    add_trade(&db_path, broker.id, "2024-01-15", "AAPL", 4.0, 100.0, 1.0);
+
+    let conn = Connection::open(&db_path).unwrap();
+    conn.execute(
+        "INSERT INTO daily_stock_prices (ticker, date, price) VALUES ('AAPL', '2024-02-01', 110.0), ('AAPL', '2024-03-01', 120.0)",
+        [],
+    )
+    .unwrap();
+    conn.execute(
+        "INSERT INTO rules (priority, match_field, match_pattern, action_field, action_value) VALUES (1, 'payee', '@shop', 'category', 'Shopping')",
+        [],
+    )
+    .unwrap();
+    conn.execute(
+        "INSERT INTO custom_exchange_rates (currency, rate) VALUES ('XAU', 2000.0)",
+        [],
+    )
+    .unwrap();
+    (dir, db_path, checking.id, broker.id)
+}
+
+#[test]
+fn test_csv_export_uses_delimiter_decimal_and_columns() {
+    let (dir, db_path, checking, _) = sample_ledger();
+    let file = dir.path().join("ledger.csv");
+    let mut opts = options(LedgerFormat::Csv);
+    opts.delimiter = Some(";".to_string());
+    opts.decimal_separator = Some(",".to_string());
+    opts.account_ids = Some(vec![checking]);
+    opts.columns = Some(vec![
+        LedgerColumn::Date,
+        LedgerColumn::Payee,
+        LedgerColumn::Amount,
+        LedgerColumn::Currency,
+    ]);
+
+    let written = export_ledger_db(&db_path, &file, opts).unwrap();
+    assert_eq!(written, 3);
+    let text = std::fs::read_to_string(&file).unwrap();
+    let lines: Vec<&str> = text.lines().collect();
+    assert_eq!(lines[0], "Date;Payee;Amount;Currency");
+    assert_eq!(lines[1], "2024-01-05;Bakery;-12,5;EUR");
+    // Formula-like text is neutralised, negative numbers are left alone
+    assert_eq!(lines[2], "2024-02-10;\"'=HYPERLINK(\"\"x\"\")\";-3;EUR");
+}
+
+#[test]
+fn test_date_range_filters_transactions() {
+    let (dir, db_path, _, _) = sample_ledger();
+    let file = dir.path().join("ledger.json");
+    let mut opts = options(LedgerFormat::Json);
+    opts.date_from = Some("2024-01-10".to_string());
+    opts.date_to = Some("2024-02-28".to_string());
+
+    assert_eq!(export_ledger_db(&db_path, &file, opts).unwrap(), 2);
+    let value: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(&file).unwrap()).unwrap();
+    let txs = value["transactions"].as_array().unwrap();
+    assert_eq!(txs[0]["date"], "2024-01-15");
+    assert_eq!(txs[0]["account"], "Broker");
+    assert_eq!(txs[0]["shares"], 4.0);
+    assert_eq!(txs[0]["amount"], -401.0);
+    // JSON keeps the raw text, there is no spreadsheet to protect
+    assert_eq!(txs[1]["payee"], "=HYPERLINK(\"x\")");
+    assert_eq!(txs[1]["ticker"], serde_json::Value::Null);
+    assert_eq!(value["accounts"].as_array().unwrap().len(), 2);
+}
+
+#[test]
+fn test_xlsx_export_writes_typed_cells_on_every_sheet() {
+    let (dir, db_path, _, _) = sample_ledger();
+    let file = dir.path().join("ledger.xlsx");
+    let mut opts = options(LedgerFormat::Xlsx);
+    opts.date_to = Some("2024-02-15".to_string());
+    assert_eq!(export_ledger_db(&db_path, &file, opts).unwrap(), 3);
+
+    let mut workbook: Xlsx<_> = open_workbook(&file).unwrap();
+    assert_eq!(
+        workbook.sheet_names(),
+        vec![
+            "Transactions",
+            "Accounts",
+            "Holdings",
+            "Rules",
+            "Exchange Rates",
+            "Daily Prices"
+        ]
+    );
+
+    let txs = workbook.worksheet_range("Transactions").unwrap();
+    assert_eq!(txs.get((0, 0)), Some(&Data::String("Date".to_string())));
+    assert!(matches!(txs.get((1, 0)), Some(Data::DateTime(_))));
+    assert_eq!(txs.get((1, 4)), Some(&Data::Float(-12.5)));
+    assert_eq!(
+        txs.get((3, 2)),
+        Some(&Data::String("'=HYPERLINK(\"x\")".to_string()))
+    );
+
+    // Valued at the last price on or before the end of the range
+    let holdings = workbook.worksheet_range("Holdings").unwrap();
+    assert_eq!(
+        holdings.get((1, 1)),
+        Some(&Data::String("AAPL".to_string()))
+    );
+    assert_eq!(holdings.get((1, 3)), Some(&Data::Float(110.0)));
+    assert_eq!(holdings.get((1, 5)), Some(&Data::Float(440.0)));
+
+    let rules = workbook.worksheet_range("Rules").unwrap();
+    assert_eq!(
+        rules.get((0, 2)),
+        Some(&Data::String("Conditions".to_string()))
+    );
+    assert_eq!(
+        rules.get((1, 2)),
+        Some(&Data::String("payee equals @shop".to_string()))
+    );
+    let rates = workbook.worksheet_range("Exchange Rates").unwrap();
+    assert_eq!(rates.get((1, 1)), Some(&Data::Float(2000.0)));
+    let prices = workbook.worksheet_range("Daily Prices").unwrap();
+    assert_eq!(prices.height(), 2);
+}
+
+#[test]
+fn test_xlsx_rules_sheet_lists_every_condition_and_action() {
+    use crate::rules::{MatchJoin, MatchOperator, RuleAction, RuleCondition};
+    let (dir, db_path, _, _) = sample_ledger();
+    let rule_id = add_rule(&db_path, 5, "Costa", ("category", "Coffee"));
+    crate::rules::set_rule_conditions_db(
+        &db_path,
+        rule_id,
+        MatchJoin::Or,
+        vec![
+            RuleCondition {
+                field: "payee".to_string(),
+                operator: MatchOperator::Contains,
+                value: "Costa".to_string(),
+                value_to: None,
+                case_sensitive: false,
+            },
+            RuleCondition {
+                field: "amount".to_string(),
+                operator: MatchOperator::AmountBetween,
+                value: "-10".to_string(),
+                value_to: Some("0".to_string()),
+                case_sensitive: false,
+            },
+        ],
+    )
+    .unwrap();
+    crate::rules::set_rule_actions_db(
+        &db_path,
+        rule_id,
+        vec![
+            RuleAction::SetField {
+                field: "category".to_string(),
+                value: "Coffee".to_string(),
+            },
+            RuleAction::AddTag {
+                tag: "treats".to_string(),
+            },
+        ],
+    )
+    .unwrap();
+
+    let file = dir.path().join("ledger.xlsx");
+    export_ledger_db(&db_path, &file, options(LedgerFormat::Xlsx)).unwrap();
+    let mut workbook: Xlsx<_> = open_workbook(&file).unwrap();
+    let rules = workbook.worksheet_range("Rules").unwrap();
+    let row: Vec<String> = (0..4)
+        .map(|col| rules.get((1, col)).unwrap().to_string())
+        .collect();
+    assert_eq!(
+        row,
+        vec![
+            "5",
+            "or",
+            "payee contains Costa\namount amountBetween -10 to 0",
+            "category: Coffee\naddTag: treats"
+        ]
+    );
+}
+
+#[test]
+fn test_invalid_csv_separators_are_rejected() {
+    let (dir, db_path, _, _) = sample_ledger();
+    let file = dir.path().join("ledger.csv");
+    let mut opts = options(LedgerFormat::Csv);
+    opts.delimiter = Some(",".to_string());
+    opts.decimal_separator = Some(",".to_string());
+    assert!(export_ledger_db(&db_path, &file, opts.clone()).is_err());
+
+    opts.delimiter = Some("||".to_string());
+    opts.decimal_separator = None;
+    assert!(export_ledger_db(&db_path, &file, opts).is_err());
+}
+
+#[test]
+fn test_sanitize_cell_prefixes_formula_triggers() {
+    for text in ["=1+1", "+1", "-1", "@SUM(A1)"] {
+        assert_eq!(sanitize_cell(text), format!("'{}", text));
+    }
+    assert_eq!(sanitize_cell("Bakery"), "Bakery");
+    assert_eq!(sanitize_cell(""), "");
+}
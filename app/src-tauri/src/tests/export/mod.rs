@@ -0,0 +1,5 @@
+pub use super::common;
+
+pub mod journal_export;
+pub mod ledger_export;
+pub mod snapshot_export;
@@ -0,0 +1,313 @@
+use crate::export::snapshot::{
+    export_snapshot_db, import_snapshot_db, read_snapshot, SnapshotMode, SNAPSHOT_VERSION,
+};
+use crate::tests::common::full_db;
+use rusqlite::Connection;
+use std::path::PathBuf;
+
+fn transfer(account_id: i32, payee: &str, amount: f64) -> crate::CreateTransactionArgs {
+    crate::CreateTransactionArgs {
+        account_id,
+        date: "2024-01-10".to_string(),
+        payee: payee.to_string(),
+        notes: None,
+        category: Some("Transfer".to_string()),
+        amount,
+        ticker: None,
+        shares: None,
+        price_per_share: None,
+        fee: None,
+        currency: None,
+    }
+}
+
+// Two accounts with a linked transfer, a rule, a payee rewrite and a couple of prices
+fn sample_ledger(db_path: &PathBuf) {
+    let checking = crate::create_account_db(db_path, "Checking".to_string(), 100.0, None).unwrap();
+    crate::create_account_db(db_path, "Savings".to_string(), 0.0, Some("EUR".to_string())).unwrap();
+    crate::create_transaction_db(db_path, transfer(checking.id, "Savings", -40.0)).unwrap();
+
+    let conn = Connection::open(db_path).unwrap();
+    conn.execute(
+        "INSERT INTO rules (priority, match_field, match_pattern, action_field, action_value) VALUES (2, 'payee', 'Grocer', 'category', 'Food')",
+        [],
+    )
+    .unwrap();
+    conn.execute(
+        "INSERT INTO payee_rewrites (position, pattern, replacement) VALUES (0, 'AMZN MKTP', 'Amazon')",
+        [],
+    )
+    .unwrap();
+    conn.execute(
+        "INSERT INTO custom_exchange_rates (currency, rate) VALUES ('XAU', 2100.5)",
+        [],
+    )
+    .unwrap();
+    conn.execute(
+        "INSERT INTO daily_stock_prices (ticker, date, price) VALUES ('VT', '2024-01-02', 101.25)",
+        [],
+    )
+    .unwrap();
+}
+
+fn linked_pairs(db_path: &PathBuf) -> Vec<(i32, i32)> {
+    let conn = Connection::open(db_path).unwrap();
+    let mut stmt = conn
+        .prepare(
+            "SELECT id, linked_tx_id FROM transactions WHERE linked_tx_id IS NOT NULL ORDER BY id",
+        )
+        .unwrap();
+    stmt.query_map([], |r| Ok((r.get(0)?, r.get(1)?)))
+        .unwrap()
+        .map(|r| r.unwrap())
+        .collect()
+}
+
+#[test]
+fn test_snapshot_holds_every_table() {
+    let (dir, db_path) = full_db();
+    sample_ledger(&db_path);
+
+    let file = dir.path().join("snapshot.json");
+    let report = export_snapshot_db(&db_path, &file).unwrap();
+    assert_eq!(report.accounts, 2);
+    assert_eq!(report.transactions, 3);
+    assert_eq!(report.rules, 1);
+    assert_eq!(report.exchange_rates, 1);
+    assert_eq!(report.daily_prices, 1);
+
+    let value: serde_json::Value =
+        serde_json::from_str(&std::fs::read_to_string(&file).unwrap()).unwrap();
+    assert_eq!(value["version"], SNAPSHOT_VERSION);
+    let links: Vec<&serde_json::Value> = value["transactions"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|t| &t["linked_tx_id"])
+        .collect();
+    assert_eq!(links.iter().filter(|l| !l.is_null()).count(), 2);
+}
+
+#[test]
+fn test_merge_remaps_ids_and_transfer_links() {
+    let (dir, source) = full_db();
+    sample_ledger(&source);
+    let file = dir.path().join("snapshot.json");
+    export_snapshot_db(&source, &file).unwrap();
+
+    let (_target_dir, target) = full_db();
+    let existing = crate::create_account_db(&target, "Brokerage".to_string(), 50.0, None).unwrap();
+    crate::create_account_db(
+        &target,
+        "Savings".to_string(),
+        10.0,
+        Some("EUR".to_string()),
+    )
+    .unwrap();
+
+    let report = import_snapshot_db(&target, &file, SnapshotMode::Merge).unwrap();
+    assert_eq!(report.accounts, 1);
+    assert_eq!(report.transactions, 3);
+
+    let accounts = crate::get_accounts_db(&target).unwrap();
+    assert_eq!(accounts.len(), 3);
+    let balance = |name: &str| accounts.iter().find(|a| a.name == name).unwrap().balance;
+    assert_eq!(balance("Brokerage"), 50.0);
+    assert_eq!(balance("Savings"), 50.0);
+    assert_eq!(balance("Checking"), 60.0);
+
+    // The pair points at each other under the new ids
+    let pairs = linked_pairs(&target);
+    assert_eq!(pairs.len(), 2);
+    assert_eq!(pairs[0], (pairs[1].1, pairs[1].0));
+    assert!(pairs.iter().all(|(id, _)| *id > existing.id));
+}
+
+#[test]
+fn test_merging_twice_does_not_duplicate_rules() {
+    let (dir, source) = full_db();
+    sample_ledger(&source);
+    let file = dir.path().join("snapshot.json");
+    export_snapshot_db(&source, &file).unwrap();
+
+    let (_target_dir, target) = full_db();
+    import_snapshot_db(&target, &file, SnapshotMode::Merge).unwrap();
+    let second = import_snapshot_db(&target, &file, SnapshotMode::Merge).unwrap();
+    assert_eq!(second.rules, 0);
+
+    let conn = Connection::open(&target).unwrap();
+    let rules: i64 = conn
+        .query_row("SELECT COUNT(*) FROM rules", [], |r| r.get(0))
+        .unwrap();
+    assert_eq!(rules, 1);
+    let rewrites: i64 = conn
+        .query_row("SELECT COUNT(*) FROM payee_rewrites", [], |r| r.get(0))
+        .unwrap();
+    assert_eq!(rewrites, 1);
+    let prices: i64 = conn
+        .query_row("SELECT COUNT(*) FROM daily_stock_prices", [], |r| r.get(0))
+        .unwrap();
+    assert_eq!(prices, 1);
+}
+
+#[test]
+fn test_merge_keeps_the_ledgers_prices_and_matches_names_ignoring_case() {
+    let (dir, source) = full_db();
+    sample_ledger(&source);
+    let conn = Connection::open(&source).unwrap();
+    conn.execute(
+        "INSERT INTO stock_prices (ticker, price, last_updated) VALUES ('VT', 102.0, '2024-01-03T00:00:00Z'), ('BND', 70.0, '2024-01-01T00:00:00Z')",
+        [],
+    )
+    .unwrap();
+    let file = dir.path().join("snapshot.json");
+    export_snapshot_db(&source, &file).unwrap();
+
+    let (_target_dir, target) = full_db();
+    crate::create_account_db(&target, "SAVINGS".to_string(), 10.0, None).unwrap();
+    let conn = Connection::open(&target).unwrap();
+    conn.execute_batch(
+        "INSERT INTO custom_exchange_rates (currency, rate) VALUES ('XAU', 2000.0);
+         INSERT INTO daily_stock_prices (ticker, date, price) VALUES ('VT', '2024-01-02', 99.0);
+         INSERT INTO stock_prices (ticker, price, last_updated) VALUES ('VT', 100.0, '2024-01-01T00:00:00Z'), ('BND', 72.0, '2024-01-05T00:00:00Z');",
+    )
+    .unwrap();
+    import_snapshot_db(&target, &file, SnapshotMode::Merge).unwrap();
+
+    let names: Vec<String> = crate::get_accounts_db(&target)
+        .unwrap()
+        .into_iter()
+        .map(|a| a.name)
+        .collect();
+    assert_eq!(names.len(), 2, "{:?}", names);
+    let price = |sql: &str| -> f64 { conn.query_row(sql, [], |r| r.get(0)).unwrap() };
+    assert_eq!(
+        price("SELECT rate FROM custom_exchange_rates WHERE currency = 'XAU'"),
+        2000.0
+    );
+    assert_eq!(
+        price("SELECT price FROM daily_stock_prices WHERE ticker = 'VT'"),
+        99.0
+    );
+    // The newer of the two current prices wins
+    assert_eq!(
+        price("SELECT price FROM stock_prices WHERE ticker = 'VT'"),
+        102.0
+    );
+    assert_eq!(
+        price("SELECT price FROM stock_prices WHERE ticker = 'BND'"),
+        72.0
+    );
+}
+
+#[test]
+fn test_merge_keeps_rules_that_differ_beyond_the_first_condition() {
+    use crate::rules::{write_conditions, MatchOperator, RuleCondition};
+    let condition = |field: &str, value: &str| RuleCondition {
+        field: field.to_string(),
+        operator: MatchOperator::Contains,
+        value: value.to_string(),
+        value_to: None,
+        case_sensitive: false,
+    };
+    let (dir, source) = full_db();
+    sample_ledger(&source);
+    let conn = Connection::open(&source).unwrap();
+    let rule_id: i32 = conn
+        .query_row("SELECT id FROM rules", [], |r| r.get(0))
+        .unwrap();
+    write_conditions(
+        &conn,
+        rule_id,
+        &[condition("payee", "Grocer"), condition("notes", "weekly")],
+    )
+    .unwrap();
+    let file = dir.path().join("snapshot.json");
+    export_snapshot_db(&source, &file).unwrap();
+
+    let (_target_dir, target) = full_db();
+    let conn = Connection::open(&target).unwrap();
+    conn.execute(
+        "INSERT INTO rules (priority, match_field, match_pattern, action_field, action_value) VALUES (2, 'payee', 'Grocer', 'category', 'Food')",
+        [],
+    )
+    .unwrap();
+    write_conditions(&conn, 1, &[condition("payee", "Grocer")]).unwrap();
+
+    let report = import_snapshot_db(&target, &file, SnapshotMode::Merge).unwrap();
+    assert_eq!(report.rules, 1);
+    let again = import_snapshot_db(&target, &file, SnapshotMode::Merge).unwrap();
+    assert_eq!(again.rules, 0);
+}
+
+#[test]
+fn test_rejects_links_to_transactions_outside_the_snapshot() {
+    let (dir, db_path) = full_db();
+    sample_ledger(&db_path);
+    let conn = Connection::open(&db_path).unwrap();
+    let snapshot = read_snapshot(&conn).unwrap();
+    let file = dir.path().join("snapshot.json");
+
+    let mut dangling = snapshot.clone();
+    dangling.transactions[0].split_id = Some(999);
+    std::fs::write(&file, serde_json::to_string(&dangling).unwrap()).unwrap();
+    let err = import_snapshot_db(&db_path, &file, SnapshotMode::Merge).unwrap_err();
+    assert!(err.contains("missing split transaction 999"), "{}", err);
+
+    let mut dangling = snapshot.clone();
+    dangling
+        .lot_selections
+        .push(crate::export::snapshot::SnapshotLotSelection {
+            sale_id: snapshot.transactions[0].id,
+            lot_id: 999,
+            shares: 1.0,
+        });
+    std::fs::write(&file, serde_json::to_string(&dangling).unwrap()).unwrap();
+    let err = import_snapshot_db(&db_path, &file, SnapshotMode::Replace).unwrap_err();
+    assert!(err.contains("missing transaction 999"), "{}", err);
+}
+
+#[test]
+fn test_rejects_newer_versions_and_missing_accounts() {
+    let (dir, db_path) = full_db();
+    sample_ledger(&db_path);
+    let conn = Connection::open(&db_path).unwrap();
+    let snapshot = read_snapshot(&conn).unwrap();
+    let file = dir.path().join("snapshot.json");
+
+    let mut newer = snapshot.clone();
+    newer.version = SNAPSHOT_VERSION + 1;
+    std::fs::write(&file, serde_json::to_string(&newer).unwrap()).unwrap();
+    let err = import_snapshot_db(&db_path, &file, SnapshotMode::Replace).unwrap_err();
+    assert!(err.contains("Unsupported snapshot version"), "{}", err);
+
+    let mut orphaned = snapshot.clone();
+    orphaned.accounts.retain(|a| a.name != "Savings");
+    std::fs::write(&file, serde_json::to_string(&orphaned).unwrap()).unwrap();
+    let err = import_snapshot_db(&db_path, &file, SnapshotMode::Replace).unwrap_err();
+    assert!(err.contains("refers to missing account"), "{}", err);
+
+    // Nothing was written by the failed imports
+    assert_eq!(
+        crate::get_all_transactions_db(&db_path).unwrap().len(),
+        snapshot.transactions.len()
+    );
+}
+
+#[test]
+fn test_older_snapshots_without_optional_tables_load() {
+    let (dir, db_path) = full_db();
+    let file = dir.path().join("snapshot.json");
+    std::fs::write(
+        &file,
+        r#"{"version": 1, "exported_at": "2024-01-01T00:00:00Z",
+            "accounts": [{"id": 7, "name": "Cash", "balance": 5.0}],
+            "transactions": [{"id": 3, "account_id": 7, "date": "2024-01-01", "payee": "Shop", "amount": 5.0}]}"#,
+    )
+    .unwrap();
+
+    let report = import_snapshot_db(&db_path, &file, SnapshotMode::Replace).unwrap();
+    assert_eq!(report.transactions, 1);
+    let txs = crate::get_transactions_db(&db_path, 7).unwrap();
+    assert_eq!(txs[0].id, 3);
+}
@@ -0,0 +1,212 @@
+use crate::holdings::{
+    get_cost_basis_method_db, get_holdings_db, select_lots_db, set_cost_basis_method_db,
+    CostBasisMethod, LotPick,
+};
+use crate::tests::common::{add_trade, full_db};
+use rusqlite::Connection;
+use std::path::PathBuf;
+
+// The full schema, as holdings read quotes from the price tables
+// Two buys of VT, then a sale of 5 shares
+fn ledger(db_path: &PathBuf, method: CostBasisMethod) -> (i32, [i32; 3]) {
+    let account = crate::create_account_db(db_path, "Broker".to_string(), 0.0, None).unwrap();
+    set_cost_basis_method_db(db_path, account.id, method).unwrap();
+    let first = add_trade(db_path, account.id, "2024-01-01", "VT", 10.0, 100.0, 10.0);
+    let second = add_trade(db_path, account.id, "2024-02-01", "VT", 10.0, 150.0, 0.0);
+    let sale = add_trade(db_path, account.id, "2024-03-01", "VT", -5.0, 160.0, 2.0);
+    (account.id, [first, second, sale])
+}
+
+fn lots(db_path: &PathBuf) -> Vec<(i32, f64, f64)> {
+    get_holdings_db(db_path, None).unwrap()[0]
+        .lots
+        .iter()
+        .map(|lot| (lot.transaction_id, lot.shares, lot.cost_basis))
+        .collect()
+}
+
+#[test]
+fn test_fifo_and_lifo_close_opposite_lots() {
+    let (_dir, db_path) = full_db();
+    let (account_id, [first, second, _]) = ledger(&db_path, CostBasisMethod::Fifo);
+    assert_eq!(
+        lots(&db_path),
+        vec![(first, 5.0, 505.0), (second, 10.0, 1500.0)]
+    );
+
+    set_cost_basis_method_db(&db_path, account_id, CostBasisMethod::Lifo).unwrap();
+    assert_eq!(
+        get_cost_basis_method_db(&db_path, account_id).unwrap(),
+        CostBasisMethod::Lifo
+    );
+    assert_eq!(
+        lots(&db_path),
+        vec![(first, 10.0, 1010.0), (second, 5.0, 750.0)]
+    );
+    let holding = &get_holdings_db(&db_path, Some(account_id)).unwrap()[0];
+    assert_eq!(holding.shares, 15.0);
+    assert_eq!(holding.cost_basis, 1760.0);
+    assert!(set_cost_basis_method_db(&db_path, 99, CostBasisMethod::Fifo).is_err());
+}
+
+#[test]
+fn test_average_cost_pools_shares_at_each_sale() {
+    let (_dir, db_path) = full_db();
+    let (account_id, _) = ledger(&db_path, CostBasisMethod::Average);
+    let holding = &get_holdings_db(&db_path, None).unwrap()[0];
+    assert!((holding.cost_basis - 15.0 * 125.5).abs() < 1e-9);
+    assert!(holding.lots.iter().all(|l| l.cost_per_share == 125.5));
+
+    // A later buy keeps its own price until the next sale
+    let third = add_trade(&db_path, account_id, "2024-04-01", "VT", 5.0, 200.0, 0.0);
+    let holding = &get_holdings_db(&db_path, None).unwrap()[0];
+    assert_eq!(holding.shares, 20.0);
+    assert!((holding.average_cost - (1882.5 + 1000.0) / 20.0).abs() < 1e-9);
+    assert_eq!(holding.lots[2].transaction_id, third);
+    assert_eq!(holding.lots[2].cost_per_share, 200.0);
+}
+
+#[test]
+fn test_specific_lots_are_closed_first() {
+    let (_dir, db_path) = full_db();
+    let (account_id, [first, second, sale]) = ledger(&db_path, CostBasisMethod::SpecificLot);
+    // Nothing picked yet, so the oldest lot closes
+    assert_eq!(lots(&db_path)[0], (first, 5.0, 505.0));
+
+    select_lots_db(
+        &db_path,
+        sale,
+        vec![LotPick {
+            lot_id: second,
+            shares: 3.0,
+        }],
+    )
+    .unwrap();
+    assert_eq!(
+        lots(&db_path),
+        vec![(first, 8.0, 808.0), (second, 7.0, 1050.0)]
+    );
+
+    let pick = |lot_id, shares| vec![LotPick { lot_id, shares }];
+    assert!(select_lots_db(&db_path, sale, pick(second, 6.0)).is_err());
+    assert!(select_lots_db(&db_path, first, pick(second, 1.0)).is_err());
+    assert!(select_lots_db(&db_path, sale, pick(sale, 1.0)).is_err());
+    let other = add_trade(&db_path, account_id, "2024-01-05", "VXUS", 4.0, 50.0, 0.0);
+    assert!(select_lots_db(&db_path, sale, pick(other, 1.0)).is_err());
+
+    // Deleting the sale drops its picks along with it
+    crate::delete_transaction_db(&db_path, sale).unwrap();
+    let conn = Connection::open(&db_path).unwrap();
+    let picks: i64 = conn
+        .query_row("SELECT COUNT(*) FROM lot_selections", [], |r| r.get(0))
+        .unwrap();
+    assert_eq!(picks, 0);
+}
+
+#[test]
+fn test_holdings_are_valued_per_account_at_latest_price() {
+    let (_dir, db_path) = full_db();
+    let (account_id, _) = ledger(&db_path, CostBasisMethod::Fifo);
+    let other = crate::create_account_db(&db_path, "IRA".to_string(), 0.0, None).unwrap();
+    add_trade(&db_path, other.id, "2024-01-10", "VT", 2.0, 120.0, 0.0);
+    add_trade(&db_path, other.id, "2024-01-11", "BND", 3.0, 70.0, 0.0);
+    add_trade(&db_path, other.id, "2024-02-11", "BND", -3.0, 72.0, 0.0);
+
+    let unpriced = get_holdings_db(&db_path, Some(other.id)).unwrap();
+    assert_eq!(unpriced.len(), 1);
+    assert_eq!(unpriced[0].price, None);
+    assert_eq!(unpriced[0].unrealized_gain, None);
+
+    let conn = Connection::open(&db_path).unwrap();
+    conn.execute(
+        "INSERT INTO daily_stock_prices (ticker, date, price) VALUES ('VT', '2024-03-01', 150.0)",
+        [],
+    )
+    .unwrap();
+    conn.execute(
+        "INSERT INTO stock_prices (ticker, price, last_updated) VALUES ('VT', 170.0, '2024-03-02')",
+        [],
+    )
+    .unwrap();
+
+    let holdings = get_holdings_db(&db_path, None).unwrap();
+    assert_eq!(holdings.len(), 2);
+    let broker = holdings
+        .iter()
+        .find(|h| h.account_id == account_id)
+        .unwrap();
+    assert_eq!(broker.price, Some(170.0));
+    assert_eq!(broker.market_value, Some(15.0 * 170.0));
+    assert_eq!(broker.unrealized_gain, Some(15.0 * 170.0 - 2005.0));
+    assert_eq!(broker.lots[0].unrealized_gain, Some(5.0 * 170.0 - 505.0));
+    let ira = holdings.iter().find(|h| h.account_id == other.id).unwrap();
+    assert_eq!(ira.cost_basis, 240.0);
+    assert_eq!(ira.unrealized_gain_percent, Some(100.0 / 240.0 * 100.0));
+}
+
+#[test]
+fn test_snapshot_keeps_method_and_picked_lots() {
+    let (dir, db_path) = full_db();
+    let (_, [_, second, sale]) = ledger(&db_path, CostBasisMethod::SpecificLot);
+    select_lots_db(
+        &db_path,
+        sale,
+        vec![LotPick {
+            lot_id: second,
+            shares: 5.0,
+        }],
+    )
+    .unwrap();
+    let file = dir.path().join("snapshot.json");
+    crate::export::snapshot::export_snapshot_db(&db_path, &file).unwrap();
+
+    let target = dir.path().join("target.db");
+    crate::init_db_at_path(&target).unwrap();
+    crate::create_account_db(&target, "Cash".to_string(), 0.0, None).unwrap();
+    crate::export::snapshot::import_snapshot_db(
+        &target,
+        &file,
+        crate::export::snapshot::SnapshotMode::Merge,
+    )
+    .unwrap();
+    let restored = &get_holdings_db(&target, None).unwrap()[0];
+    assert_eq!(restored.method, CostBasisMethod::SpecificLot);
+    let shares: Vec<f64> = restored.lots.iter().map(|l| l.shares).collect();
+    assert_eq!(shares, vec![10.0, 5.0]);
+}
+
+#[test]
+fn test_shares_sold_beyond_the_lots_are_reported() {
+    let (_dir, db_path) = full_db();
+    let (account_id, _) = ledger(&db_path, CostBasisMethod::Fifo);
+    add_trade(&db_path, account_id, "2024-04-01", "VT", -18.0, 170.0, 0.0);
+
+    let holdings = get_holdings_db(&db_path, None).unwrap();
+    assert_eq!(holdings.len(), 1);
+    assert!(holdings[0].lots.is_empty());
+    assert_eq!(holdings[0].shares, 0.0);
+    assert_eq!(holdings[0].oversold_shares, 3.0);
+}
+
+#[test]
+fn test_deleting_the_account_removes_its_picked_lots() {
+    let (_dir, db_path) = full_db();
+    let (account_id, [first, _, sale]) = ledger(&db_path, CostBasisMethod::SpecificLot);
+    select_lots_db(
+        &db_path,
+        sale,
+        vec![LotPick {
+            lot_id: first,
+            shares: 5.0,
+        }],
+    )
+    .unwrap();
+
+    crate::delete_account_db(&db_path, account_id).unwrap();
+
+    let conn = Connection::open(&db_path).unwrap();
+    let picks: i64 = conn
+        .query_row("SELECT COUNT(*) FROM lot_selections", [], |r| r.get(0))
+        .unwrap();
+    assert_eq!(picks, 0);
+}
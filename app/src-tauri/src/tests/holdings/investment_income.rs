@@ -0,0 +1,151 @@
+use crate::holdings::get_holdings_db;
+use crate::holdings::income::{get_investment_income_db, IncomeKind, IncomeQuery};
+use crate::tests::common::{add_trade, full_db};
+use rusqlite::Connection;
+
+fn income(
+    account_id: i32,
+    date: &str,
+    gross_amount: f64,
+    withholding_tax: Option<f64>,
+) -> crate::CreateInvestmentIncomeArgs {
+    crate::CreateInvestmentIncomeArgs {
+        account_id,
+        date: date.to_string(),
+        ticker: "VT".to_string(),
+        kind: IncomeKind::Dividend,
+        gross_amount,
+        withholding_tax,
+        reinvest_shares: None,
+        reinvest_price: None,
+        currency: None,
+    }
+}
+
+fn query(date_from: Option<&str>, date_to: Option<&str>, as_of: &str) -> IncomeQuery {
+    IncomeQuery {
+        date_from: date_from.map(str::to_string),
+        date_to: date_to.map(str::to_string),
+        account_id: None,
+        as_of: Some(as_of.to_string()),
+    }
+}
+
+#[test]
+fn test_dividend_with_withholding_and_reinvestment() {
+    let (_dir, db_path) = full_db();
+    let account = crate::create_account_db(&db_path, "Broker".to_string(), 0.0, None).unwrap();
+    add_trade(&db_path, account.id, "2024-01-10", "VT", 10.0, 100.0, 0.0);
+
+    let created = crate::create_investment_income_db(
+        &db_path,
+        crate::CreateInvestmentIncomeArgs {
+            reinvest_shares: Some(0.2),
+            reinvest_price: Some(110.0),
+            ..income(account.id, "2024-03-15", 25.0, Some(3.75))
+        },
+    )
+    .unwrap();
+    assert_eq!(created.income.amount, 21.25);
+    assert_eq!(created.income.fee, Some(3.75));
+    assert_eq!(created.income.ticker.as_deref(), Some("VT"));
+    assert_eq!(created.income.category.as_deref(), Some("Dividends"));
+    let reinvestment = created.reinvestment.unwrap();
+    assert_eq!(reinvestment.shares, Some(0.2));
+
+    let balance = crate::get_accounts_db(&db_path).unwrap()[0].balance;
+    assert!((balance - (-1000.0 + 21.25 - 22.0)).abs() < 1e-9);
+    let holding = &get_holdings_db(&db_path, None).unwrap()[0];
+    assert_eq!(holding.lots.len(), 2);
+    assert_eq!(holding.lots[1].transaction_id, reinvestment.id);
+    assert!((holding.cost_basis - 1022.0).abs() < 1e-9);
+}
+
+#[test]
+fn test_invalid_income_is_rejected() {
+    let (_dir, db_path) = full_db();
+    let account = crate::create_account_db(&db_path, "Broker".to_string(), 0.0, None).unwrap();
+    let create = |args| crate::create_investment_income_db(&db_path, args);
+    assert!(create(income(account.id, "2024-03-15", 0.0, None)).is_err());
+    assert!(create(income(account.id, "2024-03-15", 10.0, Some(12.0))).is_err());
+    assert!(create(income(account.id, "15/03/2024", 10.0, None)).is_err());
+    assert!(create(income(account.id, "2024-02-30", 10.0, None)).is_err());
+    assert!(create(crate::CreateInvestmentIncomeArgs {
+        ticker: " ".to_string(),
+        ..income(account.id, "2024-03-15", 10.0, None)
+    })
+    .is_err());
+    assert!(create(crate::CreateInvestmentIncomeArgs {
+        reinvest_shares: Some(1.0),
+        ..income(account.id, "2024-03-15", 10.0, None)
+    })
+    .is_err());
+    assert!(crate::get_all_transactions_db(&db_path).unwrap().is_empty());
+}
+
+#[test]
+fn test_income_per_holding_with_trailing_yield_on_cost() {
+    let (_dir, db_path) = full_db();
+    let account = crate::create_account_db(&db_path, "Broker".to_string(), 0.0, None).unwrap();
+    add_trade(&db_path, account.id, "2023-01-10", "VT", 100.0, 50.0, 0.0);
+    for (date, gross, withholding) in [
+        ("2023-06-01", 40.0, Some(6.0)),
+        ("2024-03-01", 50.0, Some(7.5)),
+        ("2024-09-01", 60.0, None),
+    ] {
+        crate::create_investment_income_db(&db_path, income(account.id, date, gross, withholding))
+            .unwrap();
+    }
+    // Imported statements book the withholding on its own line
+    let conn = Connection::open(&db_path).unwrap();
+    for (category, amount) in [("Dividends", 20.0), ("Taxes", -3.0)] {
+        conn.execute(
+            "INSERT INTO transactions (account_id, date, payee, category, amount, ticker) VALUES (?1, '2024-06-01', 'VT', ?2, ?3, 'VT')",
+            rusqlite::params![account.id, category, amount],
+        )
+        .unwrap();
+    }
+    // Left out of the report instead of failing it
+    conn.execute(
+        "INSERT INTO transactions (account_id, date, payee, category, amount, ticker) VALUES (?1, '06/01/2024', 'VT', 'Dividends', 99.0, 'VT')",
+        [account.id],
+    )
+    .unwrap();
+
+    let year = &get_investment_income_db(
+        &db_path,
+        query(Some("2024-01-01"), Some("2024-12-31"), "2024-12-31"),
+    )
+    .unwrap()[0];
+    assert_eq!(year.gross, 130.0);
+    assert_eq!(year.withholding, 10.5);
+    assert_eq!(year.net, 119.5);
+    assert_eq!(year.payments, 3);
+    assert_eq!(year.last_paid.as_deref(), Some("2024-09-01"));
+    assert_eq!(year.trailing_12_months, 130.0);
+    assert_eq!(year.cost_basis, 5000.0);
+    assert!((year.yield_on_cost.unwrap() - 2.6).abs() < 1e-9);
+
+    let all = &get_investment_income_db(&db_path, query(None, None, "2024-04-30")).unwrap()[0];
+    assert_eq!(all.gross, 170.0);
+    assert_eq!(all.trailing_12_months, 90.0);
+}
+
+#[test]
+fn test_closed_position_has_no_yield() {
+    let (_dir, db_path) = full_db();
+    let account = crate::create_account_db(&db_path, "Broker".to_string(), 0.0, None).unwrap();
+    add_trade(&db_path, account.id, "2024-01-10", "VT", 10.0, 100.0, 0.0);
+    crate::create_investment_income_db(&db_path, income(account.id, "2024-02-01", 5.0, None))
+        .unwrap();
+    add_trade(&db_path, account.id, "2024-03-01", "VT", -10.0, 105.0, 0.0);
+
+    let holdings = get_investment_income_db(&db_path, query(None, None, "2024-06-30")).unwrap();
+    assert_eq!(holdings.len(), 1);
+    assert_eq!(holdings[0].shares, 0.0);
+    assert_eq!(holdings[0].trailing_12_months, 5.0);
+    assert_eq!(holdings[0].yield_on_cost, None);
+    assert!(
+        get_investment_income_db(&db_path, query(Some("2024-13-01"), None, "2024-06-30")).is_err()
+    );
+}
@@ -0,0 +1,3 @@
+pub mod cost_basis;
+pub mod investment_income;
+pub mod realized_gains;
@@ -0,0 +1,171 @@
+use crate::holdings::gains::{
+    export_realized_gains_db, get_realized_gains_db, HoldingTerm, RealizedGainsQuery,
+};
+use crate::tests::common::{add_trade, close, full_db};
+use rusqlite::Connection;
+use std::path::PathBuf;
+
+// A lot held over a year and one bought a month before they are both sold
+fn ledger(db_path: &PathBuf, name: &str, currency: Option<&str>, ticker: &str) -> i32 {
+    let account =
+        crate::create_account_db(db_path, name.to_string(), 0.0, currency.map(str::to_string))
+            .unwrap();
+    add_trade(db_path, account.id, "2023-01-02", ticker, 10.0, 100.0, 10.0);
+    add_trade(db_path, account.id, "2024-02-01", ticker, 10.0, 150.0, 0.0);
+    add_trade(db_path, account.id, "2024-03-01", ticker, -15.0, 160.0, 3.0);
+    account.id
+}
+
+#[test]
+fn test_sale_is_split_by_lot_and_holding_period() {
+    let (_dir, db_path) = full_db();
+    ledger(&db_path, "Broker", None, "VT");
+
+    let report = get_realized_gains_db(&db_path, RealizedGainsQuery::default()).unwrap();
+    assert_eq!(report.currency, "USD");
+    assert_eq!(report.gains.len(), 2);
+    let (long, short) = (&report.gains[0], &report.gains[1]);
+    assert_eq!(
+        (long.acquired.as_str(), long.disposed.as_str()),
+        ("2023-01-02", "2024-03-01")
+    );
+    assert_eq!(long.term, HoldingTerm::Long);
+    assert_eq!(long.holding_days, 424);
+    assert!(close(long.proceeds, 1600.0) && close(long.cost_basis, 1010.0));
+    assert!(close(long.fees, 2.0) && close(long.gain, 588.0));
+    assert_eq!(short.term, HoldingTerm::Short);
+    assert!(close(short.shares, 5.0) && close(short.gain, 800.0 - 1.0 - 750.0));
+
+    assert!(close(report.long_term_gain, 588.0));
+    assert!(close(report.short_term_gain, 49.0));
+    assert!(close(
+        report.total_gain,
+        report.proceeds - report.fees - report.cost_basis
+    ));
+}
+
+#[test]
+fn test_date_range_selects_sales_of_a_tax_year() {
+    let (_dir, db_path) = full_db();
+    let account_id = ledger(&db_path, "Broker", None, "VT");
+    add_trade(&db_path, account_id, "2025-04-10", "VT", -5.0, 170.0, 0.0);
+
+    let year = |from: &str, to: &str| {
+        get_realized_gains_db(
+            &db_path,
+            RealizedGainsQuery {
+                date_from: Some(from.to_string()),
+                date_to: Some(to.to_string()),
+                ..Default::default()
+            },
+        )
+        .unwrap()
+    };
+    assert_eq!(year("2024-01-01", "2024-12-31").gains.len(), 2);
+    // The later sale closes what is left of the second lot
+    let later = year("2025-01-01", "2025-12-31");
+    assert_eq!(later.gains.len(), 1);
+    assert!(close(later.gains[0].cost_basis, 750.0));
+    assert!(year("2023-01-01", "2023-12-31").gains.is_empty());
+
+    let err = get_realized_gains_db(
+        &db_path,
+        RealizedGainsQuery {
+            date_from: Some("01/01/2024".to_string()),
+            ..Default::default()
+        },
+    )
+    .unwrap_err();
+    assert!(err.contains("Invalid date"), "{}", err);
+}
+
+#[test]
+fn test_amounts_use_the_rate_of_each_trade_date() {
+    let (_dir, db_path) = full_db();
+    ledger(&db_path, "Depot", Some("EUR"), "VWCE");
+    let conn = Connection::open(&db_path).unwrap();
+    for (date, rate) in [
+        ("2023-01-01", 1.05),
+        ("2024-01-31", 1.08),
+        ("2024-02-29", 1.1),
+    ] {
+        conn.execute(
+            "INSERT INTO daily_stock_prices (ticker, date, price) VALUES ('EURUSD=X', ?1, ?2)",
+            rusqlite::params![date, rate],
+        )
+        .unwrap();
+    }
+
+    let report = get_realized_gains_db(&db_path, RealizedGainsQuery::default()).unwrap();
+    let long = &report.gains[0];
+    assert_eq!(long.trade_currency, "EUR");
+    assert_eq!((long.acquisition_rate, long.disposal_rate), (1.05, 1.1));
+    assert!(close(long.cost_basis, 1010.0 * 1.05));
+    assert!(close(long.proceeds, 1600.0 * 1.1));
+    assert_eq!(report.gains[1].acquisition_rate, 1.08);
+
+    let eur = get_realized_gains_db(
+        &db_path,
+        RealizedGainsQuery {
+            currency: Some("eur".to_string()),
+            ..Default::default()
+        },
+    )
+    .unwrap();
+    assert!(close(eur.total_gain, 588.0 + 49.0));
+
+    let err = get_realized_gains_db(
+        &db_path,
+        RealizedGainsQuery {
+            currency: Some("JPY".to_string()),
+            ..Default::default()
+        },
+    )
+    .unwrap_err();
+    assert!(err.contains("No EUR to JPY exchange rate"), "{}", err);
+}
+
+#[test]
+fn test_csv_export_sanitizes_text_cells() {
+    let (dir, db_path) = full_db();
+    ledger(&db_path, "@Broker", None, "=VT");
+    let file = dir.path().join("gains.csv");
+
+    let rows = export_realized_gains_db(&db_path, &file, RealizedGainsQuery::default()).unwrap();
+    assert_eq!(rows, 2);
+    let text = std::fs::read_to_string(&file).unwrap();
+    let lines: Vec<&str> = text.lines().collect();
+    assert_eq!(
+        lines[0],
+        "Account,Ticker,Shares,Acquired,Disposed,Term,Proceeds,Cost Basis,Fees,Gain,Currency"
+    );
+    assert_eq!(
+        lines[1],
+        "'@Broker,'=VT,10,2023-01-02,2024-03-01,Long-term,1600.00,1010.00,2.00,588.00,USD"
+    );
+}
+
+#[test]
+fn test_bad_dates_and_shares_without_lots_do_not_fail_the_report() {
+    let (_dir, db_path) = full_db();
+    let account_id = ledger(&db_path, "Broker", None, "VT");
+    add_trade(&db_path, account_id, "2024-04-01", "VT", -8.0, 170.0, 0.0);
+    add_trade(&db_path, account_id, "2024-05-01", "BND", 2.0, 70.0, 0.0);
+    add_trade(&db_path, account_id, "2024-05-02", "BND", -2.0, 72.0, 0.0);
+    let conn = Connection::open(&db_path).unwrap();
+    conn.execute(
+        "UPDATE transactions SET date = '02/05/2024' WHERE ticker = 'BND' AND shares < 0",
+        [],
+    )
+    .unwrap();
+
+    let report = get_realized_gains_db(&db_path, RealizedGainsQuery::default()).unwrap();
+    assert!(report.gains.iter().all(|g| g.ticker == "VT"));
+    assert_eq!(report.unmatched.len(), 1);
+    let unmatched = &report.unmatched[0];
+    assert_eq!(
+        (unmatched.ticker.as_str(), unmatched.disposed.as_str()),
+        ("VT", "2024-04-01")
+    );
+    assert!(close(unmatched.shares, 3.0));
+}
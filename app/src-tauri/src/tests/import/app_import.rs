@@ -0,0 +1,374 @@
+use super::common::setup_db;
+use crate::import::apps::{
+    actual::parse_actual, firefly::parse_firefly, import_app_db, mmex::parse_mmex,
+    ynab::parse_ynab, SourceApp, SourceDatabase,
+};
+use crate::rules::RuleSelection;
+use rusqlite::Connection;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+const YNAB: &str = include_str!("fixtures/ynab_register.csv");
+const FIREFLY: &str = include_str!("fixtures/firefly_export.csv");
+
+// The parts of Actual's schema the importer reads
+const ACTUAL_SCHEMA: &str = "
+    CREATE TABLE accounts (id TEXT PRIMARY KEY, name TEXT, offbudget INTEGER DEFAULT 0, closed INTEGER DEFAULT 0, sort_order REAL, tombstone INTEGER DEFAULT 0);
+    CREATE TABLE category_groups (id TEXT PRIMARY KEY, name TEXT, tombstone INTEGER DEFAULT 0);
+    CREATE TABLE categories (id TEXT PRIMARY KEY, name TEXT, cat_group TEXT, tombstone INTEGER DEFAULT 0);
+    CREATE TABLE category_mapping (id TEXT PRIMARY KEY, transferId TEXT);
+    CREATE TABLE payees (id TEXT PRIMARY KEY, name TEXT, transfer_acct TEXT, tombstone INTEGER DEFAULT 0);
+    CREATE TABLE payee_mapping (id TEXT PRIMARY KEY, targetId TEXT);
+    CREATE TABLE transactions (id TEXT PRIMARY KEY, isParent INTEGER DEFAULT 0, isChild INTEGER DEFAULT 0, acct TEXT, category TEXT, amount INTEGER, description TEXT, notes TEXT, date INTEGER, starting_balance_flag INTEGER DEFAULT 0, transferred_id TEXT, parent_id TEXT, sort_order REAL, tombstone INTEGER DEFAULT 0);
+
+    INSERT INTO accounts (id, name, sort_order, tombstone) VALUES ('a1', 'Checking', 1, 0), ('a2', 'Savings', 2, 0), ('a3', 'Closed card', 3, 1);
+    INSERT INTO category_groups (id, name) VALUES ('g1', 'Food');
+    INSERT INTO categories (id, name, cat_group, tombstone) VALUES ('c1', 'Groceries', 'g1', 0), ('c2', 'Dining', 'g1', 0), ('c3', 'Restaurants', 'g1', 1);
+    INSERT INTO category_mapping (id, transferId) VALUES ('c3', 'c2');
+    INSERT INTO payees (id, name, transfer_acct) VALUES ('p1', 'Grocer', NULL), ('p2', '', 'a2'), ('p3', '', 'a1'), ('p4', 'Starting Balance', NULL), ('p5', 'Grocer Inc', NULL);
+    INSERT INTO payee_mapping (id, targetId) VALUES ('p5', 'p1');
+    INSERT INTO transactions (id, acct, date, amount, description, category, notes, isParent, isChild, parent_id, transferred_id, starting_balance_flag, tombstone) VALUES
+        ('t1', 'a1', 20240101, 150000, 'p4', 'c1', NULL, 0, 0, NULL, NULL, 1, 0),
+        ('t2', 'a1', 20240105, -5000, 'p1', NULL, 'Weekly shop', 1, 0, NULL, NULL, 0, 0),
+        ('t3', 'a1', 20240105, -3000, 'p1', 'c1', 'Food', 0, 1, 't2', NULL, 0, 0),
+        ('t4', 'a1', 20240105, -2000, 'p1', 'c3', NULL, 0, 1, 't2', NULL, 0, 0),
+        ('t5', 'a1', 20240110, -10000, 'p2', NULL, NULL, 0, 0, NULL, 't6', 0, 0),
+        ('t6', 'a2', 20240110, 10000, 'p3', NULL, NULL, 0, 0, NULL, 't5', 0, 0),
+        ('t7', 'a3', 20240111, -100, 'p1', 'c1', NULL, 0, 0, NULL, NULL, 0, 0),
+        ('t8', 'a1', 20240112, -700, 'p5', 'c2', NULL, 0, 0, NULL, NULL, 0, 0),
+        ('t9', 'a1', 20240113, -900, 'p1', 'c2', NULL, 0, 0, NULL, NULL, 0, 1);
+";
+
+// A Money Manager Ex 1.6 database: nested categories, a split, a transfer between currencies
+const MMEX_SCHEMA: &str = "
+    CREATE TABLE CURRENCYFORMATS_V1 (CURRENCYID INTEGER PRIMARY KEY, CURRENCYNAME TEXT, CURRENCY_SYMBOL TEXT);
+    CREATE TABLE ACCOUNTLIST_V1 (ACCOUNTID INTEGER PRIMARY KEY, ACCOUNTNAME TEXT, ACCOUNTTYPE TEXT, STATUS TEXT, INITIALBAL NUMERIC, INITIALDATE TEXT, CURRENCYID INTEGER);
+    CREATE TABLE PAYEE_V1 (PAYEEID INTEGER PRIMARY KEY, PAYEENAME TEXT);
+    CREATE TABLE CATEGORY_V1 (CATEGID INTEGER PRIMARY KEY, CATEGNAME TEXT, ACTIVE INTEGER, PARENTID INTEGER);
+    CREATE TABLE CHECKINGACCOUNT_V1 (TRANSID INTEGER PRIMARY KEY, ACCOUNTID INTEGER, TOACCOUNTID INTEGER, PAYEEID INTEGER, TRANSCODE TEXT, TRANSAMOUNT NUMERIC, STATUS TEXT, TRANSACTIONNUMBER TEXT, NOTES TEXT, CATEGID INTEGER, TRANSDATE TEXT, TOTRANSAMOUNT NUMERIC, DELETEDTIME TEXT);
+    CREATE TABLE SPLITTRANSACTIONS_V1 (SPLITTRANSID INTEGER PRIMARY KEY, TRANSID INTEGER, CATEGID INTEGER, SPLITTRANSAMOUNT NUMERIC, NOTES TEXT);
+    CREATE TABLE STOCK_V1 (STOCKID INTEGER PRIMARY KEY, HELDAT INTEGER, SYMBOL TEXT, NUMSHARES NUMERIC);
+
+    INSERT INTO CURRENCYFORMATS_V1 VALUES (1, 'Euro', 'EUR'), (2, 'US dollar', 'USD');
+    INSERT INTO ACCOUNTLIST_V1 VALUES (1, 'Checking', 'Checking', 'Open', 500, '2024-01-01', 1), (2, 'Dollars', 'Checking', 'Open', 0, '2024-01-01', 2), (3, 'Broker', 'Investment', 'Open', 0, '2024-01-01', 1);
+    INSERT INTO PAYEE_V1 VALUES (1, 'Grocer'), (2, 'Employer');
+    INSERT INTO CATEGORY_V1 VALUES (1, 'Food', 1, -1), (2, 'Groceries', 1, 1), (3, 'Income', 1, -1);
+    INSERT INTO CHECKINGACCOUNT_V1 VALUES
+        (1, 1, -1, 1, 'Withdrawal', 25.5, 'R', '', '', 2, '2024-01-03', 25.5, ''),
+        (2, 1, -1, 1, 'Withdrawal', 60, '', '', 'weekly', -1, '2024-01-04', 60, ''),
+        (3, 1, 2, -1, 'Transfer', 100, '', '', '', -1, '2024-01-05', 108, ''),
+        (4, 1, -1, 2, 'Deposit', 2000, '', '', '', 3, '2024-01-25', 2000, ''),
+        (5, 1, -1, 1, 'Withdrawal', 10, 'V', '', '', 2, '2024-01-26', 10, ''),
+        (6, 1, -1, 1, 'Withdrawal', 5, '', '', '', 2, '2024-01-27', 5, '2024-02-01T10:00:00');
+    INSERT INTO SPLITTRANSACTIONS_V1 VALUES (1, 2, 2, 40, 'food'), (2, 2, 1, 20, NULL);
+    INSERT INTO STOCK_V1 VALUES (1, 3, 'VT', 10);
+";
+
+fn source_db(dir: &Path, name: &str, schema: &str) -> PathBuf {
+    let path = dir.join(name);
+    Connection::open(&path)
+        .unwrap()
+        .execute_batch(schema)
+        .unwrap();
+    path
+}
+
+fn balances(db_path: &PathBuf) -> Vec<(String, f64)> {
+    let mut accounts: Vec<(String, f64)> = crate::get_accounts_db(db_path)
+        .unwrap()
+        .into_iter()
+        .map(|a| (a.name, (a.balance * 100.0).round() / 100.0))
+        .collect();
+    accounts.sort_by(|a, b| a.0.cmp(&b.0));
+    accounts
+}
+
+#[test]
+fn test_ynab_register_maps_splits_transfers_and_categories() {
+    let migration = parse_ynab(YNAB, None).unwrap();
+    let parsed = &migration.parsed;
+    assert_eq!(parsed.total_rows, 7);
+    assert_eq!(parsed.errors.len(), 1);
+    assert_eq!(parsed.errors[0].row, 7);
+
+    let rows: Vec<(&str, f64, Option<&str>, Option<&str>)> = parsed
+        .rows
+        .iter()
+        .map(|r| {
+            (
+                r.payee.as_str(),
+                r.amount,
+                r.category.as_deref(),
+                r.notes.as_deref(),
+            )
+        })
+        .collect();
+    assert_eq!(
+        rows,
+        vec![
+            ("Starting Balance", 1500.0, None, None),
+            (
+                "Grocer",
+                -40.0,
+                Some("Everyday Expenses:Groceries"),
+                Some("Food")
+            ),
+            (
+                "Grocer",
+                -12.5,
+                Some("Everyday Expenses:Household"),
+                Some("Soap")
+            ),
+            ("Savings", -200.0, Some("Transfer"), Some("Monthly saving")),
+            ("Employer", 2000.0, Some("Income"), Some("Salary")),
+        ]
+    );
+    assert_eq!(parsed.rows[0].date, "2024-01-02");
+
+    let summary = migration.summary();
+    assert_eq!(summary.accounts, 2);
+    assert_eq!(summary.split_lines, 2);
+    assert_eq!(summary.transfers, 1);
+    assert_eq!(summary.categories, 3);
+    assert_eq!(summary.skipped, 1);
+}
+
+#[test]
+fn test_ynab_import_creates_accounts_and_links_transfer() {
+    let (dir, db_path) = setup_db();
+    let file = dir.path().join("register.csv");
+    std::fs::write(&file, YNAB).unwrap();
+
+    let report = import_app_db(
+        &db_path,
+        &file,
+        SourceApp::Ynab,
+        None,
+        false,
+        &RuleSelection::All,
+    )
+    .unwrap();
+    assert_eq!(report.import.imported, 5);
+    assert_eq!(report.import.created_accounts, vec!["Checking", "Savings"]);
+    assert_eq!(report.summary.transactions, 5);
+    assert_eq!(
+        balances(&db_path),
+        vec![
+            ("Checking".to_string(), 3247.5),
+            ("Savings".to_string(), 200.0)
+        ]
+    );
+
+    let conn = Connection::open(&db_path).unwrap();
+    let linked: i64 = conn
+        .query_row(
+            "SELECT COUNT(*) FROM transactions WHERE linked_tx_id IS NOT NULL",
+            [],
+            |r| r.get(0),
+        )
+        .unwrap();
+    assert_eq!(linked, 2);
+}
+
+#[test]
+fn test_firefly_export_maps_journals_and_converts_transfers() {
+    let migration = parse_firefly(FIREFLY, None).unwrap();
+    let parsed = &migration.parsed;
+    assert_eq!(parsed.rows.len(), 6);
+    assert_eq!(parsed.errors.len(), 1);
+    assert!(parsed.errors[0]
+        .message
+        .contains("neither is an asset account"));
+    assert_eq!(migration.split_lines, 2);
+
+    let opening = &parsed.rows[0];
+    assert_eq!(opening.payee, "Opening Balance");
+    assert_eq!(opening.amount, 1000.0);
+    assert_eq!(opening.date, "2024-01-01");
+    assert_eq!(parsed.rows[1].amount, -45.2);
+    assert_eq!(parsed.rows[1].payee, "Supermarket");
+    assert_eq!(
+        parsed.rows[2].notes.as_deref(),
+        Some("Big store run - Food")
+    );
+    assert_eq!(parsed.rows[5].notes.as_deref(), Some("Salary - January"));
+
+    let dollar_account = parsed
+        .accounts
+        .iter()
+        .find(|a| a.name == "Dollar account")
+        .unwrap();
+    assert_eq!(dollar_account.currency.as_deref(), Some("USD"));
+
+    // Committed, the dollar side receives the foreign amount; a re-import finds every journal
+    let (dir, db_path) = setup_db();
+    let file = dir.path().join("firefly.csv");
+    std::fs::write(&file, FIREFLY).unwrap();
+    import_app_db(
+        &db_path,
+        &file,
+        SourceApp::Firefly,
+        None,
+        false,
+        &RuleSelection::All,
+    )
+    .unwrap();
+    assert_eq!(
+        balances(&db_path),
+        vec![
+            ("Checking".to_string(), 3304.8),
+            ("Dollar account".to_string(), 108.5)
+        ]
+    );
+    let again = import_app_db(
+        &db_path,
+        &file,
+        SourceApp::Firefly,
+        None,
+        false,
+        &RuleSelection::All,
+    )
+    .unwrap();
+    assert_eq!(again.import.imported, 0);
+    assert_eq!(again.import.duplicates.len(), 6);
+}
+
+#[test]
+fn test_actual_zip_export_resolves_splits_mappings_and_transfers() {
+    let dir = tempfile::tempdir().unwrap();
+    let sqlite = source_db(dir.path(), "db.sqlite", ACTUAL_SCHEMA);
+    let archive = dir.path().join("My-Budget.zip");
+    let mut zip = zip::ZipWriter::new(std::fs::File::create(&archive).unwrap());
+    zip.start_file("metadata.json", zip::write::SimpleFileOptions::default())
+        .unwrap();
+    zip.write_all(b"{\"budgetName\":\"My Budget\"}").unwrap();
+    zip.start_file("db.sqlite", zip::write::SimpleFileOptions::default())
+        .unwrap();
+    zip.write_all(&std::fs::read(&sqlite).unwrap()).unwrap();
+    zip.finish().unwrap();
+
+    let migration =
+        parse_actual(&SourceDatabase::open(&archive, Some("db.sqlite")).unwrap()).unwrap();
+    let parsed = &migration.parsed;
+    assert_eq!(parsed.total_rows, 8);
+    assert_eq!(parsed.errors.len(), 1);
+    assert!(parsed.errors[0].message.contains("deleted account"));
+    assert_eq!(migration.split_lines, 2);
+
+    let rows: Vec<(&str, f64, Option<&str>)> = parsed
+        .rows
+        .iter()
+        .map(|r| (r.payee.as_str(), r.amount, r.category.as_deref()))
+        .collect();
+    assert_eq!(
+        rows,
+        vec![
+            ("Starting Balance", 1500.0, None),
+            ("Grocer", -30.0, Some("Food:Groceries")),
+            ("Grocer", -20.0, Some("Food:Dining")),
+            ("Savings", -100.0, Some("Transfer")),
+            ("Grocer", -7.0, Some("Food:Dining")),
+        ]
+    );
+    assert_eq!(parsed.rows[1].external_id.as_deref(), Some("t3"));
+    assert_eq!(parsed.rows[4].date, "2024-01-12");
+}
+
+#[test]
+fn test_mmex_database_imports_opening_balances_and_splits() {
+    let (dir, db_path) = setup_db();
+    let file = source_db(dir.path(), "finances.mmb", MMEX_SCHEMA);
+
+    let report = import_app_db(
+        &db_path,
+        &file,
+        SourceApp::Mmex,
+        None,
+        false,
+        &RuleSelection::All,
+    )
+    .unwrap();
+    let rows = &report.import.rows;
+    assert_eq!(report.import.total_rows, 8);
+    assert_eq!(rows.len(), 6);
+    assert_eq!(rows[0].payee, "Opening Balance");
+    assert_eq!(rows[0].date, "2024-01-01");
+    assert_eq!(rows[1].category.as_deref(), Some("Food:Groceries"));
+    assert_eq!(rows[2].notes.as_deref(), Some("food"));
+    assert_eq!(rows[3].category.as_deref(), Some("Food"));
+    assert_eq!(rows[3].notes.as_deref(), Some("weekly"));
+    assert_eq!(rows[4].transfer_amount, Some(108.0));
+
+    let skipped: Vec<&str> = report
+        .import
+        .errors
+        .iter()
+        .map(|e| e.message.as_str())
+        .collect();
+    assert_eq!(skipped.len(), 3);
+    assert!(skipped[2].contains("VT"));
+    assert_eq!(report.summary.split_lines, 2);
+    assert_eq!(
+        balances(&db_path),
+        vec![
+            ("Broker".to_string(), 0.0),
+            ("Checking".to_string(), 2314.5),
+            ("Dollars".to_string(), 108.0)
+        ]
+    );
+    let dollars = crate::get_accounts_db(&db_path)
+        .unwrap()
+        .into_iter()
+        .find(|a| a.name == "Dollars")
+        .unwrap();
+    assert_eq!(dollars.currency.as_deref(), Some("USD"));
+}
+
+#[test]
+fn test_split_lines_are_committed_as_one_split() {
+    let dir = tempfile::tempdir().unwrap();
+    let ynab = dir.path().join("register.csv");
+    std::fs::write(&ynab, YNAB).unwrap();
+    let firefly = dir.path().join("firefly.csv");
+    std::fs::write(&firefly, FIREFLY).unwrap();
+    let sources = [
+        (SourceApp::Ynab, ynab),
+        (SourceApp::Firefly, firefly),
+        (
+            SourceApp::Actual,
+            source_db(dir.path(), "db.sqlite", ACTUAL_SCHEMA),
+        ),
+        (
+            SourceApp::Mmex,
+            source_db(dir.path(), "finances.mmb", MMEX_SCHEMA),
+        ),
+    ];
+    for (app, file) in sources {
+        let (_ledger, db_path) = setup_db();
+        import_app_db(&db_path, &file, app, None, false, &RuleSelection::All).unwrap();
+        let conn = Connection::open(&db_path).unwrap();
+        let (groups, lines): (i64, i64) = conn
+            .query_row(
+                "SELECT COUNT(DISTINCT split_id), COUNT(*) FROM transactions WHERE split_id IS NOT NULL",
+                [],
+                |r| Ok((r.get(0)?, r.get(1)?)),
+            )
+            .unwrap();
+        assert_eq!((groups, lines), (1, 2), "{:?}", app);
+    }
+}
+
+#[test]
+fn test_files_from_other_apps_are_rejected() {
+    let dir = tempfile::tempdir().unwrap();
+    let actual = source_db(dir.path(), "db.sqlite", ACTUAL_SCHEMA);
+    let err = parse_mmex(&SourceDatabase::open(&actual, None).unwrap()).unwrap_err();
+    assert!(err.contains("Not a Money Manager Ex database"), "{}", err);
+
+    let csv = dir.path().join("register.csv");
+    std::fs::write(&csv, YNAB).unwrap();
+    let err = SourceDatabase::open(&csv, Some("db.sqlite")).err().unwrap();
+    assert_eq!(err, "Not a SQLite database");
+    let err = parse_firefly(YNAB, None).unwrap_err();
+    assert!(err.contains("Missing column"), "{}", err);
+}
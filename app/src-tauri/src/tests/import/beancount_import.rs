@@ -0,0 +1,195 @@
+use crate::export::journal::{export_journal_db, JournalFormat};
+use crate::import::beancount::{import_beancount_db, parse_beancount};
+use crate::rules::RuleSelection;
+use crate::tests::common::full_db;
+use rusqlite::Connection;
+use std::path::PathBuf;
+
+const LEDGER: &str = r#"option "operating_currency" "USD"
+* Accounts
+2024-01-01 open Assets:Bank:Checking USD
+2024-01-01 open Assets:Savings USD
+2024-01-01 open Assets:Euro EUR
+2024-01-01 open Assets:Broker USD,AAPL
+2024-01-01 open Expenses:Food
+2024-01-01 open Expenses:Home
+2024-01-01 open Expenses:Fees
+2024-01-01 open Equity:Opening-Balances
+
+2024-01-01 * "Opening"
+  Assets:Bank:Checking  1,000.00 USD
+  Equity:Opening-Balances
+
+2024-01-05 * "Grocer" "Weekly shop" #food
+  receipt: "r-17"
+  Assets:Bank:Checking  -60.00 USD
+  Expenses:Food  45.00 USD ; fresh
+  Expenses:Home  15.00 USD
+
+2024-01-10 * "Move to savings"
+  Assets:Bank:Checking  -200.00 USD
+  Assets:Savings  200.00 USD
+
+2024-01-12 * "Exchange"
+  Assets:Bank:Checking  -110.00 USD @@ 100.00 EUR
+  Assets:Euro  100.00 EUR
+
+2024-01-15 * "Buy Apple"
+  Assets:Broker  10 AAPL {150.00 USD}
+  Assets:Bank:Checking  -1501.00 USD
+  Expenses:Fees  1.00 USD
+
+2024-02-01 price AAPL 155.00 USD
+2024-02-02 balance Assets:Savings 200.00 USD
+2024-02-03 pad Assets:Savings Equity:Opening-Balances
+
+2024-02-04 * "Mystery"
+  Assets:Bank:Checking  -1.00 USD
+  Assets:Savings  0.50 USD
+  Assets:Euro  0.50 EUR
+"#;
+
+#[test]
+fn test_parse_beancount_maps_postings_and_reports_the_rest() {
+    let parsed = parse_beancount(LEDGER).unwrap();
+    assert_eq!(parsed.total_rows, 6);
+    assert_eq!(parsed.rows.len(), 7);
+
+    let names: Vec<(&str, Option<&str>)> = parsed
+        .accounts
+        .iter()
+        .map(|a| (a.name.as_str(), a.currency.as_deref()))
+        .collect();
+    assert_eq!(names[0], ("Bank:Checking", Some("USD")));
+    assert_eq!(names[2], ("Euro", Some("EUR")));
+
+    // The elided equity posting is inferred
+    let opening = &parsed.rows[0];
+    assert_eq!(opening.amount, 1000.0);
+    assert_eq!(opening.category.as_deref(), Some("Opening-Balances"));
+
+    // Split into one row per category
+    assert_eq!(parsed.rows[1].amount, -45.0);
+    assert_eq!(parsed.rows[1].category.as_deref(), Some("Food"));
+    assert_eq!(parsed.rows[1].notes.as_deref(), Some("Weekly shop"));
+    assert_eq!(parsed.rows[2].amount, -15.0);
+    assert_eq!(parsed.rows[1].split_group, parsed.rows[2].split_group);
+    assert!(parsed.rows[1].split_group.is_some());
+
+    let buy = parsed.rows.iter().find(|r| r.is_buy == Some(true)).unwrap();
+    assert_eq!(buy.ticker.as_deref(), Some("AAPL"));
+    assert_eq!(buy.shares, Some(10.0));
+    assert_eq!(buy.price_per_share, Some(150.0));
+    assert_eq!(buy.fee, Some(1.0));
+
+    assert_eq!(parsed.prices.len(), 1);
+    assert_eq!(parsed.balances[0].date, "2024-02-01");
+
+    let messages: Vec<&str> = parsed.errors.iter().map(|e| e.message.as_str()).collect();
+    assert_eq!(parsed.errors.len(), 2, "{:?}", messages);
+    assert!(messages[0].contains("'pad'"));
+    assert!(messages[1].contains("Cannot map"));
+}
+
+#[test]
+fn test_import_beancount_creates_accounts_transfers_and_trades() {
+    let (dir, db_path) = full_db();
+    let file = dir.path().join("main.beancount");
+    std::fs::write(&file, LEDGER).unwrap();
+
+    let report = import_beancount_db(&db_path, &file, false, &RuleSelection::All).unwrap();
+    assert_eq!(report.imported, 7);
+    assert_eq!(report.prices, 1);
+    assert_eq!(report.created_accounts.len(), 4);
+    assert!(
+        report.balance_checks[0].matches,
+        "{:?}",
+        report.balance_checks
+    );
+
+    let accounts = crate::get_accounts_db(&db_path).unwrap();
+    let balance = |name: &str| accounts.iter().find(|a| a.name == name).unwrap().balance;
+    assert!((balance("Bank:Checking") + 871.0).abs() < 1e-9);
+    assert_eq!(balance("Savings"), 200.0);
+    assert_eq!(balance("Euro"), 100.0);
+    assert!(balance("Broker").abs() < 1e-9);
+    let euro = accounts.iter().find(|a| a.name == "Euro").unwrap();
+    assert_eq!(euro.currency.as_deref(), Some("EUR"));
+
+    let conn = Connection::open(&db_path).unwrap();
+    let unlinked: i64 = conn
+        .query_row(
+            "SELECT COUNT(*) FROM transactions WHERE category = 'Transfer' AND linked_tx_id IS NULL",
+            [],
+            |r| r.get(0),
+        )
+        .unwrap();
+    assert_eq!(unlinked, 0);
+    // The two category postings of the grocery run stay one split
+    let split: Vec<f64> = conn
+        .prepare("SELECT amount FROM transactions WHERE split_id IS NOT NULL ORDER BY id")
+        .unwrap()
+        .query_map([], |r| r.get(0))
+        .unwrap()
+        .collect::<Result<_, _>>()
+        .unwrap();
+    assert_eq!(split, vec![-45.0, -15.0]);
+    let shares: f64 = conn
+        .query_row(
+            "SELECT shares FROM transactions WHERE ticker = 'AAPL'",
+            [],
+            |r| r.get(0),
+        )
+        .unwrap();
+    assert_eq!(shares, 10.0);
+    let price: f64 = conn
+        .query_row(
+            "SELECT price FROM daily_stock_prices WHERE ticker = 'AAPL' AND date = '2024-02-01'",
+            [],
+            |r| r.get(0),
+        )
+        .unwrap();
+    assert_eq!(price, 155.0);
+}
+
+#[test]
+fn test_dry_run_leaves_database_untouched() {
+    let (dir, db_path) = full_db();
+    let file = dir.path().join("main.beancount");
+    std::fs::write(&file, LEDGER).unwrap();
+
+    let report = import_beancount_db(&db_path, &file, true, &RuleSelection::All).unwrap();
+    assert_eq!(report.rows.len(), 7);
+    assert_eq!(report.imported, 0);
+    assert!(crate::get_accounts_db(&db_path).unwrap().is_empty());
+}
+
+#[test]
+fn test_exported_journal_imports_with_same_balances() {
+    let (dir, source) = full_db();
+    let file = dir.path().join("main.beancount");
+    std::fs::write(&file, LEDGER).unwrap();
+    import_beancount_db(&source, &file, false, &RuleSelection::All).unwrap();
+
+    let exported = dir.path().join("export.beancount");
+    export_journal_db(&source, &exported, JournalFormat::Beancount).unwrap();
+    let (_dir2, target) = full_db();
+    let report = import_beancount_db(&target, &exported, false, &RuleSelection::All).unwrap();
+    assert!(report.errors.is_empty(), "{:?}", report.errors);
+
+    let balances = |db: &PathBuf| {
+        let mut all: Vec<(String, i64)> = crate::get_accounts_db(db)
+            .unwrap()
+            .into_iter()
+            .map(|a| (a.name, (a.balance * 100.0).round() as i64))
+            .collect();
+        all.sort();
+        all
+    };
+    assert_eq!(balances(&source), balances(&target));
+}
+
+#[test]
+fn test_rejects_files_without_directives() {
+    assert!(parse_beancount("; just a comment\n").is_err());
+}
@@ -0,0 +1,195 @@
+use super::common::{close, setup_db};
+use crate::import::broker::{import_broker_db, parse_broker, BrokerFormat};
+use crate::rules::RuleSelection;
+use rusqlite::Connection;
+
+const IBKR: &str = include_str!("fixtures/ibkr_flex.xml");
+const DEGIRO_TRANSACTIONS: &str = include_str!("fixtures/degiro_transactions.csv");
+const DEGIRO_ACCOUNT: &str = include_str!("fixtures/degiro_account.csv");
+const TRADING212: &str = include_str!("fixtures/trading212.csv");
+
+#[test]
+fn test_ibkr_flex_maps_trades_cash_and_fx() {
+    let parsed = parse_broker(IBKR, BrokerFormat::InteractiveBrokers, 1).unwrap();
+    assert_eq!(parsed.total_rows, 8);
+    assert_eq!(parsed.rows.len(), 9);
+    assert_eq!(parsed.errors.len(), 1);
+    assert!(parsed.errors[0].message.contains("Price Adjustments"));
+
+    let buy = &parsed.rows[0];
+    assert_eq!(buy.is_buy, Some(true));
+    assert_eq!(buy.ticker.as_deref(), Some("AAPL"));
+    assert_eq!(buy.shares, Some(10.0));
+    assert_eq!(buy.price_per_share, Some(185.5));
+    assert_eq!(buy.fee, Some(1.0));
+    assert_eq!(buy.amount, -1856.0);
+    assert_eq!(buy.currency.as_deref(), Some("USD"));
+    assert_eq!(buy.external_id.as_deref(), Some("501"));
+
+    let sell = &parsed.rows[1];
+    assert_eq!(sell.is_buy, Some(false));
+    assert_eq!(sell.shares, Some(-4.0));
+    assert!(close(sell.amount, 715.98));
+
+    // EUR.USD: both legs in their own currency, commission separately
+    let fx: Vec<(f64, &str)> = parsed.rows[2..5]
+        .iter()
+        .map(|r| (r.amount, r.currency.as_deref().unwrap()))
+        .collect();
+    assert_eq!(fx, vec![(1000.0, "EUR"), (-1092.5, "USD"), (-2.0, "USD")]);
+    assert_eq!(parsed.rows[2].external_id.as_deref(), Some("503:base"));
+
+    let dividend = &parsed.rows[5];
+    assert_eq!(dividend.category.as_deref(), Some("Dividends"));
+    assert_eq!(dividend.ticker.as_deref(), Some("AAPL"));
+    assert_eq!(dividend.date, "2024-02-15");
+    assert_eq!(parsed.rows[6].category.as_deref(), Some("Taxes"));
+    assert_eq!(parsed.rows[6].amount, -0.36);
+    assert_eq!(parsed.rows[8].payee, "Deposit");
+    assert_eq!(parsed.rows[8].currency.as_deref(), Some("EUR"));
+}
+
+#[test]
+fn test_degiro_transactions_use_account_currency_and_split_fills() {
+    let parsed = parse_broker(DEGIRO_TRANSACTIONS, BrokerFormat::Degiro, 1).unwrap();
+    assert!(parsed.errors.is_empty(), "{:?}", parsed.errors);
+    assert_eq!(parsed.rows.len(), 4);
+
+    // A USD trade settled in EUR is priced in EUR, with the local price kept in the notes
+    let first = &parsed.rows[0];
+    assert_eq!(first.ticker.as_deref(), Some("US0378331005"));
+    assert_eq!(first.currency.as_deref(), Some("EUR"));
+    assert!(close(first.price_per_share.unwrap(), 170.032));
+    assert!(close(first.amount, -1701.32));
+    assert!(first.notes.as_deref().unwrap().contains("185.5 USD"));
+
+    // Partial fills of one order get distinct ids
+    assert_eq!(first.external_id.as_deref(), Some("4f1d6c7e-0001:1"));
+    assert_eq!(
+        parsed.rows[1].external_id.as_deref(),
+        Some("4f1d6c7e-0001:2")
+    );
+
+    let etf = &parsed.rows[2];
+    assert_eq!(etf.price_per_share, Some(110.2));
+    assert_eq!(etf.fee, Some(2.0));
+
+    let sell = &parsed.rows[3];
+    assert_eq!(sell.shares, Some(-3.0));
+    assert!(close(sell.amount, 491.2));
+}
+
+#[test]
+fn test_degiro_account_statement_skips_trades_and_autofx() {
+    let parsed = parse_broker(DEGIRO_ACCOUNT, BrokerFormat::Degiro, 1).unwrap();
+    assert_eq!(parsed.total_rows, 11);
+    assert_eq!(parsed.errors.len(), 4, "{:?}", parsed.errors);
+    assert!(parsed.errors[0].message.contains("Transactions export"));
+    assert!(parsed.errors[1]
+        .message
+        .contains("Automatic currency conversion"));
+
+    let summary: Vec<(&str, f64, &str)> = parsed
+        .rows
+        .iter()
+        .map(|r| {
+            (
+                r.category.as_deref().unwrap_or(r.payee.as_str()),
+                r.amount,
+                r.currency.as_deref().unwrap(),
+            )
+        })
+        .collect();
+    assert_eq!(
+        summary,
+        vec![
+            ("Deposit", 2500.0, "EUR"),
+            ("Currency Exchange", 109.0, "USD"),
+            ("Currency Exchange", -100.0, "EUR"),
+            ("Dividends", 2.4, "USD"),
+            ("Taxes", -0.36, "USD"),
+            ("Fees", -2.5, "EUR"),
+        ]
+    );
+    assert_eq!(parsed.rows[3].ticker.as_deref(), Some("US0378331005"));
+}
+
+#[test]
+fn test_trading212_history_maps_actions() {
+    let parsed = parse_broker(TRADING212, BrokerFormat::Trading212, 1).unwrap();
+    assert!(parsed.errors.is_empty(), "{:?}", parsed.errors);
+    assert_eq!(parsed.total_rows, 8);
+    assert_eq!(parsed.rows.len(), 10);
+
+    let buy = &parsed.rows[1];
+    assert_eq!(buy.ticker.as_deref(), Some("AAPL"));
+    assert_eq!(buy.shares, Some(2.5));
+    assert!(close(buy.price_per_share.unwrap(), 169.724));
+    assert_eq!(buy.fee, Some(0.99));
+    assert!(close(buy.amount, -425.3));
+    assert_eq!(buy.external_id.as_deref(), Some("EOF0001"));
+
+    assert_eq!(parsed.rows[2].price_per_share, Some(27.5));
+    let sell = &parsed.rows[3];
+    assert_eq!(sell.shares, Some(-1.0));
+    assert!(close(sell.amount, 165.87));
+
+    let dividend = &parsed.rows[4];
+    assert_eq!(dividend.category.as_deref(), Some("Dividends"));
+    assert_eq!(dividend.notes.as_deref(), Some("Withholding tax 0.09 USD"));
+    assert_eq!(parsed.rows[6].amount, -100.0);
+
+    let conversion: Vec<(f64, &str)> = parsed.rows[7..]
+        .iter()
+        .map(|r| (r.amount, r.currency.as_deref().unwrap()))
+        .collect();
+    assert_eq!(
+        conversion,
+        vec![(-10.0, "EUR"), (10.85, "USD"), (-0.02, "EUR")]
+    );
+}
+
+#[test]
+fn test_reimport_skips_known_trade_ids() {
+    let (dir, db_path) = setup_db();
+    let broker =
+        crate::create_account_db(&db_path, "IBKR".to_string(), 0.0, Some("USD".to_string()))
+            .unwrap();
+    let file = dir.path().join("flex.xml");
+    std::fs::write(&file, IBKR).unwrap();
+
+    let first = import_broker_db(
+        &db_path,
+        &file,
+        BrokerFormat::InteractiveBrokers,
+        broker.id,
+        false,
+        &RuleSelection::All,
+    )
+    .unwrap();
+    assert_eq!(first.imported, 9);
+    let second = import_broker_db(
+        &db_path,
+        &file,
+        BrokerFormat::InteractiveBrokers,
+        broker.id,
+        false,
+        &RuleSelection::All,
+    )
+    .unwrap();
+    assert_eq!(second.imported, 0);
+    assert_eq!(second.duplicates.len(), 9);
+
+    let conn = Connection::open(&db_path).unwrap();
+    let (shares, price, fee, currency): (f64, f64, f64, String) = conn
+        .query_row(
+            "SELECT SUM(shares), MAX(price_per_share), SUM(fee), MAX(currency) FROM transactions WHERE ticker = 'AAPL' AND shares IS NOT NULL",
+            [],
+            |r| Ok((r.get(0)?, r.get(1)?, r.get(2)?, r.get(3)?)),
+        )
+        .unwrap();
+    assert_eq!(shares, 6.0);
+    assert_eq!(price, 185.5);
+    assert!(close(fee, 2.02));
+    assert_eq!(currency, "USD");
+}
@@ -0,0 +1,170 @@
+use super::common::setup_db;
+use crate::import::csv::{import_csv_db, parse_csv, CsvMapping, SignConvention};
+use crate::import::{parse_amount, parse_date};
+use crate::rules::RuleSelection;
+
+fn write_file(dir: &std::path::Path, name: &str, content: &str) -> std::path::PathBuf {
+    let path = dir.join(name);
+    std::fs::write(&path, content).unwrap();
+    path
+}
+
+#[test]
+fn test_parse_amount_locales() {
+    assert_eq!(parse_amount("1,234.56", '.', Some(',')).unwrap(), 1234.56);
+    assert_eq!(parse_amount("1.234,56", ',', Some('.')).unwrap(), 1234.56);
+    assert_eq!(parse_amount("€ 1 234,50", ',', None).unwrap(), 1234.5);
+    assert_eq!(parse_amount("(12.00)", '.', None).unwrap(), -12.0);
+    assert_eq!(parse_amount("12.00-", '.', None).unwrap(), -12.0);
+    assert_eq!(parse_amount("EUR 12.00", '.', None).unwrap(), 12.0);
+    assert_eq!(parse_amount("12.00 EUR", '.', None).unwrap(), 12.0);
+    assert_eq!(parse_amount("SEK 5", '.', None).unwrap(), 5.0);
+    assert_eq!(
+        parse_amount("-1.234,50EUR", ',', Some('.')).unwrap(),
+        -1234.5
+    );
+    assert_eq!(parse_amount("1.5e2", '.', None).unwrap(), 150.0);
+    assert!(parse_amount("abc?", '.', None).is_err());
+    assert!(parse_amount("  ", '.', None).is_err());
+}
+
+#[test]
+fn test_parse_date_formats() {
+    assert_eq!(parse_date("2024-03-05", None).unwrap(), "2024-03-05");
+    assert_eq!(parse_date("05/03/2024", None).unwrap(), "2024-03-05");
+    assert_eq!(
+        parse_date("2024-03-05T10:00:00Z", None).unwrap(),
+        "2024-03-05"
+    );
+    assert_eq!(
+        parse_date("03/05/2024", Some("%m/%d/%Y")).unwrap(),
+        "2024-03-05"
+    );
+    assert!(parse_date("31/02/2024", None).is_err());
+    assert!(parse_date("03/05/2024", Some("%Y-%m-%d")).is_err());
+}
+
+#[test]
+fn test_parse_csv_semicolon_debit_credit_with_row_errors() {
+    let content = "Fecha;Concepto;Cargo;Abono\n\
+                   01.02.2024;Supermercado;45,10;\n\
+                   02.02.2024;Nomina;;1.500,00\n\
+                   not-a-date;Broken;1,00;\n";
+    let mapping = CsvMapping {
+        date: Some("fecha".to_string()),
+        payee: Some("Concepto".to_string()),
+        debit: Some("Cargo".to_string()),
+        credit: Some("Abono".to_string()),
+        decimal_separator: ',',
+        thousands_separator: Some('.'),
+        account_id: Some(1),
+        ..Default::default()
+    };
+
+    let parsed = parse_csv(content, &mapping).unwrap();
+    assert_eq!(parsed.total_rows, 3);
+    assert_eq!(parsed.rows.len(), 2);
+    assert_eq!(parsed.rows[0].date, "2024-02-01");
+    assert_eq!(parsed.rows[0].amount, -45.1);
+    assert_eq!(parsed.rows[1].amount, 1500.0);
+    assert_eq!(parsed.errors.len(), 1);
+    assert_eq!(parsed.errors[0].row, 4);
+}
+
+#[test]
+fn test_parse_csv_without_header_counts_quoted_columns_once() {
+    let content = "\"Smith, J\",2024-01-05,-20.00\nBakery,2024-01-06,-3.50\n";
+    let mapping = CsvMapping {
+        has_header: false,
+        payee: Some("Column 1".to_string()),
+        date: Some("Column 2".to_string()),
+        amount: Some("Column 3".to_string()),
+        account_id: Some(1),
+        ..Default::default()
+    };
+
+    let parsed = parse_csv(content, &mapping).unwrap();
+    assert!(parsed.errors.is_empty(), "{:?}", parsed.errors);
+    assert_eq!(parsed.rows.len(), 2);
+    assert_eq!(parsed.rows[0].payee, "Smith, J");
+    assert_eq!(parsed.rows[0].amount, -20.0);
+    // A fourth column only exists if the quoted comma is split
+    let mapping = CsvMapping {
+        notes: Some("Column 4".to_string()),
+        ..mapping
+    };
+    assert!(parse_csv(content, &mapping).is_err());
+}
+
+#[test]
+fn test_parse_csv_unknown_column_is_an_error() {
+    let mapping = CsvMapping {
+        date: Some("Date".to_string()),
+        amount: Some("Missing".to_string()),
+        account_id: Some(1),
+        ..Default::default()
+    };
+    assert!(parse_csv("Date,Amount\n2024-01-01,1\n", &mapping).is_err());
+}
+
+#[test]
+fn test_import_dry_run_then_commit() {
+    let (dir, db_path) = setup_db();
+    let account = crate::create_account_db(&db_path, "Card".to_string(), 0.0, None).unwrap();
+    let file = write_file(
+        dir.path(),
+        "card.csv",
+        "Date,Description,Amount\n2024-01-05,Coffee,3.50\n2024-01-06,Refund,-10.00\n",
+    );
+    let mapping = CsvMapping {
+        date: Some("Date".to_string()),
+        payee: Some("Description".to_string()),
+        amount: Some("Amount".to_string()),
+        sign_convention: SignConvention::PositiveIsExpense,
+        account_id: Some(account.id),
+        ..Default::default()
+    };
+
+    let preview =
+        import_csv_db(&db_path, &file, mapping.clone(), true, &RuleSelection::All).unwrap();
+    assert!(preview.dry_run);
+    assert_eq!(preview.rows.len(), 2);
+    assert_eq!(preview.imported, 0);
+    assert!(crate::get_transactions_db(&db_path, account.id)
+        .unwrap()
+        .is_empty());
+
+    let report = import_csv_db(&db_path, &file, mapping, false, &RuleSelection::All).unwrap();
+    assert_eq!(report.imported, 2);
+    let accounts = crate::get_accounts_db(&db_path).unwrap();
+    assert!((accounts[0].balance - 6.5).abs() < 1e-9);
+}
+
+#[test]
+fn test_import_account_column_creates_missing_accounts() {
+    let (dir, db_path) = setup_db();
+    crate::create_account_db(&db_path, "Checking".to_string(), 0.0, None).unwrap();
+    let file = write_file(
+        dir.path(),
+        "multi.csv",
+        "date,account,payee,amount\n2024-01-01,checking,Rent,-900\n2024-01-02,Savings,Interest,1.25\n",
+    );
+    let mut mapping = CsvMapping {
+        date: Some("date".to_string()),
+        account: Some("account".to_string()),
+        payee: Some("payee".to_string()),
+        amount: Some("amount".to_string()),
+        ..Default::default()
+    };
+
+    let preview =
+        import_csv_db(&db_path, &file, mapping.clone(), true, &RuleSelection::All).unwrap();
+    assert_eq!(preview.rows.len(), 1);
+    assert_eq!(preview.errors.len(), 1);
+
+    mapping.create_missing_accounts = true;
+    let report = import_csv_db(&db_path, &file, mapping, false, &RuleSelection::All).unwrap();
+    assert_eq!(report.imported, 2);
+    assert_eq!(report.created_accounts, vec!["Savings".to_string()]);
+    assert_eq!(crate::get_accounts_db(&db_path).unwrap().len(), 2);
+}
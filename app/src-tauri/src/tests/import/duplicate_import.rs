@@ -0,0 +1,188 @@
+use super::common::{add_transaction, csv_mapping, setup_db};
+use crate::duplicates::{
+    find_duplicates_db, merge_duplicates_db, normalize_payee, MatchKind, DEFAULT_WINDOW_DAYS,
+};
+use crate::import::csv::import_csv_db;
+use crate::rules::RuleSelection;
+use rusqlite::{params, Connection};
+
+#[test]
+fn test_normalize_payee_drops_numbers_and_punctuation() {
+    assert_eq!(
+        normalize_payee("AMAZON MKTPLACE 1234*XY"),
+        "amazon mktplace xy"
+    );
+    assert_eq!(normalize_payee("  Café-Bar #12 "), "café bar");
+    assert_eq!(normalize_payee("4711"), "");
+}
+
+#[test]
+fn test_reimporting_an_overlapping_file_skips_exact_matches() {
+    let (dir, db_path) = setup_db();
+    let account = crate::create_account_db(&db_path, "Card".to_string(), 0.0, None).unwrap();
+    let first = dir.path().join("january.csv");
+    std::fs::write(
+        &first,
+        "Date,Payee,Amount\n2024-01-05,Coffee Shop,-3.50\n2024-01-06,Grocer,-42.10\n",
+    )
+    .unwrap();
+    assert_eq!(
+        import_csv_db(
+            &db_path,
+            &first,
+            csv_mapping(account.id),
+            false,
+            &RuleSelection::All
+        )
+        .unwrap()
+        .imported,
+        2
+    );
+
+    // The second export overlaps the first and has two identical coffees on one day
+    let second = dir.path().join("january-full.csv");
+    std::fs::write(
+        &second,
+        "Date,Payee,Amount\n2024-01-05,COFFEE SHOP #0042,-3.50\n2024-01-05,Coffee Shop,-3.50\n2024-01-06,Grocer,-42.10\n",
+    )
+    .unwrap();
+    let report = import_csv_db(
+        &db_path,
+        &second,
+        csv_mapping(account.id),
+        false,
+        &RuleSelection::All,
+    )
+    .unwrap();
+    assert_eq!(report.duplicates.len(), 2);
+    assert_eq!(report.imported, 1);
+    assert!(report.possible_duplicates.is_empty());
+
+    let accounts = crate::get_accounts_db(&db_path).unwrap();
+    assert!((accounts[0].balance + 49.1).abs() < 1e-9);
+}
+
+#[test]
+fn test_import_preview_flags_fuzzy_matches_within_window() {
+    let (dir, db_path) = setup_db();
+    let account = crate::create_account_db(&db_path, "Card".to_string(), 0.0, None).unwrap();
+    let existing = add_transaction(&db_path, account.id, "2024-02-01", "Amazon", None, -25.0).id;
+    add_transaction(&db_path, account.id, "2024-01-20", "Bakery", None, -4.0);
+
+    let file = dir.path().join("feb.csv");
+    std::fs::write(
+        &file,
+        "Date,Payee,Amount\n2024-02-03,AMAZON MKTPLACE 99812,-25.00\n2024-01-30,Bakery,-4.00\n",
+    )
+    .unwrap();
+    let preview = import_csv_db(
+        &db_path,
+        &file,
+        csv_mapping(account.id),
+        true,
+        &RuleSelection::All,
+    )
+    .unwrap();
+    assert!(preview.duplicates.is_empty());
+    assert_eq!(preview.rows.len(), 2);
+    // The bakery row is ten days away, outside the window
+    assert_eq!(preview.possible_duplicates.len(), 1);
+    let flagged = &preview.possible_duplicates[0];
+    assert_eq!(flagged.row, 2);
+    assert_eq!(flagged.transaction_id, existing);
+    assert_eq!(flagged.kind, MatchKind::Fuzzy);
+    assert_eq!(flagged.days_apart, 2);
+}
+
+#[test]
+fn test_find_duplicates_keeps_the_transaction_with_a_bank_id() {
+    let (_dir, db_path) = setup_db();
+    let checking = crate::create_account_db(&db_path, "Checking".to_string(), 0.0, None).unwrap();
+    let savings = crate::create_account_db(&db_path, "Savings".to_string(), 0.0, None).unwrap();
+    let manual = add_transaction(&db_path, checking.id, "2024-03-01", "Netflix", None, -15.99).id;
+    let imported = add_transaction(
+        &db_path,
+        checking.id,
+        "2024-03-02",
+        "NETFLIX.COM",
+        None,
+        -15.99,
+    )
+    .id;
+    add_transaction(&db_path, checking.id, "2024-03-20", "Netflix", None, -15.99);
+    add_transaction(&db_path, savings.id, "2024-03-01", "Netflix", None, -15.99);
+    let conn = Connection::open(&db_path).unwrap();
+    conn.execute(
+        "UPDATE transactions SET external_id = 'FIT-1' WHERE id = ?1",
+        params![imported],
+    )
+    .unwrap();
+
+    let pairs = find_duplicates_db(&db_path, DEFAULT_WINDOW_DAYS, None).unwrap();
+    assert_eq!(pairs.len(), 1);
+    assert_eq!(pairs[0].kind, MatchKind::Fuzzy);
+    assert_eq!(pairs[0].days_apart, 1);
+    assert_eq!(pairs[0].keep.id, imported);
+    assert_eq!(pairs[0].duplicate.id, manual);
+
+    assert!(
+        find_duplicates_db(&db_path, DEFAULT_WINDOW_DAYS, Some(savings.id))
+            .unwrap()
+            .is_empty()
+    );
+}
+
+#[test]
+fn test_merge_duplicates_moves_details_and_fixes_balance() {
+    let (_dir, db_path) = setup_db();
+    let checking = crate::create_account_db(&db_path, "Checking".to_string(), 100.0, None).unwrap();
+    let other = crate::create_account_db(&db_path, "Other".to_string(), 0.0, None).unwrap();
+    let keep = add_transaction(&db_path, checking.id, "2024-04-01", "Gym", None, -30.0).id;
+    let duplicate = add_transaction(&db_path, checking.id, "2024-04-01", "Gym", None, -30.0).id;
+    let elsewhere = add_transaction(&db_path, other.id, "2024-04-01", "Gym", None, -30.0).id;
+    let conn = Connection::open(&db_path).unwrap();
+    conn.execute(
+        "UPDATE transactions SET category = 'Health', notes = 'Monthly' WHERE id = ?1",
+        params![duplicate],
+    )
+    .unwrap();
+
+    crate::categorize::retrain_category_model_db(&db_path, false).unwrap();
+    conn.execute(
+        "INSERT INTO lot_selections (sale_id, lot_id, shares) VALUES (?1, ?2, 1)",
+        params![duplicate, keep],
+    )
+    .unwrap();
+
+    assert!(merge_duplicates_db(&db_path, keep, keep).is_err());
+    assert!(merge_duplicates_db(&db_path, keep, elsewhere).is_err());
+
+    let merged = merge_duplicates_db(&db_path, keep, duplicate).unwrap();
+    assert_eq!(merged.id, keep);
+    assert_eq!(merged.category.as_deref(), Some("Health"));
+    assert_eq!(merged.notes.as_deref(), Some("Monthly"));
+
+    let remaining = crate::get_transactions_db(&db_path, checking.id).unwrap();
+    assert!(remaining.iter().all(|t| t.id != duplicate));
+    let accounts = crate::get_accounts_db(&db_path).unwrap();
+    let balance = accounts
+        .iter()
+        .find(|a| a.id == checking.id)
+        .unwrap()
+        .balance;
+    assert!((balance - 70.0).abs() < 1e-9);
+
+    // Nothing keeps pointing at the removed duplicate
+    let left = |sql: &str| -> i64 {
+        conn.query_row(sql, params![duplicate], |r| r.get(0))
+            .unwrap()
+    };
+    assert_eq!(
+        left("SELECT COUNT(*) FROM lot_selections WHERE sale_id = ?1 OR lot_id = ?1"),
+        0
+    );
+    assert_eq!(
+        left("SELECT COUNT(*) FROM category_training WHERE transaction_id = ?1"),
+        0
+    );
+}
@@ -0,0 +1,157 @@
+use super::common::{csv_mapping, setup_db};
+use crate::import::batches::{list_import_batches_db, rollback_import_batch_db};
+use crate::import::csv::import_csv_db;
+use crate::rules::RuleSelection;
+
+fn balance(db_path: &std::path::PathBuf, account_id: i32) -> f64 {
+    crate::get_accounts_db(db_path)
+        .unwrap()
+        .into_iter()
+        .find(|a| a.id == account_id)
+        .unwrap()
+        .balance
+}
+
+#[test]
+fn test_commit_records_batch_with_counts_and_hash() {
+    let (dir, db_path) = setup_db();
+    let account = crate::create_account_db(&db_path, "Card".to_string(), 0.0, None).unwrap();
+    let file = dir.path().join("march.csv");
+    std::fs::write(
+        &file,
+        "Date,Payee,Amount\n2024-03-01,Coffee,-3.50\n2024-03-02,Lunch,-12.00\nbad,Broken,-1\n",
+    )
+    .unwrap();
+
+    let preview = import_csv_db(
+        &db_path,
+        &file,
+        csv_mapping(account.id),
+        true,
+        &RuleSelection::All,
+    )
+    .unwrap();
+    assert_eq!(preview.batch_id, None);
+    assert!(list_import_batches_db(&db_path).unwrap().is_empty());
+
+    let report = import_csv_db(
+        &db_path,
+        &file,
+        csv_mapping(account.id),
+        false,
+        &RuleSelection::All,
+    )
+    .unwrap();
+    let batches = list_import_batches_db(&db_path).unwrap();
+    assert_eq!(batches.len(), 1);
+    let batch = &batches[0];
+    assert_eq!(report.batch_id, Some(batch.id));
+    assert_eq!(batch.file_name, "march.csv");
+    assert_eq!(batch.file_hash.len(), 64);
+    assert_eq!(batch.total_rows, 3);
+    assert_eq!(batch.imported_rows, 2);
+    assert_eq!(batch.error_rows, 1);
+    assert_eq!(batch.duplicate_rows, 0);
+    assert_eq!(batch.transactions, 2);
+}
+
+#[test]
+fn test_rollback_removes_batch_and_reverts_balance() {
+    let (dir, db_path) = setup_db();
+    let account = crate::create_account_db(&db_path, "Card".to_string(), 50.0, None).unwrap();
+    let first = dir.path().join("first.csv");
+    std::fs::write(&first, "Date,Payee,Amount\n2024-03-01,Coffee,-3.50\n").unwrap();
+    let second = dir.path().join("second.csv");
+    std::fs::write(
+        &second,
+        "Date,Payee,Amount\n2024-03-05,Grocer,-40.00\n2024-03-06,Refund,10.00\n",
+    )
+    .unwrap();
+    import_csv_db(
+        &db_path,
+        &first,
+        csv_mapping(account.id),
+        false,
+        &RuleSelection::All,
+    )
+    .unwrap();
+    let batch_id = import_csv_db(
+        &db_path,
+        &second,
+        csv_mapping(account.id),
+        false,
+        &RuleSelection::All,
+    )
+    .unwrap()
+    .batch_id
+    .unwrap();
+    assert!((balance(&db_path, account.id) - 16.5).abs() < 1e-9);
+    let conn = rusqlite::Connection::open(&db_path).unwrap();
+    conn.execute(
+        "UPDATE transactions SET category = 'Food' WHERE import_batch_id = ?1",
+        [batch_id],
+    )
+    .unwrap();
+    crate::categorize::retrain_category_model_db(&db_path, false).unwrap();
+
+    let report = rollback_import_batch_db(&db_path, batch_id).unwrap();
+    assert_eq!(report.removed_transactions, 2);
+    assert_eq!(report.batch.file_name, "second.csv");
+    assert!((balance(&db_path, account.id) - 46.5).abs() < 1e-9);
+
+    // The earlier import and the opening balance are untouched
+    let remaining = crate::get_transactions_db(&db_path, account.id).unwrap();
+    assert_eq!(remaining.len(), 2);
+    let batches = list_import_batches_db(&db_path).unwrap();
+    assert_eq!(batches.len(), 1);
+    assert_eq!(batches[0].file_name, "first.csv");
+    // What the category model learned from the rolled back rows is gone too
+    let trained: i64 = conn
+        .query_row(
+            "SELECT COUNT(*) FROM category_training
+             WHERE transaction_id NOT IN (SELECT id FROM transactions)",
+            [],
+            |r| r.get(0),
+        )
+        .unwrap();
+    assert_eq!(trained, 0);
+
+    assert!(rollback_import_batch_db(&db_path, batch_id).is_err());
+}
+
+#[test]
+fn test_rollback_removes_transfer_counterparts() {
+    let (dir, db_path) = setup_db();
+    let checking = crate::create_account_db(&db_path, "Checking".to_string(), 0.0, None).unwrap();
+    let savings = crate::create_account_db(&db_path, "Savings".to_string(), 0.0, None).unwrap();
+    let file = dir.path().join("checking.csv");
+    std::fs::write(&file, "Date,Payee,Amount\n2024-04-01,Savings,-200.00\n").unwrap();
+
+    let batch_id = import_csv_db(
+        &db_path,
+        &file,
+        csv_mapping(checking.id),
+        false,
+        &RuleSelection::All,
+    )
+    .unwrap()
+    .batch_id
+    .unwrap();
+    assert!((balance(&db_path, savings.id) - 200.0).abs() < 1e-9);
+    assert_eq!(list_import_batches_db(&db_path).unwrap()[0].transactions, 2);
+
+    let report = rollback_import_batch_db(&db_path, batch_id).unwrap();
+    assert_eq!(report.removed_transactions, 2);
+    assert!(balance(&db_path, checking.id).abs() < 1e-9);
+    assert!(balance(&db_path, savings.id).abs() < 1e-9);
+    assert!(crate::get_transactions_db(&db_path, savings.id)
+        .unwrap()
+        .is_empty());
+}
+
+#[test]
+fn test_rollback_unknown_batch_is_an_error() {
+    let (_dir, db_path) = setup_db();
+    let err = rollback_import_batch_db(&db_path, 42).unwrap_err();
+    assert!(err.contains("not found"));
+}
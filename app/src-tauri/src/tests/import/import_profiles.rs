@@ -0,0 +1,162 @@
+use super::common::setup_db;
+use crate::import::csv::{CsvMapping, SignConvention};
+use crate::import::profiles::{
+    create_import_profile_db, delete_import_profile_db, detect_import_profile_db,
+    import_with_profile_db, list_import_profiles_db,
+};
+
+fn write_file(dir: &std::path::Path, name: &str, content: &str) -> std::path::PathBuf {
+    let path = dir.join(name);
+    std::fs::write(&path, content).unwrap();
+    path
+}
+
+fn card_mapping(account_id: i32) -> CsvMapping {
+    CsvMapping {
+        date: Some("Booking Date".to_string()),
+        payee: Some("Merchant".to_string()),
+        amount: Some("Amount".to_string()),
+        date_format: Some("%d.%m.%Y".to_string()),
+        decimal_separator: ',',
+        sign_convention: SignConvention::PositiveIsExpense,
+        account_id: Some(account_id),
+        ..Default::default()
+    }
+}
+
+#[test]
+fn test_create_profile_stores_mapping_and_replaces_by_name() {
+    let (dir, db_path) = setup_db();
+    let sample = write_file(
+        dir.path(),
+        "jan.csv",
+        "Booking Date;Merchant;Amount\n01.01.2024;Bakery;3,20\n",
+    );
+
+    let profile = create_import_profile_db(
+        &db_path,
+        " Visa ".to_string(),
+        card_mapping(1),
+        None,
+        &sample,
+    )
+    .unwrap();
+    assert_eq!(profile.name, "Visa");
+    assert_eq!(profile.header_fingerprint, "booking date|merchant|amount");
+    assert_eq!(profile.mapping, card_mapping(1));
+
+    let updated = create_import_profile_db(
+        &db_path,
+        "Visa".to_string(),
+        card_mapping(2),
+        Some(vec![3, 1]),
+        &sample,
+    )
+    .unwrap();
+    assert_eq!(updated.id, profile.id);
+    let profiles = list_import_profiles_db(&db_path).unwrap();
+    assert_eq!(profiles.len(), 1);
+    assert_eq!(profiles[0].mapping.account_id, Some(2));
+    assert_eq!(profiles[0].rule_ids, Some(vec![3, 1]));
+
+    assert!(
+        create_import_profile_db(&db_path, "  ".to_string(), card_mapping(1), None, &sample)
+            .is_err()
+    );
+}
+
+#[test]
+fn test_detect_profile_from_header_fingerprint() {
+    let (dir, db_path) = setup_db();
+    let sample = write_file(
+        dir.path(),
+        "jan.csv",
+        "Booking Date;Merchant;Amount\n01.01.2024;Bakery;3,20\n",
+    );
+    create_import_profile_db(&db_path, "Visa".to_string(), card_mapping(1), None, &sample).unwrap();
+    // A bank that puts an account summary above the header
+    let preamble = write_file(
+        dir.path(),
+        "savings.csv",
+        "Account: 1234\nPeriod: January\nDate,Text,Value\n2024-01-02,Interest,1.00\n",
+    );
+    let savings = CsvMapping {
+        date: Some("Date".to_string()),
+        payee: Some("Text".to_string()),
+        amount: Some("Value".to_string()),
+        skip_rows: 2,
+        account_id: Some(1),
+        ..Default::default()
+    };
+    create_import_profile_db(&db_path, "Savings".to_string(), savings, None, &preamble).unwrap();
+
+    let february = write_file(
+        dir.path(),
+        "feb.csv",
+        "BOOKING DATE;Merchant;Amount\n03.02.2024;Cinema;12,00\n",
+    );
+    let detected = detect_import_profile_db(&db_path, &february).unwrap();
+    assert_eq!(detected.map(|p| p.name).as_deref(), Some("Visa"));
+
+    let march = write_file(
+        dir.path(),
+        "savings-march.csv",
+        "Account: 1234\nPeriod: March\nDate,Text,Value\n2024-03-02,Interest,1.10\n",
+    );
+    let detected = detect_import_profile_db(&db_path, &march).unwrap();
+    assert_eq!(detected.map(|p| p.name).as_deref(), Some("Savings"));
+
+    let unknown = write_file(dir.path(), "other.csv", "When,Who,How much\n");
+    assert!(detect_import_profile_db(&db_path, &unknown)
+        .unwrap()
+        .is_none());
+}
+
+#[test]
+fn test_import_with_detected_profile_is_one_step() {
+    let (dir, db_path) = setup_db();
+    let account = crate::create_account_db(&db_path, "Visa".to_string(), 0.0, None).unwrap();
+    let sample = write_file(
+        dir.path(),
+        "jan.csv",
+        "Booking Date;Merchant;Amount\n01.01.2024;Bakery;3,20\n",
+    );
+    create_import_profile_db(
+        &db_path,
+        "Visa".to_string(),
+        card_mapping(account.id),
+        None,
+        &sample,
+    )
+    .unwrap();
+
+    let february = write_file(
+        dir.path(),
+        "feb.csv",
+        "Booking Date;Merchant;Amount\n03.02.2024;Cinema;12,50\n04.02.2024;Refund;-2,00\n",
+    );
+    let report = import_with_profile_db(&db_path, &february, None, false).unwrap();
+    assert_eq!(report.imported, 2);
+    let transactions = crate::get_transactions_db(&db_path, account.id).unwrap();
+    let cinema = transactions.iter().find(|t| t.payee == "Cinema").unwrap();
+    assert_eq!(cinema.date, "2024-02-03");
+    assert_eq!(cinema.amount, -12.5);
+
+    let unknown = write_file(dir.path(), "other.csv", "When,Who,How much\n");
+    let err = import_with_profile_db(&db_path, &unknown, None, true).unwrap_err();
+    assert!(err.contains("No import profile"));
+    assert!(import_with_profile_db(&db_path, &february, Some(99), true).is_err());
+}
+
+#[test]
+fn test_delete_profile() {
+    let (dir, db_path) = setup_db();
+    let sample = write_file(dir.path(), "jan.csv", "Booking Date;Merchant;Amount\n");
+    let profile =
+        create_import_profile_db(&db_path, "Visa".to_string(), card_mapping(1), None, &sample)
+            .unwrap();
+
+    delete_import_profile_db(&db_path, profile.id).unwrap();
+    assert!(list_import_profiles_db(&db_path).unwrap().is_empty());
+    assert!(delete_import_profile_db(&db_path, profile.id).is_err());
+}
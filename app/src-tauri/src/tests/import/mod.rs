@@ -0,0 +1,12 @@
+pub use super::common;
+
+pub mod app_import;
+pub mod beancount_import;
+pub mod broker_import;
+pub mod csv_import;
+pub mod duplicate_import;
+pub mod import_batches;
+pub mod import_profiles;
+pub mod ofx_import;
+pub mod qif_import;
+pub mod statement_import;
@@ -0,0 +1,157 @@
+use super::common::setup_db;
+use crate::import::ofx::{import_ofx_db, parse_ofx, parse_ofx_tree};
+use crate::rules::RuleSelection;
+
+const SGML_BANK: &str = "OFXHEADER:100
+DATA:OFXSGML
+VERSION:102
+CHARSET:1252
+
+<OFX>
+<SIGNONMSGSRSV1><SONRS><STATUS><CODE>0<SEVERITY>INFO</STATUS><DTSERVER>20240131</SONRS></SIGNONMSGSRSV1>
+<BANKMSGSRSV1><STMTTRNRS><TRNUID>1<STMTRS>
+<CURDEF>USD
+<BANKACCTFROM><BANKID>121000248<ACCTID>000123<ACCTTYPE>CHECKING</BANKACCTFROM>
+<BANKTRANLIST><DTSTART>20240101<DTEND>20240131
+<STMTTRN><TRNTYPE>DEBIT<DTPOSTED>20240105120000[-5:EST]<TRNAMT>-42.50<FITID>A1<NAME>GROCER &amp; CO<MEMO>Card 1234</STMTTRN>
+<STMTTRN><TRNTYPE>CREDIT<DTPOSTED>20240115<TRNAMT>1000.00<FITID>A2<MEMO>PAYROLL</STMTTRN>
+</BANKTRANLIST>
+<LEDGERBAL><BALAMT>1057.50<DTASOF>20240131</LEDGERBAL>
+</STMTRS></STMTTRNRS></BANKMSGSRSV1>
+</OFX>
+";
+
+const XML_INVEST: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<?OFX OFXHEADER="200" VERSION="220" SECURITY="NONE"?>
+<OFX>
+  <INVSTMTMSGSRSV1><INVSTMTTRNRS><TRNUID>1</TRNUID><INVSTMTRS>
+    <DTASOF>20240301</DTASOF>
+    <CURDEF>USD</CURDEF>
+    <INVACCTFROM><BROKERID>broker.example</BROKERID><ACCTID>999</ACCTID></INVACCTFROM>
+    <INVTRANLIST>
+      <DTSTART>20240101</DTSTART><DTEND>20240301</DTEND>
+      <BUYSTOCK>
+        <INVBUY>
+          <INVTRAN><FITID>T1</FITID><DTTRADE>20240110</DTTRADE><MEMO></MEMO></INVTRAN>
+          <SECID><UNIQUEID>037833100</UNIQUEID><UNIQUEIDTYPE>CUSIP</UNIQUEIDTYPE></SECID>
+          <UNITS>10</UNITS><UNITPRICE>150.00</UNITPRICE><COMMISSION>1.00</COMMISSION>
+          <TOTAL>-1501.00</TOTAL><SUBACCTSEC>CASH</SUBACCTSEC><SUBACCTFUND>CASH</SUBACCTFUND>
+        </INVBUY>
+        <BUYTYPE>BUY</BUYTYPE>
+      </BUYSTOCK>
+      <SELLSTOCK>
+        <INVSELL>
+          <INVTRAN><FITID>T2</FITID><DTTRADE>20240220</DTTRADE></INVTRAN>
+          <SECID><UNIQUEID>037833100</UNIQUEID><UNIQUEIDTYPE>CUSIP</UNIQUEIDTYPE></SECID>
+          <UNITS>-4</UNITS><UNITPRICE>160.00</UNITPRICE><FEES>0.50</FEES><TOTAL>639.50</TOTAL>
+        </INVSELL>
+        <SELLTYPE>SELL</SELLTYPE>
+      </SELLSTOCK>
+      <INVBANKTRAN>
+        <STMTTRN><TRNTYPE>CREDIT</TRNTYPE><DTPOSTED>20240105</DTPOSTED><TRNAMT>2000</TRNAMT><FITID>C1</FITID><NAME>Deposit</NAME></STMTTRN>
+        <SUBACCTFUND>CASH</SUBACCTFUND>
+      </INVBANKTRAN>
+    </INVTRANLIST>
+    <INVBAL><AVAILCASH>1138.50</AVAILCASH></INVBAL>
+  </INVSTMTRS></INVSTMTTRNRS></INVSTMTMSGSRSV1>
+  <SECLISTMSGSRSV1><SECLIST>
+    <STOCKINFO><SECINFO><SECID><UNIQUEID>037833100</UNIQUEID><UNIQUEIDTYPE>CUSIP</UNIQUEIDTYPE></SECID><SECNAME>Apple Inc</SECNAME><TICKER>AAPL</TICKER></SECINFO></STOCKINFO>
+  </SECLIST></SECLISTMSGSRSV1>
+</OFX>
+"#;
+
+#[test]
+fn test_parse_sgml_tree_handles_unclosed_leaves() {
+    let tree = parse_ofx_tree(SGML_BANK).unwrap();
+    assert_eq!(tree.name, "OFX");
+    let parsed = parse_ofx(SGML_BANK, 1).unwrap();
+    assert_eq!(parsed.rows.len(), 2);
+    assert!(parsed.errors.is_empty());
+
+    let first = &parsed.rows[0];
+    assert_eq!(first.date, "2024-01-05");
+    assert_eq!(first.payee, "GROCER & CO");
+    assert_eq!(first.notes.as_deref(), Some("Card 1234"));
+    assert_eq!(first.amount, -42.5);
+    assert_eq!(first.external_id.as_deref(), Some("A1"));
+    assert_eq!(first.currency.as_deref(), Some("USD"));
+
+    // Without NAME the memo becomes the payee
+    assert_eq!(parsed.rows[1].payee, "PAYROLL");
+    assert_eq!(parsed.balances[0].amount, 1057.5);
+    assert_eq!(parsed.balances[0].date, "2024-01-31");
+}
+
+#[test]
+fn test_parse_xml_investment_statement() {
+    let parsed = parse_ofx(XML_INVEST, 7).unwrap();
+    assert!(parsed.errors.is_empty(), "{:?}", parsed.errors);
+    assert_eq!(parsed.rows.len(), 3);
+
+    let buy = &parsed.rows[0];
+    assert_eq!(buy.is_buy, Some(true));
+    assert_eq!(buy.ticker.as_deref(), Some("AAPL"));
+    assert_eq!(buy.shares, Some(10.0));
+    assert_eq!(buy.fee, Some(1.0));
+    assert_eq!(buy.amount, -1501.0);
+
+    let sell = &parsed.rows[1];
+    assert_eq!(sell.is_buy, Some(false));
+    assert_eq!(sell.shares, Some(4.0));
+    assert_eq!(sell.amount, 639.5);
+
+    assert_eq!(parsed.rows[2].payee, "Deposit");
+    assert_eq!(parsed.balances[0].amount, 1138.5);
+}
+
+#[test]
+fn test_import_ofx_investments_use_investment_path() {
+    let (dir, db_path) = setup_db();
+    let account = crate::create_account_db(&db_path, "Broker".to_string(), 0.0, None).unwrap();
+    let file = dir.path().join("broker.ofx");
+    std::fs::write(&file, XML_INVEST).unwrap();
+
+    let report = import_ofx_db(&db_path, &file, account.id, false, &RuleSelection::All).unwrap();
+    assert_eq!(report.imported, 3);
+    assert_eq!(report.balance_checks.len(), 1);
+    assert!(report.balance_checks[0].matches);
+
+    let txs = crate::get_transactions_db(&db_path, account.id).unwrap();
+    let sell = txs.iter().find(|t| t.payee == "Sell").unwrap();
+    assert_eq!(sell.shares, Some(-4.0));
+    assert_eq!(sell.category.as_deref(), Some("Investment"));
+}
+
+#[test]
+fn test_reimport_skips_known_fitids_and_checks_ledger_balance() {
+    let (dir, db_path) = setup_db();
+    let account = crate::create_account_db(&db_path, "Checking".to_string(), 0.0, None).unwrap();
+    let file = dir.path().join("bank.qfx");
+    std::fs::write(&file, SGML_BANK).unwrap();
+
+    // The statement claims 1057.50 but its lines only add up to 957.50
+    let preview = import_ofx_db(&db_path, &file, account.id, true, &RuleSelection::All).unwrap();
+    assert_eq!(preview.rows.len(), 2);
+    let check = &preview.balance_checks[0];
+    assert_eq!(check.statement_balance, 1057.5);
+    assert!((check.computed_balance - 957.5).abs() < 1e-9);
+    assert!(!check.matches);
+
+    let first = import_ofx_db(&db_path, &file, account.id, false, &RuleSelection::All).unwrap();
+    assert_eq!(first.imported, 2);
+
+    let second = import_ofx_db(&db_path, &file, account.id, false, &RuleSelection::All).unwrap();
+    assert_eq!(second.imported, 0);
+    assert_eq!(second.duplicates.len(), 2);
+    assert_eq!(
+        crate::get_transactions_db(&db_path, account.id)
+            .unwrap()
+            .len(),
+        2
+    );
+}
+
+#[test]
+fn test_rejects_non_ofx_content() {
+    assert!(parse_ofx("Date,Amount\n2024-01-01,1\n", 1).is_err());
+}
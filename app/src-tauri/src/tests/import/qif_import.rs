@@ -0,0 +1,291 @@
+use super::common::setup_db;
+use crate::export::qif::export_qif_db;
+use crate::import::qif::{import_qif_db, parse_qif};
+use crate::rules::RuleSelection;
+
+const MULTI_ACCOUNT: &str = "!Account
+NChecking
+TBank
+^
+!Type:Bank
+D1/ 5'24
+T-1,250.00
+PLandlord
+MJanuary
+LRent/Home
+^
+D01/10/2024
+T-120.00
+PSupermarket
+SFood:Groceries
+EWeekly shop
+$-100.00
+SHousehold
+$-15.00
+^
+D01/12/2024
+T-300.00
+PMove to savings
+L[Savings]
+^
+!Account
+NSavings
+TBank
+^
+!Type:Bank
+D01/12/2024
+T300.00
+L[Checking]
+^
+";
+
+const INVESTMENTS: &str = "!Type:Invst
+D02/01/2024
+NBuyX
+YAAPL
+I150
+Q10
+O1
+T1501
+L[Checking]
+^
+D02/15/2024
+NDiv
+YAAPL
+T12.5
+^
+D02/20/2024
+NSell
+YAAPL
+I160
+Q4
+T640
+^
+D02/25/2024
+NFoo
+^
+";
+
+#[test]
+fn test_parse_bank_sections_with_splits_and_classes() {
+    let parsed = parse_qif(MULTI_ACCOUNT, None, None).unwrap();
+    assert!(parsed.errors.is_empty(), "{:?}", parsed.errors);
+    let declared: Vec<&str> = parsed.accounts.iter().map(|a| a.name.as_str()).collect();
+    assert_eq!(declared, vec!["Checking", "Savings"]);
+
+    let rent = &parsed.rows[0];
+    assert_eq!(rent.date, "2024-01-05");
+    assert_eq!(rent.amount, -1250.0);
+    assert_eq!(rent.category.as_deref(), Some("Rent"));
+    assert_eq!(rent.account_name.as_deref(), Some("Checking"));
+
+    // Two splits plus the 5.00 the splits leave unassigned
+    let shop: Vec<_> = parsed.rows.iter().filter(|r| r.row == 2).collect();
+    assert_eq!(shop.len(), 3);
+    assert_eq!(shop[0].category.as_deref(), Some("Food:Groceries"));
+    assert_eq!(shop[0].notes.as_deref(), Some("Weekly shop"));
+    assert_eq!(shop[1].amount, -15.0);
+    assert!((shop[2].amount + 5.0).abs() < 1e-9);
+    assert!(shop.iter().all(|r| r.split_group == Some(2)));
+    assert_eq!(rent.split_group, None);
+}
+
+#[test]
+fn test_split_stays_grouped_through_export() {
+    let (dir, source) = setup_db();
+    let file = dir.path().join("splits.qif");
+    std::fs::write(&file, MULTI_ACCOUNT).unwrap();
+    import_qif_db(&source, &file, None, None, false, &RuleSelection::All).unwrap();
+    let checking = crate::get_accounts_db(&source)
+        .unwrap()
+        .into_iter()
+        .find(|a| a.name == "Checking")
+        .unwrap();
+    crate::create_transaction_db(
+        &source,
+        crate::CreateTransactionArgs {
+            account_id: checking.id,
+            date: "2024-01-20".to_string(),
+            payee: "Bakery".to_string(),
+            notes: None,
+            category: Some("Food".to_string()),
+            amount: 0.1 + 0.2,
+            ticker: None,
+            shares: None,
+            price_per_share: None,
+            fee: None,
+            currency: None,
+        },
+    )
+    .unwrap();
+
+    let exported = dir.path().join("export.qif");
+    export_qif_db(&source, &exported, None).unwrap();
+    let text = std::fs::read_to_string(&exported).unwrap();
+    assert!(text.contains(
+        "T-120.00\nPSupermarket\nSFood:Groceries\nEWeekly shop\n$-100.00\nSHousehold\n$-15.00\nS\n$-5.00\n^"
+    ));
+    assert!(text.contains("T0.30\n"));
+
+    let (_dir2, target) = setup_db();
+    import_qif_db(&target, &exported, None, None, false, &RuleSelection::All).unwrap();
+    let conn = rusqlite::Connection::open(&target).unwrap();
+    let groups: Vec<(i64, i64)> = conn
+        .prepare("SELECT split_id, COUNT(*) FROM transactions WHERE split_id IS NOT NULL GROUP BY split_id")
+        .unwrap()
+        .query_map([], |r| Ok((r.get(0)?, r.get(1)?)))
+        .unwrap()
+        .collect::<Result<_, _>>()
+        .unwrap();
+    assert_eq!(groups.len(), 1);
+    assert_eq!(groups[0].1, 3);
+}
+
+#[test]
+fn test_mirrored_transfer_is_imported_once() {
+    let (dir, db_path) = setup_db();
+    let file = dir.path().join("accounts.qif");
+    std::fs::write(&file, MULTI_ACCOUNT).unwrap();
+
+    let report = import_qif_db(&db_path, &file, None, None, false, &RuleSelection::All).unwrap();
+    assert!(report.errors.is_empty(), "{:?}", report.errors);
+    assert_eq!(report.created_accounts, vec!["Checking", "Savings"]);
+
+    let accounts = crate::get_accounts_db(&db_path).unwrap();
+    let checking = accounts.iter().find(|a| a.name == "Checking").unwrap();
+    let savings = accounts.iter().find(|a| a.name == "Savings").unwrap();
+    assert!((checking.balance + 1670.0).abs() < 1e-9);
+    assert_eq!(savings.balance, 300.0);
+
+    let savings_txs = crate::get_transactions_db(&db_path, savings.id).unwrap();
+    assert_eq!(savings_txs.len(), 1);
+    assert_eq!(savings_txs[0].payee, "Checking");
+    assert_eq!(savings_txs[0].category.as_deref(), Some("Transfer"));
+}
+
+#[test]
+fn test_transfers_to_new_accounts_keep_the_account_name() {
+    let (dir, db_path) = setup_db();
+    let file = dir.path().join("accounts.qif");
+    std::fs::write(
+        &file,
+        "!Account\nNSAVINGS\nTBank\n^\n!Type:Bank\nD01/12/2024\nT300.00\nL[CHECKING 1234]\n^\n",
+    )
+    .unwrap();
+
+    let report = import_qif_db(&db_path, &file, None, None, false, &RuleSelection::All).unwrap();
+    assert!(report.errors.is_empty(), "{:?}", report.errors);
+    let accounts = crate::get_accounts_db(&db_path).unwrap();
+    let savings = accounts.iter().find(|a| a.name == "SAVINGS").unwrap();
+    let checking = accounts.iter().find(|a| a.name == "CHECKING 1234").unwrap();
+    let savings_txs = crate::get_transactions_db(&db_path, savings.id).unwrap();
+    assert_eq!(savings_txs[0].payee, "CHECKING 1234");
+    assert_eq!(savings_txs[0].category.as_deref(), Some("Transfer"));
+    assert_eq!(checking.balance, -300.0);
+}
+
+#[test]
+fn test_investment_actions_into_default_account() {
+    let (dir, db_path) = setup_db();
+    let broker = crate::create_account_db(&db_path, "Broker".to_string(), 0.0, None).unwrap();
+    let file = dir.path().join("broker.qif");
+    std::fs::write(&file, INVESTMENTS).unwrap();
+
+    let report = import_qif_db(
+        &db_path,
+        &file,
+        Some(broker.id),
+        None,
+        false,
+        &RuleSelection::All,
+    )
+    .unwrap();
+    assert_eq!(report.errors.len(), 1);
+    assert!(report.errors[0].message.contains("Foo"));
+    assert_eq!(report.created_accounts, vec!["Checking"]);
+
+    let txs = crate::get_transactions_db(&db_path, broker.id).unwrap();
+    let buy = txs.iter().find(|t| t.payee == "Buy").unwrap();
+    assert_eq!(buy.shares, Some(10.0));
+    assert_eq!(buy.amount, -1501.0);
+    let sell = txs.iter().find(|t| t.payee == "Sell").unwrap();
+    assert_eq!(sell.shares, Some(-4.0));
+    assert_eq!(sell.amount, 640.0);
+    assert!(txs
+        .iter()
+        .any(|t| t.payee == "Dividend AAPL" && t.amount == 12.5));
+    // BuyX funds the purchase from Checking
+    assert!(txs
+        .iter()
+        .any(|t| t.payee == "Checking" && t.amount == 1501.0));
+}
+
+#[test]
+fn test_export_then_import_round_trips() {
+    let (dir, source) = setup_db();
+    crate::create_account_db(&source, "Checking".to_string(), 0.0, None).unwrap();
+    crate::create_account_db(&source, "Savings".to_string(), 0.0, None).unwrap();
+    let broker = crate::create_account_db(&source, "Broker".to_string(), 0.0, None).unwrap();
+    let original = dir.path().join("original.qif");
+    std::fs::write(&original, MULTI_ACCOUNT).unwrap();
+    import_qif_db(&source, &original, None, None, false, &RuleSelection::All).unwrap();
+    std::fs::write(&original, INVESTMENTS).unwrap();
+    import_qif_db(
+        &source,
+        &original,
+        Some(broker.id),
+        None,
+        false,
+        &RuleSelection::All,
+    )
+    .unwrap();
+
+    let exported = dir.path().join("export.qif");
+    let written = export_qif_db(&source, &exported, None).unwrap();
+    let text = std::fs::read_to_string(&exported).unwrap();
+    assert!(text.contains("I150.000000\nQ10.000000\n"), "{}", text);
+
+    let (_dir2, target) = setup_db();
+    let report = import_qif_db(&target, &exported, None, None, false, &RuleSelection::All).unwrap();
+    assert!(report.errors.is_empty(), "{:?}", report.errors);
+    // Mirrored transfer lines come back as the linked counterpart, not as separate imports
+    assert_eq!(
+        crate::get_all_transactions_db(&target).unwrap().len(),
+        written
+    );
+    assert!(report.imported < written);
+
+    let snapshot = |db| {
+        let names: std::collections::HashMap<i32, String> = crate::get_accounts_db(db)
+            .unwrap()
+            .into_iter()
+            .map(|a| (a.id, a.name))
+            .collect();
+        let mut rows: Vec<String> = crate::get_all_transactions_db(db)
+            .unwrap()
+            .into_iter()
+            .map(|t| {
+                format!(
+                    "{}|{}|{}|{:.2}|{:?}|{:?}|{:?}|{:?}|{:?}",
+                    names[&t.account_id],
+                    t.date,
+                    t.payee,
+                    t.amount,
+                    t.category,
+                    t.notes,
+                    t.ticker,
+                    t.shares,
+                    t.price_per_share
+                )
+            })
+            .collect();
+        rows.sort();
+        rows
+    };
+    assert_eq!(snapshot(&source), snapshot(&target));
+}
+
+#[test]
+fn test_rejects_content_without_headers() {
+    assert!(parse_qif("D01/01/2024\nT1\n^\n", Some(1), None).is_err());
+}
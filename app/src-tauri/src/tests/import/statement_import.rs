@@ -0,0 +1,269 @@
+use super::common::setup_db;
+use crate::import::camt::{import_camt053_db, parse_camt053};
+use crate::import::mt940::{import_mt940_db, parse_mt940};
+use crate::rules::RuleSelection;
+
+const CAMT: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<Document xmlns="urn:iso:std:iso:20022:tech:xsd:camt.053.001.02">
+  <BkToCstmrStmt>
+    <GrpHdr><MsgId>MSG1</MsgId><CreDtTm>2024-02-01T06:00:00</CreDtTm></GrpHdr>
+    <Stmt>
+      <Id>STMT-2024-01</Id>
+      <Acct><Id><IBAN>DE89370400440532013000</IBAN></Id><Ccy>EUR</Ccy></Acct>
+      <Bal>
+        <Tp><CdOrPrtry><Cd>OPBD</Cd></CdOrPrtry></Tp>
+        <Amt Ccy="EUR">1000.00</Amt><CdtDbtInd>CRDT</CdtDbtInd><Dt><Dt>2024-01-01</Dt></Dt>
+      </Bal>
+      <Bal>
+        <Tp><CdOrPrtry><Cd>CLBD</Cd></CdOrPrtry></Tp>
+        <Amt Ccy="EUR">1957.50</Amt><CdtDbtInd>CRDT</CdtDbtInd><Dt><Dt>2024-01-31</Dt></Dt>
+      </Bal>
+      <Ntry>
+        <Amt Ccy="EUR">42.50</Amt><CdtDbtInd>DBIT</CdtDbtInd><Sts>BOOK</Sts>
+        <BookgDt><Dt>2024-01-05</Dt></BookgDt><ValDt><Dt>2024-01-04</Dt></ValDt>
+        <AcctSvcrRef>BANK-001</AcctSvcrRef>
+        <NtryDtls><TxDtls>
+          <Refs><EndToEndId>E2E-GROCER-1</EndToEndId></Refs>
+          <RltdPties>
+            <Cdtr><Nm>Grocer GmbH</Nm></Cdtr>
+            <CdtrAcct><Id><IBAN>DE02120300000000202051</IBAN></Id></CdtrAcct>
+          </RltdPties>
+          <RmtInf><Ustrd>Invoice 4711</Ustrd></RmtInf>
+        </TxDtls></NtryDtls>
+      </Ntry>
+      <Ntry>
+        <Amt Ccy="EUR">1000.00</Amt><CdtDbtInd>CRDT</CdtDbtInd><Sts>BOOK</Sts>
+        <BookgDt><Dt>2024-01-15</Dt></BookgDt><ValDt><Dt>2024-01-15</Dt></ValDt>
+        <AcctSvcrRef>BANK-002</AcctSvcrRef>
+        <NtryDtls>
+          <TxDtls>
+            <Refs><AcctSvcrRef>BANK-002-1</AcctSvcrRef><EndToEndId>NOTPROVIDED</EndToEndId></Refs>
+            <AmtDtls><TxAmt><Amt Ccy="EUR">600.00</Amt></TxAmt></AmtDtls>
+            <RltdPties><Dbtr><Nm>Alice</Nm></Dbtr></RltdPties>
+          </TxDtls>
+          <TxDtls>
+            <Refs><AcctSvcrRef>BANK-002-2</AcctSvcrRef></Refs>
+            <AmtDtls><TxAmt><Amt Ccy="EUR">400.00</Amt></TxAmt></AmtDtls>
+            <RltdPties><Dbtr><Nm>Bob</Nm></Dbtr></RltdPties>
+          </TxDtls>
+        </NtryDtls>
+      </Ntry>
+      <Ntry>
+        <Amt Ccy="EUR">9.99</Amt><CdtDbtInd>DBIT</CdtDbtInd><Sts>PDNG</Sts>
+        <BookgDt><Dt>2024-01-31</Dt></BookgDt>
+      </Ntry>
+    </Stmt>
+  </BkToCstmrStmt>
+</Document>
+"#;
+
+const MT940: &str = "{1:F01BANKDEFFXXXX0000000000}{2:O940}{4:
+:20:STMT0001
+:25:37040044/0532013000
+:28C:1/1
+:60F:C231231EUR1000,00
+:61:2401040105D42,50NTRFNONREF//BANK-001
+:86:166?00SEPA-UEBERWEISUNG?20EREF+E2E-GROCER-1?21SVWZ+Invoice 4711?31DE02120
+300000000202051?32Grocer GmbH
+:61:2401150115C1000,00NTRFSALARY JAN
+:86:/TRTP/SEPA CREDIT TRANSFER/IBAN/NL91ABNA0417164300/BIC/ABNANL2A/NAME/ACME B.V./REMI/Salary
+ January/EREF/PAY-2024-01
+:62F:C240131EUR1957,50
+-}";
+
+fn seeded_account(db_path: &std::path::PathBuf) -> i32 {
+    let account = crate::create_account_db(db_path, "Girokonto".to_string(), 0.0, None).unwrap();
+    // Carried-over balance from before the statement period
+    crate::create_transaction_db(
+        db_path,
+        crate::CreateTransactionArgs {
+            account_id: account.id,
+            date: "2023-12-31".to_string(),
+            payee: "Opening Balance".to_string(),
+            notes: None,
+            category: Some("Income".to_string()),
+            amount: 1000.0,
+            ticker: None,
+            shares: None,
+            price_per_share: None,
+            fee: None,
+            currency: None,
+        },
+    )
+    .unwrap();
+    account.id
+}
+
+#[test]
+fn test_parse_camt053_entries_batches_and_balances() {
+    let parsed = parse_camt053(CAMT, 3).unwrap();
+    assert!(parsed.errors.is_empty(), "{:?}", parsed.errors);
+    // The pending entry is skipped and the batch becomes one row per payment
+    assert_eq!(parsed.total_rows, 2);
+    assert_eq!(parsed.rows.len(), 3);
+
+    let grocer = &parsed.rows[0];
+    assert_eq!(grocer.date, "2024-01-05");
+    assert_eq!(grocer.value_date.as_deref(), Some("2024-01-04"));
+    assert_eq!(grocer.amount, -42.5);
+    assert_eq!(grocer.payee, "Grocer GmbH");
+    assert_eq!(
+        grocer.notes.as_deref(),
+        Some("Invoice 4711; IBAN DE02120300000000202051; Ref E2E-GROCER-1")
+    );
+    assert_eq!(grocer.external_id.as_deref(), Some("E2E-GROCER-1:DBIT"));
+    assert_eq!(grocer.currency.as_deref(), Some("EUR"));
+
+    assert_eq!(parsed.rows[1].payee, "Alice");
+    assert_eq!(parsed.rows[1].amount, 600.0);
+    assert_eq!(parsed.rows[1].external_id.as_deref(), Some("BANK-002-1"));
+    assert_eq!(parsed.rows[2].amount, 400.0);
+    assert!(parsed.rows[2].value_date.is_none());
+
+    let balance = &parsed.balances[0];
+    assert_eq!(balance.date, "2024-01-31");
+    assert_eq!(balance.amount, 1957.5);
+    assert_eq!(balance.opening_amount, Some(1000.0));
+}
+
+#[test]
+fn test_import_camt053_verifies_closing_balance_and_skips_reimports() {
+    let (dir, db_path) = setup_db();
+    let account_id = seeded_account(&db_path);
+    let file = dir.path().join("statement.xml");
+    std::fs::write(&file, CAMT).unwrap();
+
+    let report =
+        import_camt053_db(&db_path, &file, account_id, false, &RuleSelection::All).unwrap();
+    assert_eq!(report.imported, 3);
+    let check = &report.balance_checks[0];
+    assert!(check.matches, "{:?}", check);
+    assert_eq!(check.opening_balance, Some(1000.0));
+
+    let again = import_camt053_db(&db_path, &file, account_id, false, &RuleSelection::All).unwrap();
+    assert_eq!(again.imported, 0);
+    assert_eq!(again.duplicates.len(), 3);
+    assert!(again.balance_checks[0].matches);
+}
+
+#[test]
+fn test_parse_mt940_structured_narratives() {
+    let parsed = parse_mt940(MT940, 3).unwrap();
+    assert!(parsed.errors.is_empty(), "{:?}", parsed.errors);
+    assert_eq!(parsed.rows.len(), 2);
+
+    // German `?NN` subfields
+    let grocer = &parsed.rows[0];
+    assert_eq!(grocer.date, "2024-01-05");
+    assert_eq!(grocer.value_date.as_deref(), Some("2024-01-04"));
+    assert_eq!(grocer.amount, -42.5);
+    assert_eq!(grocer.payee, "Grocer GmbH");
+    assert_eq!(
+        grocer.notes.as_deref(),
+        Some("Invoice 4711; IBAN DE02120300000000202051; Ref E2E-GROCER-1")
+    );
+    assert_eq!(grocer.external_id.as_deref(), Some("E2E-GROCER-1:DBIT"));
+
+    // `/KEY/value` pairs, with a value wrapped onto the next line
+    let salary = &parsed.rows[1];
+    assert_eq!(salary.payee, "ACME B.V.");
+    assert_eq!(salary.amount, 1000.0);
+    assert_eq!(
+        salary.notes.as_deref(),
+        Some("Salary January; IBAN NL91ABNA0417164300; Ref PAY-2024-01")
+    );
+    assert_eq!(salary.external_id.as_deref(), Some("PAY-2024-01:CRDT"));
+    assert_eq!(salary.currency.as_deref(), Some("EUR"));
+
+    assert_eq!(parsed.balances[0].amount, 1957.5);
+    assert_eq!(parsed.balances[0].opening_amount, Some(1000.0));
+}
+
+#[test]
+fn test_import_mt940_flags_balance_mismatch() {
+    let (dir, db_path) = setup_db();
+    let account = crate::create_account_db(&db_path, "Girokonto".to_string(), 0.0, None).unwrap();
+    let file = dir.path().join("statement.sta");
+    std::fs::write(&file, MT940).unwrap();
+
+    // Without the carried-over 1000 the ledger ends 1000 short of the statement
+    let report = import_mt940_db(&db_path, &file, account.id, true, &RuleSelection::All).unwrap();
+    let check = &report.balance_checks[0];
+    assert!(!check.matches);
+    assert!((check.computed_balance - 957.5).abs() < 1e-9);
+    assert!(crate::get_transactions_db(&db_path, account.id)
+        .unwrap()
+        .is_empty());
+}
+
+#[test]
+fn test_statement_parsers_reject_foreign_or_mixed_files() {
+    assert!(parse_camt053("<Document/>", 1).is_err());
+    assert!(parse_mt940("Date,Amount\n2024-01-01,1\n", 1).is_err());
+
+    let two_accounts = format!("{}\n{}", MT940, MT940.replace("0532013000", "0999999999"));
+    let err = parse_mt940(&two_accounts, 1).unwrap_err();
+    assert!(err.contains("several accounts"));
+}
+
+#[test]
+fn test_mt940_rejects_malformed_balance_without_panicking() {
+    let malformed = MT940.replace(":62F:C240131EUR1957,50", ":62F:C24€131EUR1957,50");
+    let err = parse_mt940(&malformed, 1).unwrap_err();
+    assert!(err.contains("Invalid balance"));
+}
+
+#[test]
+fn test_import_stores_value_date() {
+    let (dir, db_path) = setup_db();
+    let account_id = seeded_account(&db_path);
+    let file = dir.path().join("statement.sta");
+    std::fs::write(&file, MT940).unwrap();
+    import_mt940_db(&db_path, &file, account_id, false, &RuleSelection::All).unwrap();
+
+    let conn = rusqlite::Connection::open(&db_path).unwrap();
+    let (value_date, external_id): (Option<String>, Option<String>) = conn
+        .query_row(
+            "SELECT value_date, external_id FROM transactions WHERE payee = 'Grocer GmbH'",
+            [],
+            |r| Ok((r.get(0)?, r.get(1)?)),
+        )
+        .unwrap();
+    assert_eq!(value_date.as_deref(), Some("2024-01-04"));
+    assert_eq!(external_id.as_deref(), Some("E2E-GROCER-1:DBIT"));
+}
+
+#[test]
+fn test_batch_details_without_own_reference_get_distinct_ids() {
+    let camt = CAMT
+        .replace(
+            "<AcctSvcrRef>BANK-002-1</AcctSvcrRef><EndToEndId>NOTPROVIDED</EndToEndId>",
+            "",
+        )
+        .replace("<Refs><AcctSvcrRef>BANK-002-2</AcctSvcrRef></Refs>", "");
+    let parsed = parse_camt053(&camt, 3).unwrap();
+    let ids: Vec<Option<&str>> = parsed.rows[1..3]
+        .iter()
+        .map(|r| r.external_id.as_deref())
+        .collect();
+    assert_eq!(ids, vec![Some("BANK-002/1"), Some("BANK-002/2")]);
+}
+
+#[test]
+fn test_return_reusing_the_end_to_end_reference_is_imported() {
+    let (dir, db_path) = setup_db();
+    let account_id = seeded_account(&db_path);
+    // The grocer sends the payment back under the same end-to-end reference
+    let with_return = MT940.replace(
+        ":62F:C240131EUR1957,50",
+        ":61:2401200120C42,50NRTINONREF//BANK-003\n:86:159?00RETOURE?20EREF+E2E-GROCER-1?32Grocer GmbH\n:62F:C240131EUR2000,00",
+    );
+    let file = dir.path().join("statement.sta");
+    std::fs::write(&file, with_return).unwrap();
+
+    let report = import_mt940_db(&db_path, &file, account_id, false, &RuleSelection::All).unwrap();
+    assert!(report.duplicates.is_empty(), "{:?}", report.duplicates);
+    assert_eq!(report.imported, 3);
+    let returned = report.rows.iter().find(|r| r.amount == 42.5).unwrap();
+    assert_eq!(returned.external_id.as_deref(), Some("E2E-GROCER-1:CRDT"));
+}
@@ -5,6 +5,9 @@ pub mod accounts;
 pub mod app;
 pub mod brokerage;
 pub mod errors;
+pub mod export;
+pub mod holdings;
+pub mod import;
 pub mod multicurrency;
 pub mod payees;
 pub mod property;
@@ -0,0 +1,184 @@
+use super::common::{add_transaction, setup_db};
+use crate::categorize::{features, retrain_category_model_db, suggest_category_db};
+
+fn history(db_path: &std::path::PathBuf) -> i32 {
+    let account = crate::create_account_db(db_path, "Card".to_string(), 0.0, None).unwrap();
+    for payee in ["Starbucks 1021", "STARBUCKS Main St", "Costa Coffee"] {
+        add_transaction(
+            db_path,
+            account.id,
+            "2024-03-01",
+            payee,
+            Some("Coffee"),
+            -4.5,
+        );
+    }
+    for payee in ["Tesco Express", "TESCO STORES 2231", "Aldi"] {
+        add_transaction(
+            db_path,
+            account.id,
+            "2024-03-01",
+            payee,
+            Some("Groceries"),
+            -42.0,
+        );
+    }
+    add_transaction(
+        db_path,
+        account.id,
+        "2024-03-01",
+        "Acme Corp Payroll",
+        Some("Salary"),
+        2500.0,
+    );
+    account.id
+}
+
+#[test]
+fn test_features_tokenize_payee_notes_and_amount() {
+    assert_eq!(
+        features("AMAZON Mktp*1234 DE", Some("gift for Sam"), -35.2),
+        vec!["a:-1", "n:for", "n:gift", "n:sam", "p:amazon", "p:de", "p:mktp"]
+    );
+    assert_eq!(features("", None, 0.5), vec!["a:+0"]);
+}
+
+#[test]
+fn test_suggestions_are_ranked_with_confidence() {
+    let (_dir, db_path) = setup_db();
+    history(&db_path);
+
+    let suggestions = suggest_category_db(&db_path, "STARBUCKS 88", None, -5.1, None).unwrap();
+    assert_eq!(suggestions.len(), 3);
+    assert_eq!(suggestions[0].category, "Coffee");
+    assert!(suggestions[0].confidence > 0.8);
+    assert!(suggestions
+        .windows(2)
+        .all(|w| w[0].confidence >= w[1].confidence));
+
+    let groceries = suggest_category_db(&db_path, "Tesco Metro", None, -38.0, Some(1)).unwrap();
+    assert_eq!(groceries.len(), 1);
+    assert_eq!(groceries[0].category, "Groceries");
+
+    // Same input, same answer
+    assert_eq!(
+        suggest_category_db(&db_path, "STARBUCKS 88", None, -5.1, None).unwrap(),
+        suggestions
+    );
+}
+
+#[test]
+fn test_model_follows_ledger_changes_incrementally() {
+    let (_dir, db_path) = setup_db();
+    let account_id = history(&db_path);
+    let nothing_to_do = |db_path: &std::path::PathBuf| {
+        let report = retrain_category_model_db(db_path, false).unwrap();
+        assert_eq!((report.learned, report.forgotten), (0, 0));
+        report
+    };
+    assert_eq!(nothing_to_do(&db_path).categories, 3);
+
+    add_transaction(
+        &db_path,
+        account_id,
+        "2024-03-01",
+        "PureGym",
+        Some("Fitness"),
+        -25.0,
+    );
+    let gym = add_transaction(
+        &db_path,
+        account_id,
+        "2024-03-01",
+        "PUREGYM LTD",
+        Some("Fitness"),
+        -25.0,
+    );
+    assert_eq!(
+        suggest_category_db(&db_path, "PureGym", None, -25.0, None).unwrap()[0].category,
+        "Fitness"
+    );
+    nothing_to_do(&db_path);
+
+    crate::update_transaction_db(
+        &db_path,
+        crate::UpdateTransactionArgs {
+            id: gym.id,
+            account_id,
+            date: gym.date.clone(),
+            payee: gym.payee.clone(),
+            notes: None,
+            category: Some("Health".to_string()),
+            amount: -25.0,
+            currency: None,
+        },
+    )
+    .unwrap();
+    nothing_to_do(&db_path);
+
+    crate::delete_transaction_db(&db_path, gym.id).unwrap();
+    assert_eq!(nothing_to_do(&db_path).categories, 4);
+}
+
+#[test]
+fn test_suggestions_do_not_write_and_retrain_catches_up() {
+    let (_dir, db_path) = setup_db();
+    history(&db_path);
+    let before = suggest_category_db(&db_path, "Aldi", None, -42.0, None).unwrap();
+
+    // Edited behind the app's back: suggestions keep reading the model as it was
+    let conn = rusqlite::Connection::open(&db_path).unwrap();
+    conn.execute(
+        "UPDATE transactions SET category = 'Discounters' WHERE payee = 'Aldi'",
+        [],
+    )
+    .unwrap();
+    assert_eq!(
+        suggest_category_db(&db_path, "Aldi", None, -42.0, None).unwrap(),
+        before
+    );
+
+    let report = retrain_category_model_db(&db_path, false).unwrap();
+    assert_eq!((report.learned, report.forgotten), (1, 1));
+    assert_eq!(
+        suggest_category_db(&db_path, "Aldi", None, -42.0, None).unwrap()[0].category,
+        "Discounters"
+    );
+}
+
+#[test]
+fn test_rebuild_matches_incremental_model() {
+    let (_dir, db_path) = setup_db();
+    history(&db_path);
+    let incremental = suggest_category_db(&db_path, "Aldi Sud", None, -12.0, None).unwrap();
+
+    let report = retrain_category_model_db(&db_path, true).unwrap();
+    assert_eq!(report.learned, 7);
+    assert_eq!(
+        suggest_category_db(&db_path, "Aldi Sud", None, -12.0, None).unwrap(),
+        incremental
+    );
+}
+
+#[test]
+fn test_uncategorized_and_transfers_are_not_learned() {
+    let (_dir, db_path) = setup_db();
+    assert!(suggest_category_db(&db_path, "Anything", None, -1.0, None)
+        .unwrap()
+        .is_empty());
+
+    let checking = crate::create_account_db(&db_path, "Checking".to_string(), 0.0, None).unwrap();
+    crate::create_account_db(&db_path, "Savings".to_string(), 0.0, None).unwrap();
+    add_transaction(&db_path, checking.id, "2024-03-01", "Savings", None, -100.0);
+    add_transaction(
+        &db_path,
+        checking.id,
+        "2024-03-01",
+        "Corner shop",
+        None,
+        -3.0,
+    );
+    let report = retrain_category_model_db(&db_path, false).unwrap();
+    assert_eq!(report.learned, 0);
+    assert_eq!(report.categories, 0);
+}
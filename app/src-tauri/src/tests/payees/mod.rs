@@ -1,3 +1,5 @@
 pub use super::common;
 
+pub mod category_suggestions;
+pub mod payee_cleaning;
 pub mod payees_categories;
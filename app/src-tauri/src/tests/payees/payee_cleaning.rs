@@ -0,0 +1,152 @@
+use super::common::{add_rule, add_transaction, setup_db};
+use crate::import::csv::{import_csv_db, CsvMapping, SignConvention};
+use crate::payees::{
+    clean_payee_db, create_payee_rewrite_db, delete_payee_rewrite_db, list_payee_rewrites_db,
+};
+use crate::rules::RuleSelection;
+use rusqlite::Connection;
+
+fn raw_payee(db_path: &std::path::PathBuf, id: i32) -> Option<String> {
+    let conn = Connection::open(db_path).unwrap();
+    conn.query_row(
+        "SELECT raw_payee FROM transactions WHERE id = ?1",
+        [id],
+        |row| row.get(0),
+    )
+    .unwrap()
+}
+
+#[test]
+fn test_built_in_cleaners_strip_card_numbers_references_and_location() {
+    let (_dir, db_path) = setup_db();
+    let clean = |payee: &str| clean_payee_db(&db_path, payee).unwrap();
+    assert_eq!(
+        clean("CARD 1234 AMZN MKTP US*2K3H7 SEATTLE WA"),
+        "AMZN MKTP"
+    );
+    assert_eq!(clean("POS STARBUCKS #1021 SAN FRANCISCO CA"), "STARBUCKS");
+    assert_eq!(clean("NETFLIX.COM REF 88AB21 USA"), "NETFLIX.COM");
+    // Mixed case descriptors are typed by hand, so trailing words are kept
+    assert_eq!(clean("Dinner with Sam WA"), "Dinner with Sam WA");
+    assert_eq!(clean("12345"), "12345");
+    // Numbers belong to the name unless marked or trailing an all-capitals descriptor
+    assert_eq!(clean("1800 Flowers"), "1800 Flowers");
+    assert_eq!(clean("1800 FLOWERS"), "1800 FLOWERS");
+    assert_eq!(clean("7-Eleven 2231"), "7-Eleven 2231");
+    assert_eq!(clean("7-ELEVEN 2231"), "7-ELEVEN");
+    assert_eq!(clean("WALGREENS 1234 CHICAGO IL"), "WALGREENS");
+}
+
+#[test]
+fn test_rewrites_apply_in_order_after_built_ins() {
+    let (_dir, db_path) = setup_db();
+    let plain = create_payee_rewrite_db(
+        &db_path,
+        "AMZN MKTP".to_string(),
+        "Amazon".to_string(),
+        false,
+    )
+    .unwrap();
+    let regex = create_payee_rewrite_db(
+        &db_path,
+        r"^SQ \*?(.+)$".to_string(),
+        "$1".to_string(),
+        true,
+    )
+    .unwrap();
+    assert_eq!((plain.position, regex.position), (0, 1));
+    assert_eq!(list_payee_rewrites_db(&db_path).unwrap().len(), 2);
+
+    assert_eq!(
+        clean_payee_db(&db_path, "CARD 1234 AMZN MKTP US*2K3H7 SEATTLE WA").unwrap(),
+        "Amazon"
+    );
+    assert_eq!(
+        clean_payee_db(&db_path, "SQ *BLUE BOTTLE OAKLAND CA").unwrap(),
+        "BLUE BOTTLE"
+    );
+
+    assert!(create_payee_rewrite_db(&db_path, "(".to_string(), String::new(), true).is_err());
+    assert!(create_payee_rewrite_db(&db_path, " ".to_string(), String::new(), false).is_err());
+    delete_payee_rewrite_db(&db_path, plain.id).unwrap();
+    assert!(delete_payee_rewrite_db(&db_path, plain.id).is_err());
+    assert_eq!(
+        clean_payee_db(&db_path, "AMZN MKTP US*2K3H7").unwrap(),
+        "AMZN MKTP"
+    );
+}
+
+#[test]
+fn test_new_transactions_keep_raw_payee_and_transfers() {
+    let (_dir, db_path) = setup_db();
+    let checking = crate::create_account_db(&db_path, "Checking".to_string(), 0.0, None).unwrap();
+    crate::create_account_db(&db_path, "Savings".to_string(), 0.0, None).unwrap();
+    add_rule(&db_path, 1, "AMZN MKTP", ("category", "Shopping"));
+
+    let card = add_transaction(
+        &db_path,
+        checking.id,
+        "2024-03-01",
+        "CARD 1234 AMZN MKTP US*2K3H7 SEATTLE WA",
+        None,
+        -12.0,
+    );
+    assert_eq!(card.payee, "AMZN MKTP");
+    assert_eq!(card.category.as_deref(), Some("Shopping"));
+    assert_eq!(
+        raw_payee(&db_path, card.id).as_deref(),
+        Some("CARD 1234 AMZN MKTP US*2K3H7 SEATTLE WA")
+    );
+
+    let plain = add_transaction(
+        &db_path,
+        checking.id,
+        "2024-03-01",
+        "Corner shop",
+        None,
+        -12.0,
+    );
+    assert_eq!(raw_payee(&db_path, plain.id), None);
+
+    let transfer = add_transaction(&db_path, checking.id, "2024-03-01", "Savings", None, -12.0);
+    assert_eq!(transfer.payee, "Savings");
+    assert_eq!(transfer.category.as_deref(), Some("Transfer"));
+}
+
+#[test]
+fn test_csv_import_cleans_payees_before_rules() {
+    let (dir, db_path) = setup_db();
+    let account = crate::create_account_db(&db_path, "Card".to_string(), 0.0, None).unwrap();
+    add_rule(&db_path, 1, "STARBUCKS", ("category", "Coffee"));
+    let file = dir.path().join("card.csv");
+    std::fs::write(
+        &file,
+        "Date,Description,Amount\n2024-01-05,POS STARBUCKS #1021 SEATTLE WA,4.50\n",
+    )
+    .unwrap();
+    let mapping = CsvMapping {
+        date: Some("Date".to_string()),
+        payee: Some("Description".to_string()),
+        amount: Some("Amount".to_string()),
+        sign_convention: SignConvention::PositiveIsExpense,
+        account_id: Some(account.id),
+        ..Default::default()
+    };
+
+    let preview =
+        import_csv_db(&db_path, &file, mapping.clone(), true, &RuleSelection::All).unwrap();
+    assert_eq!(preview.rows[0].payee, "STARBUCKS");
+    assert_eq!(
+        preview.rows[0].raw_payee.as_deref(),
+        Some("POS STARBUCKS #1021 SEATTLE WA")
+    );
+
+    import_csv_db(&db_path, &file, mapping, false, &RuleSelection::All).unwrap();
+    let transactions = crate::get_transactions_db(&db_path, account.id).unwrap();
+    assert_eq!(transactions[0].payee, "STARBUCKS");
+    assert_eq!(transactions[0].category.as_deref(), Some("Coffee"));
+    assert_eq!(
+        raw_payee(&db_path, transactions[0].id).as_deref(),
+        Some("POS STARBUCKS #1021 SEATTLE WA")
+    );
+}
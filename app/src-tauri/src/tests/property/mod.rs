@@ -2,3 +2,4 @@ pub use super::common;
 
 pub mod property_invariants;
 pub mod property_proptest;
+pub mod snapshot_proptest;
@@ -0,0 +1,277 @@
+use crate::export::snapshot::{export_snapshot_db, import_snapshot_db, SnapshotMode};
+use crate::import::csv::CsvMapping;
+use crate::rules::retroactive::TransactionFilter;
+use crate::rules::{RuleAction, RuleSelection, SplitPart};
+use proptest::prelude::*;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use rusqlite::types::Value;
+use rusqlite::{params, Connection};
+use std::collections::BTreeMap;
+use std::path::Path;
+
+// Row ids of rule conditions and actions are not referenced anywhere; rows are identified by
+// rule and position, and every rewrite of a rule hands out new ids
+const SURROGATE_IDS: [&str; 2] = ["rule_conditions", "rule_actions"];
+
+// Every row of every table, as stored, straight from the database rather than through the
+// snapshot code under test
+fn dump(db_path: &Path) -> BTreeMap<String, Vec<String>> {
+    let conn = Connection::open(db_path).unwrap();
+    let tables: Vec<String> = conn
+        .prepare("SELECT name FROM sqlite_master WHERE type = 'table' AND name NOT LIKE 'sqlite_%'")
+        .unwrap()
+        .query_map([], |r| r.get(0))
+        .unwrap()
+        .collect::<Result<_, _>>()
+        .unwrap();
+    let mut dump = BTreeMap::new();
+    for table in tables {
+        let columns: Vec<String> = conn
+            .prepare(&format!("PRAGMA table_info({})", table))
+            .unwrap()
+            .query_map([], |r| r.get(1))
+            .unwrap()
+            .collect::<Result<Vec<String>, _>>()
+            .unwrap()
+            .into_iter()
+            .filter(|c| !(c == "id" && SURROGATE_IDS.contains(&table.as_str())))
+            .collect();
+        let mut stmt = conn
+            .prepare(&format!("SELECT {} FROM {}", columns.join(", "), table))
+            .unwrap();
+        let mut rows: Vec<String> = stmt
+            .query_map([], |r| {
+                let values = (0..columns.len())
+                    .map(|i| r.get::<_, Value>(i).map(|v| format!("{:?}", v)))
+                    .collect::<Result<Vec<_>, _>>()?;
+                Ok(values.join("|"))
+            })
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        rows.sort();
+        dump.insert(table, rows);
+    }
+    dump
+}
+
+fn random_ledger(db_path: &Path, seed: u64) {
+    let db_path = db_path.to_path_buf();
+    let mut rng = StdRng::seed_from_u64(seed);
+    let currencies = [None, Some("USD"), Some("EUR")];
+
+    let mut accounts = Vec::new();
+    for i in 0..rng.random_range(1..4) {
+        let currency = currencies[rng.random_range(0..currencies.len())].map(str::to_string);
+        let balance = rng.random_range(-500..500) as f64 / 4.0;
+        accounts.push(
+            crate::create_account_db(&db_path, format!("Acc{}", i), balance, currency).unwrap(),
+        );
+    }
+
+    for _ in 0..rng.random_range(0..40) {
+        let a = rng.random_range(0..accounts.len());
+        let op: f64 = rng.random();
+        if op < 0.5 {
+            let payee = if rng.random_bool(0.3) {
+                accounts[(a + 1) % accounts.len()].name.clone()
+            } else {
+                format!("Payee {}", rng.random_range(0..5))
+            };
+            let _ = crate::create_transaction_db(
+                &db_path,
+                crate::CreateTransactionArgs {
+                    account_id: accounts[a].id,
+                    date: format!(
+                        "2024-0{}-1{}",
+                        rng.random_range(1..10),
+                        rng.random_range(0..10)
+                    ),
+                    payee,
+                    notes: rng.random_bool(0.5).then(|| "note \"quoted\"".to_string()),
+                    category: rng.random_bool(0.7).then(|| "Food".to_string()),
+                    amount: rng.random_range(-10_000..10_000) as f64 / 100.0,
+                    ticker: None,
+                    shares: None,
+                    price_per_share: None,
+                    fee: None,
+                    currency: currencies[rng.random_range(0..currencies.len())].map(str::to_string),
+                },
+            );
+        } else if op < 0.75 {
+            let _ = crate::create_investment_transaction_db(
+                &db_path,
+                crate::CreateInvestmentTransactionArgs {
+                    account_id: accounts[a].id,
+                    date: "2024-03-01".to_string(),
+                    ticker: "VT".to_string(),
+                    shares: rng.random_range(1..20) as f64 / 2.0,
+                    price_per_share: rng.random_range(1..500) as f64 / 3.0,
+                    fee: rng.random_range(0..3) as f64,
+                    is_buy: rng.random_bool(0.6),
+                    currency: None,
+                },
+            );
+        } else {
+            // Deletes leave gaps in the id sequence that a restore must preserve
+            let all = crate::get_all_transactions_db(&db_path).unwrap();
+            if !all.is_empty() {
+                let tx = &all[rng.random_range(0..all.len())];
+                let _ = crate::delete_transaction_db(&db_path, tx.id);
+            }
+        }
+    }
+
+    // Rules through the API, so they carry their actions; one of them splits
+    let mut rule_ids = Vec::new();
+    for i in 0..rng.random_range(0..3) {
+        rule_ids.push(
+            crate::create_rule_db(
+                &db_path,
+                rng.random_range(0..5),
+                "payee".to_string(),
+                format!("Payee {}", i),
+                "category".to_string(),
+                "Groceries".to_string(),
+            )
+            .unwrap(),
+        );
+    }
+    if let Some(rule_id) = rule_ids.first() {
+        crate::rules::set_rule_actions_db(
+            &db_path,
+            *rule_id,
+            vec![
+                RuleAction::SetField {
+                    field: "notes".to_string(),
+                    value: "from rule".to_string(),
+                },
+                RuleAction::Split {
+                    parts: vec![
+                        SplitPart {
+                            category: "Food".to_string(),
+                            percent: 60.0,
+                        },
+                        SplitPart {
+                            category: "Household".to_string(),
+                            percent: 40.0,
+                        },
+                    ],
+                },
+            ],
+        )
+        .unwrap();
+    }
+    crate::rules::retroactive::apply_rules_to_existing_db(
+        &db_path,
+        &RuleSelection::All,
+        &TransactionFilter::default(),
+        false,
+        None,
+    )
+    .unwrap();
+
+    // An import batch and a saved profile for its layout
+    let csv = db_path.with_extension("csv");
+    let mut content = "Date,Payee,Amount\n".to_string();
+    for i in 0..rng.random_range(1..4) {
+        content.push_str(&format!(
+            "2024-04-0{},Payee {},{}\n",
+            i + 1,
+            rng.random_range(0..3),
+            rng.random_range(-5_000..5_000) as f64 / 100.0
+        ));
+    }
+    std::fs::write(&csv, content).unwrap();
+    let mapping = CsvMapping {
+        date: Some("Date".to_string()),
+        payee: Some("Payee".to_string()),
+        amount: Some("Amount".to_string()),
+        account_id: Some(accounts[0].id),
+        ..Default::default()
+    };
+    crate::import::csv::import_csv_db(&db_path, &csv, mapping.clone(), false, &RuleSelection::All)
+        .unwrap();
+    crate::import::profiles::create_import_profile_db(
+        &db_path,
+        "Bank".to_string(),
+        mapping,
+        Some(rule_ids.clone()),
+        &csv,
+    )
+    .unwrap();
+
+    crate::payees::create_payee_rewrite_db(
+        &db_path,
+        format!("SHOP {}", rng.random_range(0..9)),
+        "Shop".to_string(),
+        false,
+    )
+    .unwrap();
+    crate::categorize::retrain_category_model_db(&db_path, false).unwrap();
+
+    let conn = Connection::open(&db_path).unwrap();
+    conn.execute(
+        "UPDATE transactions SET value_date = '2024-01-02' WHERE id = (SELECT MIN(id) FROM transactions)",
+        [],
+    )
+    .unwrap();
+    // A hand-picked lot for the first sale that has a buy to pick from
+    let trades: Vec<(i32, i32, f64)> = conn
+        .prepare("SELECT id, account_id, shares FROM transactions WHERE ticker = 'VT' ORDER BY id")
+        .unwrap()
+        .query_map([], |r| Ok((r.get(0)?, r.get(1)?, r.get(2)?)))
+        .unwrap()
+        .collect::<Result<_, _>>()
+        .unwrap();
+    for (sale, account, sold) in trades.iter().filter(|t| t.2 < 0.0) {
+        if let Some((lot, _, bought)) = trades.iter().find(|t| t.1 == *account && t.2 > 0.0) {
+            let pick = crate::holdings::LotPick {
+                lot_id: *lot,
+                shares: bought.min(-sold),
+            };
+            crate::holdings::select_lots_db(&db_path, *sale, vec![pick]).unwrap();
+            break;
+        }
+    }
+    conn.execute(
+        "INSERT INTO custom_exchange_rates (currency, rate) VALUES ('XAU', ?1)",
+        params![rng.random::<f64>() * 3000.0],
+    )
+    .unwrap();
+    conn.execute(
+        "INSERT INTO stock_prices (ticker, price, last_updated) VALUES ('VT', ?1, '2024-03-02T10:00:00Z')",
+        params![rng.random::<f64>() * 100.0],
+    )
+    .unwrap();
+    for day in 1..rng.random_range(1..6) {
+        conn.execute(
+            "INSERT INTO daily_stock_prices (ticker, date, price) VALUES ('VT', ?1, ?2)",
+            params![format!("2024-03-0{}", day), 1.0 / day as f64],
+        )
+        .unwrap();
+    }
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(24))]
+
+    #[test]
+    fn prop_snapshot_export_import_is_identity(seed in any::<u64>(), noise in any::<u64>()) {
+        let dir = tempfile::tempdir().unwrap();
+        let source = dir.path().join("source.db");
+        let target = dir.path().join("target.db");
+        crate::init_db_at_path(&source).unwrap();
+        crate::init_db_at_path(&target).unwrap();
+        random_ledger(&source, seed);
+        // Whatever was in the target before a replace must not survive it
+        random_ledger(&target, noise);
+
+        let file = dir.path().join("snapshot.json");
+        export_snapshot_db(&source, &file).unwrap();
+        import_snapshot_db(&target, &file, SnapshotMode::Replace).unwrap();
+
+        prop_assert_eq!(dump(&source), dump(&target));
+    }
+}
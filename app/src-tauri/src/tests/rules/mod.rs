@@ -1,4 +1,11 @@
 pub mod create_rule;
 pub mod delete_rule;
 pub mod order_rules;
+pub mod retroactive_rules;
+pub mod rule_actions;
+pub mod rule_coverage;
+pub mod rules_engine;
+pub mod rules_on_create;
+pub mod rules_sharing;
+pub mod suggest_rules;
 pub mod update_rule;
@@ -0,0 +1,196 @@
+use crate::rules::retroactive::{
+    apply_rules_to_existing_db, undo_rule_run_db, FieldChange, TransactionFilter,
+};
+use crate::rules::RuleSelection;
+use crate::tests::common::{add_transaction, contains_rule, setup_db};
+
+// Inserted with rules disabled, as if typed before the rules existed
+fn balance(db_path: &std::path::PathBuf, account_id: i32) -> f64 {
+    crate::get_accounts_db(db_path)
+        .unwrap()
+        .into_iter()
+        .find(|a| a.id == account_id)
+        .unwrap()
+        .balance
+}
+
+#[test]
+fn test_dry_run_reports_field_changes_without_writing() {
+    let (_dir, db_path) = setup_db();
+    let account = crate::create_account_db(&db_path, "Card".to_string(), 0.0, None).unwrap();
+    let netflix = add_transaction(
+        &db_path,
+        account.id,
+        "2024-01-05",
+        "NETFLIX.COM",
+        None,
+        -15.99,
+    )
+    .id;
+    add_transaction(&db_path, account.id, "2024-01-06", "Bakery", None, -3.0);
+    let rule = contains_rule(&db_path, 1, "netflix", ("category", "Subscriptions"));
+
+    let report = apply_rules_to_existing_db(
+        &db_path,
+        &RuleSelection::All,
+        &TransactionFilter::default(),
+        true,
+        None,
+    )
+    .unwrap();
+    assert!(report.dry_run);
+    assert_eq!(report.applied, 0);
+    assert_eq!(report.run_id, None);
+    assert_eq!(report.diffs.len(), 1);
+    let diff = &report.diffs[0];
+    assert_eq!(diff.transaction_id, netflix);
+    assert_eq!(diff.rule_ids, vec![rule]);
+    assert_eq!(
+        diff.changes,
+        vec![FieldChange {
+            field: "category".to_string(),
+            old_value: None,
+            new_value: Some("Subscriptions".to_string()),
+        }]
+    );
+
+    let transactions = crate::get_transactions_db(&db_path, account.id).unwrap();
+    assert!(transactions.iter().all(|t| t.category.is_none()));
+}
+
+#[test]
+fn test_commit_applies_only_approved_changes() {
+    let (_dir, db_path) = setup_db();
+    let account = crate::create_account_db(&db_path, "Card".to_string(), 100.0, None).unwrap();
+    let january = add_transaction(&db_path, account.id, "2024-01-05", "Netflix", None, -15.99).id;
+    let february = add_transaction(&db_path, account.id, "2024-02-05", "Netflix", None, -15.99).id;
+    contains_rule(&db_path, 2, "netflix", ("category", "Subscriptions"));
+    contains_rule(&db_path, 1, "netflix", ("amount", "-17.99"));
+
+    let report = apply_rules_to_existing_db(
+        &db_path,
+        &RuleSelection::All,
+        &TransactionFilter::default(),
+        false,
+        Some(vec![february]),
+    )
+    .unwrap();
+    assert_eq!(report.applied, 1);
+    assert!(report.run_id.is_some());
+    assert_eq!(report.diffs[0].changes.len(), 2);
+
+    let transactions = crate::get_transactions_db(&db_path, account.id).unwrap();
+    let find = |id: i32| transactions.iter().find(|t| t.id == id).unwrap();
+    assert_eq!(find(january).category, None);
+    assert_eq!(find(february).category.as_deref(), Some("Subscriptions"));
+    assert_eq!(find(february).amount, -17.99);
+    assert!((balance(&db_path, account.id) - (100.0 - 15.99 - 17.99)).abs() < 1e-9);
+}
+
+#[test]
+fn test_undo_restores_fields_and_balance() {
+    let (_dir, db_path) = setup_db();
+    let account = crate::create_account_db(&db_path, "Card".to_string(), 100.0, None).unwrap();
+    let id = add_transaction(&db_path, account.id, "2024-01-05", "AMZN Mktp", None, -20.0).id;
+    contains_rule(&db_path, 2, "amzn", ("payee", "Amazon"));
+    // Sees the payee as renamed by the rule above
+    contains_rule(&db_path, 1, "amazon", ("amount", "-25"));
+
+    let run_id = apply_rules_to_existing_db(
+        &db_path,
+        &RuleSelection::All,
+        &TransactionFilter::default(),
+        false,
+        None,
+    )
+    .unwrap()
+    .run_id
+    .unwrap();
+    assert!((balance(&db_path, account.id) - 75.0).abs() < 1e-9);
+
+    let report = undo_rule_run_db(&db_path, run_id).unwrap();
+    assert_eq!(report.restored, 1);
+    let transactions = crate::get_transactions_db(&db_path, account.id).unwrap();
+    let restored = transactions.iter().find(|t| t.id == id).unwrap();
+    assert_eq!(restored.payee, "AMZN Mktp");
+    assert_eq!(restored.amount, -20.0);
+    assert!((balance(&db_path, account.id) - 80.0).abs() < 1e-9);
+
+    let err = undo_rule_run_db(&db_path, run_id).unwrap_err();
+    assert!(err.contains("not found"));
+}
+
+#[test]
+fn test_undo_keeps_later_edits_to_other_fields() {
+    let (_dir, db_path) = setup_db();
+    let account = crate::create_account_db(&db_path, "Card".to_string(), 0.0, None).unwrap();
+    let id = add_transaction(&db_path, account.id, "2024-01-05", "Netflix", None, -15.99).id;
+    contains_rule(&db_path, 1, "netflix", ("category", "Subscriptions"));
+    let run_id = apply_rules_to_existing_db(
+        &db_path,
+        &RuleSelection::All,
+        &TransactionFilter::default(),
+        false,
+        None,
+    )
+    .unwrap()
+    .run_id
+    .unwrap();
+
+    crate::update_transaction_db(
+        &db_path,
+        crate::UpdateTransactionArgs {
+            id,
+            account_id: account.id,
+            date: "2024-01-05".to_string(),
+            payee: "Netflix".to_string(),
+            notes: Some("family plan".to_string()),
+            category: Some("Subscriptions".to_string()),
+            amount: -17.99,
+            currency: None,
+        },
+    )
+    .unwrap();
+
+    undo_rule_run_db(&db_path, run_id).unwrap();
+    let transactions = crate::get_transactions_db(&db_path, account.id).unwrap();
+    let restored = transactions.iter().find(|t| t.id == id).unwrap();
+    assert_eq!(restored.category, None);
+    assert_eq!(restored.notes.as_deref(), Some("family plan"));
+    assert_eq!(restored.amount, -17.99);
+    assert!((balance(&db_path, account.id) + 17.99).abs() < 1e-9);
+}
+
+#[test]
+fn test_filter_and_selection_limit_the_run() {
+    let (_dir, db_path) = setup_db();
+    let card = crate::create_account_db(&db_path, "Card".to_string(), 0.0, None).unwrap();
+    let checking = crate::create_account_db(&db_path, "Checking".to_string(), 0.0, None).unwrap();
+    add_transaction(&db_path, card.id, "2023-12-30", "Netflix", None, -15.99);
+    let in_range = add_transaction(&db_path, card.id, "2024-01-05", "Netflix", None, -15.99).id;
+    add_transaction(&db_path, checking.id, "2024-01-05", "Netflix", None, -15.99);
+    // Transfers are never rewritten
+    add_transaction(&db_path, card.id, "2024-01-07", "Checking", None, -50.0);
+    contains_rule(&db_path, 2, "netflix", ("category", "Subscriptions"));
+    let rent = contains_rule(&db_path, 1, "checking", ("notes", "Rent"));
+
+    let filter = TransactionFilter {
+        account_ids: Some(vec![card.id]),
+        date_from: Some("2024-01-01".to_string()),
+        ..Default::default()
+    };
+    let report =
+        apply_rules_to_existing_db(&db_path, &RuleSelection::All, &filter, true, None).unwrap();
+    let ids: Vec<i32> = report.diffs.iter().map(|d| d.transaction_id).collect();
+    assert_eq!(ids, vec![in_range]);
+
+    let report = apply_rules_to_existing_db(
+        &db_path,
+        &RuleSelection::Only(vec![rent]),
+        &TransactionFilter::default(),
+        true,
+        None,
+    )
+    .unwrap();
+    assert!(report.diffs.is_empty());
+}
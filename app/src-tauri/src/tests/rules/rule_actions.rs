@@ -0,0 +1,268 @@
+use crate::rules::{set_rule_actions_db, RuleAction, SplitPart};
+use crate::tests::common::{add_rule, csv_mapping, setup_db};
+use crate::{get_rules_db, update_rule_db};
+use rusqlite::Connection;
+
+fn rule(db_path: &std::path::PathBuf, payee: &str, actions: Vec<RuleAction>) -> i32 {
+    let id = add_rule(db_path, 1, payee, ("category", ""));
+    set_rule_actions_db(db_path, id, actions).unwrap();
+    id
+}
+
+fn args(account_id: i32, payee: &str, amount: f64) -> crate::CreateTransactionArgs {
+    crate::CreateTransactionArgs {
+        account_id,
+        date: "2024-07-01".to_string(),
+        payee: payee.to_string(),
+        notes: None,
+        category: None,
+        amount,
+        ticker: None,
+        shares: None,
+        price_per_share: None,
+        fee: None,
+        currency: None,
+    }
+}
+
+fn set(field: &str, value: &str) -> RuleAction {
+    RuleAction::SetField {
+        field: field.to_string(),
+        value: value.to_string(),
+    }
+}
+
+fn part(category: &str, percent: f64) -> SplitPart {
+    SplitPart {
+        category: category.to_string(),
+        percent,
+    }
+}
+
+#[test]
+fn test_one_rule_sets_several_fields() {
+    let (_dir, db_path) = setup_db();
+    let account = crate::create_account_db(&db_path, "Card".to_string(), 0.0, None).unwrap();
+    rule(
+        &db_path,
+        "AMZN Mktp",
+        vec![
+            set("payee", "Amazon"),
+            set("category", "Shopping"),
+            RuleAction::AppendNote {
+                text: "online".to_string(),
+            },
+            RuleAction::AddTag {
+                tag: "amazon".to_string(),
+            },
+        ],
+    );
+
+    let mut typed = args(account.id, "AMZN Mktp", -30.0);
+    typed.notes = Some("gift".to_string());
+    let tx = crate::create_transaction_db(&db_path, typed).unwrap();
+    assert_eq!(tx.payee, "Amazon");
+    assert_eq!(tx.category.as_deref(), Some("Shopping"));
+    assert_eq!(tx.notes.as_deref(), Some("gift online"));
+
+    let conn = Connection::open(&db_path).unwrap();
+    let tags: String = conn
+        .query_row(
+            "SELECT tags FROM transactions WHERE id = ?1",
+            [tx.id],
+            |r| r.get(0),
+        )
+        .unwrap();
+    assert_eq!(tags, r#"["amazon"]"#);
+}
+
+#[test]
+fn test_actions_are_stored_in_order_and_mirrored() {
+    let (_dir, db_path) = setup_db();
+    let id = rule(
+        &db_path,
+        "Netflix",
+        vec![
+            RuleAction::AddTag {
+                tag: "tv".to_string(),
+            },
+            set("category", "Subscriptions"),
+        ],
+    );
+    let rules = get_rules_db(&db_path).unwrap();
+    assert_eq!(rules[0].action_field, "addTag");
+    assert_eq!(rules[0].action_value, "tv");
+    assert_eq!(rules[0].actions.len(), 2);
+    assert_eq!(rules[0].actions[1], set("category", "Subscriptions"));
+
+    // The simple form edits the first action and keeps the rest
+    update_rule_db(
+        &db_path,
+        id,
+        1,
+        "payee".to_string(),
+        "Netflix".to_string(),
+        "category".to_string(),
+        "TV".to_string(),
+    )
+    .unwrap();
+    assert_eq!(
+        get_rules_db(&db_path).unwrap()[0].actions,
+        vec![set("category", "TV"), set("category", "Subscriptions")]
+    );
+}
+
+#[test]
+fn test_transfer_action_links_the_transfer() {
+    let (_dir, db_path) = setup_db();
+    let checking = crate::create_account_db(&db_path, "Checking".to_string(), 0.0, None).unwrap();
+    let savings = crate::create_account_db(&db_path, "Savings".to_string(), 0.0, None).unwrap();
+    rule(
+        &db_path,
+        "STANDING ORDER 42",
+        vec![RuleAction::TransferTo {
+            account_id: savings.id,
+        }],
+    );
+
+    let tx = crate::create_transaction_db(&db_path, args(checking.id, "STANDING ORDER 42", -100.0))
+        .unwrap();
+    assert_eq!(tx.payee, "Savings");
+    assert_eq!(tx.category.as_deref(), Some("Transfer"));
+    let counterpart = crate::get_transactions_db(&db_path, savings.id).unwrap();
+    assert_eq!(counterpart.len(), 1);
+    assert_eq!(counterpart[0].amount, 100.0);
+}
+
+#[test]
+fn test_split_action_creates_one_transaction_per_part() {
+    let (_dir, db_path) = setup_db();
+    let account = crate::create_account_db(&db_path, "Card".to_string(), 0.0, None).unwrap();
+    rule(
+        &db_path,
+        "Costco",
+        vec![RuleAction::Split {
+            parts: vec![
+                part("Groceries", 33.0),
+                part("Household", 33.0),
+                part("Garden", 34.0),
+            ],
+        }],
+    );
+
+    crate::create_transaction_db(&db_path, args(account.id, "Costco", -100.01)).unwrap();
+    let transactions = crate::get_transactions_db(&db_path, account.id).unwrap();
+    assert_eq!(transactions.len(), 3);
+    let amount = |category: &str| {
+        transactions
+            .iter()
+            .find(|t| t.category.as_deref() == Some(category))
+            .unwrap()
+            .amount
+    };
+    assert_eq!(amount("Groceries"), -33.0);
+    assert_eq!(amount("Household"), -33.0);
+    assert_eq!(amount("Garden"), -34.01);
+    let balance = crate::get_accounts_db(&db_path).unwrap()[0].balance;
+    assert!((balance + 100.01).abs() < 1e-9);
+}
+
+#[test]
+fn test_invalid_actions_are_rejected() {
+    let (_dir, db_path) = setup_db();
+    let id = add_rule(&db_path, 1, "X", ("category", "Y"));
+    let invalid = [
+        set("amount", "ten"),
+        set("colour", "red"),
+        RuleAction::AddTag {
+            tag: " ".to_string(),
+        },
+        RuleAction::TransferTo { account_id: 99 },
+        RuleAction::Split {
+            parts: vec![part("A", 50.0), part("B", 40.0)],
+        },
+        RuleAction::Split {
+            parts: vec![part("A", 100.0)],
+        },
+    ];
+    for action in invalid {
+        assert!(set_rule_actions_db(&db_path, id, vec![action]).is_err());
+    }
+    assert!(set_rule_actions_db(&db_path, id, vec![]).is_err());
+    assert!(set_rule_actions_db(&db_path, 99, vec![set("category", "Z")]).is_err());
+}
+
+#[test]
+fn test_migration_moves_single_actions_into_child_table() {
+    let dir = tempfile::tempdir().unwrap();
+    let db_path = dir.path().join("legacy.db");
+    crate::init_db_at_path(&db_path).unwrap();
+    let conn = Connection::open(&db_path).unwrap();
+    conn.execute(
+        "INSERT INTO rules (priority, match_field, match_pattern, action_field, action_value) VALUES (1, 'payee', 'Shell', 'category', 'Fuel')",
+        [],
+    )
+    .unwrap();
+
+    crate::init_db_at_path(&db_path).unwrap();
+    crate::init_db_at_path(&db_path).unwrap();
+    let rows: Vec<(String, String, String)> = conn
+        .prepare("SELECT kind, field, value FROM rule_actions")
+        .unwrap()
+        .query_map([], |r| Ok((r.get(0)?, r.get(1)?, r.get(2)?)))
+        .unwrap()
+        .map(|r| r.unwrap())
+        .collect();
+    assert_eq!(
+        rows,
+        vec![(
+            "setField".to_string(),
+            "category".to_string(),
+            "Fuel".to_string()
+        )]
+    );
+}
+
+#[test]
+fn test_import_expands_split_rows_and_records_tags() {
+    let (dir, db_path) = setup_db();
+    let account = crate::create_account_db(&db_path, "Card".to_string(), 0.0, None).unwrap();
+    rule(
+        &db_path,
+        "Costco",
+        vec![
+            RuleAction::Split {
+                parts: vec![part("Groceries", 75.0), part("Household", 25.0)],
+            },
+            RuleAction::AddTag {
+                tag: "bulk".to_string(),
+            },
+        ],
+    );
+    let file = dir.path().join("july.csv");
+    std::fs::write(&file, "Date,Payee,Amount\n2024-07-02,Costco,-80.00\n").unwrap();
+    let mapping = csv_mapping(account.id);
+
+    let report = crate::import::csv::import_csv_db(
+        &db_path,
+        &file,
+        mapping,
+        false,
+        &crate::rules::RuleSelection::All,
+    )
+    .unwrap();
+    assert_eq!(report.imported, 2);
+    let amounts: Vec<(Option<String>, f64)> = report
+        .rows
+        .iter()
+        .map(|r| (r.category.clone(), r.amount))
+        .collect();
+    assert_eq!(
+        amounts,
+        vec![
+            (Some("Groceries".to_string()), -60.0),
+            (Some("Household".to_string()), -20.0)
+        ]
+    );
+    assert!(report.rows.iter().all(|r| r.tags == vec!["bulk"]));
+}
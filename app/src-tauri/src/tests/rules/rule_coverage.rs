@@ -0,0 +1,217 @@
+use crate::rules::coverage::test_rules_db;
+use crate::rules::RuleSelection;
+use crate::tests::common::{add_transaction, contains_rule, setup_db};
+use rusqlite::Connection;
+
+#[test]
+fn test_counts_matches_and_changes() {
+    let (_dir, db_path) = setup_db();
+    let account = crate::create_account_db(&db_path, "Card".to_string(), 0.0, None).unwrap();
+    add_transaction(
+        &db_path,
+        account.id,
+        "2024-02-01",
+        "NETFLIX.COM",
+        None,
+        -9.99,
+    );
+    add_transaction(
+        &db_path,
+        account.id,
+        "2024-02-01",
+        "Netflix",
+        Some("Subscriptions"),
+        -9.99,
+    );
+    add_transaction(
+        &db_path,
+        account.id,
+        "2024-02-01",
+        "netflix 2",
+        Some("TV"),
+        -9.99,
+    );
+    add_transaction(&db_path, account.id, "2024-02-01", "Bakery", None, -9.99);
+    let rule = contains_rule(&db_path, 1, "netflix", ("category", "Subscriptions"));
+
+    let report = test_rules_db(&db_path).unwrap();
+    assert_eq!(report.transactions, 4);
+    let coverage = &report.rules[0];
+    assert_eq!(coverage.rule_id, rule);
+    assert_eq!((coverage.matched, coverage.changed), (3, 2));
+    assert_eq!(coverage.shadowed, 0);
+    assert!(!coverage.never_matched);
+    assert_eq!(coverage.error, None);
+
+    // Nothing is written
+    let transactions = crate::get_transactions_db(&db_path, account.id).unwrap();
+    assert_eq!(
+        transactions
+            .iter()
+            .filter(|t| t.category.as_deref() == Some("Subscriptions"))
+            .count(),
+        1
+    );
+}
+
+#[test]
+fn test_reports_rules_shadowed_by_higher_priority() {
+    let (_dir, db_path) = setup_db();
+    let account = crate::create_account_db(&db_path, "Card".to_string(), 0.0, None).unwrap();
+    add_transaction(
+        &db_path,
+        account.id,
+        "2024-02-01",
+        "Amazon Prime",
+        None,
+        -9.99,
+    );
+    add_transaction(
+        &db_path,
+        account.id,
+        "2024-02-01",
+        "Amazon Marketplace",
+        None,
+        -9.99,
+    );
+    let broad = contains_rule(&db_path, 3, "amazon", ("category", "Shopping"));
+    let narrow = contains_rule(&db_path, 2, "amazon prime", ("category", "Subscriptions"));
+    // Writes another field, so it is not shadowed
+    let notes = contains_rule(&db_path, 1, "prime", ("notes", "yearly"));
+
+    let report = test_rules_db(&db_path).unwrap();
+    let ids: Vec<i32> = report.rules.iter().map(|r| r.rule_id).collect();
+    assert_eq!(ids, vec![broad, narrow, notes]);
+    assert_eq!(report.rules[0].changed, 2);
+    assert_eq!(report.rules[1].matched, 1);
+    assert_eq!(report.rules[1].changed, 0);
+    assert_eq!(report.rules[1].shadowed, 1);
+    assert_eq!(report.rules[1].shadowed_by, vec![broad]);
+    assert_eq!(report.rules[2].shadowed, 0);
+    assert_eq!(report.rules[2].changed, 1);
+}
+
+#[test]
+fn test_flags_invalid_and_unmatched_rules() {
+    let (_dir, db_path) = setup_db();
+    let account = crate::create_account_db(&db_path, "Card".to_string(), 0.0, None).unwrap();
+    add_transaction(&db_path, account.id, "2024-02-01", "Shell", None, -9.99);
+    let unused = contains_rule(&db_path, 2, "esso", ("category", "Fuel"));
+    let broken = contains_rule(&db_path, 1, "shell", ("category", "Fuel"));
+    // A regex saved before validation existed
+    Connection::open(&db_path)
+        .unwrap()
+        .execute(
+            "UPDATE rule_conditions SET operator = 'regex', value = '(shell' WHERE rule_id = ?1",
+            [broken],
+        )
+        .unwrap();
+
+    let report = test_rules_db(&db_path).unwrap();
+    let find = |id: i32| report.rules.iter().find(|r| r.rule_id == id).unwrap();
+    assert!(find(unused).never_matched);
+    assert_eq!(find(unused).error, None);
+    assert!(find(broken)
+        .error
+        .as_deref()
+        .unwrap()
+        .contains("Invalid regex"));
+    assert!(!find(broken).never_matched);
+    assert_eq!(find(broken).matched, 0);
+}
+
+#[test]
+fn test_rewriting_the_same_value_is_not_a_change() {
+    let (_dir, db_path) = setup_db();
+    let account = crate::create_account_db(&db_path, "Card".to_string(), 0.0, None).unwrap();
+    add_transaction(
+        &db_path,
+        account.id,
+        "2024-02-01",
+        "Amazon",
+        Some("Shopping"),
+        -9.99,
+    );
+    let same = contains_rule(&db_path, 2, "amazon", ("category", "Shopping"));
+    let lower = contains_rule(&db_path, 1, "amazon", ("category", "Other"));
+
+    let report = test_rules_db(&db_path).unwrap();
+    assert_eq!((report.rules[0].matched, report.rules[0].changed), (1, 0));
+    // The field is still taken, so the lower rule cannot change it
+    assert_eq!(report.rules[1].shadowed_by, vec![same]);
+    assert_eq!(report.rules[1].rule_id, lower);
+
+    let created = crate::create_transaction_with_rules_db(
+        &db_path,
+        crate::CreateTransactionArgs {
+            account_id: account.id,
+            date: "2024-02-02".to_string(),
+            payee: "Amazon".to_string(),
+            notes: None,
+            category: Some("Shopping".to_string()),
+            amount: -5.0,
+            ticker: None,
+            shares: None,
+            price_per_share: None,
+            fee: None,
+            currency: None,
+        },
+        &RuleSelection::All,
+    )
+    .unwrap();
+    let rule_ids: Option<String> = Connection::open(&db_path)
+        .unwrap()
+        .query_row(
+            "SELECT rule_ids FROM transactions WHERE id = ?1",
+            [created.id],
+            |r| r.get(0),
+        )
+        .unwrap();
+    assert_eq!(rule_ids, None);
+}
+
+#[test]
+fn test_engine_skips_rules_with_invalid_actions() {
+    let (_dir, db_path) = setup_db();
+    let account = crate::create_account_db(&db_path, "Card".to_string(), 0.0, None).unwrap();
+    let savings = crate::create_account_db(&db_path, "Savings".to_string(), 0.0, None).unwrap();
+    let rule = contains_rule(&db_path, 1, "rent", ("category", "Housing"));
+    crate::rules::set_rule_actions_db(
+        &db_path,
+        rule,
+        vec![
+            crate::rules::RuleAction::SetField {
+                field: "category".to_string(),
+                value: "Housing".to_string(),
+            },
+            crate::rules::RuleAction::TransferTo {
+                account_id: savings.id,
+            },
+        ],
+    )
+    .unwrap();
+    crate::delete_account_db(&db_path, savings.id).unwrap();
+    add_transaction(&db_path, account.id, "2024-02-01", "Rent", None, -9.99);
+
+    let report = test_rules_db(&db_path).unwrap();
+    assert!(report.rules[0].error.is_some());
+    let created = crate::create_transaction_with_rules_db(
+        &db_path,
+        crate::CreateTransactionArgs {
+            account_id: account.id,
+            date: "2024-02-02".to_string(),
+            payee: "Rent".to_string(),
+            notes: None,
+            category: None,
+            amount: -500.0,
+            ticker: None,
+            shares: None,
+            price_per_share: None,
+            fee: None,
+            currency: None,
+        },
+        &RuleSelection::All,
+    )
+    .unwrap();
+    assert_eq!(created.category, None);
+}
@@ -0,0 +1,216 @@
+use crate::rules::{
+    apply_rules_db, set_rule_conditions_db, DraftTransaction, MatchJoin, MatchOperator,
+    RuleCondition,
+};
+use crate::tests::common::{add_rule, setup_db};
+use crate::{get_rules_db, update_rule_db};
+
+fn condition(field: &str, operator: MatchOperator, value: &str) -> RuleCondition {
+    RuleCondition {
+        field: field.to_string(),
+        operator,
+        value: value.to_string(),
+        value_to: None,
+        case_sensitive: false,
+    }
+}
+
+fn draft(payee: &str, amount: f64) -> DraftTransaction {
+    DraftTransaction {
+        account_id: Some(1),
+        date: "2024-05-10".to_string(),
+        payee: payee.to_string(),
+        amount,
+        ..Default::default()
+    }
+}
+
+#[test]
+fn test_text_operators_and_case_sensitivity() {
+    let (_dir, db_path) = setup_db();
+    let contains = add_rule(&db_path, 4, "", ("category", "Subscriptions"));
+    set_rule_conditions_db(
+        &db_path,
+        contains,
+        MatchJoin::And,
+        vec![condition("payee", MatchOperator::Contains, "netflix")],
+    )
+    .unwrap();
+    let regex = add_rule(&db_path, 3, "", ("notes", "card payment"));
+    set_rule_conditions_db(
+        &db_path,
+        regex,
+        MatchJoin::And,
+        vec![RuleCondition {
+            case_sensitive: true,
+            ..condition("payee", MatchOperator::Regex, r"^[A-Z]+\.COM\b")
+        }],
+    )
+    .unwrap();
+
+    let outcome = apply_rules_db(&db_path, draft("NETFLIX.COM 8443", -15.99)).unwrap();
+    assert_eq!(
+        outcome.transaction.category.as_deref(),
+        Some("Subscriptions")
+    );
+    assert_eq!(outcome.transaction.notes.as_deref(), Some("card payment"));
+    assert_eq!(outcome.rule_ids, vec![contains, regex]);
+
+    // The regex is case sensitive, `contains` is not
+    let outcome = apply_rules_db(&db_path, draft("netflix.com", -15.99)).unwrap();
+    assert_eq!(outcome.rule_ids, vec![contains]);
+    assert_eq!(outcome.transaction.notes, None);
+}
+
+#[test]
+fn test_amount_date_and_account_conditions_with_and_or() {
+    let (_dir, db_path) = setup_db();
+    let big_spend = add_rule(&db_path, 2, "", ("category", "Large purchase"));
+    set_rule_conditions_db(
+        &db_path,
+        big_spend,
+        MatchJoin::And,
+        vec![
+            RuleCondition {
+                value_to: Some("-100".to_string()),
+                ..condition("amount", MatchOperator::AmountBetween, "")
+            },
+            RuleCondition {
+                value_to: Some("2024-12-31".to_string()),
+                ..condition("date", MatchOperator::DateRange, "2024-01-01")
+            },
+        ],
+    )
+    .unwrap();
+    let either = add_rule(&db_path, 1, "", ("notes", "Flagged"));
+    set_rule_conditions_db(
+        &db_path,
+        either,
+        MatchJoin::Or,
+        vec![
+            condition("amount", MatchOperator::AmountSign, "positive"),
+            condition("account", MatchOperator::AccountIs, "7"),
+        ],
+    )
+    .unwrap();
+
+    let outcome = apply_rules_db(&db_path, draft("Store", -250.0)).unwrap();
+    assert_eq!(outcome.rule_ids, vec![big_spend]);
+
+    let mut last_year = draft("Store", -250.0);
+    last_year.date = "2023-12-31".to_string();
+    assert!(apply_rules_db(&db_path, last_year)
+        .unwrap()
+        .rule_ids
+        .is_empty());
+
+    assert_eq!(
+        apply_rules_db(&db_path, draft("Salary", 3000.0))
+            .unwrap()
+            .rule_ids,
+        vec![either]
+    );
+    let mut other_account = draft("Store", -5.0);
+    other_account.account_id = Some(7);
+    assert_eq!(
+        apply_rules_db(&db_path, other_account).unwrap().rule_ids,
+        vec![either]
+    );
+}
+
+#[test]
+fn test_priority_wins_and_later_rules_see_earlier_changes() {
+    let (_dir, db_path) = setup_db();
+    let rename = add_rule(&db_path, 10, "", ("payee", "Amazon"));
+    set_rule_conditions_db(
+        &db_path,
+        rename,
+        MatchJoin::And,
+        vec![condition("payee", MatchOperator::StartsWith, "amzn")],
+    )
+    .unwrap();
+    let shopping = add_rule(&db_path, 5, "Amazon", ("category", "Shopping"));
+    let fallback = add_rule(&db_path, 1, "", ("category", "Misc"));
+    set_rule_conditions_db(
+        &db_path,
+        fallback,
+        MatchJoin::And,
+        vec![condition("payee", MatchOperator::EndsWith, "on")],
+    )
+    .unwrap();
+
+    let outcome = apply_rules_db(&db_path, draft("AMZN Mktp DE", -20.0)).unwrap();
+    assert_eq!(outcome.transaction.payee, "Amazon");
+    assert_eq!(outcome.transaction.category.as_deref(), Some("Shopping"));
+    assert_eq!(outcome.rule_ids, vec![rename, shopping]);
+}
+
+#[test]
+fn test_legacy_rules_match_exactly_and_simple_update_drops_conditions() {
+    let (_dir, db_path) = setup_db();
+    let id = add_rule(&db_path, 0, "Starbucks", ("category", "Coffee"));
+    assert!(apply_rules_db(&db_path, draft("starbucks", -4.0))
+        .unwrap()
+        .rule_ids
+        .is_empty());
+    assert_eq!(
+        apply_rules_db(&db_path, draft("Starbucks", -4.0))
+            .unwrap()
+            .rule_ids,
+        vec![id]
+    );
+
+    set_rule_conditions_db(
+        &db_path,
+        id,
+        MatchJoin::Or,
+        vec![condition("payee", MatchOperator::Contains, "coffee")],
+    )
+    .unwrap();
+    let rules = get_rules_db(&db_path).unwrap();
+    assert_eq!(rules[0].match_join, MatchJoin::Or);
+    assert_eq!(rules[0].match_pattern, "coffee");
+    assert_eq!(rules[0].conditions.len(), 1);
+
+    update_rule_db(
+        &db_path,
+        id,
+        0,
+        "payee".to_string(),
+        "Costa".to_string(),
+        "category".to_string(),
+        "Coffee".to_string(),
+    )
+    .unwrap();
+    // The simple form edits the first condition and keeps how it matches
+    let rules = get_rules_db(&db_path).unwrap();
+    assert_eq!(
+        rules[0].conditions,
+        vec![condition("payee", MatchOperator::Contains, "Costa")]
+    );
+    assert_eq!(rules[0].match_join, MatchJoin::Or);
+}
+
+#[test]
+fn test_invalid_conditions_are_rejected() {
+    let (_dir, db_path) = setup_db();
+    let id = add_rule(&db_path, 0, "", ("category", "X"));
+    let invalid = [
+        condition("payee", MatchOperator::Regex, "(unclosed"),
+        condition("amount", MatchOperator::AmountBetween, "ten"),
+        condition("amount", MatchOperator::AmountSign, "up"),
+        condition("date", MatchOperator::DateRange, "05/10/2024"),
+        condition("account", MatchOperator::AccountIs, ""),
+    ];
+    for c in invalid {
+        assert!(set_rule_conditions_db(&db_path, id, MatchJoin::And, vec![c]).is_err());
+    }
+    assert!(set_rule_conditions_db(&db_path, id, MatchJoin::And, vec![]).is_err());
+    assert!(set_rule_conditions_db(
+        &db_path,
+        999,
+        MatchJoin::And,
+        vec![condition("payee", MatchOperator::Equals, "x")]
+    )
+    .is_err());
+}
@@ -0,0 +1,163 @@
+use crate::import::csv::import_csv_db;
+use crate::import::profiles::{create_import_profile_db, import_with_profile_db};
+use crate::rules::RuleSelection;
+use crate::tests::common::{add_rule, csv_mapping, setup_db};
+use rusqlite::Connection;
+
+fn args(account_id: i32, payee: &str) -> crate::CreateTransactionArgs {
+    crate::CreateTransactionArgs {
+        account_id,
+        date: "2024-06-01".to_string(),
+        payee: payee.to_string(),
+        notes: None,
+        category: None,
+        amount: -4.5,
+        ticker: None,
+        shares: None,
+        price_per_share: None,
+        fee: None,
+        currency: None,
+    }
+}
+
+fn recorded_rule_ids(db_path: &std::path::PathBuf, transaction_id: i32) -> Option<String> {
+    let conn = Connection::open(db_path).unwrap();
+    conn.query_row(
+        "SELECT rule_ids FROM transactions WHERE id = ?1",
+        [transaction_id],
+        |row| row.get(0),
+    )
+    .unwrap()
+}
+
+#[test]
+fn test_create_transaction_applies_rules_and_records_them() {
+    let (_dir, db_path) = setup_db();
+    let account = crate::create_account_db(&db_path, "Card".to_string(), 0.0, None).unwrap();
+    let coffee = add_rule(&db_path, 1, "Starbucks", ("category", "Coffee"));
+
+    let tx = crate::create_transaction_db(&db_path, args(account.id, "Starbucks")).unwrap();
+    assert_eq!(tx.category.as_deref(), Some("Coffee"));
+    assert_eq!(
+        recorded_rule_ids(&db_path, tx.id).as_deref(),
+        Some(format!("[{}]", coffee).as_str())
+    );
+
+    let untouched = crate::create_transaction_db(&db_path, args(account.id, "Bakery")).unwrap();
+    assert_eq!(untouched.category, None);
+    assert_eq!(recorded_rule_ids(&db_path, untouched.id), None);
+}
+
+#[test]
+fn test_create_transaction_rule_selection() {
+    let (_dir, db_path) = setup_db();
+    let account = crate::create_account_db(&db_path, "Card".to_string(), 0.0, None).unwrap();
+    add_rule(&db_path, 2, "Starbucks", ("category", "Coffee"));
+    let treats = add_rule(&db_path, 1, "Starbucks", ("category", "Treats"));
+
+    let disabled = crate::create_transaction_with_rules_db(
+        &db_path,
+        args(account.id, "Starbucks"),
+        &RuleSelection::Disabled,
+    )
+    .unwrap();
+    assert_eq!(disabled.category, None);
+    assert_eq!(recorded_rule_ids(&db_path, disabled.id), None);
+
+    // Only the lower-priority rule is selected, so it is free to set the category
+    let only = crate::create_transaction_with_rules_db(
+        &db_path,
+        args(account.id, "Starbucks"),
+        &RuleSelection::Only(vec![treats]),
+    )
+    .unwrap();
+    assert_eq!(only.category.as_deref(), Some("Treats"));
+}
+
+#[test]
+fn test_csv_import_applies_rules_in_preview_and_commit() {
+    let (dir, db_path) = setup_db();
+    let account = crate::create_account_db(&db_path, "Card".to_string(), 0.0, None).unwrap();
+    let coffee = add_rule(&db_path, 1, "Starbucks", ("category", "Coffee"));
+    let file = dir.path().join("june.csv");
+    std::fs::write(
+        &file,
+        "Date,Payee,Amount\n2024-06-01,Starbucks,-4.50\n2024-06-02,Bakery,-3.00\n",
+    )
+    .unwrap();
+
+    let preview = import_csv_db(
+        &db_path,
+        &file,
+        csv_mapping(account.id),
+        true,
+        &RuleSelection::All,
+    )
+    .unwrap();
+    assert_eq!(preview.rows[0].category.as_deref(), Some("Coffee"));
+    assert_eq!(preview.rows[0].rule_ids, vec![coffee]);
+    assert!(preview.rows[1].rule_ids.is_empty());
+
+    import_csv_db(
+        &db_path,
+        &file,
+        csv_mapping(account.id),
+        false,
+        &RuleSelection::All,
+    )
+    .unwrap();
+    let transactions = crate::get_transactions_db(&db_path, account.id).unwrap();
+    let starbucks = transactions
+        .iter()
+        .find(|t| t.payee == "Starbucks")
+        .unwrap();
+    assert_eq!(starbucks.category.as_deref(), Some("Coffee"));
+    assert_eq!(
+        recorded_rule_ids(&db_path, starbucks.id).as_deref(),
+        Some(format!("[{}]", coffee).as_str())
+    );
+}
+
+#[test]
+fn test_import_with_rules_disabled_leaves_rows_untouched() {
+    let (dir, db_path) = setup_db();
+    let account = crate::create_account_db(&db_path, "Card".to_string(), 0.0, None).unwrap();
+    add_rule(&db_path, 1, "Starbucks", ("category", "Coffee"));
+    let file = dir.path().join("june.csv");
+    std::fs::write(&file, "Date,Payee,Amount\n2024-06-01,Starbucks,-4.50\n").unwrap();
+
+    let report = import_csv_db(
+        &db_path,
+        &file,
+        csv_mapping(account.id),
+        false,
+        &RuleSelection::Disabled,
+    )
+    .unwrap();
+    assert_eq!(report.imported, 1);
+    let transactions = crate::get_transactions_db(&db_path, account.id).unwrap();
+    assert_eq!(transactions[0].category, None);
+    assert_eq!(recorded_rule_ids(&db_path, transactions[0].id), None);
+}
+
+#[test]
+fn test_profile_rule_ids_limit_the_rules_applied() {
+    let (dir, db_path) = setup_db();
+    let account = crate::create_account_db(&db_path, "Card".to_string(), 0.0, None).unwrap();
+    add_rule(&db_path, 2, "Starbucks", ("category", "Coffee"));
+    let treats = add_rule(&db_path, 1, "Starbucks", ("category", "Treats"));
+    let file = dir.path().join("june.csv");
+    std::fs::write(&file, "Date,Payee,Amount\n2024-06-01,Starbucks,-4.50\n").unwrap();
+    let profile = create_import_profile_db(
+        &db_path,
+        "Card".to_string(),
+        csv_mapping(account.id),
+        Some(vec![treats]),
+        &file,
+    )
+    .unwrap();
+
+    let report = import_with_profile_db(&db_path, &file, Some(profile.id), true).unwrap();
+    assert_eq!(report.rows[0].category.as_deref(), Some("Treats"));
+    assert_eq!(report.rows[0].rule_ids, vec![treats]);
+}
@@ -0,0 +1,240 @@
+use crate::get_rules_db;
+use crate::rules::sharing::{
+    export_rules_db, import_rules_db, parse_rule_set, read_rule_set, ConflictStrategy,
+    RuleSetFormat, RULE_SET_VERSION,
+};
+use crate::rules::{set_rule_actions_db, set_rule_conditions_db, MatchJoin, MatchOperator};
+use crate::rules::{RuleAction, RuleCondition};
+use crate::tests::common::{add_rule, setup_db};
+use rusqlite::Connection;
+
+fn contains(value: &str) -> RuleCondition {
+    RuleCondition {
+        field: "payee".to_string(),
+        operator: MatchOperator::Contains,
+        value: value.to_string(),
+        value_to: None,
+        case_sensitive: false,
+    }
+}
+
+fn rule_set(rules: &str) -> String {
+    format!(
+        r#"{{"version": {}, "accounts": [{{"id": 7, "name": "Savings"}}], "rules": [{}]}}"#,
+        RULE_SET_VERSION, rules
+    )
+}
+
+#[test]
+fn test_rules_round_trip_between_ledgers_as_json() {
+    let (dir, db_path) = setup_db();
+    crate::create_account_db(&db_path, "Checking".to_string(), 0.0, None).unwrap();
+    let savings = crate::create_account_db(&db_path, "Savings".to_string(), 0.0, None).unwrap();
+    add_rule(&db_path, 2, "Shell", ("category", "Fuel"));
+    let standing = add_rule(&db_path, 1, "", ("category", ""));
+    set_rule_conditions_db(
+        &db_path,
+        standing,
+        MatchJoin::Or,
+        vec![contains("standing order")],
+    )
+    .unwrap();
+    set_rule_actions_db(
+        &db_path,
+        standing,
+        vec![RuleAction::TransferTo {
+            account_id: savings.id,
+        }],
+    )
+    .unwrap();
+    let file = dir.path().join("rules.json");
+    assert_eq!(export_rules_db(&db_path, &file, None, None).unwrap(), 2);
+
+    let (_other_dir, other_db) = setup_db();
+    let other_savings =
+        crate::create_account_db(&other_db, "Savings".to_string(), 0.0, None).unwrap();
+    assert_ne!(other_savings.id, savings.id);
+    let report = import_rules_db(&other_db, &file, None, ConflictStrategy::Skip).unwrap();
+    assert_eq!(report.imported, 2);
+
+    let rules = get_rules_db(&other_db).unwrap();
+    assert_eq!(rules[0].match_pattern, "Shell");
+    // The legacy rule is written out as the exact match it always was
+    assert_eq!(rules[0].conditions[0].operator, MatchOperator::Equals);
+    assert!(rules[0].conditions[0].case_sensitive);
+    assert_eq!(rules[1].match_join, MatchJoin::Or);
+    assert_eq!(
+        rules[1].actions,
+        vec![RuleAction::TransferTo {
+            account_id: other_savings.id
+        }]
+    );
+}
+
+#[test]
+fn test_rules_round_trip_as_toml() {
+    let (dir, db_path) = setup_db();
+    let netflix = add_rule(&db_path, 3, "", ("category", ""));
+    set_rule_conditions_db(&db_path, netflix, MatchJoin::And, vec![contains("netflix")]).unwrap();
+    set_rule_actions_db(
+        &db_path,
+        netflix,
+        vec![
+            RuleAction::SetField {
+                field: "category".to_string(),
+                value: "TV".to_string(),
+            },
+            RuleAction::AddTag {
+                tag: "streaming".to_string(),
+            },
+        ],
+    )
+    .unwrap();
+    let file = dir.path().join("rules.toml");
+    export_rules_db(&db_path, &file, None, None).unwrap();
+    let text = std::fs::read_to_string(&file).unwrap();
+    assert!(text.contains("version = 1"));
+    // Keys are camelCase at every level, as in the rule conditions
+    assert!(text.contains("matchJoin = ") && text.contains("exportedAt = "));
+    assert!(!text.contains("match_join") && !text.contains("exported_at"));
+
+    let (_other_dir, other_db) = setup_db();
+    import_rules_db(&other_db, &file, None, ConflictStrategy::Skip).unwrap();
+    let exported = read_rule_set(&Connection::open(&db_path).unwrap(), None).unwrap();
+    let imported = read_rule_set(&Connection::open(&other_db).unwrap(), None).unwrap();
+    assert_eq!(imported.rules, exported.rules);
+    assert_eq!(
+        parse_rule_set(&text, RuleSetFormat::Toml).unwrap().rules,
+        exported.rules
+    );
+}
+
+#[test]
+fn test_conflict_strategies() {
+    let incoming = rule_set(
+        r#"{"priority": 5, "conditions": [{"field": "payee", "operator": "contains", "value": "gym"}],
+            "actions": [{"type": "setField", "field": "category", "value": "Fitness"}]}"#,
+    );
+    let priorities = |db_path: &std::path::PathBuf| -> Vec<(i32, String)> {
+        get_rules_db(db_path)
+            .unwrap()
+            .into_iter()
+            .map(|r| (r.priority, r.action_value))
+            .collect()
+    };
+
+    for (strategy, expected) in [
+        (ConflictStrategy::Skip, vec![(6, "Other"), (5, "Sport")]),
+        (
+            ConflictStrategy::Replace,
+            vec![(6, "Other"), (5, "Fitness")],
+        ),
+        (
+            ConflictStrategy::Renumber,
+            vec![(6, "Other"), (5, "Sport"), (4, "Fitness")],
+        ),
+    ] {
+        let (dir, db_path) = setup_db();
+        add_rule(&db_path, 5, "Gym", ("category", "Sport"));
+        add_rule(&db_path, 6, "Other", ("category", "Other"));
+        let file = dir.path().join("rules.json");
+        std::fs::write(&file, &incoming).unwrap();
+        let report = import_rules_db(&db_path, &file, None, strategy).unwrap();
+        let expected: Vec<(i32, String)> = expected
+            .into_iter()
+            .map(|(p, v)| (p, v.to_string()))
+            .collect();
+        assert_eq!(priorities(&db_path), expected, "{:?}", strategy);
+        match strategy {
+            ConflictStrategy::Skip => assert_eq!(report.skipped, 1),
+            ConflictStrategy::Replace => assert_eq!(report.replaced, 1),
+            ConflictStrategy::Renumber => assert_eq!(report.renumbered, 1),
+        }
+
+        // Importing the same file again finds nothing new
+        let again = import_rules_db(&db_path, &file, None, strategy).unwrap();
+        assert_eq!((again.imported, again.skipped), (0, 1));
+    }
+}
+
+#[test]
+fn test_rules_sharing_a_priority_in_the_set_are_renumbered() {
+    let incoming = rule_set(
+        r#"{"priority": 5, "conditions": [{"field": "payee", "operator": "contains", "value": "gym"}],
+            "actions": [{"type": "setField", "field": "category", "value": "Fitness"}]},
+           {"priority": 5, "conditions": [{"field": "payee", "operator": "contains", "value": "yoga"}],
+            "actions": [{"type": "setField", "field": "category", "value": "Yoga"}]}"#,
+    );
+    let (dir, db_path) = setup_db();
+    add_rule(&db_path, 5, "Gym", ("category", "Sport"));
+    add_rule(&db_path, 6, "Other", ("category", "Other"));
+    let file = dir.path().join("rules.json");
+    std::fs::write(&file, &incoming).unwrap();
+
+    let report = import_rules_db(&db_path, &file, None, ConflictStrategy::Replace).unwrap();
+    assert_eq!(
+        (report.imported, report.replaced, report.renumbered),
+        (2, 1, 1)
+    );
+    let priorities: Vec<(i32, String)> = get_rules_db(&db_path)
+        .unwrap()
+        .into_iter()
+        .map(|r| (r.priority, r.action_value))
+        .collect();
+    assert_eq!(
+        priorities,
+        vec![
+            (6, "Other".to_string()),
+            (5, "Fitness".to_string()),
+            (4, "Yoga".to_string())
+        ]
+    );
+}
+
+#[test]
+fn test_invalid_rule_sets_are_rejected_before_writing() {
+    let (dir, db_path) = setup_db();
+    let file = dir.path().join("rules.json");
+    let valid = r#"{"priority": 1, "conditions": [{"field": "payee", "operator": "equals", "value": "A"}],
+        "actions": [{"type": "setField", "field": "category", "value": "B"}]}"#;
+    let invalid = [
+        r#"{"priority": 2, "conditions": [{"field": "payee", "operator": "regex", "value": "(["}],
+            "actions": [{"type": "setField", "field": "category", "value": "B"}]}"#,
+        r#"{"priority": 2, "conditions": [{"field": "colour", "operator": "equals", "value": "red"}],
+            "actions": [{"type": "setField", "field": "category", "value": "B"}]}"#,
+        r#"{"priority": 2, "conditions": [{"field": "payee", "operator": "equals", "value": "A"}],
+            "actions": [{"type": "setField", "field": "colour", "value": "red"}]}"#,
+        r#"{"priority": 2, "conditions": [{"field": "payee", "operator": "equals", "value": "A"}],
+            "actions": [{"type": "transferTo", "accountId": 7}]}"#,
+    ];
+    for rule in invalid {
+        std::fs::write(&file, rule_set(&format!("{}, {}", valid, rule))).unwrap();
+        assert!(import_rules_db(&db_path, &file, None, ConflictStrategy::Skip).is_err());
+    }
+    assert!(get_rules_db(&db_path).unwrap().is_empty());
+
+    std::fs::write(&file, r#"{"version": 99, "rules": []}"#).unwrap();
+    let err = import_rules_db(&db_path, &file, None, ConflictStrategy::Skip).unwrap_err();
+    assert!(err.contains("Unsupported rule set version"));
+}
+
+#[test]
+fn test_exporting_a_rule_for_a_deleted_account_fails() {
+    let (dir, db_path) = setup_db();
+    let savings = crate::create_account_db(&db_path, "Savings".to_string(), 0.0, None).unwrap();
+    let standing = add_rule(&db_path, 1, "Standing order", ("category", ""));
+    set_rule_actions_db(
+        &db_path,
+        standing,
+        vec![RuleAction::TransferTo {
+            account_id: savings.id,
+        }],
+    )
+    .unwrap();
+    crate::delete_account_db(&db_path, savings.id).unwrap();
+
+    let file = dir.path().join("rules.json");
+    let err = export_rules_db(&db_path, &file, None, None).unwrap_err();
+    assert!(err.contains("no longer exists"), "{}", err);
+    assert!(!file.exists());
+}
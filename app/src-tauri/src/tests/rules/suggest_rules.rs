@@ -0,0 +1,213 @@
+use crate::rules::suggest::suggest_rules_db;
+use crate::rules::MatchOperator;
+use crate::tests::common::{add_rule, add_transaction, setup_db};
+
+fn card(db_path: &std::path::PathBuf) -> i32 {
+    crate::create_account_db(db_path, "Card".to_string(), 0.0, None)
+        .unwrap()
+        .id
+}
+
+#[test]
+fn test_consistent_payees_become_contains_rules() {
+    let (_dir, db_path) = setup_db();
+    let account_id = card(&db_path);
+    for payee in [
+        "STARBUCKS 1021",
+        "Starbucks Main St",
+        "STARBUCKS 88",
+        "Starbucks",
+    ] {
+        add_transaction(
+            &db_path,
+            account_id,
+            "2024-05-01",
+            payee,
+            Some("Coffee"),
+            -10.0,
+        );
+    }
+    add_transaction(
+        &db_path,
+        account_id,
+        "2024-05-01",
+        "Starbucks 5",
+        Some("Snacks"),
+        -10.0,
+    );
+    add_transaction(
+        &db_path,
+        account_id,
+        "2024-05-01",
+        "STARBUCKS 77",
+        None,
+        -10.0,
+    );
+    add_transaction(
+        &db_path,
+        account_id,
+        "2024-05-01",
+        "Bakery",
+        Some("Food"),
+        -10.0,
+    );
+
+    let suggestions = suggest_rules_db(&db_path, Some(3), Some(0.75)).unwrap();
+    assert_eq!(suggestions.len(), 1);
+    let suggestion = &suggestions[0];
+    assert_eq!(suggestion.category, "Coffee");
+    assert_eq!((suggestion.occurrences, suggestion.transactions), (4, 5));
+    assert_eq!(suggestion.consistency, 0.8);
+    // The uncategorized one and the snack
+    assert_eq!(suggestion.affected, 2);
+    assert_eq!(suggestion.rule.id, 0);
+    assert_eq!(suggestion.rule.conditions.len(), 1);
+    assert_eq!(
+        suggestion.rule.conditions[0].operator,
+        MatchOperator::Contains
+    );
+    assert_eq!(suggestion.rule.conditions[0].value, "starbucks");
+    assert_eq!(suggestion.rule.action_value, "Coffee");
+
+    // The default consistency of 90% rules it out
+    assert!(suggest_rules_db(&db_path, None, None).unwrap().is_empty());
+}
+
+#[test]
+fn test_varying_descriptors_become_a_regex() {
+    let (_dir, db_path) = setup_db();
+    let account_id = card(&db_path);
+    for payee in [
+        "SQ *BLUE BOTTLE 12",
+        "SQ*BLUE-BOTTLE",
+        "Sq * Blue Bottle 998",
+    ] {
+        add_transaction(
+            &db_path,
+            account_id,
+            "2024-05-01",
+            payee,
+            Some("Coffee"),
+            -10.0,
+        );
+    }
+
+    let suggestions = suggest_rules_db(&db_path, None, None).unwrap();
+    assert_eq!(suggestions.len(), 1);
+    let condition = &suggestions[0].rule.conditions[0];
+    assert_eq!(condition.operator, MatchOperator::Regex);
+    assert_eq!(
+        condition.value,
+        r"\bsq[\W_]+(?:\d+[\W_]+)*blue[\W_]+(?:\d+[\W_]+)*bottle"
+    );
+    assert_eq!(suggestions[0].examples.len(), 3);
+    assert_eq!(suggestions[0].affected, 0);
+}
+
+#[test]
+fn test_mixed_groups_are_narrowed_by_more_words() {
+    let (_dir, db_path) = setup_db();
+    let account_id = card(&db_path);
+    for _ in 0..3 {
+        add_transaction(
+            &db_path,
+            account_id,
+            "2024-05-01",
+            "Amazon Prime",
+            Some("Subscriptions"),
+            -10.0,
+        );
+        add_transaction(
+            &db_path,
+            account_id,
+            "2024-05-01",
+            "AMAZON MKTP 123",
+            Some("Shopping"),
+            -10.0,
+        );
+    }
+    add_transaction(
+        &db_path,
+        account_id,
+        "2024-05-01",
+        "Amazon Fresh",
+        Some("Groceries"),
+        -10.0,
+    );
+
+    let suggestions = suggest_rules_db(&db_path, None, None).unwrap();
+    let patterns: Vec<(&str, &str)> = suggestions
+        .iter()
+        .map(|s| (s.rule.match_pattern.as_str(), s.category.as_str()))
+        .collect();
+    assert_eq!(
+        patterns,
+        vec![
+            ("amazon mktp", "Shopping"),
+            ("amazon prime", "Subscriptions")
+        ]
+    );
+    assert!(suggest_rules_db(&db_path, Some(4), None)
+        .unwrap()
+        .is_empty());
+}
+
+#[test]
+fn test_payees_covered_by_a_rule_are_skipped() {
+    let (_dir, db_path) = setup_db();
+    let account_id = card(&db_path);
+    for _ in 0..3 {
+        add_transaction(
+            &db_path,
+            account_id,
+            "2024-05-01",
+            "Netflix",
+            Some("TV"),
+            -10.0,
+        );
+        add_transaction(
+            &db_path,
+            account_id,
+            "2024-05-01",
+            "Shell 4411",
+            Some("Fuel"),
+            -10.0,
+        );
+    }
+    add_rule(&db_path, 1, "Netflix", ("category", "TV"));
+
+    let suggestions = suggest_rules_db(&db_path, None, None).unwrap();
+    assert_eq!(suggestions.len(), 1);
+    assert_eq!(suggestions[0].category, "Fuel");
+    assert!(suggest_rules_db(&db_path, None, Some(1.5)).is_err());
+}
+
+#[test]
+fn test_a_rule_covering_some_payees_leaves_the_rest_to_suggest() {
+    let (_dir, db_path) = setup_db();
+    let account_id = card(&db_path);
+    for _ in 0..3 {
+        add_transaction(
+            &db_path,
+            account_id,
+            "2024-05-01",
+            "Shell 4411",
+            Some("Fuel"),
+            -10.0,
+        );
+    }
+    add_transaction(
+        &db_path,
+        account_id,
+        "2024-05-01",
+        "Shell Express",
+        Some("Fuel"),
+        -10.0,
+    );
+    add_rule(&db_path, 1, "Shell Express", ("category", "Fuel"));
+
+    let suggestions = suggest_rules_db(&db_path, None, None).unwrap();
+    assert_eq!(suggestions.len(), 1);
+    assert_eq!(suggestions[0].category, "Fuel");
+    assert_eq!(suggestions[0].occurrences, 3);
+}
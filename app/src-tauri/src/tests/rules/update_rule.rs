@@ -1,3 +1,7 @@
+use crate::rules::{
+    set_rule_actions_db, set_rule_conditions_db, MatchJoin, MatchOperator, RuleAction,
+    RuleCondition,
+};
 use crate::tests::common::setup_db;
 use crate::{create_rule_db, get_rules_db, update_rule_db};
 
@@ -35,3 +39,83 @@ fn test_update_rule() {
     assert_eq!(rule.action_field, "amount");
     assert_eq!(rule.action_value, "50.00");
 }
+
+#[test]
+fn test_update_rule_keeps_further_conditions_and_actions() {
+    let (_dir, db_path) = setup_db();
+    let id = create_rule_db(
+        &db_path,
+        10,
+        "payee".to_string(),
+        "Starbucks".to_string(),
+        "category".to_string(),
+        "Coffee".to_string(),
+    )
+    .unwrap();
+    let condition = |field: &str, value: &str| RuleCondition {
+        field: field.to_string(),
+        operator: MatchOperator::Contains,
+        value: value.to_string(),
+        value_to: None,
+        case_sensitive: false,
+    };
+    set_rule_conditions_db(
+        &db_path,
+        id,
+        MatchJoin::And,
+        vec![condition("payee", "starbucks"), condition("notes", "latte")],
+    )
+    .unwrap();
+    let tag = RuleAction::AddTag {
+        tag: "treat".to_string(),
+    };
+    set_rule_actions_db(
+        &db_path,
+        id,
+        vec![
+            RuleAction::SetField {
+                field: "category".to_string(),
+                value: "Coffee".to_string(),
+            },
+            tag.clone(),
+        ],
+    )
+    .unwrap();
+
+    update_rule_db(
+        &db_path,
+        id,
+        20,
+        "payee".to_string(),
+        "costa".to_string(),
+        "category".to_string(),
+        "Cafe".to_string(),
+    )
+    .unwrap();
+
+    let rule = &get_rules_db(&db_path).unwrap()[0];
+    assert_eq!(
+        rule.conditions,
+        vec![condition("payee", "costa"), condition("notes", "latte")]
+    );
+    assert_eq!(
+        rule.actions,
+        vec![
+            RuleAction::SetField {
+                field: "category".to_string(),
+                value: "Cafe".to_string(),
+            },
+            tag
+        ]
+    );
+    assert!(update_rule_db(
+        &db_path,
+        999,
+        0,
+        "payee".to_string(),
+        "x".to_string(),
+        "category".to_string(),
+        "y".to_string(),
+    )
+    .is_err());
+}
@@ -160,6 +160,40 @@ fn test_delete_transaction_fallback_by_notes() {
     assert_eq!(a2_after, 0.0);
 }
 
+#[test]
+fn test_delete_transaction_fallback_leaves_linked_transfers_alone() {
+    let (_dir, db_path) = setup_db();
+    let acc1 = crate::create_account_db(&db_path, "Acc1".to_string(), 100.0, None).unwrap();
+    crate::create_account_db(&db_path, "Acc2".to_string(), 0.0, None).unwrap();
+    let args = |payee: &str| crate::CreateTransactionArgs {
+        account_id: acc1.id,
+        date: "2023-01-01".to_string(),
+        payee: payee.to_string(),
+        notes: Some("XFER".to_string()),
+        category: None,
+        amount: -20.0,
+        ticker: None,
+        shares: None,
+        price_per_share: None,
+        fee: None,
+        currency: None,
+    };
+    crate::create_transaction_db(&db_path, args("Acc2")).unwrap();
+    let lone = crate::create_transaction_db(&db_path, args("Shop")).unwrap();
+
+    crate::delete_transaction_db(&db_path, lone.id).unwrap();
+
+    let conn = Connection::open(&db_path).unwrap();
+    let transfers: i64 = conn
+        .query_row(
+            "SELECT COUNT(*) FROM transactions WHERE category = 'Transfer'",
+            [],
+            |r| r.get(0),
+        )
+        .unwrap();
+    assert_eq!(transfers, 2);
+}
+
 #[test]
 fn test_delete_transaction_missing_id_should_error() {
     let (_dir, db_path) = setup_db();